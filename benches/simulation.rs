@@ -0,0 +1,170 @@
+//! Measures the headless parts of the game that bots and many simultaneous boards depend on
+
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use klocki::{
+    action::Action,
+    engine::Engine,
+    piece::Piece,
+    rules::Rules,
+    scheduler::Scheduler,
+    settings,
+    shape::ShapeType,
+    stack::{ClearDelays, GarbageRow, Stack},
+};
+
+const ACTIONS: [Action; 8] = [
+    Action::MoveRight,
+    Action::MoveLeft,
+    Action::MoveDown,
+    Action::RotateClockwise,
+    Action::RotateCounterClockwise,
+    Action::HardDrop,
+    Action::SoftDrop,
+    Action::HoldPiece,
+];
+
+/// Actions per iteration of the random game
+const GAME_STEPS: u64 = 1000;
+
+/// Standard stack filled with garbage up to the given height
+fn stack(rows: usize, rng: &mut StdRng) -> Stack {
+    let mut stack = Stack::new(10, 20, 20);
+    let garbage: Vec<GarbageRow> = (0..rows)
+        .map(|_| GarbageRow {
+            hole: rng.gen_range(0, 10),
+        })
+        .collect();
+
+    stack.insert_garbage(&garbage);
+    stack.finish_clearing();
+    stack
+}
+
+fn collision(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let stack = stack(8, &mut rng);
+
+    // Every column and row the piece can occupy, most of them overlapping the garbage
+    let mut pieces = vec![];
+    for x in -2..10 {
+        for y in 0..40 {
+            let mut piece = Piece::new(ShapeType::T, &stack);
+            piece.x = x;
+            piece.y = y;
+            pieces.push(piece);
+        }
+    }
+
+    c.bench_function("collision", |b| {
+        b.iter(|| {
+            pieces
+                .iter()
+                .filter(|p| stack.collision(black_box(p)))
+                .count()
+        })
+    });
+}
+
+fn kicks(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(1);
+    let stack = stack(12, &mut rng);
+
+    // Pieces resting on the garbage, where most rotations need to try the kicks
+    let pieces: Vec<Piece> = [ShapeType::I, ShapeType::J, ShapeType::S, ShapeType::T]
+        .iter()
+        .map(|&shape| {
+            let mut piece = Piece::new(shape, &stack);
+            piece.fall(&stack);
+            piece
+        })
+        .collect();
+
+    c.bench_function("kicks", |b| {
+        b.iter(|| {
+            let mut rotated = 0;
+            for piece in &pieces {
+                let mut piece = piece.clone();
+                for &clockwise in &[true, true, false, false, false, true] {
+                    if piece.rotate(black_box(clockwise), &stack) {
+                        rotated += 1;
+                    }
+                }
+            }
+            rotated
+        })
+    });
+}
+
+fn line_clear(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(2);
+
+    // Four rows with a hole in the first column, closed with a vertical I for a tetris
+    let mut setup = || {
+        let mut stack = stack(8, &mut rng);
+        stack.insert_garbage(&[GarbageRow { hole: 0 }; 4]);
+        stack.finish_clearing();
+
+        let mut piece = Piece::new(ShapeType::I, &stack);
+        piece.rotate(true, &stack);
+        while piece.shift(-1, 0, &stack) {}
+        piece.fall(&stack);
+
+        (stack, piece)
+    };
+
+    c.bench_function("line clear", |b| {
+        b.iter_batched(
+            &mut setup,
+            |(mut stack, piece)| stack.lock(&piece, ClearDelays::flat(0)),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn random_game(c: &mut Criterion) {
+    let settings = settings::Gameplay::default();
+    let rules = Rules::default();
+
+    let mut group = c.benchmark_group("random game");
+    group.throughput(Throughput::Elements(GAME_STEPS));
+    group.bench_function("actions", |b| {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut engine = Engine::new(&rng.gen(), true, &rules);
+
+        b.iter(|| {
+            for _ in 0..GAME_STEPS {
+                let action = ACTIONS[rng.gen_range(0, ACTIONS.len())];
+                engine.skip(&settings, action);
+                while let Some(action) = engine.pop_action() {
+                    engine.skip(&settings, action);
+                }
+
+                if engine.game_over() {
+                    engine.reset(&rng.gen());
+                }
+            }
+        })
+    });
+    group.finish();
+}
+
+fn boards(c: &mut Criterion) {
+    let rules = Rules::default();
+
+    // Battle royale sized lobby, a tick has to fit in a 60 FPS frame with room to spare
+    let mut scheduler = Scheduler::new(0);
+    for i in 0..64 {
+        scheduler.add(&[i; 32], &rules, Duration::from_millis(16));
+    }
+
+    c.bench_function("64 boards tick", |b| {
+        b.iter(|| scheduler.tick(Duration::from_millis(16)))
+    });
+}
+
+criterion_group!(benches, collision, kicks, line_clear, random_game, boards);
+criterion_main!(benches);
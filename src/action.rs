@@ -14,4 +14,55 @@ pub enum Action {
     FallPiece,
     LockPiece,
     GameOver,
+
+    // New actions go at the end, so that saved replays keep their meaning
+    ShiftRight,
+    ShiftLeft,
+    ActivateZone,
+    EndZone,
+    /// Takes back a hard drop that has not locked yet
+    UndoDrop,
+    Rotate180,
+}
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveRight => "Move right",
+            Action::MoveLeft => "Move left",
+            Action::MoveDown => "Move down",
+            Action::RotateClockwise => "Rotate clockwise",
+            Action::RotateCounterClockwise => "Rotate counterclockwise",
+            Action::HardDrop => "Hard drop",
+            Action::SoftDrop => "Soft drop",
+            Action::HoldPiece => "Hold",
+            Action::FallPiece => "Fall",
+            Action::LockPiece => "Lock",
+            Action::GameOver => "Game over",
+            Action::ShiftRight => "Shift right",
+            Action::ShiftLeft => "Shift left",
+            Action::ActivateZone => "Activate zone",
+            Action::EndZone => "End zone",
+            Action::UndoDrop => "Undo drop",
+            Action::Rotate180 => "Rotate 180",
+        }
+    }
+
+    /// Movement keeps repeating while the key is held
+    pub fn repeats(self) -> bool {
+        match self {
+            Action::MoveRight | Action::MoveLeft | Action::MoveDown => true,
+            _ => false,
+        }
+    }
+
+    /// Single action replacing the repeated one when the auto repeat rate is zero
+    pub fn instant(self) -> Action {
+        match self {
+            Action::MoveRight => Action::ShiftRight,
+            Action::MoveLeft => Action::ShiftLeft,
+            Action::MoveDown => Action::SoftDrop,
+            action => action,
+        }
+    }
 }
@@ -0,0 +1,95 @@
+use std::{env, path::PathBuf};
+
+use crate::{mode::GameMode, utils};
+
+pub const USAGE: &str = "\
+Usage: klocki [OPTIONS] [REPLAY]
+       klocki verify <REPLAY>
+
+Options:
+    --mode <mode>       Start directly into a game of the given mode
+    --seed <code>       Use a fixed seed, either 64 hex digits or any text
+    --resources <dir>   Load resources from an additional directory
+    --fullscreen        Start in fullscreen
+    --replay <file>     Start watching the given replay
+    -h, --help          Print this message";
+
+#[derive(Default)]
+pub struct Args {
+    pub mode: Option<GameMode>,
+    pub seed: Option<[u8; 32]>,
+    pub resources: Option<PathBuf>,
+    pub fullscreen: bool,
+    pub replay: Option<PathBuf>,
+    pub verify: Option<PathBuf>,
+    pub help: bool,
+}
+
+impl Args {
+    pub fn parse() -> Result<Args, String> {
+        Args::parse_from(env::args().skip(1))
+    }
+
+    pub fn parse_from<I: Iterator<Item = String>>(iter: I) -> Result<Args, String> {
+        let mut args = Args::default();
+
+        let mut iter = iter.peekable();
+        if iter.peek().map(String::as_str) == Some("verify") {
+            iter.next();
+            let path = iter.next().ok_or("missing replay to verify")?;
+            args.verify = Some(PathBuf::from(path));
+            return Ok(args);
+        }
+
+        while let Some(arg) = iter.next() {
+            let mut value = |name: &str| {
+                iter.next()
+                    .ok_or_else(|| format!("missing value for {}", name))
+            };
+
+            match arg.as_str() {
+                "--mode" => args.mode = Some(value("--mode")?.parse()?),
+                "--seed" => args.seed = Some(utils::seed_from_code(&value("--seed")?)),
+                "--resources" => args.resources = Some(PathBuf::from(value("--resources")?)),
+                "--fullscreen" => args.fullscreen = true,
+                "--replay" => args.replay = Some(PathBuf::from(value("--replay")?)),
+                "-h" | "--help" => args.help = true,
+                // Opening a replay file with the game passes it as the only argument
+                _ if !arg.starts_with('-') && args.replay.is_none() => {
+                    args.replay = Some(PathBuf::from(arg))
+                }
+                _ => return Err(format!("unexpected argument '{}'", arg)),
+            }
+        }
+
+        Ok(args)
+    }
+
+    /// Whether the game should skip the menu and start playing right away
+    pub fn start_game(&self) -> bool {
+        self.mode.is_some() || self.seed.is_some()
+    }
+}
+
+#[test]
+fn args_test() {
+    let parse = |s: &str| Args::parse_from(s.split_whitespace().map(String::from));
+
+    let args = parse("--mode endless --seed abc --fullscreen").unwrap();
+    assert_eq!(args.mode, Some(GameMode::Endless));
+    assert_eq!(args.seed, Some(utils::seed_from_code("abc")));
+    assert!(args.fullscreen);
+    assert!(args.start_game());
+
+    let args = parse("replay.klocki").unwrap();
+    assert_eq!(args.replay, Some(PathBuf::from("replay.klocki")));
+    assert!(!args.start_game());
+
+    let args = parse("verify replay.klocki").unwrap();
+    assert_eq!(args.verify, Some(PathBuf::from("replay.klocki")));
+    assert!(parse("verify").is_err());
+
+    assert!(parse("--mode").is_err());
+    assert!(parse("--mode unknown").is_err());
+    assert!(parse("--unknown").is_err());
+}
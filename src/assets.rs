@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+
+use ggez::{
+    graphics::{Font, Image},
+    Context, GameResult,
+};
+
+use crate::utils;
+
+/// Fonts and images loaded once and shared between scenes, keyed by their path.
+/// Cloned images share the same texture, so nothing is uploaded twice.
+#[derive(Default)]
+pub struct Assets {
+    fonts: HashMap<String, Font>,
+    images: HashMap<String, Image>,
+}
+
+impl Assets {
+    pub fn font(&mut self, ctx: &mut Context, path: &str) -> GameResult<Font> {
+        if let Some(&font) = self.fonts.get(path) {
+            return Ok(font);
+        }
+
+        let font = Font::new(ctx, utils::path(ctx, path))?;
+        self.fonts.insert(path.to_string(), font);
+        Ok(font)
+    }
+
+    pub fn image(&mut self, ctx: &mut Context, path: &str) -> GameResult<Image> {
+        if let Some(image) = self.images.get(path) {
+            return Ok(image.clone());
+        }
+
+        let image = Image::new(ctx, utils::path(ctx, path))?;
+        self.images.insert(path.to_string(), image.clone());
+        Ok(image)
+    }
+
+    /// Image made at runtime, generated only the first time the key is asked for
+    pub fn generated_image<F>(
+        &mut self,
+        ctx: &mut Context,
+        key: &str,
+        generate: F,
+    ) -> GameResult<Image>
+    where
+        F: FnOnce(&mut Context) -> GameResult<Image>,
+    {
+        if let Some(image) = self.images.get(key) {
+            return Ok(image.clone());
+        }
+
+        let image = generate(ctx)?;
+        self.images.insert(key.to_string(), image.clone());
+        Ok(image)
+    }
+}
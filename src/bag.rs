@@ -9,46 +9,99 @@ use ggez::{
     nalgebra::{Point2, Vector2},
     Context, GameResult,
 };
-use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
 
 use crate::{
     blocks::Blocks,
+    rules::Randomizer,
     shape::{self, Shape, ShapeType},
+    utils::Fnv64,
 };
 
+/// Next pieces shown beside the board
+pub const PREVIEW: usize = 6;
+
+#[derive(Clone)]
 pub struct Bag {
     bag: VecDeque<ShapeType>,
     rng: StdRng,
+    randomizer: Randomizer,
+    /// Pieces dealt over and over by the loop randomizer
+    sequence: Vec<ShapeType>,
+    /// Pieces taken out so far
+    popped: usize,
 }
 
 impl Bag {
     pub fn new(seed: &[u8; 32]) -> Bag {
+        Bag::with_randomizer(seed, Randomizer::Bag)
+    }
+
+    pub fn with_randomizer(seed: &[u8; 32], randomizer: Randomizer) -> Bag {
         let rng: StdRng = SeedableRng::from_seed(*seed);
 
         let mut bag = Bag {
             bag: VecDeque::with_capacity(14),
             rng,
+            randomizer,
+            sequence: vec![],
+            popped: 0,
         };
 
         bag.fill();
         bag
     }
 
+    /// Deals the sequence in a loop, the seed is kept for the rest of the game
+    pub fn with_loop(seed: &[u8; 32], sequence: &[ShapeType]) -> Bag {
+        let mut bag = Bag::with_randomizer(seed, Randomizer::Loop);
+        bag.sequence = sequence.to_vec();
+        bag.bag.clear();
+        bag.fill();
+        bag
+    }
+
     pub fn pop(&mut self) -> ShapeType {
         let shape = self.bag.pop_front();
+        self.popped += 1;
         self.fill();
         shape.unwrap()
     }
 
+    pub fn popped(&self) -> usize {
+        self.popped
+    }
+
     pub fn peek(&self, n: usize) -> Take<Iter<ShapeType>> {
         self.bag.iter().take(n)
     }
 
+    pub fn push_front(&mut self, shapes: &[ShapeType]) {
+        for &shape in shapes.iter().rev() {
+            self.bag.push_front(shape);
+        }
+    }
+
+    /// The next number of the generator stands in for its whole state
+    pub fn hash(&self, hash: &mut Fnv64) {
+        hash.write(self.randomizer as u64);
+        hash.write(self.bag.len() as u64);
+        for &shape in &self.bag {
+            hash.write(shape as u64);
+        }
+        hash.write(self.rng.clone().gen());
+    }
+
+    pub fn set_queue(&mut self, queue: &[ShapeType]) {
+        self.bag.clear();
+        self.bag.extend(queue);
+        self.fill();
+    }
+
     pub fn draw(
         &self,
         ctx: &mut Context,
         position: Point2<f32>,
-        blocks: &mut Blocks,
         block_size: i32,
         text_color: Color,
         font: Font,
@@ -65,37 +118,49 @@ impl Bag {
             Align::Center,
         );
 
-        graphics::draw(ctx, &text, DrawParam::new().dest(position))?;
+        graphics::draw(ctx, &text, DrawParam::new().dest(position))
+    }
 
+    /// Adds the next pieces below the label to the batch
+    pub fn add_blocks(&self, position: Point2<f32>, blocks: &mut Blocks, block_size: i32) {
         let position = position + Vector2::new(0.0, block_size as f32 * 2.5);
 
-        for (i, &shape) in self.peek(6).enumerate() {
+        for (i, &shape) in self.peek(PREVIEW).enumerate() {
             let shape = Shape::new(shape);
             let position = position
                 + Vector2::new(
                     block_size as f32 * 3.0 - shape.grids[0].width as f32 * block_size as f32 / 2.0,
                     (i as i32 * block_size * 3) as f32,
                 );
-            shape.draw(ctx, 0, position, blocks, block_size, 0.9)?;
+            shape.add(0, position, blocks, block_size, 0.9);
         }
-
-        Ok(())
     }
 
     fn fill(&mut self) {
-        match self.bag.len() {
-            0 => {
-                self.fill_7();
-                self.fill_7();
-            }
-            7 => self.fill_7(),
-            _ => (),
+        while self.bag.len() <= 7 {
+            self.fill_7();
         }
     }
 
     fn fill_7(&mut self) {
         let mut shapes = shape::all_shape_types();
-        shapes.shuffle(&mut self.rng);
+
+        match self.randomizer {
+            Randomizer::Bag => shapes.shuffle(&mut self.rng),
+            Randomizer::Random => {
+                let all = shape::all_shape_types();
+                for shape in shapes.iter_mut() {
+                    *shape = all[self.rng.gen_range(0, all.len())];
+                }
+            }
+            // Without a sequence the loop would never fill up, the shapes go in order then
+            Randomizer::Loop => {
+                if !self.sequence.is_empty() {
+                    shapes = self.sequence.clone();
+                }
+            }
+        }
+
         self.bag.extend(shapes);
     }
 }
@@ -123,3 +188,33 @@ fn bag_test() {
         assert!(types.contains(&shape));
     }
 }
+
+#[test]
+fn bag_loop_test() {
+    let sequence = shape::parse_sequence("SZSZT").unwrap();
+    let mut bag = Bag::with_loop(&[0; 32], &sequence);
+    for _ in 0..3 {
+        for &shape in &sequence {
+            assert_eq!(shape, bag.pop());
+        }
+    }
+
+    let mut bag = Bag::with_randomizer(&[0; 32], Randomizer::Loop);
+    assert_eq!(bag.pop(), ShapeType::I);
+}
+
+#[test]
+fn bag_push_front_test() {
+    let seed = [0; 32];
+    let mut bag = Bag::new(&seed);
+    let next: Vec<ShapeType> = bag.peek(14).cloned().collect();
+
+    let sequence = shape::parse_sequence("tij los").unwrap();
+    bag.push_front(&sequence);
+
+    for &shape in sequence.iter().chain(next.iter()) {
+        assert_eq!(shape, bag.pop());
+    }
+
+    assert_eq!(Err('X'), shape::parse_sequence("TX"));
+}
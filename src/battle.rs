@@ -0,0 +1,368 @@
+use std::{collections::VecDeque, time::Duration};
+
+use ggez::{Context, GameResult};
+use rand::{rngs::ThreadRng, thread_rng, Rng};
+
+use crate::{
+    engine::Engine, gameplay::Gameplay, global::Global, rules::Rules, scheduler::Scheduler,
+    stack::GarbageRow,
+};
+
+/// Time incoming garbage waits in the pool, so that it can still be cancelled
+const GARBAGE_DELAY: Duration = Duration::from_secs(1);
+
+/// Board played by a person, drawn in full
+pub struct Board {
+    pub gameplay: Gameplay,
+    pub team: usize,
+}
+
+/// Board of a bot, played without graphics on the scheduler
+struct BotBoard {
+    id: usize,
+    team: usize,
+}
+
+struct Attack {
+    lines: i32,
+    hole: usize,
+    waiting: Duration,
+}
+
+/// Garbage waiting to be sent to the members of a team
+#[derive(Default)]
+pub struct AttackPool {
+    incoming: VecDeque<Attack>,
+    /// Garbage waits for a piece to lock instead of a timer, see `Rules::garbage_on_lock`
+    on_lock: bool,
+}
+
+impl AttackPool {
+    pub fn new(on_lock: bool) -> AttackPool {
+        AttackPool {
+            incoming: VecDeque::new(),
+            on_lock,
+        }
+    }
+
+    pub fn add(&mut self, lines: i32, hole: usize) {
+        self.incoming.push_back(Attack {
+            lines,
+            hole,
+            waiting: Duration::new(0, 0),
+        });
+    }
+
+    /// Cancels incoming garbage, returns the lines left over
+    pub fn cancel(&mut self, mut lines: i32) -> i32 {
+        while lines > 0 {
+            match self.incoming.front_mut() {
+                Some(attack) if attack.lines > lines => {
+                    attack.lines -= lines;
+                    return 0;
+                }
+                Some(attack) => {
+                    lines -= attack.lines;
+                    self.incoming.pop_front();
+                }
+                None => break,
+            }
+        }
+
+        lines
+    }
+
+    pub fn pending(&self) -> i32 {
+        self.incoming.iter().map(|a| a.lines).sum()
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        if self.on_lock {
+            return;
+        }
+
+        for attack in &mut self.incoming {
+            attack.waiting += dt;
+        }
+    }
+
+    /// Lines and hole column of the next attack done waiting, if any
+    pub fn pop_ready(&mut self) -> Option<(i32, usize)> {
+        match self.incoming.front() {
+            Some(attack) if !self.on_lock && attack.waiting >= GARBAGE_DELAY => {
+                self.incoming.pop_front().map(|a| (a.lines, a.hole))
+            }
+            _ => None,
+        }
+    }
+
+    /// Every attack left, called as a piece of the receiver locks without clearing.
+    /// Nothing comes out when the garbage goes by the timer.
+    pub fn lock(&mut self) -> Vec<(i32, usize)> {
+        if !self.on_lock {
+            return vec![];
+        }
+
+        self.incoming.drain(..).map(|a| (a.lines, a.hole)).collect()
+    }
+
+    /// Lines of every incoming attack along with the time left until it's queued,
+    /// none when it waits for a lock
+    pub fn forecast(&self) -> Vec<(i32, Option<Duration>)> {
+        self.incoming
+            .iter()
+            .map(|a| {
+                let eta = GARBAGE_DELAY.checked_sub(a.waiting).unwrap_or_default();
+                (a.lines, if self.on_lock { None } else { Some(eta) })
+            })
+            .collect()
+    }
+}
+
+/// Teams of boards sending garbage to each other, members of a team share the attack pool.
+/// Members are numbered with the boards of the players first, then the bots.
+pub struct Battle {
+    pub boards: Vec<Board>,
+    bots: Vec<BotBoard>,
+    /// Created along with the first bot, all bots are updated at once on its thread pool
+    scheduler: Option<Scheduler>,
+    pools: Vec<AttackPool>,
+    rng: ThreadRng,
+    winner: Option<usize>,
+}
+
+impl Battle {
+    pub fn new(boards: Vec<Board>) -> Battle {
+        let teams = boards.iter().map(|b| b.team + 1).max().unwrap_or(0);
+        let on_lock = boards
+            .first()
+            .map_or(false, |b| b.gameplay.engine.rules().garbage_on_lock);
+
+        Battle {
+            boards,
+            bots: vec![],
+            scheduler: None,
+            pools: (0..teams).map(|_| AttackPool::new(on_lock)).collect(),
+            rng: thread_rng(),
+            winner: None,
+        }
+    }
+
+    /// Adds a bot to the team, playing with the given delay between its actions
+    pub fn add_bot(&mut self, team: usize, seed: &[u8; 32], rules: &Rules, delay: Duration) {
+        let scheduler = self.scheduler.get_or_insert_with(|| Scheduler::new(0));
+        let id = scheduler.add(seed, rules, delay);
+        self.bots.push(BotBoard { id, team });
+
+        while self.pools.len() <= team {
+            self.pools.push(AttackPool::new(rules.garbage_on_lock));
+        }
+    }
+
+    /// Boards of the bots in the order they were added
+    pub fn bots(&self) -> impl Iterator<Item = &Engine> {
+        self.bots.iter().map(move |b| self.bot_engine(b.id))
+    }
+
+    fn bot_engine(&self, id: usize) -> &Engine {
+        &self.scheduler.as_ref().unwrap().boards()[id].engine
+    }
+
+    fn members(&self) -> usize {
+        self.boards.len() + self.bots.len()
+    }
+
+    fn team(&self, member: usize) -> usize {
+        match member.checked_sub(self.boards.len()) {
+            None => self.boards[member].team,
+            Some(bot) => self.bots[bot].team,
+        }
+    }
+
+    fn engine(&self, member: usize) -> &Engine {
+        match member.checked_sub(self.boards.len()) {
+            None => &self.boards[member].gameplay.engine,
+            Some(bot) => self.bot_engine(self.bots[bot].id),
+        }
+    }
+
+    fn engine_mut(&mut self, member: usize) -> &mut Engine {
+        match member.checked_sub(self.boards.len()) {
+            None => &mut self.boards[member].gameplay.engine,
+            Some(bot) => {
+                let id = self.bots[bot].id;
+                let board = self.scheduler.as_mut().unwrap().board_mut(id).unwrap();
+                &mut board.engine
+            }
+        }
+    }
+
+    pub fn winner(&self) -> Option<usize> {
+        self.winner
+    }
+
+    pub fn team_out(&self, team: usize) -> bool {
+        (0..self.members())
+            .filter(|&m| self.team(m) == team)
+            .all(|m| self.engine(m).game_over())
+    }
+
+    /// Garbage lines waiting in the pool of the team
+    pub fn pending(&self, team: usize) -> i32 {
+        self.pools[team].pending()
+    }
+
+    /// Garbage on its way to the team, in the order it arrives
+    pub fn forecast(&self, team: usize) -> Vec<(i32, Option<Duration>)> {
+        self.pools[team].forecast()
+    }
+
+    fn send(&mut self, team: usize, lines: i32) {
+        let lines = self.pools[team].cancel(lines);
+        if lines == 0 {
+            return;
+        }
+
+        let width = self.engine(0).stack.width as usize;
+        let hole = self.rng.gen_range(0, width);
+
+        for (target, pool) in self.pools.iter_mut().enumerate() {
+            if target != team {
+                pool.add(lines, hole);
+            }
+        }
+    }
+
+    /// Splits ready garbage evenly between members of the team that are still alive
+    fn distribute(&mut self, team: usize, dt: Duration) {
+        let members: Vec<usize> = (0..self.members())
+            .filter(|&m| self.team(m) == team && !self.engine(m).game_over())
+            .collect();
+
+        if members.is_empty() {
+            self.pools[team].incoming.clear();
+            return;
+        }
+
+        self.pools[team].update(dt);
+
+        while let Some((lines, hole)) = self.pools[team].pop_ready() {
+            let share = lines as usize / members.len();
+            let remainder = lines as usize % members.len();
+
+            // Whoever has the least garbage queued takes the leftover lines
+            let mut order = members.clone();
+            order.sort_by_key(|&m| self.engine(m).pending_garbage());
+
+            for (n, &m) in order.iter().enumerate() {
+                let lines = share + if n < remainder { 1 } else { 0 };
+                let rows = vec![GarbageRow { hole }; lines];
+                self.engine_mut(m).queue_garbage(&rows);
+            }
+        }
+    }
+
+    pub fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        let dt = g.clock.delta();
+
+        for board in &mut self.boards {
+            board.gameplay.update(ctx, g, true)?;
+        }
+
+        // Bots wait for the countdown of the players, like the players for the bots
+        let waiting = g.imgui_state.paused
+            || g.settings_state.edit_hud
+            || self.boards.iter().any(|b| b.gameplay.counting_down());
+        if let Some(scheduler) = &mut self.scheduler {
+            if !waiting {
+                scheduler.tick(dt);
+            }
+        }
+
+        for member in 0..self.members() {
+            let (attack, clean_locks) = match member.checked_sub(self.boards.len()) {
+                None => {
+                    let gameplay = &mut self.boards[member].gameplay;
+                    (gameplay.take_attack(), gameplay.take_clean_locks())
+                }
+                Some(bot) => {
+                    let scheduler = self.scheduler.as_ref().unwrap();
+                    let board = &scheduler.boards()[self.bots[bot].id];
+                    (board.attack(), board.clean_locks())
+                }
+            };
+
+            let team = self.team(member);
+            if attack > 0 {
+                self.send(team, attack);
+            }
+
+            // Waiting for a lock, the whole pool of the team goes to whoever locked first
+            if clean_locks > 0 {
+                for (lines, hole) in self.pools[team].lock() {
+                    let rows = vec![GarbageRow { hole }; lines as usize];
+                    self.engine_mut(member).insert_garbage(&rows);
+                }
+            }
+        }
+
+        for team in 0..self.pools.len() {
+            self.distribute(team, dt);
+        }
+
+        if self.winner.is_none() {
+            let alive: Vec<usize> = (0..self.pools.len())
+                .filter(|&t| !self.team_out(t))
+                .collect();
+
+            if alive.len() == 1 {
+                self.winner = Some(alive[0]);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn forecast_test() {
+    let mut pool = AttackPool::default();
+    for &(lines, waiting) in &[(3, 600), (2, 100)] {
+        pool.incoming.push_back(Attack {
+            lines,
+            hole: 0,
+            waiting: Duration::from_millis(waiting),
+        });
+    }
+
+    assert_eq!(
+        pool.forecast(),
+        vec![
+            (3, Some(Duration::from_millis(400))),
+            (2, Some(Duration::from_millis(900)))
+        ]
+    );
+    assert!(pool.lock().is_empty());
+
+    // Clears take away from the batch arriving first
+    assert_eq!(pool.cancel(4), 0);
+    assert_eq!(pool.forecast(), vec![(1, Some(Duration::from_millis(900)))]);
+    assert_eq!(pool.cancel(3), 2);
+    assert!(pool.forecast().is_empty());
+}
+
+#[test]
+fn garbage_on_lock_test() {
+    let mut pool = AttackPool::new(true);
+    pool.add(3, 1);
+    pool.add(2, 4);
+
+    // However long it waits, garbage only comes out as a piece locks
+    pool.update(Duration::from_secs(10));
+    assert!(pool.pop_ready().is_none());
+    assert_eq!(pool.forecast(), vec![(3, None), (2, None)]);
+
+    assert_eq!(pool.cancel(1), 0);
+    assert_eq!(pool.lock(), vec![(2, 1), (2, 4)]);
+    assert_eq!(pool.pending(), 0);
+}
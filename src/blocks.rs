@@ -4,16 +4,49 @@ use ggez::{
     Context, GameResult,
 };
 
+use crate::profiler;
+
+/// Tiles in a skin
 pub const BLOCKS_NUM: usize = 10;
+pub const GARBAGE_BLOCK: usize = 8;
+/// Rows with steel never clear
+pub const STEEL_BLOCK: usize = 9;
+/// Garbage blasting the cells around it when its row clears, drawn as tinted garbage
+pub const BOMB_BLOCK: usize = 10;
+/// Kinds of cells a stack can hold, the ones past the skin share its tiles
+pub const CELLS_NUM: usize = 11;
+
+/// Tile of the skin a cell is drawn with
+pub fn tile(cell: usize) -> usize {
+    match cell {
+        BOMB_BLOCK => GARBAGE_BLOCK,
+        cell => cell,
+    }
+}
+
+pub fn is_garbage(cell: usize) -> bool {
+    cell == GARBAGE_BLOCK || cell == BOMB_BLOCK
+}
 
+fn tint(cell: usize) -> Color {
+    match cell {
+        BOMB_BLOCK => Color::new(1.0, 0.45, 0.35, 1.0),
+        _ => graphics::WHITE,
+    }
+}
+
+/// Sprite batch of a tileset, kept between frames so that an unchanged board
+/// doesn't have to be laid out again
 pub struct Blocks {
     batch: SpriteBatch,
+    key: Option<u64>,
     rects: Vec<Rect>,
+    colors: Vec<Color>,
     tileset_size: i32,
 }
 
 impl Blocks {
-    pub fn new(tileset: Image) -> Blocks {
+    pub fn new(ctx: &mut Context, tileset: Image) -> Blocks {
         let tileset_size = (tileset.width() as usize / BLOCKS_NUM) as i32;
 
         if tileset.height() != tileset_size as u16 {
@@ -25,6 +58,7 @@ impl Blocks {
             std::process::exit(1);
         }
 
+        let colors = Blocks::sample_colors(ctx, &tileset, tileset_size as usize);
         let batch = SpriteBatch::new(tileset);
 
         let mut rects: Vec<Rect> = Vec::with_capacity(BLOCKS_NUM);
@@ -40,24 +74,96 @@ impl Blocks {
 
         Blocks {
             batch,
+            key: None,
             rects,
+            colors,
             tileset_size,
         }
     }
 
+    /// Average color of the inner part of every tile
+    fn sample_colors(ctx: &mut Context, tileset: &Image, tileset_size: usize) -> Vec<Color> {
+        let rgba = match tileset.to_rgba8(ctx) {
+            Ok(rgba) => rgba,
+            Err(e) => {
+                log::error!("Unable to read tileset pixels: {:?}", e);
+                return vec![graphics::WHITE; BLOCKS_NUM];
+            }
+        };
+
+        let width = tileset.width() as usize;
+        let margin = tileset_size / 4;
+
+        (0..BLOCKS_NUM)
+            .map(|i| {
+                let mut sum = [0u32; 3];
+                let mut count = 0;
+
+                for y in margin..tileset_size - margin {
+                    for x in i * tileset_size + margin..(i + 1) * tileset_size - margin {
+                        let pixel = (y * width + x) * 4;
+                        for (c, s) in sum.iter_mut().enumerate() {
+                            *s += u32::from(rgba[pixel + c]);
+                        }
+                        count += 1;
+                    }
+                }
+
+                let count = count.max(1) as f32 * 255.0;
+                Color::new(
+                    sum[0] as f32 / count,
+                    sum[1] as f32 / count,
+                    sum[2] as f32 / count,
+                    1.0,
+                )
+            })
+            .collect()
+    }
+
+    pub fn color(&self, block_id: usize) -> Color {
+        let color = self
+            .colors
+            .get(tile(block_id))
+            .cloned()
+            .unwrap_or(graphics::WHITE);
+
+        let tint = tint(block_id);
+        Color::new(
+            color.r * tint.r,
+            color.g * tint.g,
+            color.b * tint.b,
+            color.a,
+        )
+    }
+
     pub fn clear(&mut self) {
         self.batch.clear();
+        self.key = None;
+    }
+
+    /// Keeps the batch if it was filled for the same key, otherwise clears it
+    /// and returns false so that the caller fills it again.
+    /// Without a key the batch is never reused.
+    pub fn reuse(&mut self, key: Option<u64>) -> bool {
+        if key.is_some() && key == self.key {
+            return true;
+        }
+
+        self.clear();
+        self.key = key;
+        false
     }
 
     pub fn add(&mut self, block_id: usize, size: i32, dest: Point2<f32>, alpha: f32) {
         let scale = size as f32 / self.tileset_size as f32;
         let scale = Vector2::new(scale, scale);
 
-        let color = Color::new(1.0, 1.0, 1.0, alpha);
+        let mut color = tint(block_id);
+        color.a = alpha;
 
         self.batch.add(
             DrawParam::new()
-                .src(self.rects[block_id])
+                .src(self.rects[tile(block_id)])
                 .dest(dest)
                 .scale(scale)
                 .color(color),
@@ -69,16 +175,17 @@ impl Blocks {
         let scale = Vector2::new(scale, scale);
 
         match block_id {
-            1..=BLOCKS_NUM => {
+            0 => (),
+            id if id < CELLS_NUM => {
                 self.batch
-                    .add(params.src(self.rects[block_id - 1]).scale(scale));
+                    .add(params.src(self.rects[tile(block_id)]).scale(scale));
             }
-            0 => (),
             _ => log::error!("Attempt to draw a non-existing block: {}", block_id),
         }
     }
 
     pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        profiler::count_draw();
         graphics::draw(ctx, &self.batch, DrawParam::new())?;
 
         Ok(())
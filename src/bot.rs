@@ -0,0 +1,115 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{
+    action::Action,
+    engine::Engine,
+    stack::{Grid, Surface},
+};
+
+/// Computer opponent that places every piece where the resulting stack looks best
+pub struct Bot {
+    action_delay: Duration,
+    elapsed: Duration,
+    planned: u32,
+    plan: VecDeque<Action>,
+}
+
+impl Bot {
+    /// Bots with shorter action delays play faster
+    pub fn new(action_delay: Duration) -> Bot {
+        Bot {
+            action_delay,
+            elapsed: Duration::new(0, 0),
+            planned: 0,
+            plan: VecDeque::new(),
+        }
+    }
+
+    pub fn update(&mut self, engine: &Engine, dt: Duration) -> Option<Action> {
+        if engine.blocked() || engine.entering() {
+            return None;
+        }
+
+        if self.planned != engine.pieces() {
+            self.planned = engine.pieces();
+            self.plan = plan(engine).into();
+            self.elapsed = Duration::new(0, 0);
+        }
+
+        self.elapsed += dt;
+        if self.elapsed < self.action_delay {
+            return None;
+        }
+
+        self.elapsed -= self.action_delay;
+        self.plan.pop_front()
+    }
+}
+
+/// Finds the best rotation and column for the current piece
+fn plan(engine: &Engine) -> Vec<Action> {
+    let stack = &engine.stack;
+    let mut best: Option<(f32, Vec<Action>)> = None;
+
+    for rotation in 0..4 {
+        for shift in -5i32..=5 {
+            let mut piece = engine.piece.clone();
+            let mut actions = vec![];
+
+            match rotation {
+                3 => {
+                    piece.rotate(false, stack);
+                    actions.push(Action::RotateCounterClockwise);
+                }
+                _ => {
+                    for _ in 0..rotation {
+                        piece.rotate(true, stack);
+                        actions.push(Action::RotateClockwise);
+                    }
+                }
+            }
+
+            let (x, action) = if shift < 0 {
+                (-1, Action::MoveLeft)
+            } else {
+                (1, Action::MoveRight)
+            };
+
+            for _ in 0..shift.abs() {
+                piece.shift(x, 0, stack);
+                actions.push(action);
+            }
+
+            piece.fall(stack);
+            actions.push(Action::HardDrop);
+
+            let mut grid = stack.grid().clone();
+            for &(x, y) in piece.cells().iter() {
+                if y >= 0 {
+                    grid[y as usize][x as usize] = 1;
+                }
+            }
+
+            let score = evaluate(&mut grid);
+            if best.as_ref().map_or(true, |(s, _)| score > *s) {
+                best = Some((score, actions));
+            }
+        }
+    }
+
+    best.map(|(_, actions)| actions).unwrap_or_default()
+}
+
+/// Weights from the well known "near perfect" genetic algorithm player
+fn evaluate(grid: &mut Grid) -> f32 {
+    let before = grid.len();
+    grid.retain(|row| row.iter().any(|&b| b == 0));
+    let lines = before - grid.len();
+
+    let surface = Surface::new(grid);
+    let aggregate = surface.aggregate_height();
+    let holes = surface.total_holes();
+    let bumpiness = surface.bumpiness;
+
+    -0.51 * aggregate as f32 + 0.76 * lines as f32 - 0.36 * holes as f32 - 0.18 * bumpiness as f32
+}
@@ -0,0 +1,76 @@
+use ggez::{
+    graphics::{self, DrawParam},
+    nalgebra::{Point2, Vector2},
+    Context, GameResult,
+};
+
+use crate::utils;
+
+/// How much the camera zooms in when the stack is about to top out
+const DANGER_ZOOM: f32 = 0.04;
+
+/// How much the camera punches in on a tetris
+const PUNCH_ZOOM: f32 = 0.06;
+
+pub struct Camera {
+    zoom: f32,
+    punch: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera {
+            zoom: 1.0,
+            punch: 0.0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn punch(&mut self, strength: f32) {
+        self.punch = self.punch.max(strength);
+    }
+
+    /// Danger goes from 0 when the board is safe to 1 when it is almost full
+    pub fn update(&mut self, ctx: &mut Context, enabled: bool, danger: f32) {
+        let dt = utils::dt_f32(ctx);
+
+        let target = if enabled {
+            1.0 + danger * DANGER_ZOOM
+        } else {
+            self.punch = 0.0;
+            1.0
+        };
+
+        self.zoom += (target - self.zoom) * (dt * 3.0).min(1.0);
+        self.punch = (self.punch - dt * 4.0).max(0.0);
+    }
+
+    fn scale(&self) -> f32 {
+        // Ease out so the punch snaps in and slowly settles back
+        self.zoom + PUNCH_ZOOM * self.punch * self.punch
+    }
+
+    /// Applies the camera transform to everything drawn until `pop`
+    pub fn push(&self, ctx: &mut Context) -> GameResult {
+        let scale = self.scale();
+        let screen = graphics::screen_coordinates(ctx);
+        let center = Point2::new(screen.w / 2.0, screen.h / 2.0);
+
+        let param = DrawParam::new()
+            .dest(Point2::new(
+                center.x * (1.0 - scale),
+                center.y * (1.0 - scale),
+            ))
+            .scale(Vector2::new(scale, scale));
+
+        graphics::push_transform(ctx, None);
+        graphics::mul_transform(ctx, param.to_matrix());
+        graphics::apply_transformations(ctx)
+    }
+
+    pub fn pop(&self, ctx: &mut Context) -> GameResult {
+        graphics::pop_transform(ctx);
+        graphics::apply_transformations(ctx)
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{
+    blocks::{GARBAGE_BLOCK, STEEL_BLOCK},
+    stack::Grid,
+};
+
+/// Decides what clears after a piece locks, the stack takes care of the rest
+pub trait ClearDetector: Send {
+    /// Rows clearing as a whole, looked for above the given row
+    fn full_rows(&self, grid: &Grid, rows: usize, full: &mut Vec<i32>);
+
+    /// Groups of blocks clearing on their own, their cells go into the buffer.
+    /// Returns the number of groups.
+    fn groups(&self, grid: &Grid, cells: &mut Vec<(usize, usize)>) -> i32;
+}
+
+/// Rows filled from wall to wall, except the ones with steel
+pub struct LineClear;
+
+impl ClearDetector for LineClear {
+    fn full_rows(&self, grid: &Grid, rows: usize, full: &mut Vec<i32>) {
+        full.clear();
+
+        for (y, row) in grid.iter().enumerate().take(rows) {
+            if row.iter().all(|&b| b != 0 && b != STEEL_BLOCK) {
+                full.push(y as i32);
+            }
+        }
+    }
+
+    fn groups(&self, _grid: &Grid, cells: &mut Vec<(usize, usize)>) -> i32 {
+        cells.clear();
+        0
+    }
+}
+
+/// Connected blocks of the same color, at least this many of them.
+/// Every piece is a single color, so it takes more than a piece's worth.
+pub struct ColorMatch {
+    pub min: usize,
+}
+
+impl ClearDetector for ColorMatch {
+    fn full_rows(&self, _grid: &Grid, _rows: usize, full: &mut Vec<i32>) {
+        full.clear();
+    }
+
+    fn groups(&self, grid: &Grid, cells: &mut Vec<(usize, usize)>) -> i32 {
+        cells.clear();
+
+        let height = grid.len();
+        let width = grid.first().map_or(0, |row| row.len());
+        let mut visited = vec![vec![false; width]; height];
+        let mut groups = 0;
+        let mut group = vec![];
+
+        for y in 0..height {
+            for x in 0..width {
+                // Garbage and the special blocks have no color to match
+                let color = grid[y][x];
+                if color == 0 || color >= GARBAGE_BLOCK || visited[y][x] {
+                    continue;
+                }
+
+                group.clear();
+                group.push((x, y));
+                visited[y][x] = true;
+
+                let mut i = 0;
+                while i < group.len() {
+                    let (cx, cy) = group[i];
+                    i += 1;
+
+                    let neighbours = [
+                        (cx.wrapping_sub(1), cy),
+                        (cx + 1, cy),
+                        (cx, cy.wrapping_sub(1)),
+                        (cx, cy + 1),
+                    ];
+                    for &(nx, ny) in &neighbours {
+                        if nx < width && ny < height && grid[ny][nx] == color && !visited[ny][nx] {
+                            visited[ny][nx] = true;
+                            group.push((nx, ny));
+                        }
+                    }
+                }
+
+                if group.len() >= self.min {
+                    cells.extend_from_slice(&group);
+                    groups += 1;
+                }
+            }
+        }
+
+        groups
+    }
+}
+
+#[test]
+fn color_match_test() {
+    let mut grid = vec![vec![0; 4]; 4];
+    for x in 0..3 {
+        grid[3][x] = 6;
+        grid[2][x] = 6;
+    }
+    grid[3][3] = 2;
+    grid[1][0] = 2;
+
+    let mut cells = vec![];
+    assert_eq!(ColorMatch { min: 5 }.groups(&grid, &mut cells), 1);
+    assert_eq!(cells.len(), 6);
+    assert!(!cells.contains(&(3, 3)));
+
+    assert_eq!(ColorMatch { min: 8 }.groups(&grid, &mut cells), 0);
+    assert!(cells.is_empty());
+
+    let mut full = vec![];
+    LineClear.full_rows(&grid, 4, &mut full);
+    assert_eq!(full, vec![3]);
+    LineClear.full_rows(&grid, 3, &mut full);
+    assert!(full.is_empty());
+}
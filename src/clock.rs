@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+/// Length of a single simulation tick
+pub const TICK: Duration = Duration::from_millis(1);
+
+/// Time source of the simulation, advancing in whole ticks.
+/// Frame times are rounded down to ticks with the rest carried over to the next frame,
+/// so that recorded durations are always whole ticks and replay the same way on any machine.
+#[derive(Default)]
+pub struct Clock {
+    ticks: u64,
+    delta: u32,
+    remainder: Duration,
+}
+
+impl Clock {
+    /// Advances by the real time elapsed since the last frame
+    pub fn update(&mut self, dt: Duration) {
+        self.remainder += dt;
+
+        let ticks = (self.remainder.as_nanos() / TICK.as_nanos()) as u32;
+        self.remainder -= TICK * ticks;
+        self.advance(ticks);
+    }
+
+    /// Advances by exactly this many ticks, for simulations and tests without a window
+    pub fn advance(&mut self, ticks: u32) {
+        self.delta = ticks;
+        self.ticks += u64::from(ticks);
+    }
+
+    /// Ticks since the clock was created
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Time the simulation moved by in the current frame
+    pub fn delta(&self) -> Duration {
+        TICK * self.delta
+    }
+
+    pub fn delta_f32(&self) -> f32 {
+        self.delta().as_secs_f32()
+    }
+}
+
+#[test]
+fn clock_test() {
+    let mut clock = Clock::default();
+    clock.update(Duration::from_micros(16_700));
+    assert_eq!(clock.delta(), Duration::from_millis(16));
+
+    clock.update(Duration::from_micros(16_700));
+    assert_eq!(clock.delta(), Duration::from_millis(17));
+    assert_eq!(clock.ticks(), 33);
+
+    clock.update(Duration::from_micros(600));
+    assert_eq!(clock.delta(), Duration::from_millis(1));
+
+    clock.advance(0);
+    assert_eq!(clock.delta(), Duration::new(0, 0));
+    assert_eq!(clock.ticks(), 34);
+}
@@ -0,0 +1,173 @@
+use std::time::Duration;
+
+use crate::{
+    action::Action,
+    engine::{Engine, Event},
+    hints,
+    piece::Piece,
+    replay::ReplayData,
+    settings,
+    shape::ShapeType,
+    stack::{Grid, Stack, Surface},
+};
+
+/// Placement worth another look after the game
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MistakeKind {
+    /// Covered this many empty cells without clearing anything
+    Holes(i32),
+    /// T piece placed without a T-Spin while a slot was ready
+    BurnedT,
+    /// I piece placed elsewhere while a well was ready for a Tetris
+    MissedTetris,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mistake {
+    /// When the piece locked
+    pub time: Duration,
+    pub kind: MistakeKind,
+}
+
+impl Mistake {
+    pub fn description(&self) -> String {
+        match self.kind {
+            MistakeKind::Holes(1) => String::from("Created a hole"),
+            MistakeKind::Holes(holes) => format!("Created {} holes", holes),
+            MistakeKind::BurnedT => String::from("Burned a T piece"),
+            MistakeKind::MissedTetris => String::from("Missed a Tetris"),
+        }
+    }
+}
+
+/// Plays back the replay instantly and judges every placement on the board it was made on
+pub fn review(replay_data: &ReplayData) -> Vec<Mistake> {
+    let settings = settings::Gameplay::default();
+    let mut engine = Engine::new(&replay_data.seed, false, &replay_data.rules);
+    let mut events = vec![];
+    let mut mistakes = vec![];
+    let mut time = Duration::new(0, 0);
+
+    for timed in &replay_data.actions {
+        time += timed.duration;
+
+        if timed.action != Action::LockPiece {
+            engine.skip(&settings, timed.action);
+            continue;
+        }
+
+        let piece = engine.piece.clone();
+        let holes = holes_covered(&piece, engine.stack.grid());
+        let t_slot = piece.shape() == ShapeType::T
+            && hints::t_slot(&Piece::new(ShapeType::T, &engine.stack), &engine.stack);
+        let tetris = piece.shape() == ShapeType::I && tetris_ready(&engine.stack);
+
+        engine.events(&mut events);
+        engine.skip(&settings, timed.action);
+        engine.events(&mut events);
+
+        let locked = events.iter().find_map(|e| match *e {
+            Event::Locked { rows, t_spin, .. } => Some((rows, t_spin)),
+            _ => None,
+        });
+
+        let kind = match locked {
+            Some((0, _)) if holes > 0 => Some(MistakeKind::Holes(holes)),
+            Some((_, false)) if t_slot => Some(MistakeKind::BurnedT),
+            Some((rows, _)) if tetris && rows < 4 => Some(MistakeKind::MissedTetris),
+            Some(_) | None => None,
+        };
+
+        if let Some(kind) = kind {
+            mistakes.push(Mistake { time, kind });
+        }
+    }
+
+    mistakes
+}
+
+/// Holes the piece adds to the grid by locking where it is
+fn holes_covered(piece: &Piece, grid: &Grid) -> i32 {
+    let before = Surface::new(grid).total_holes();
+
+    let mut placed = grid.clone();
+    for &(x, y) in piece.cells().iter() {
+        if let Some(cell) = placed
+            .get_mut(y as usize)
+            .and_then(|row| row.get_mut(x as usize))
+        {
+            *cell = 1;
+        }
+    }
+
+    Surface::new(&placed).total_holes() - before
+}
+
+/// Whether an upright I piece dropped into some column clears four rows
+fn tetris_ready(stack: &Stack) -> bool {
+    let mut upright = Piece::new(ShapeType::I, stack);
+    if !upright.rotate(true, stack) {
+        return false;
+    }
+
+    for shift in -stack.width..=stack.width {
+        let mut dropped = upright.clone();
+        for _ in 0..shift.abs() {
+            dropped.shift(shift.signum(), 0, stack);
+        }
+        dropped.fall(stack);
+
+        if full_rows(&dropped, stack.grid()) == 4 {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn full_rows(piece: &Piece, grid: &Grid) -> usize {
+    let cells = piece.cells();
+    let mut rows: Vec<i32> = cells.iter().map(|&(_, y)| y).collect();
+    rows.dedup();
+
+    rows.iter()
+        .filter(|&&y| {
+            y >= 0
+                && grid[y as usize]
+                    .iter()
+                    .enumerate()
+                    .all(|(x, &b)| b != 0 || cells.contains(&(x as i32, y)))
+        })
+        .count()
+}
+
+#[test]
+fn coach_test() {
+    let mut stack = Stack::new(10, 20, 20);
+    assert!(!tetris_ready(&stack));
+
+    // Four rows with a well on the right
+    for y in 36..40 {
+        for x in 0..9 {
+            stack.place_random(x, y);
+        }
+    }
+    assert!(tetris_ready(&stack));
+
+    // An O resting on the stack covers nothing, one hanging over the well does
+    let mut o = Piece::new(ShapeType::O, &stack);
+    o.fall(&stack);
+    assert_eq!(holes_covered(&o, stack.grid()), 0);
+
+    stack.place_random(9, 39);
+    let mut overhang = Piece::new(ShapeType::O, &stack);
+    while overhang.shift(1, 0, &stack) {}
+    overhang.fall(&stack);
+    assert_eq!(holes_covered(&overhang, stack.grid()), 3);
+
+    let mistake = Mistake {
+        time: Duration::from_secs(3),
+        kind: MistakeKind::Holes(2),
+    };
+    assert_eq!(mistake.description(), "Created 2 holes");
+}
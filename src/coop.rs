@@ -0,0 +1,358 @@
+use std::time::Duration;
+
+use ggez::{
+    graphics::{self, Color, Font, Scale},
+    input::keyboard::KeyCode,
+    nalgebra::{Point2, Vector2},
+    Context, GameResult,
+};
+
+use crate::{
+    action::Action,
+    bag::Bag,
+    blocks::Blocks,
+    global::Global,
+    input::Input,
+    piece::Piece,
+    score::Score,
+    stack::{Locked, Stack},
+};
+
+const FALL_INTERVAL: Duration = Duration::from_secs(1);
+
+struct Player {
+    input: Input,
+    piece: Option<Piece>,
+    column: i32,
+    falling: Duration,
+    entering: Duration,
+}
+
+impl Player {
+    fn new(input: Input, column: i32) -> Player {
+        Player {
+            input,
+            piece: None,
+            column,
+            falling: Duration::new(0, 0),
+            entering: Duration::new(0, 0),
+        }
+    }
+}
+
+/// Two players on a double width stack, each controlling their own piece.
+/// Active pieces can't pass through each other and the score is shared.
+pub struct Coop {
+    pub stack: Stack,
+    pub bag: Bag,
+    pub score: Score,
+    players: [Player; 2],
+    game_over: bool,
+    font: Font,
+    blocks: Blocks,
+}
+
+fn overlaps(a: &Piece, b: &Piece) -> bool {
+    let cells = b.cells();
+    a.cells().iter().any(|c| cells.contains(c))
+}
+
+impl Coop {
+    pub fn new(ctx: &mut Context, g: &mut Global, seed: &[u8; 32]) -> GameResult<Coop> {
+        let mut left = Input::new();
+        left.bind(KeyCode::D, Action::MoveRight, true)
+            .bind(KeyCode::A, Action::MoveLeft, true)
+            .bind(KeyCode::S, Action::MoveDown, true)
+            .bind(KeyCode::W, Action::RotateClockwise, false)
+            .bind(KeyCode::Q, Action::RotateCounterClockwise, false)
+            .bind(KeyCode::E, Action::Rotate180, false)
+            .bind(KeyCode::LShift, Action::HardDrop, false)
+            .exclude(KeyCode::D, KeyCode::A)
+            .exclude(KeyCode::A, KeyCode::D);
+
+        let mut right = Input::new();
+        right
+            .bind(KeyCode::Right, Action::MoveRight, true)
+            .bind(KeyCode::Left, Action::MoveLeft, true)
+            .bind(KeyCode::Down, Action::MoveDown, true)
+            .bind(KeyCode::Up, Action::RotateClockwise, false)
+            .bind(KeyCode::RControl, Action::RotateCounterClockwise, false)
+            .bind(KeyCode::RShift, Action::Rotate180, false)
+            .bind(KeyCode::Return, Action::HardDrop, false)
+            .exclude(KeyCode::Right, KeyCode::Left)
+            .exclude(KeyCode::Left, KeyCode::Right);
+
+        let stack = Stack::new(20, 20, 20);
+        let quarter = stack.width / 4;
+
+        let tileset = g.settings.tileset(ctx, &g.settings_state, &mut g.assets)?;
+
+        Ok(Coop {
+            players: [Player::new(left, quarter), Player::new(right, quarter * 3)],
+            stack,
+            bag: Bag::new(seed),
+            score: Score::default(),
+            game_over: false,
+            font: g.assets.font(ctx, "fonts/bold.ttf")?,
+            blocks: Blocks::new(ctx, tileset),
+        })
+    }
+
+    pub fn game_over(&self) -> bool {
+        self.game_over
+    }
+
+    /// The active piece of the other player, if any
+    fn other(&self, player: usize) -> Option<&Piece> {
+        self.players[1 - player].piece.as_ref()
+    }
+
+    fn blocked_by_other(&self, player: usize, piece: &Piece) -> bool {
+        self.other(player)
+            .map_or(false, |other| overlaps(piece, other))
+    }
+
+    fn spawn(&mut self, player: usize) {
+        let mut piece = Piece::new(self.bag.pop(), &self.stack);
+        piece.x = self.players[player].column - piece.grid().width / 2;
+
+        if self.stack.collision(&piece) || self.blocked_by_other(player, &piece) {
+            self.game_over = true;
+            self.stack.game_over();
+        }
+
+        let p = &mut self.players[player];
+        p.piece = Some(piece);
+        p.falling = Duration::new(0, 0);
+    }
+
+    /// Applies the action to a copy of the piece and keeps it only if it doesn't
+    /// run into the other player's piece
+    fn process(&mut self, g: &mut Global, player: usize, action: Action) {
+        let mut piece = match self.players[player].piece.clone() {
+            Some(piece) => piece,
+            None => return,
+        };
+
+        let moved = match action {
+            Action::MoveLeft => piece.shift(-1, 0, &self.stack),
+            Action::MoveRight => piece.shift(1, 0, &self.stack),
+            Action::ShiftLeft | Action::ShiftRight | Action::SoftDrop => {
+                let (x, y) = match action {
+                    Action::ShiftLeft => (-1, 0),
+                    Action::ShiftRight => (1, 0),
+                    _ => (0, 1),
+                };
+
+                let mut moved = false;
+                let mut next = piece.clone();
+                while next.shift(x, y, &self.stack) && !self.blocked_by_other(player, &next) {
+                    piece = next.clone();
+                    moved = true;
+                }
+                moved
+            }
+            Action::MoveDown | Action::FallPiece => piece.shift(0, 1, &self.stack),
+            Action::RotateClockwise => piece.rotate(true, &self.stack),
+            Action::RotateCounterClockwise => piece.rotate(false, &self.stack),
+            Action::Rotate180 => piece.rotate_180(&self.stack),
+            Action::HardDrop => {
+                let mut rows = 0;
+                let mut next = piece.clone();
+                while next.shift(0, 1, &self.stack) && !self.blocked_by_other(player, &next) {
+                    piece = next.clone();
+                    rows += 1;
+                }
+
+                self.score.hard_drop(rows);
+                self.players[player].piece = Some(piece);
+                self.lock(g, player);
+                return;
+            }
+            _ => false,
+        };
+
+        if !moved || self.blocked_by_other(player, &piece) {
+            return;
+        }
+
+        if action == Action::MoveDown || action == Action::SoftDrop {
+            self.players[player].falling = Duration::new(0, 0);
+        }
+
+        if action != Action::FallPiece {
+            g.sfx.play(match action {
+                Action::RotateClockwise | Action::RotateCounterClockwise => "rotate",
+                Action::Rotate180 => "rotate180",
+                _ => "move",
+            });
+        }
+
+        self.players[player].piece = Some(piece);
+    }
+
+    fn lock(&mut self, g: &mut Global, player: usize) {
+        let piece = match self.players[player].piece.take() {
+            Some(piece) => piece,
+            None => return,
+        };
+
+        let t_spin = piece.t_spin(&self.stack);
+        let clear_delays = g.settings.gameplay.clear_delays();
+
+        match self.stack.lock(&piece, clear_delays) {
+            Locked::Collision => {
+                self.game_over = true;
+                self.stack.game_over();
+                g.sfx.play("gameover");
+                return;
+            }
+            Locked::Success(0) => {
+                self.score.reset_combo();
+                g.sfx.play("lock");
+            }
+            Locked::Success(rows) => {
+                self.score.lock(rows, t_spin, 1);
+                g.sfx.play(match rows {
+                    1 => "erase1",
+                    2 => "erase2",
+                    3 => "erase3",
+                    _ => "erase4",
+                });
+            }
+        }
+
+        self.players[player].entering = Duration::new(0, 0);
+
+        // Rows collapsing under the other piece may push blocks into it,
+        // the game is over when there's no room left above
+        if let Some(other) = self.players[1 - player].piece.as_mut() {
+            while self.stack.collision(other) && other.y > 0 {
+                other.y -= 1;
+            }
+
+            if self.stack.collision(other) {
+                self.game_over = true;
+                self.stack.game_over();
+                g.sfx.play("gameover");
+            }
+        }
+    }
+
+    pub fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        self.stack.update(ctx, g, &mut self.blocks)?;
+
+        let dt = g.clock.delta();
+        let paused = self.game_over || self.stack.blocked() || g.imgui_state.paused;
+
+        for player in 0..2 {
+            let p = &mut self.players[player];
+            p.input.update(
+                ctx,
+                &g.gamepad,
+                g.settings.input.das,
+                g.settings.input.arr,
+                paused || g.imgui_state.capture_keyboard || p.piece.is_none(),
+            );
+        }
+
+        if paused {
+            return Ok(());
+        }
+
+        let entry_delay = Duration::from_millis(g.settings.gameplay.entry_delay.into());
+        let lock_delay = Duration::from_millis(g.settings.gameplay.lock_delay.into());
+
+        for player in 0..2 {
+            if self.players[player].piece.is_none() {
+                self.players[player].entering += dt;
+                if self.players[player].entering >= entry_delay {
+                    self.spawn(player);
+                }
+                continue;
+            }
+
+            let actions: Vec<Action> = self.players[player].input.actions().collect();
+            for action in actions {
+                self.process(g, player, action);
+            }
+
+            let p = &mut self.players[player];
+            let piece = match p.piece.as_mut() {
+                Some(piece) => piece,
+                None => continue,
+            };
+
+            piece.update(dt, &self.stack);
+
+            // Resting on the other piece doesn't count, only the stack locks pieces
+            if piece.locking() > lock_delay {
+                self.lock(g, player);
+                continue;
+            }
+
+            p.falling += dt;
+            if p.falling >= FALL_INTERVAL {
+                p.falling -= FALL_INTERVAL;
+                self.process(g, player, Action::FallPiece);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn draw(&mut self, ctx: &mut Context, g: &Global, position: Point2<f32>) -> GameResult {
+        let block_size = g.block_size(self.stack.width, self.stack.height);
+        let next_block_size = block_size / 2;
+        let ui_color = Color::new(0.8, 0.9, 1.0, 0.8);
+        let ui_scale = Scale::uniform(block_size as f32);
+
+        let bag_position = position + Vector2::new((self.stack.width * block_size) as f32, 0.0);
+        self.bag
+            .draw(ctx, bag_position, next_block_size, ui_color, self.font)?;
+
+        self.score.draw(
+            ctx,
+            position
+                + Vector2::new(
+                    (block_size * self.stack.width) as f32 + next_block_size as f32,
+                    (block_size * self.stack.height) as f32 - ui_scale.y * 3.0,
+                ),
+            ui_color,
+            self.font,
+            ui_scale,
+        )?;
+
+        // https://github.com/ggez/ggez/issues/664
+        graphics::pop_transform(ctx);
+        graphics::apply_transformations(ctx)?;
+
+        let cached = self.stack.draw(ctx, position, block_size)?;
+
+        self.blocks.clear();
+        if !cached {
+            self.stack
+                .add_blocks(position, &mut self.blocks, block_size);
+        }
+        self.bag
+            .add_blocks(bag_position, &mut self.blocks, next_block_size);
+
+        if !self.game_over {
+            for player in &self.players {
+                if let Some(piece) = &player.piece {
+                    piece.add(
+                        position,
+                        self.stack.vanish,
+                        &mut self.blocks,
+                        block_size,
+                        1.0,
+                    );
+                }
+            }
+        }
+
+        self.blocks.draw(ctx)?;
+        self.stack
+            .draw_sparks(ctx, position, &self.blocks, block_size)
+    }
+}
@@ -0,0 +1,111 @@
+use std::{fs, path::PathBuf, process::Command, sync::Mutex, time::Duration};
+
+use chrono::Utc;
+use dirs;
+use lazy_static::lazy_static;
+use toml;
+
+use crate::{action::Action, replay::ReplayData, rules::Rules, settings::Settings};
+
+lazy_static! {
+    static ref STATE: Mutex<CrashState> = Mutex::new(CrashState::default());
+}
+
+/// Everything needed to reproduce the game that was running during a crash
+#[derive(Default)]
+struct CrashState {
+    replay: Option<ReplayData>,
+    settings: String,
+}
+
+pub fn directory() -> PathBuf {
+    let mut path = dirs::data_local_dir().unwrap_or_default();
+    path.push("klocki");
+    path.push("crashes");
+    path
+}
+
+fn pending_path() -> PathBuf {
+    let mut path = directory();
+    path.push("pending");
+    path
+}
+
+pub fn start_game(seed: &[u8; 32], rules: &Rules, settings: &Settings) {
+    if let Ok(mut state) = STATE.lock() {
+        state.replay = Some(ReplayData::new(seed, rules));
+        state.settings = toml::to_string(settings).unwrap_or_default();
+    }
+}
+
+pub fn record_action(action: Action, duration: Duration) {
+    if let Ok(mut state) = STATE.lock() {
+        if let Some(replay) = &mut state.replay {
+            replay.add(action, duration);
+        }
+    }
+}
+
+/// Called from the panic hook, so it must never panic itself
+pub fn dump(report: &str) {
+    let directory = directory();
+    if let Err(e) = fs::create_dir_all(&directory) {
+        log::error!("Unable to create directory {:?}: {:?}", &directory, e);
+        return;
+    }
+
+    let name = format!("crash-{}", Utc::now().format("%Y%m%d_%H%M%S"));
+    let mut report_path = directory.clone();
+    report_path.push(format!("{}.txt", name));
+
+    let mut contents = format!("Klocki v{}\n\n{}\n", env!("CARGO_PKG_VERSION"), report);
+
+    // The panic could have happened while the state was locked
+    if let Ok(state) = STATE.try_lock() {
+        if let Some(replay) = &state.replay {
+            let mut replay_path = directory.clone();
+            replay_path.push(format!("{}.klocki", name));
+            if let Err(e) = replay.try_save(&replay_path) {
+                log::error!("Unable to save the replay of the crash: {}", e);
+            }
+
+            contents.push_str(&format!("\nSeed: {:02x?}\n", replay.seed));
+            contents.push_str(&format!("Actions: {}\n", replay.actions.len()));
+        }
+
+        contents.push_str("\n[settings]\n");
+        contents.push_str(&state.settings);
+    }
+
+    match fs::write(&report_path, contents) {
+        Err(e) => log::error!("Unable to write crash report: {:?}", e),
+        Ok(_) => {
+            log::info!("Saved crash report in {:?}", &report_path);
+            fs::write(pending_path(), report_path.to_string_lossy().as_bytes())
+                .unwrap_or_else(|e| log::error!("Unable to mark crash report: {:?}", e));
+        }
+    }
+}
+
+/// Returns the report of a crash that has not been shown to the player yet
+pub fn pending() -> Option<PathBuf> {
+    let path = pending_path();
+    let report = fs::read_to_string(&path).ok()?;
+    fs::remove_file(&path).unwrap_or_else(|e| log::warn!("Unable to remove {:?}: {:?}", path, e));
+
+    Some(PathBuf::from(report))
+}
+
+pub fn open_directory() {
+    let program = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+
+    if let Err(e) = Command::new(program).arg(directory()).spawn() {
+        log::error!("Unable to open crash directory: {:?}", e);
+    }
+}
@@ -0,0 +1,105 @@
+//! Re-simulates the reference replays from `tests/replays` and compares the final board
+//! with `tests/replays/expected.toml`, so that changes breaking old replays get noticed.
+//! A replay without expectations or playing back differently fails the test, run it with
+//! `KLOCKI_BLESS` set to record the expectations again after an intentional rules change.
+
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{engine::Engine, replay::ReplayData, utils::Fnv64, verify};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Expected {
+    score: i32,
+    lines: i32,
+    hash: String,
+    state: String,
+}
+
+fn board_hash(engine: &Engine) -> String {
+    let mut hash = Fnv64::default();
+
+    for row in engine.stack.grid() {
+        for &block in row {
+            hash.write(block as u64);
+        }
+    }
+
+    let piece = engine.piece.state();
+    hash.write(piece.shape as u64);
+    hash.write(piece.x as u64);
+    hash.write(piece.y as u64);
+    hash.write(piece.rotation as u64);
+    hash.write(engine.holder.shape_type().map_or(0, |s| s as u64));
+    hash.write(engine.score.score() as u64);
+    hash.write(engine.score.lines() as u64);
+
+    format!("{:016x}", hash.finish())
+}
+
+#[test]
+fn replay_determinism_test() {
+    let mut directory = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    directory.push("tests");
+    directory.push("replays");
+
+    let mut expected_path = directory.clone();
+    expected_path.push("expected.toml");
+
+    let bless = env::var_os("KLOCKI_BLESS").is_some();
+    let expected: BTreeMap<String, Expected> = match fs::read_to_string(&expected_path) {
+        Ok(s) => toml::from_str(&s).expect("Unable to parse the replay expectations"),
+        Err(_) if bless => BTreeMap::new(),
+        Err(e) => panic!("Unable to read {:?}: {}", expected_path, e),
+    };
+    let mut recorded = BTreeMap::new();
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |e| e == "klocki"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "No reference replays in {:?}", directory);
+
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let replay_data = ReplayData::load(&path).unwrap();
+        let simulation = verify::simulate(&replay_data);
+        assert_eq!(
+            None, simulation.desync,
+            "Replay {} desyncs at a checkpoint",
+            name
+        );
+
+        let engine = simulation.engine;
+
+        let actual = Expected {
+            score: engine.score.score(),
+            lines: engine.score.lines(),
+            hash: board_hash(&engine),
+            state: format!("{:016x}", engine.state_hash()),
+        };
+
+        if !bless {
+            let expected = expected.get(&name).unwrap_or_else(|| {
+                panic!(
+                    "No expectations for replay {}, run with KLOCKI_BLESS to record them",
+                    name
+                )
+            });
+            assert_eq!(
+                expected, &actual,
+                "Replay {} no longer plays back the same",
+                name
+            );
+        }
+
+        recorded.insert(name, actual);
+    }
+
+    if bless {
+        fs::write(&expected_path, toml::to_string(&recorded).unwrap()).unwrap();
+    }
+}
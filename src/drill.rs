@@ -0,0 +1,296 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use dirs;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use toml;
+
+use crate::battle::AttackPool;
+
+/// Garbage sent by the script at some point of its pattern
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScriptedAttack {
+    /// Seconds from the start of the pattern
+    pub at: f32,
+    pub lines: i32,
+    /// Column of the hole, a random one when left out
+    pub hole: Option<usize>,
+}
+
+/// Opponent sending the same garbage at the same times on every try
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Script {
+    pub name: String,
+    /// Seconds before the pattern plays for the first time
+    pub start: f32,
+    /// Seconds between the starts of the pattern, 0 plays it once
+    pub repeat: f32,
+    /// Seed of the holes left random, the same seed gives the same holes
+    pub seed: u64,
+    pub attacks: Vec<ScriptedAttack>,
+}
+
+impl Default for Script {
+    fn default() -> Script {
+        Script {
+            name: String::from("Script"),
+            start: 5.0,
+            repeat: 0.0,
+            seed: 0,
+            attacks: vec![],
+        }
+    }
+}
+
+impl Script {
+    /// Two Tetrises a few seconds apart, the usual burst of a fast opponent
+    pub fn spikes() -> Script {
+        Script {
+            name: String::from("4-0-4 spikes every 20s"),
+            repeat: 20.0,
+            attacks: vec![
+                ScriptedAttack {
+                    at: 0.0,
+                    lines: 4,
+                    hole: None,
+                },
+                ScriptedAttack {
+                    at: 4.0,
+                    lines: 4,
+                    hole: None,
+                },
+            ],
+            ..Script::default()
+        }
+    }
+
+    /// A single line every few seconds, filling the board with cheese
+    pub fn drip() -> Script {
+        Script {
+            name: String::from("Cheese drip every 3s"),
+            repeat: 3.0,
+            attacks: vec![ScriptedAttack {
+                at: 0.0,
+                lines: 1,
+                hole: None,
+            }],
+            ..Script::default()
+        }
+    }
+
+    pub fn directory() -> PathBuf {
+        let mut path = dirs::data_local_dir().unwrap_or_default();
+        path.push("klocki");
+        path.push("drills");
+        path
+    }
+
+    pub fn load(path: &Path) -> Option<Script> {
+        let contents = fs::read_to_string(path).ok()?;
+
+        match toml::from_str::<Script>(&contents) {
+            Ok(mut script) => {
+                script
+                    .attacks
+                    .sort_by(|a, b| a.at.partial_cmp(&b.at).unwrap_or(std::cmp::Ordering::Equal));
+                Some(script)
+            }
+            Err(e) => {
+                log::error!("Error while reading drill {:?}: {:?}", path, e);
+                None
+            }
+        }
+    }
+
+    /// The built-in scripts followed by every script in the drills directory, sorted by name
+    pub fn all() -> Vec<Script> {
+        let mut scripts: Vec<Script> = match fs::read_dir(Script::directory()) {
+            Err(_) => vec![],
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().map_or(false, |e| e == "toml"))
+                .filter_map(|p| Script::load(&p))
+                .collect(),
+        };
+
+        scripts.sort_by(|a, b| a.name.cmp(&b.name));
+        scripts.insert(0, Script::drip());
+        scripts.insert(0, Script::spikes());
+        scripts
+    }
+
+    /// Time of the attack in the given repetition of the pattern
+    fn time(&self, round: u32, attack: usize) -> Duration {
+        let seconds = self.start + self.repeat * round as f32 + self.attacks[attack].at;
+        Duration::from_secs_f32(seconds.max(0.0))
+    }
+}
+
+/// Plays the script, putting its attacks into the player's pool when they're due
+pub struct ScriptedOpponent {
+    script: Script,
+    width: usize,
+    elapsed: Duration,
+    round: u32,
+    next: usize,
+    rng: StdRng,
+}
+
+impl ScriptedOpponent {
+    pub fn new(script: Script, width: usize) -> ScriptedOpponent {
+        ScriptedOpponent {
+            rng: StdRng::seed_from_u64(script.seed),
+            script,
+            width,
+            elapsed: Duration::new(0, 0),
+            round: 0,
+            next: 0,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.script.name
+    }
+
+    /// Round and attack coming after the given one, none once the script is over
+    fn after(&self, round: u32, attack: usize) -> Option<(u32, usize)> {
+        if attack + 1 < self.script.attacks.len() {
+            Some((round, attack + 1))
+        } else if self.script.repeat > 0.0 {
+            Some((round + 1, 0))
+        } else {
+            None
+        }
+    }
+
+    fn pending(&self) -> Option<(u32, usize)> {
+        if self.next < self.script.attacks.len() {
+            Some((self.round, self.next))
+        } else {
+            None
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.pending().is_none()
+    }
+
+    pub fn update(&mut self, dt: Duration, pool: &mut AttackPool) {
+        self.elapsed += dt;
+
+        while let Some((round, attack)) = self.pending() {
+            if self.script.time(round, attack) > self.elapsed {
+                break;
+            }
+
+            let ScriptedAttack { lines, hole, .. } = self.script.attacks[attack];
+            let hole = match hole {
+                Some(hole) => hole.min(self.width - 1),
+                None => self.rng.gen_range(0, self.width),
+            };
+            if lines > 0 {
+                pool.add(lines, hole);
+            }
+
+            match self.after(round, attack) {
+                Some((round, attack)) => {
+                    self.round = round;
+                    self.next = attack;
+                }
+                None => self.next = self.script.attacks.len(),
+            }
+        }
+    }
+
+    /// Lines and time left of the next few attacks
+    pub fn upcoming(&self, count: usize) -> Vec<(i32, Duration)> {
+        let mut upcoming = vec![];
+        let mut current = self.pending();
+
+        while let Some((round, attack)) = current {
+            if upcoming.len() >= count {
+                break;
+            }
+
+            let time = self.script.time(round, attack);
+            upcoming.push((
+                self.script.attacks[attack].lines,
+                time.checked_sub(self.elapsed).unwrap_or_default(),
+            ));
+            current = self.after(round, attack);
+        }
+
+        upcoming
+    }
+}
+
+#[test]
+fn drill_test() {
+    let script: Script = toml::from_str(
+        r#"
+            name = "Test"
+            start = 1.0
+            repeat = 10.0
+            seed = 7
+
+            [[attacks]]
+            at = 0.0
+            lines = 4
+            hole = 3
+
+            [[attacks]]
+            at = 2.0
+            lines = 2
+        "#,
+    )
+    .unwrap();
+
+    let mut opponent = ScriptedOpponent::new(script.clone(), 10);
+    let mut pool = AttackPool::default();
+
+    opponent.update(Duration::from_millis(500), &mut pool);
+    assert_eq!(pool.pending(), 0);
+    assert_eq!(
+        opponent.upcoming(3),
+        vec![
+            (4, Duration::from_millis(500)),
+            (2, Duration::from_millis(2500)),
+            (4, Duration::from_millis(10_500)),
+        ]
+    );
+
+    opponent.update(Duration::from_secs(3), &mut pool);
+    assert_eq!(pool.pending(), 6);
+    opponent.update(Duration::from_secs(10), &mut pool);
+    assert_eq!(pool.pending(), 12);
+    assert!(!opponent.finished());
+
+    // The same script sends the same holes
+    let holes = |script: &Script| {
+        let mut opponent = ScriptedOpponent::new(script.clone(), 10);
+        let mut pool = AttackPool::default();
+        opponent.update(Duration::from_secs(60), &mut pool);
+        pool.update(Duration::from_secs(1));
+        let mut holes = vec![];
+        while let Some((_, hole)) = pool.pop_ready() {
+            holes.push(hole);
+        }
+        holes
+    };
+    assert_eq!(holes(&script), holes(&script));
+    assert_eq!(holes(&script)[0], 3);
+
+    let once = Script {
+        repeat: 0.0,
+        ..script
+    };
+    let mut opponent = ScriptedOpponent::new(once, 10);
+    opponent.update(Duration::from_secs(60), &mut pool);
+    assert!(opponent.finished());
+}
@@ -0,0 +1,1116 @@
+use std::{cell::Cell, collections::VecDeque, mem, sync::Arc, time::Duration};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::Action,
+    bag::Bag,
+    holder::Holder,
+    piece::{Piece, PieceState},
+    rotation::RotationSystem,
+    rules::{Randomizer, Rules},
+    score::Score,
+    settings,
+    shape::ShapeType,
+    stack::{GarbageRow, Grid, Locked, Stack},
+    utils::Fnv64,
+    zone::Zone,
+};
+
+/// When the active piece locks on its own
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum LockMode {
+    /// Lock after the lock delay expires
+    Delay,
+    /// Never lock automatically, only hard drop commits the piece
+    HardDropOnly,
+    /// Soft dropping onto the floor switches to the shorter soft drop lock delay
+    SoftDropFast,
+}
+
+pub const LOCK_MODES: [LockMode; 3] = [
+    LockMode::Delay,
+    LockMode::HardDropOnly,
+    LockMode::SoftDropFast,
+];
+
+impl LockMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            LockMode::Delay => "Lock delay",
+            LockMode::HardDropOnly => "Hard drop only",
+            LockMode::SoftDropFast => "Fast soft drop",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BoardState {
+    pub grid: Grid,
+    pub piece: PieceState,
+    pub hold: Option<ShapeType>,
+    pub queue: Vec<ShapeType>,
+}
+
+/// Something that happened while processing actions, used to drive sounds and effects
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Event {
+    Moved,
+    Rotated,
+    /// Turned around with a single 180 degree rotation
+    RotatedHalf,
+    Held,
+    HoldFailed,
+    Locked {
+        rows: i32,
+        t_spin: bool,
+        attack: i32,
+    },
+    /// Rows cleared by floating blocks falling after the clear of the locked piece
+    Chain {
+        /// Clears in a row, the one of the piece included
+        length: i32,
+        rows: i32,
+        attack: i32,
+    },
+    /// The piece went back to where it was before the hard drop
+    DropUndone,
+    ZoneStarted,
+    ZoneEnded {
+        rows: i32,
+        attack: i32,
+    },
+    /// Gravity got faster
+    LevelUp {
+        level: u32,
+        interval: Duration,
+    },
+    GameOver,
+}
+
+/// Everything deciding how the game goes on, to retry a situation in practice.
+/// Timers and effects are left out, the game continues as if the piece just spawned.
+#[derive(Clone)]
+pub struct Snapshot {
+    grid: Grid,
+    bag: Bag,
+    piece: Piece,
+    holder: Holder,
+    score: Score,
+    zone: Zone,
+    garbage: Vec<GarbageRow>,
+    garbage_rng: StdRng,
+    garbage_hole: Option<usize>,
+    pieces: u32,
+    fall_interval: Duration,
+}
+
+/// Hard drop waiting to lock, which can still be taken back
+struct Undo {
+    /// The piece before the drop
+    piece: Piece,
+    rows: i32,
+    elapsed: Duration,
+    locking: bool,
+}
+
+/// Rules of the game without any graphics, audio or input,
+/// so that it can be simulated without a window
+pub struct Engine {
+    interactive: bool,
+    rules: Rules,
+    /// Rotation system of the rules, shared by every piece
+    system: Arc<dyn RotationSystem>,
+    garbage_rng: StdRng,
+    garbage_hole: Option<usize>,
+    actions: VecDeque<Action>,
+    events: Vec<Event>,
+
+    pub stack: Stack,
+    pub bag: Bag,
+    pub piece: Piece,
+    pub holder: Holder,
+    pub score: Score,
+    pub zone: Zone,
+
+    garbage: Vec<GarbageRow>,
+    pieces: u32,
+    soft_dropped: bool,
+    game_over: bool,
+    falling: Duration,
+    fall_interval: Duration,
+    entering: Option<Duration>,
+    undo: Option<Undo>,
+    /// How long hard drops wait before locking, so that misdrops can be undone
+    undo_grace: Option<Duration>,
+    /// How long the first press of a two-step hard drop waits for the second one
+    drop_confirmation: Option<Duration>,
+    /// Time since the hard drop was armed by its first press
+    armed: Option<Duration>,
+    /// Gravity multiplier of an analog soft drop, 1 falls at the usual speed
+    soft_drop: f32,
+    /// Landing row of the piece along with the piece and stack revision it was found for
+    drop_row: Cell<Option<(PieceState, u32, i32)>>,
+}
+
+impl Engine {
+    /// Non-interactive engines never decide to fall, lock or end the game on their own,
+    /// they rely on those actions being fed from a replay
+    pub fn new(seed: &[u8; 32], interactive: bool, rules: &Rules) -> Engine {
+        let mut stack = Stack::new(rules.width, rules.height, 20);
+        stack.set_cascade(rules.cascade);
+        stack.set_detector(rules.clear.detector());
+        let mut bag = match rules.randomizer {
+            Randomizer::Loop => Bag::with_loop(seed, &rules.sequence()),
+            randomizer => Bag::with_randomizer(seed, randomizer),
+        };
+        let system = rules.rotation_system();
+        let mut piece = Piece::new(bag.pop(), &stack);
+        piece.set_system(Arc::clone(&system));
+
+        // Garbage holes must not depend on the pieces, so they get their own generator
+        let mut garbage_seed = *seed;
+        garbage_seed.reverse();
+
+        Engine {
+            interactive,
+            rules: rules.clone(),
+            system,
+            garbage_rng: SeedableRng::from_seed(garbage_seed),
+            garbage_hole: None,
+            actions: VecDeque::new(),
+            events: vec![],
+            stack,
+            bag,
+            piece,
+            holder: Holder::default(),
+            score: Score::default(),
+            zone: Zone::default(),
+            garbage: vec![],
+            pieces: 1,
+            soft_dropped: false,
+            game_over: false,
+            falling: Duration::new(0, 0),
+            fall_interval: rules.gravity.interval(0),
+            entering: None,
+            undo: None,
+            undo_grace: None,
+            drop_confirmation: None,
+            soft_drop: 1.0,
+            armed: None,
+            drop_row: Cell::new(None),
+        }
+    }
+
+    pub fn reset(&mut self, seed: &[u8; 32]) {
+        *self = Engine::new(seed, self.interactive, &self.rules);
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    pub fn action(&mut self, action: Action, immediate: bool) {
+        if immediate {
+            self.actions.push_front(action);
+        } else {
+            self.actions.push_back(action);
+        }
+    }
+
+    /// Next queued action, unless a new piece is still entering.
+    /// While a hard drop can be undone only the undo and locking go through,
+    /// the rest waits for the next piece.
+    /// With drop confirmation the first hard drop only arms it and never gets recorded.
+    pub fn pop_action(&mut self) -> Option<Action> {
+        if self.entering.is_some() {
+            return None;
+        }
+
+        if self.undo.is_some() {
+            let id = self.actions.iter().position(|&a| match a {
+                Action::UndoDrop | Action::LockPiece | Action::GameOver => true,
+                _ => false,
+            })?;
+            return self.actions.remove(id);
+        }
+
+        loop {
+            let action = self.actions.pop_front()?;
+            let confirming = self.interactive && self.drop_confirmation.is_some();
+
+            if action == Action::HardDrop && confirming && self.armed.take().is_none() {
+                self.armed = Some(Duration::new(0, 0));
+                continue;
+            }
+
+            return Some(action);
+        }
+    }
+
+    /// None locks hard drops right away
+    pub fn set_undo_grace(&mut self, grace: Option<Duration>) {
+        self.undo_grace = grace;
+    }
+
+    /// Window for confirming a hard drop with a second press, None drops on the first one
+    pub fn set_drop_confirmation(&mut self, window: Option<Duration>) {
+        self.drop_confirmation = window;
+        if window.is_none() {
+            self.armed = None;
+        }
+    }
+
+    /// Sets how many times faster than gravity the piece falls, for soft drops of varying speed.
+    /// It's input rather than a part of the game, the moves it makes are recorded as usual.
+    pub fn set_soft_drop(&mut self, multiplier: f32) {
+        self.soft_drop = multiplier.max(1.0);
+    }
+
+    /// Whether the next hard drop commits, the landing position is highlighted meanwhile
+    pub fn drop_armed(&self) -> bool {
+        self.armed.is_some()
+    }
+
+    /// Moves the pending events into the buffer, both vectors keep their capacity
+    pub fn events(&mut self, events: &mut Vec<Event>) {
+        events.clear();
+        mem::swap(&mut self.events, events);
+    }
+
+    pub fn interactive(&self) -> bool {
+        self.interactive
+    }
+
+    pub fn game_over(&self) -> bool {
+        self.game_over
+    }
+
+    pub fn blocked(&self) -> bool {
+        self.game_over || self.stack.blocked()
+    }
+
+    pub fn entering(&self) -> bool {
+        self.entering.is_some()
+    }
+
+    /// Actions waiting to be processed
+    pub fn queued_actions(&self) -> usize {
+        self.actions.len()
+    }
+
+    /// Number of pieces that entered the stack, including the current one
+    pub fn pieces(&self) -> u32 {
+        self.pieces
+    }
+
+    /// Queues garbage that rises the next time a piece locks without clearing
+    pub fn queue_garbage(&mut self, rows: &[GarbageRow]) {
+        self.garbage.extend_from_slice(rows);
+    }
+
+    pub fn pending_garbage(&self) -> usize {
+        self.garbage.len()
+    }
+
+    /// Processes an action instantly, skipping clear and entry delays.
+    /// Returns the number of cleared rows.
+    pub fn skip(&mut self, settings: &settings::Gameplay, action: Action) -> i32 {
+        let lines = self.score.lines();
+
+        self.process(settings, action);
+        self.stack.finish_clearing();
+
+        if self.entering.is_some() {
+            self.spawn_piece();
+        }
+
+        self.score.lines() - lines
+    }
+
+    /// Advances gravity, lock delay and entry delay
+    pub fn step(&mut self, settings: &settings::Gameplay, dt: Duration) {
+        self.piece.update(dt, &self.stack);
+
+        if let (Some(armed), Some(window)) = (self.armed.as_mut(), self.drop_confirmation) {
+            *armed += dt;
+            if *armed >= window {
+                self.armed = None;
+            }
+        }
+
+        if self.interactive && self.zone.update(dt) {
+            self.action(Action::EndZone, true);
+        }
+
+        if let Some(entering) = self.entering.as_mut() {
+            *entering += dt;
+
+            if *entering >= Duration::from_millis(settings.entry_delay.into()) {
+                self.spawn_piece();
+            }
+        } else if let Some(undo) = self.undo.as_mut() {
+            // Nothing else happens until the hard drop locks or is taken back
+            undo.elapsed += dt;
+
+            let expired = self.undo_grace.map_or(true, |grace| undo.elapsed >= grace);
+            if self.interactive && expired && !undo.locking {
+                undo.locking = true;
+                self.action(Action::LockPiece, true);
+            }
+        } else if self.interactive {
+            let lock = match self.lock_delay(settings) {
+                Some(delay) => self.piece.locking() > delay,
+                None => false,
+            };
+
+            if lock {
+                self.action(Action::LockPiece, true);
+            } else if self.zone.active() {
+                // Gravity stops in the zone
+                self.falling = Duration::new(0, 0);
+            } else {
+                self.falling += dt.mul_f32(self.soft_drop);
+
+                if self.falling >= self.fall_interval && self.soft_drop > 1.0 {
+                    // Moves down like the soft drop key, resting on the floor is up to the lock delay
+                    self.falling = Duration::new(0, 0);
+                    self.action(Action::MoveDown, true);
+                } else if self.falling >= self.fall_interval {
+                    self.falling -= self.fall_interval;
+
+                    self.action(Action::FallPiece, true);
+                }
+            }
+        }
+    }
+
+    fn spawn_piece(&mut self) {
+        self.entering = None;
+        self.soft_dropped = false;
+
+        self.pieces += 1;
+        self.rise_garbage();
+
+        let shape = self.bag.pop();
+        if self.enter(shape) {
+            self.reset_fall();
+            self.holder.unlock();
+        }
+    }
+
+    /// Queues the garbage of the rules every few pieces
+    fn rise_garbage(&mut self) {
+        let garbage = self.rules.garbage;
+        if garbage.interval == 0 || (self.pieces - 1) % garbage.interval != 0 {
+            return;
+        }
+
+        for _ in 0..garbage.rows {
+            let hole = self.next_garbage_hole(garbage.messiness);
+            self.garbage.push(GarbageRow { hole });
+        }
+    }
+
+    /// Keeps the previous hole unless the messiness roll says otherwise,
+    /// a moved hole always lands in a different column
+    fn next_garbage_hole(&mut self, messiness: u32) -> usize {
+        let width = self.stack.width as usize;
+
+        let hole = match self.garbage_hole {
+            None => self.garbage_rng.gen_range(0, width),
+            Some(hole) if self.garbage_rng.gen_range(0, 100) < messiness && width > 1 => {
+                (hole + self.garbage_rng.gen_range(1, width)) % width
+            }
+            Some(hole) => hole,
+        };
+
+        self.garbage_hole = Some(hole);
+        hole
+    }
+
+    /// Places a new piece at the top of the stack, returns false if it was blocked out
+    fn enter(&mut self, shape: ShapeType) -> bool {
+        self.piece = Piece::new(shape, &self.stack);
+        self.piece.set_system(Arc::clone(&self.system));
+        self.armed = None;
+
+        if !self.stack.top_out.block_out {
+            while self.stack.collision(&self.piece) && self.piece.y > 0 {
+                self.piece.y -= 1;
+            }
+        }
+
+        if self.stack.collision(&self.piece) && self.interactive {
+            self.action(Action::GameOver, true);
+            return false;
+        }
+
+        true
+    }
+
+    /// Time the piece can rest on the floor before locking, if it locks on its own
+    pub fn lock_delay(&self, settings: &settings::Gameplay) -> Option<Duration> {
+        let delay = match settings.lock_mode {
+            LockMode::Delay => settings.lock_delay,
+            LockMode::HardDropOnly => return None,
+            LockMode::SoftDropFast if self.soft_dropped => {
+                settings.soft_drop_lock_delay.min(settings.lock_delay)
+            }
+            LockMode::SoftDropFast => settings.lock_delay,
+        };
+
+        Some(Duration::from_millis(delay.into()))
+    }
+
+    fn reset_fall(&mut self) {
+        if self.falling > self.fall_interval {
+            self.falling -= self.fall_interval
+        } else {
+            self.falling = Duration::new(0, 0);
+        }
+    }
+
+    /// Follows the level curve after lines are cleared, announcing every speed up
+    fn update_gravity(&mut self) {
+        let lines = self.score.lines();
+        let interval = self.rules.gravity.interval(lines);
+
+        if interval < self.fall_interval {
+            self.events.push(Event::LevelUp {
+                level: self.rules.gravity.level(lines),
+                interval,
+            });
+        }
+
+        self.fall_interval = interval;
+    }
+
+    /// Row the piece lands on after a hard drop, shared by the ghost and the drops.
+    /// Only searched again after the piece moves or the stack changes.
+    pub fn drop_row(&self) -> i32 {
+        let state = self.piece.state();
+        let revision = self.stack.revision();
+
+        match self.drop_row.get() {
+            Some((s, r, row)) if s == state && r == revision => row,
+            _ => {
+                let mut piece = self.piece.clone();
+                piece.fall(&self.stack);
+                self.drop_row.set(Some((state, revision, piece.y)));
+                piece.y
+            }
+        }
+    }
+
+    /// Hash of everything that decides how the game continues: the board, the piece,
+    /// the queue with its generator, the hold and the score. Timers are left out,
+    /// so the same actions give the same hash no matter how fast they were made.
+    pub fn state_hash(&self) -> u64 {
+        self.hash(true)
+    }
+
+    /// Same as the state hash without the generators of the queue and the garbage holes,
+    /// for copies of a board whose pieces are only revealed as they come
+    pub fn board_hash(&self) -> u64 {
+        self.hash(false)
+    }
+
+    fn hash(&self, generators: bool) -> u64 {
+        let mut hash = Fnv64::default();
+
+        for row in self.stack.grid() {
+            for &block in row {
+                hash.write(block as u64);
+            }
+        }
+
+        let piece = self.piece.state();
+        hash.write(piece.shape as u64);
+        hash.write(piece.x as u64);
+        hash.write(piece.y as u64);
+        hash.write(piece.rotation as u64);
+
+        if generators {
+            self.bag.hash(&mut hash);
+        }
+        self.holder.hash(&mut hash);
+        self.score.hash(&mut hash);
+        self.zone.hash(&mut hash);
+        hash.write(self.stack.zone_rows() as u64);
+
+        hash.write(u64::from(self.pieces));
+        hash.write(self.game_over as u64);
+        hash.write(self.garbage.len() as u64);
+        for row in &self.garbage {
+            hash.write(row.hole as u64);
+        }
+        if generators {
+            hash.write(self.garbage_hole.map_or(u64::max_value(), |h| h as u64));
+            hash.write(self.garbage_rng.clone().gen());
+        }
+
+        hash.finish()
+    }
+
+    /// How close the stack is to topping out, from 0 to 1
+    pub fn danger(&self) -> f32 {
+        let filled = self.stack.filled_height() as f32 / self.stack.height as f32;
+        ((filled - 0.6) / 0.4).max(0.0).min(1.0)
+    }
+
+    pub fn insert_garbage(&mut self, rows: &[GarbageRow]) {
+        // Garbage would end up below the rows piled up in the zone, so it waits for the end
+        if self.zone.active() {
+            self.queue_garbage(rows);
+            return;
+        }
+
+        let overflow = self.stack.insert_garbage(rows);
+
+        while self.stack.collision(&self.piece) && self.piece.y > 0 {
+            self.piece.y -= 1;
+        }
+
+        if (overflow || self.stack.collision(&self.piece)) && self.interactive {
+            self.action(Action::GameOver, true);
+        }
+    }
+
+    pub fn board_state(&self) -> BoardState {
+        BoardState {
+            grid: self.stack.grid().clone(),
+            piece: self.piece.state(),
+            hold: self.holder.shape_type(),
+            queue: self.bag.peek(14).cloned().collect(),
+        }
+    }
+
+    pub fn set_board_state(&mut self, state: BoardState) -> Result<(), String> {
+        let mut piece = Piece::from_state(state.piece);
+        piece.set_system(Arc::clone(&self.system));
+        let previous = self.stack.grid().clone();
+        self.stack.set_grid(state.grid)?;
+
+        if self.stack.collision(&piece) {
+            self.stack.set_grid(previous)?;
+            return Err(String::from("Piece overlaps the stack"));
+        }
+
+        self.piece = piece;
+        self.entering = None;
+        self.holder.set(state.hold);
+        self.bag.set_queue(&state.queue);
+        self.actions.clear();
+        self.reset_fall();
+
+        Ok(())
+    }
+
+    /// Not available while rows are being cleared, a hard drop can be undone or in the zone,
+    /// as the state is spread over the stack then
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        if self.stack.blocked() || self.undo.is_some() || self.zone.active() {
+            return None;
+        }
+
+        Some(Snapshot {
+            grid: self.stack.grid().clone(),
+            bag: self.bag.clone(),
+            piece: self.piece.clone(),
+            holder: self.holder.clone(),
+            score: self.score.clone(),
+            zone: self.zone.clone(),
+            garbage: self.garbage.clone(),
+            garbage_rng: self.garbage_rng.clone(),
+            garbage_hole: self.garbage_hole,
+            pieces: self.pieces,
+            fall_interval: self.fall_interval,
+        })
+    }
+
+    /// Returns false if the game can't be restored right now, for the same reasons as above
+    pub fn restore(&mut self, snapshot: &Snapshot) -> bool {
+        if self.stack.blocked() || self.undo.is_some() || self.zone.active() || self.game_over {
+            return false;
+        }
+
+        if let Err(e) = self.stack.set_grid(snapshot.grid.clone()) {
+            log::error!("Unable to restore the snapshot: {}", e);
+            return false;
+        }
+
+        self.bag = snapshot.bag.clone();
+        self.piece = snapshot.piece.clone();
+        self.holder = snapshot.holder.clone();
+        self.score = snapshot.score.clone();
+        self.zone = snapshot.zone.clone();
+        self.garbage = snapshot.garbage.clone();
+        self.garbage_rng = snapshot.garbage_rng.clone();
+        self.garbage_hole = snapshot.garbage_hole;
+        self.pieces = snapshot.pieces;
+        self.fall_interval = snapshot.fall_interval;
+        self.soft_dropped = false;
+        self.falling = Duration::new(0, 0);
+        self.entering = None;
+        self.actions.clear();
+        true
+    }
+
+    /// Goes back to the snapshot even in the middle of a line clear or an undoable hard drop,
+    /// for copies of a board that went out of sync. Returns false once the game is over.
+    pub fn rewind(&mut self, snapshot: &Snapshot) -> bool {
+        self.stack.skip_animations();
+        self.undo = None;
+        self.zone = snapshot.zone.clone();
+        self.restore(snapshot)
+    }
+
+    /// Returns false when the following actions have to wait, e.g. for the next piece
+    pub fn process(&mut self, settings: &settings::Gameplay, action: Action) -> bool {
+        match action {
+            Action::HoldPiece if !self.rules.hold => self.events.push(Event::HoldFailed),
+            Action::HoldPiece => {
+                if let Some(shape) = self.holder.hold(self.piece.shape(), &mut self.bag) {
+                    self.enter(shape);
+                    self.soft_dropped = false;
+                    self.events.push(Event::Held);
+                } else {
+                    self.events.push(Event::HoldFailed);
+                }
+            }
+            Action::FallPiece => {
+                if !self.piece.shift(0, 1, &self.stack)
+                    && self.interactive
+                    && settings.lock_mode != LockMode::HardDropOnly
+                {
+                    self.action(Action::LockPiece, true);
+                }
+            }
+            Action::LockPiece => {
+                self.undo = None;
+
+                // The stack collapses right after locking, so check for T-Spin beforehand
+                let t_spin = self.piece.t_spin(&self.stack);
+
+                match self.stack.lock(&self.piece, settings.clear_delays()) {
+                    Locked::Collision => {
+                        if self.interactive {
+                            self.action(Action::GameOver, true);
+                        }
+                    }
+                    // Rows piled up in the zone only score once it ends
+                    Locked::Success(_) if self.zone.active() => {
+                        self.events.push(Event::Locked {
+                            rows: 0,
+                            t_spin,
+                            attack: 0,
+                        });
+                        self.entering = Some(Duration::new(0, 0));
+
+                        return false;
+                    }
+                    Locked::Success(rows) => {
+                        let mut attack = 0;
+                        if rows > 0 {
+                            let multiplier = self.rules.gravity.multiplier(self.score.lines());
+                            attack = self.score.lock(rows, t_spin, multiplier);
+                            self.zone.fill(rows);
+                        } else {
+                            self.score.reset_combo();
+
+                            if !self.garbage.is_empty() {
+                                let overflow = self.stack.insert_garbage(&self.garbage);
+                                self.garbage.clear();
+
+                                if overflow && self.interactive {
+                                    self.action(Action::GameOver, true);
+                                }
+                            }
+                        }
+
+                        self.events.push(Event::Locked {
+                            rows,
+                            t_spin,
+                            attack,
+                        });
+
+                        if !self.stack.chains().is_empty() {
+                            let links = self.stack.chains().to_vec();
+                            let rows = links.iter().sum();
+                            let attack = self.score.chain(&links);
+                            self.zone.fill(rows);
+                            self.events.push(Event::Chain {
+                                length: links.len() as i32 + 1,
+                                rows,
+                                attack,
+                            });
+                        }
+
+                        self.update_gravity();
+                        self.entering = Some(Duration::new(0, 0));
+
+                        return false;
+                    }
+                };
+            }
+            Action::UndoDrop => {
+                if let Some(undo) = self.undo.take() {
+                    self.piece = undo.piece;
+                    self.score.undo_hard_drop(undo.rows);
+                    self.reset_fall();
+                    self.events.push(Event::DropUndone);
+                }
+            }
+            Action::ActivateZone => {
+                if self.zone.ready() {
+                    self.zone.start();
+                    self.stack.start_zone();
+                    self.events.push(Event::ZoneStarted);
+                }
+            }
+            Action::EndZone => {
+                if !self.zone.active() {
+                    return true;
+                }
+
+                self.zone.end();
+                let rows = self.stack.end_zone(settings.clear_delays());
+
+                // Overhangs above the piece come down along with the stack
+                while self.stack.collision(&self.piece) && self.piece.y > 0 {
+                    self.piece.y -= 1;
+                }
+
+                let attack = self.score.zone(rows);
+                self.events.push(Event::ZoneEnded { rows, attack });
+                self.update_gravity();
+
+                return rows == 0;
+            }
+            Action::GameOver => {
+                if self.game_over {
+                    return false;
+                }
+
+                self.game_over = true;
+                self.stack.game_over();
+                self.events.push(Event::GameOver);
+
+                return false;
+            }
+            Action::MoveLeft
+            | Action::MoveRight
+            | Action::MoveDown
+            | Action::RotateClockwise
+            | Action::RotateCounterClockwise
+            | Action::Rotate180
+            | Action::SoftDrop
+            | Action::HardDrop
+            | Action::ShiftRight
+            | Action::ShiftLeft => self.process_movement(action),
+        };
+
+        true
+    }
+
+    fn process_movement(&mut self, action: Action) {
+        match action {
+            Action::MoveRight | Action::MoveLeft => {
+                let x = if action == Action::MoveRight { 1 } else { -1 };
+                let moved = self.piece.shift(x, 0, &self.stack);
+                if moved {
+                    if self.piece.touching_floor(&self.stack) {
+                        self.reset_fall();
+                    }
+
+                    self.events.push(Event::Moved);
+                }
+            }
+            Action::ShiftRight | Action::ShiftLeft => {
+                let x = if action == Action::ShiftRight { 1 } else { -1 };
+                let mut moved = false;
+                while self.piece.shift(x, 0, &self.stack) {
+                    moved = true;
+                }
+
+                if moved {
+                    if self.piece.touching_floor(&self.stack) {
+                        self.reset_fall();
+                    }
+
+                    self.events.push(Event::Moved);
+                }
+            }
+            Action::MoveDown => {
+                if self.piece.shift(0, 1, &self.stack) {
+                    self.reset_fall();
+                    self.soft_dropped = self.piece.touching_floor(&self.stack);
+                    self.events.push(Event::Moved);
+                }
+            }
+            Action::RotateClockwise | Action::RotateCounterClockwise => {
+                let clockwise = action == Action::RotateClockwise;
+                let rotated = self
+                    .piece
+                    .rotate_with(clockwise, self.rules.kicks, &self.stack);
+                if rotated {
+                    if self.piece.touching_floor(&self.stack) {
+                        self.reset_fall();
+                    }
+
+                    self.events.push(Event::Rotated);
+                }
+            }
+            Action::Rotate180 => {
+                if self.piece.rotate_180_with(self.rules.kicks, &self.stack) {
+                    if self.piece.touching_floor(&self.stack) {
+                        self.reset_fall();
+                    }
+
+                    self.events.push(Event::RotatedHalf);
+                }
+            }
+            Action::SoftDrop => {
+                let rows = self.piece.drop_to(self.drop_row());
+                self.soft_dropped = true;
+                if rows > 0 {
+                    self.reset_fall();
+                    self.score.soft_drop(rows);
+                }
+            }
+            Action::HardDrop => {
+                let piece = self.piece.clone();
+                let rows = self.piece.drop_to(self.drop_row());
+                self.score.hard_drop(rows);
+
+                let locking = self.interactive && self.undo_grace.is_none();
+                self.undo = Some(Undo {
+                    piece,
+                    rows,
+                    elapsed: Duration::new(0, 0),
+                    locking,
+                });
+
+                if locking {
+                    self.action(Action::LockPiece, true);
+                }
+            }
+            _ => (),
+        };
+    }
+}
+
+#[test]
+fn state_hash_test() {
+    let settings = settings::Gameplay::default();
+    let mut a = Engine::new(&[3; 32], true, &Rules::default());
+    let mut b = Engine::new(&[3; 32], true, &Rules::default());
+    assert_eq!(a.state_hash(), b.state_hash());
+
+    a.skip(&settings, Action::HardDrop);
+    assert_ne!(a.state_hash(), b.state_hash());
+
+    b.skip(&settings, Action::HardDrop);
+    assert_eq!(a.state_hash(), b.state_hash());
+
+    let mut c = Engine::new(&[4; 32], true, &Rules::default());
+    let d = Engine::new(&[3; 32], true, &Rules::default());
+    assert_ne!(c.state_hash(), d.state_hash());
+
+    // The same board from another seed only differs in the generators
+    c.set_board_state(d.board_state()).unwrap();
+    assert_ne!(c.state_hash(), d.state_hash());
+    assert_eq!(c.board_hash(), d.board_hash());
+}
+
+#[test]
+fn drop_row_test() {
+    let mut engine = Engine::new(&[2; 32], true, &Rules::default());
+    let settings = settings::Gameplay::default();
+
+    for &action in &[Action::MoveLeft, Action::RotateClockwise, Action::MoveRight] {
+        engine.process(&settings, action);
+
+        let mut piece = engine.piece.clone();
+        piece.fall(&engine.stack);
+        assert_eq!(piece.y, engine.drop_row());
+    }
+
+    let row = engine.drop_row();
+    engine.insert_garbage(&[GarbageRow { hole: 0 }, GarbageRow { hole: 1 }]);
+    assert_eq!(row - 2, engine.drop_row());
+}
+
+#[test]
+fn garbage_messiness_test() {
+    let mut engine = Engine::new(&[1; 32], true, &Rules::default());
+    let first = engine.next_garbage_hole(0);
+    assert!((0..10).all(|_| engine.next_garbage_hole(0) == first));
+
+    let mut previous = first;
+    for _ in 0..10 {
+        let hole = engine.next_garbage_hole(100);
+        assert_ne!(previous, hole);
+        previous = hole;
+    }
+}
+
+#[test]
+fn level_up_test() {
+    let mut rules = Rules::default();
+    rules.gravity.step = 100;
+    rules.gravity.lines = 2;
+
+    let mut engine = Engine::new(&[5; 32], true, &rules);
+    let mut events = vec![];
+
+    engine.score.lock(1, false, 1);
+    engine.update_gravity();
+    engine.events(&mut events);
+    assert!(events.is_empty());
+
+    engine.score.lock(2, false, 1);
+    engine.update_gravity();
+    engine.events(&mut events);
+    assert_eq!(
+        events,
+        vec![Event::LevelUp {
+            level: 2,
+            interval: Duration::from_millis(900),
+        }]
+    );
+}
+
+#[test]
+fn undo_drop_test() {
+    let settings = settings::Gameplay::default();
+    let mut engine = Engine::new(&[6; 32], true, &Rules::default());
+    engine.set_undo_grace(Some(Duration::from_millis(300)));
+
+    let y = engine.piece.y;
+    engine.process(&settings, Action::HardDrop);
+    engine.action(Action::MoveLeft, false);
+    engine.step(&settings, Duration::from_millis(100));
+    assert_eq!(engine.pop_action(), None);
+
+    engine.action(Action::UndoDrop, false);
+    assert_eq!(engine.pop_action(), Some(Action::UndoDrop));
+    engine.process(&settings, Action::UndoDrop);
+    assert_eq!(engine.piece.y, y);
+    assert_eq!(engine.score.score(), 0);
+    assert_eq!(engine.pop_action(), Some(Action::MoveLeft));
+
+    engine.process(&settings, Action::HardDrop);
+    engine.step(&settings, Duration::from_millis(400));
+    assert_eq!(engine.pop_action(), Some(Action::LockPiece));
+}
+
+#[test]
+fn drop_confirmation_test() {
+    let settings = settings::Gameplay::default();
+    let mut engine = Engine::new(&[8; 32], true, &Rules::default());
+    engine.set_drop_confirmation(Some(Duration::from_millis(500)));
+
+    engine.action(Action::HardDrop, false);
+    engine.action(Action::MoveLeft, false);
+    assert_eq!(engine.pop_action(), Some(Action::MoveLeft));
+    assert!(engine.drop_armed());
+
+    engine.action(Action::HardDrop, false);
+    assert_eq!(engine.pop_action(), Some(Action::HardDrop));
+    assert!(!engine.drop_armed());
+
+    // The window runs out without the second press
+    engine.action(Action::HardDrop, false);
+    assert_eq!(engine.pop_action(), None);
+    engine.step(&settings, Duration::from_millis(500));
+    assert!(!engine.drop_armed());
+
+    engine.set_drop_confirmation(None);
+    engine.action(Action::HardDrop, false);
+    assert_eq!(engine.pop_action(), Some(Action::HardDrop));
+}
+
+#[test]
+fn snapshot_test() {
+    let settings = settings::Gameplay::default();
+    let mut engine = Engine::new(&[7; 32], true, &Rules::default());
+
+    // The hard drop queues the lock, snapshots wait for it
+    let drop = |engine: &mut Engine| {
+        engine.skip(&settings, Action::HardDrop);
+        while let Some(action) = engine.pop_action() {
+            engine.skip(&settings, action);
+        }
+    };
+    drop(&mut engine);
+
+    let snapshot = engine.snapshot().unwrap();
+    let hash = engine.state_hash();
+
+    for _ in 0..3 {
+        drop(&mut engine);
+    }
+    assert_ne!(engine.state_hash(), hash);
+
+    assert!(engine.restore(&snapshot));
+    assert_eq!(engine.state_hash(), hash);
+
+    // The game goes on the same way after every restore
+    drop(&mut engine);
+    let next = engine.state_hash();
+    assert!(engine.restore(&snapshot));
+    drop(&mut engine);
+    assert_eq!(engine.state_hash(), next);
+}
+
+#[test]
+fn analog_soft_drop_test() {
+    let settings = settings::Gameplay::default();
+    let mut engine = Engine::new(&[5; 32], true, &Rules::default());
+    let step = |engine: &mut Engine, millis| {
+        engine.step(&settings, Duration::from_millis(millis));
+        while let Some(action) = engine.pop_action() {
+            engine.process(&settings, action);
+        }
+    };
+    let y = engine.piece.y;
+
+    // Gravity is one row a second, ten times faster makes it a row every 100 ms
+    engine.set_soft_drop(10.0);
+    for _ in 0..5 {
+        step(&mut engine, 100);
+    }
+    assert_eq!(engine.piece.y, y + 5);
+
+    engine.set_soft_drop(0.5);
+    step(&mut engine, 500);
+    assert_eq!(engine.piece.y, y + 5);
+}
+
+#[test]
+fn rotate_180_test() {
+    let settings = settings::Gameplay::default();
+    let mut engine = Engine::new(&[7; 32], true, &Rules::default());
+    let mut events = vec![];
+
+    engine.piece = Piece::new(ShapeType::T, &engine.stack);
+    engine.process(&settings, Action::Rotate180);
+    engine.events(&mut events);
+    assert_eq!(engine.piece.state().rotation, 2);
+    assert_eq!(events, vec![Event::RotatedHalf]);
+
+    // Against the floor the half turn kicks the piece up instead of failing
+    engine.piece = Piece::new(ShapeType::T, &engine.stack);
+    engine.piece.fall(&engine.stack);
+    let y = engine.piece.y;
+    engine.process(&settings, Action::Rotate180);
+    assert_eq!(engine.piece.state().rotation, 2);
+    assert_eq!(engine.piece.y, y - 1);
+
+    engine.piece = Piece::new(ShapeType::O, &engine.stack);
+    engine.process(&settings, Action::Rotate180);
+    assert_eq!(engine.piece.state().rotation, 0);
+}
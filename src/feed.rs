@@ -0,0 +1,146 @@
+use std::{collections::VecDeque, net::UdpSocket, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json;
+
+use crate::{piece::PieceState, stack::Grid};
+
+/// Live stats of the game sent to stream overlays and scoreboards
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct FeedFrame {
+    pub score: i32,
+    pub lines: i32,
+    pub pieces: u32,
+    /// Pieces per second
+    pub pps: f32,
+    /// Attack per minute, in lines sent
+    pub apm: f32,
+    pub combo: Option<i32>,
+    pub btb: bool,
+    pub game_over: bool,
+    /// Visible rows of the stack from the top, 0 is an empty cell
+    pub board: Grid,
+    pub piece: PieceState,
+    /// Board on the screen as x, y, width and height, for placing the overlay around it
+    pub region: Option<[f32; 4]>,
+}
+
+impl FeedFrame {
+    pub fn rates(pieces: u32, attack: i32, duration: Duration) -> (f32, f32) {
+        let seconds = duration.as_secs_f32();
+        if seconds <= 0.0 {
+            return (0.0, 0.0);
+        }
+
+        (pieces as f32 / seconds, attack as f32 * 60.0 / seconds)
+    }
+}
+
+/// Sends a JSON datagram with the frame every now and then,
+/// a local relay can pass them on to a browser source
+#[derive(Default)]
+pub struct Feed {
+    socket: Option<UdpSocket>,
+    /// Set after an error so that the log is not flooded, until the feed is turned off
+    failed: bool,
+    elapsed: Duration,
+    /// Time since the feed was turned on
+    time: Duration,
+    /// Frames held back by the delay, along with when they go out
+    delayed: VecDeque<(Duration, Vec<u8>)>,
+}
+
+impl Feed {
+    /// Returns true when the next frame is due
+    pub fn update(&mut self, dt: Duration, enabled: bool, interval: Duration) -> bool {
+        if !enabled {
+            self.socket = None;
+            self.failed = false;
+            self.time = Duration::new(0, 0);
+            self.delayed.clear();
+            return false;
+        }
+
+        self.time += dt;
+        self.elapsed += dt;
+        if self.failed || self.elapsed < interval {
+            return false;
+        }
+
+        self.elapsed = Duration::new(0, 0);
+        true
+    }
+
+    /// Holds the frame back for the delay, returns the frames due by now
+    fn due(&mut self, json: Vec<u8>, delay: Duration) -> Vec<Vec<u8>> {
+        self.delayed.push_back((self.time + delay, json));
+
+        let mut due = vec![];
+        while let Some((time, _)) = self.delayed.front() {
+            if *time > self.time {
+                break;
+            }
+            due.extend(self.delayed.pop_front().map(|(_, json)| json));
+        }
+
+        due
+    }
+
+    pub fn send(&mut self, frame: &FeedFrame, address: &str, delay: Duration) {
+        if self.socket.is_none() {
+            match UdpSocket::bind("0.0.0.0:0") {
+                Ok(socket) => self.socket = Some(socket),
+                Err(e) => {
+                    log::error!("Unable to open the overlay feed socket: {:?}", e);
+                    self.failed = true;
+                    return;
+                }
+            }
+        }
+
+        let json = match serde_json::to_vec(frame) {
+            Ok(json) => json,
+            Err(e) => {
+                log::error!("Unable to serialize the overlay feed: {:?}", e);
+                self.failed = true;
+                return;
+            }
+        };
+
+        for json in self.due(json, delay) {
+            if let Some(socket) = &self.socket {
+                if let Err(e) = socket.send_to(&json, address) {
+                    log::error!("Unable to send the overlay feed to {}: {:?}", address, e);
+                    self.failed = true;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn feed_test() {
+    assert_eq!(
+        FeedFrame::rates(30, 10, Duration::from_secs(20)),
+        (1.5, 30.0)
+    );
+    assert_eq!(FeedFrame::rates(1, 0, Duration::new(0, 0)), (0.0, 0.0));
+
+    let mut feed = Feed::default();
+    let interval = Duration::from_millis(100);
+    assert!(!feed.update(Duration::from_millis(60), true, interval));
+    assert!(feed.update(Duration::from_millis(60), true, interval));
+    assert!(!feed.update(Duration::from_millis(60), false, interval));
+
+    // Delayed frames go out in order once their time comes
+    let delay = Duration::from_millis(150);
+    feed.update(Duration::from_millis(100), true, interval);
+    assert!(feed.due(vec![1], delay).is_empty());
+    feed.update(Duration::from_millis(100), true, interval);
+    assert!(feed.due(vec![2], delay).is_empty());
+    feed.update(Duration::from_millis(100), true, interval);
+    assert_eq!(feed.due(vec![3], delay), vec![vec![1]]);
+    feed.update(Duration::from_millis(200), true, interval);
+    assert_eq!(feed.due(vec![4], delay), vec![vec![2], vec![3]]);
+}
@@ -0,0 +1,221 @@
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{piece::Piece, shape::ShapeType, stack::Stack};
+
+/// Inputs taking the piece somewhere from the spawn, DAS to the wall counts as one
+const MOVES: [Move; 6] = [
+    Move::Left,
+    Move::Right,
+    Move::WallLeft,
+    Move::WallRight,
+    Move::Clockwise,
+    Move::CounterClockwise,
+];
+
+#[derive(Copy, Clone)]
+enum Move {
+    Left,
+    Right,
+    WallLeft,
+    WallRight,
+    Clockwise,
+    CounterClockwise,
+}
+
+impl Move {
+    fn apply(self, piece: &mut Piece, stack: &Stack) -> bool {
+        match self {
+            Move::Left => piece.shift(-1, 0, stack),
+            Move::Right => piece.shift(1, 0, stack),
+            Move::WallLeft | Move::WallRight => {
+                let x = if let Move::WallLeft = self { -1 } else { 1 };
+                let mut moved = false;
+                while piece.shift(x, 0, stack) {
+                    moved = true;
+                }
+                moved
+            }
+            Move::Clockwise => piece.rotate_with(true, true, stack),
+            Move::CounterClockwise => piece.rotate_with(false, true, stack),
+        }
+    }
+}
+
+/// Where a piece can end up on an empty board, rotations looking the same count once
+#[derive(Clone, Debug, PartialEq)]
+pub struct Placement {
+    pub shape: ShapeType,
+    pub index: usize,
+    /// Cells of the dropped piece, sorted
+    pub cells: Vec<(i32, i32)>,
+    /// Fewest inputs reaching it, the hard drop included
+    pub optimal: u32,
+}
+
+impl Placement {
+    /// Name the times of the placement are stored under
+    pub fn key(&self) -> String {
+        format!("{:?}{}", self.shape, self.index)
+    }
+
+    pub fn label(&self) -> String {
+        let left = self.cells.iter().map(|c| c.0).min().unwrap_or(0);
+        let right = self.cells.iter().map(|c| c.0).max().unwrap_or(0);
+        format!("{:?} columns {}-{}", self.shape, left + 1, right + 1)
+    }
+}
+
+fn dropped_cells(piece: &Piece, stack: &Stack) -> Vec<(i32, i32)> {
+    let mut dropped = piece.clone();
+    dropped.fall(stack);
+
+    let mut cells = dropped.cells().to_vec();
+    cells.sort();
+    cells
+}
+
+/// Every placement of the piece, by rotation from the spawn and then from the left wall
+pub fn placements(shape: ShapeType, stack: &Stack) -> Vec<Placement> {
+    let optimal = fewest_inputs(shape, stack);
+    let mut placements: Vec<Placement> = vec![];
+
+    for rotation in 0..4 {
+        let mut piece = Piece::new(shape, stack);
+        for _ in 0..rotation {
+            piece.rotate_with(true, true, stack);
+        }
+        while piece.shift(-1, 0, stack) {}
+
+        loop {
+            let cells = dropped_cells(&piece, stack);
+            if !placements.iter().any(|p| p.cells == cells) {
+                placements.push(Placement {
+                    shape,
+                    index: placements.len(),
+                    optimal: optimal.iter().find(|o| o.0 == cells).map_or(0, |o| o.1),
+                    cells,
+                });
+            }
+
+            if !piece.shift(1, 0, stack) {
+                break;
+            }
+        }
+    }
+
+    placements
+}
+
+/// Fewest inputs to every reachable placement, found by searching from the spawn
+fn fewest_inputs(shape: ShapeType, stack: &Stack) -> Vec<(Vec<(i32, i32)>, u32)> {
+    let spawn = Piece::new(shape, stack);
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    let mut found: Vec<(Vec<(i32, i32)>, u32)> = vec![];
+
+    let state = spawn.state();
+    visited.insert((state.x, state.y, state.rotation));
+    queue.push_back((spawn, 0));
+
+    while let Some((piece, inputs)) = queue.pop_front() {
+        let cells = dropped_cells(&piece, stack);
+        if !found.iter().any(|f| f.0 == cells) {
+            // The hard drop is an input too
+            found.push((cells, inputs + 1));
+        }
+
+        for &m in &MOVES {
+            let mut next = piece.clone();
+            if !m.apply(&mut next, stack) {
+                continue;
+            }
+
+            let state = next.state();
+            if visited.insert((state.x, state.y, state.rotation)) {
+                queue.push_back((next, inputs + 1));
+            }
+        }
+    }
+
+    found
+}
+
+/// Tries of a single placement
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+#[serde(default)]
+pub struct FinesseRecord {
+    pub tries: u32,
+    pub total: Duration,
+    /// Tries taking more inputs than needed
+    pub faults: u32,
+}
+
+impl FinesseRecord {
+    pub fn add(&mut self, time: Duration, inputs: u32, optimal: u32) {
+        self.tries += 1;
+        self.total += time;
+        if inputs > optimal {
+            self.faults += 1;
+        }
+    }
+
+    pub fn average(&self) -> Duration {
+        if self.tries == 0 {
+            Duration::new(0, 0)
+        } else {
+            self.total / self.tries
+        }
+    }
+}
+
+/// Placements with the slowest average times first, only the ones tried already
+pub fn weak_spots<'a>(
+    placements: &'a [Placement],
+    records: &BTreeMap<String, FinesseRecord>,
+) -> Vec<(&'a Placement, FinesseRecord)> {
+    let mut tried: Vec<(&Placement, FinesseRecord)> = placements
+        .iter()
+        .filter_map(|p| records.get(&p.key()).map(|r| (p, r.clone())))
+        .filter(|(_, r)| r.tries > 0)
+        .collect();
+
+    tried.sort_by(|a, b| b.1.average().cmp(&a.1.average()));
+    tried
+}
+
+#[test]
+fn finesse_test() {
+    let stack = Stack::new(10, 20, 20);
+
+    let counts: Vec<usize> = [ShapeType::T, ShapeType::I, ShapeType::O, ShapeType::S]
+        .iter()
+        .map(|&shape| placements(shape, &stack).len())
+        .collect();
+    assert_eq!(counts, vec![34, 17, 9, 17]);
+
+    // Nothing is further than a few inputs away, and every placement needs the drop
+    for placement in placements(ShapeType::T, &stack) {
+        assert!(placement.optimal >= 1 && placement.optimal <= 5);
+    }
+
+    let placements = placements(ShapeType::O, &stack);
+    let mut records = BTreeMap::new();
+    let mut slow = FinesseRecord::default();
+    slow.add(Duration::from_millis(900), 3, 2);
+    slow.add(Duration::from_millis(700), 2, 2);
+    let mut fast = FinesseRecord::default();
+    fast.add(Duration::from_millis(300), 1, 1);
+    records.insert(placements[0].key(), fast);
+    records.insert(placements[4].key(), slow.clone());
+
+    let weak = weak_spots(&placements, &records);
+    assert_eq!(weak.len(), 2);
+    assert_eq!(weak[0].0.index, 4);
+    assert_eq!(weak[0].1.average(), Duration::from_millis(800));
+    assert_eq!(slow.faults, 1);
+}
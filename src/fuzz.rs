@@ -0,0 +1,118 @@
+//! Drives the headless engine with random actions and checks the rules after every step
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    action::Action,
+    blocks::CELLS_NUM,
+    engine::{Engine, Event},
+    rules::Rules,
+    settings,
+    stack::GarbageRow,
+};
+
+const ACTIONS: [Action; 12] = [
+    Action::MoveRight,
+    Action::MoveLeft,
+    Action::MoveDown,
+    Action::RotateClockwise,
+    Action::RotateCounterClockwise,
+    Action::Rotate180,
+    Action::HardDrop,
+    Action::SoftDrop,
+    Action::HoldPiece,
+    Action::FallPiece,
+    Action::ShiftRight,
+    Action::ShiftLeft,
+];
+
+/// Processes the action along with everything it queued, e.g. locking after a hard drop
+fn apply(engine: &mut Engine, settings: &settings::Gameplay, action: Action) -> Vec<Event> {
+    engine.skip(settings, action);
+    while let Some(action) = engine.pop_action() {
+        engine.skip(settings, action);
+    }
+
+    let mut events = vec![];
+    engine.events(&mut events);
+    events
+}
+
+fn check_invariants(engine: &Engine) {
+    let stack = &engine.stack;
+    let grid = stack.grid();
+
+    assert_eq!(grid.len(), (stack.height + stack.vanish) as usize);
+    for row in grid {
+        assert_eq!(row.len(), stack.width as usize);
+        assert!(row.iter().all(|&b| b < CELLS_NUM));
+    }
+
+    if !engine.game_over() {
+        assert!(!stack.collision(&engine.piece), "Piece overlaps the stack");
+    }
+}
+
+fn fuzz(seed: u64, steps: usize) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let settings = settings::Gameplay::default();
+
+    let mut engine = Engine::new(&rng.gen(), true, &Rules::default());
+    let mut score = 0;
+    let mut lines = 0;
+
+    for _ in 0..steps {
+        if rng.gen_ratio(1, 50) {
+            let hole = rng.gen_range(0, engine.stack.width as usize);
+            engine.insert_garbage(&[GarbageRow { hole }]);
+            apply(&mut engine, &settings, Action::FallPiece);
+        }
+
+        let action = ACTIONS[rng.gen_range(0, ACTIONS.len())];
+        let hold_locked = engine.holder.locked();
+
+        let events = apply(&mut engine, &settings, action);
+        check_invariants(&engine);
+
+        if engine.game_over() {
+            engine = Engine::new(&rng.gen(), true, &Rules::default());
+            score = 0;
+            lines = 0;
+            continue;
+        }
+
+        assert!(engine.score.score() >= score, "Score decreased");
+        assert!(engine.score.lines() >= lines, "Lines decreased");
+        score = engine.score.score();
+        lines = engine.score.lines();
+
+        if action == Action::HoldPiece {
+            if hold_locked {
+                assert!(events.contains(&Event::HoldFailed));
+            } else {
+                assert!(events.contains(&Event::Held));
+                assert!(engine.holder.locked());
+                assert!(engine.holder.shape_type().is_some());
+            }
+        }
+
+        let locked = events.iter().any(|e| match e {
+            Event::Locked { .. } => true,
+            _ => false,
+        });
+
+        if locked {
+            assert!(
+                !engine.holder.locked(),
+                "Hold not unlocked for the next piece"
+            );
+        }
+    }
+}
+
+#[test]
+fn engine_fuzz_test() {
+    for seed in 0..8 {
+        fuzz(seed, 5000);
+    }
+}
@@ -1,89 +1,79 @@
 use std::{
-    env, fs,
-    path::PathBuf,
+    fs,
     time::{Duration, Instant},
 };
 
-use chrono::Utc;
-use dirs;
 use ggez::{
-    audio::{self, SoundSource},
-    event::{self, EventHandler, KeyMods, MouseButton},
-    graphics::{self, Image, Rect},
-    input::keyboard::KeyCode,
-    nalgebra::{Point2, Vector2},
+    event::{self, Axis, Button, EventHandler, KeyMods, MouseButton},
+    graphics::{self, Color, Image, Rect},
+    input::{gamepad::GamepadId, keyboard::KeyCode},
+    nalgebra::Vector2,
     timer, Context, GameResult,
 };
-use rand::{thread_rng, RngCore};
 
 use crate::{
-    gameplay::Gameplay,
+    args::Args,
+    camera::Camera,
+    feed::Feed,
     global::Global,
     imgui_wrapper::ImGuiWrapper,
+    input::GamepadButton,
+    music::Music,
     particles::ParticleAnimation,
-    replay::{Replay, ReplayData},
-    utils,
+    postprocess::PostProcess,
+    replay::ReplayData,
+    scene::{MenuScene, PlayScene, Scene, SceneStack, WatchScene},
 };
 
 pub struct Game {
     pub g: Global,
-    gameplay: Gameplay,
-    game_over: bool,
+    scenes: SceneStack,
     background: Image,
     particle_animation: ParticleAnimation,
-    music: audio::Source,
+    post_process: PostProcess,
+    camera: Camera,
+    music: Music,
+    feed: Feed,
 
     imgui_wrapper: ImGuiWrapper,
     is_fullscreen: bool,
     fullscreen_delay: Duration,
-
-    replay: Option<Replay>,
 }
 
 impl Game {
-    pub fn new(ctx: &mut Context, mut g: Global) -> GameResult<Game> {
-        let mut replay = None;
-        if let Some(path) = env::args().nth(1) {
-            let path = PathBuf::from(path);
-            if path.is_file() {
-                if let Some(replay_data) = ReplayData::load(&path) {
-                    if let Ok(r) = Replay::new(ctx, &mut g, replay_data) {
-                        replay = Some(r);
-                    }
-                }
+    pub fn new(ctx: &mut Context, mut g: Global, args: &Args) -> GameResult<Game> {
+        let mut root: Box<dyn Scene> = Box::new(MenuScene::new());
+        if let Some(path) = &args.replay {
+            if let Some(replay_data) = ReplayData::load(path) {
+                let mut scene = WatchScene::new(ctx, &mut g, replay_data)?;
+                scene.set_path(Some(path.clone()));
+                root = Box::new(scene);
             }
+        } else if args.start_game() {
+            let mode = args.mode.unwrap_or_default();
+            root = Box::new(PlayScene::new(ctx, &mut g, mode, args.seed, mode.rules())?);
         }
 
-        let mut seed = [0u8; 32];
-        thread_rng().fill_bytes(&mut seed);
-
-        let gameplay = Gameplay::new(ctx, &mut g, true, &seed)?;
-
-        let rect = graphics::screen_coordinates(ctx);
-        let particle_animation = ParticleAnimation::new(200, 80.0, rect.w, rect.h);
-
-        let mut music = audio::Source::new(ctx, utils::path(ctx, "chiptronical.ogg"))?;
-        music.set_repeat(true);
-        music.set_volume(g.settings.audio.music_volume as f32 / 100.0);
-        music.play()?;
+        let particle_animation = ParticleAnimation::new(ctx, 200, 80.0)?;
 
-        let mut path = dirs::data_local_dir().unwrap_or_default();
-        path.push("klocki");
-        path.push("replays");
+        let path = ReplayData::directory();
         fs::create_dir_all(&path)
             .unwrap_or_else(|e| log::warn!("Unable to create directory {:?}: {:?}", &path, e));
 
+        let background = g.assets.image(ctx, "background.jpg")?;
+
         let mut app = Game {
             g,
-            gameplay,
-            game_over: false,
-            background: Image::new(ctx, utils::path(ctx, "background.jpg"))?,
+            scenes: SceneStack::new(root),
+            background,
             particle_animation,
-            music,
+            post_process: PostProcess::new(ctx)?,
+            camera: Camera::default(),
+            music: Music::new(ctx)?,
+            feed: Feed::default(),
             imgui_wrapper: ImGuiWrapper::new(ctx),
             is_fullscreen: false,
             fullscreen_delay: Duration::new(0, 0),
-            replay,
         };
 
         app.resize_event(
@@ -94,6 +84,14 @@ impl Game {
 
         Ok(app)
     }
+
+    fn button_down(&mut self, ctx: &mut Context, button: GamepadButton) {
+        self.scenes.button_down(ctx, &mut self.g, button);
+
+        if let Some(gameplay) = self.scenes.gameplay() {
+            gameplay.button_down(&self.g, button);
+        }
+    }
 }
 
 impl EventHandler for Game {
@@ -115,14 +113,6 @@ impl EventHandler for Game {
             self.fullscreen_delay += timer::delta(ctx);
         }
 
-        if self.g.imgui_state.restart {
-            let mut seed = [0u8; 32];
-            thread_rng().fill_bytes(&mut seed);
-
-            self.gameplay = Gameplay::new(ctx, &mut self.g, true, &seed)?;
-            self.game_over = false;
-        }
-
         if self.g.settings_state.restart {
             event::quit(ctx);
         }
@@ -131,54 +121,80 @@ impl EventHandler for Game {
             self.particle_animation.update(ctx)?;
         }
 
-        if (self.music.volume() * 100.0) as u32 != self.g.settings.audio.music_volume {
-            self.music
-                .set_volume(self.g.settings.audio.music_volume as f32 / 100.0);
-        }
+        self.post_process.update(ctx);
 
         if self.g.sfx.volume() != self.g.settings.audio.sfx_volume {
             self.g.sfx.set_volume(self.g.settings.audio.sfx_volume);
         }
 
-        let mut gameplay = &mut self.gameplay;
+        if self.g.sfx.mix() != &self.g.settings.audio.sounds {
+            self.g.sfx.set_mix(&self.g.settings.audio.sounds);
+        }
 
-        if let Some(replay) = &mut self.replay {
-            if !replay.gameplay.paused() && !self.g.imgui_state.paused {
-                replay.update(ctx);
-            }
-            gameplay = &mut replay.gameplay;
+        if self.g.sfx.max_voices() != self.g.settings.audio.sfx_voices {
+            self.g.sfx.set_max_voices(self.g.settings.audio.sfx_voices);
         }
 
-        gameplay.update(ctx, &mut self.g, true)?;
+        self.g.clock.update(timer::delta(ctx));
+        self.scenes.update(ctx, &mut self.g)?;
+        self.g.mouse_wheel = 0.0;
+
+        self.music
+            .update(ctx, self.scenes.music(), &self.g.settings.audio)?;
 
-        if let Some(explosion) = gameplay.explosion() {
-            self.particle_animation.explode(explosion);
+        let mut danger = 0.0;
+        if let Some(gameplay) = self.scenes.gameplay() {
+            if let Some(explosion) = gameplay.explosion() {
+                self.particle_animation.explode(explosion);
+                self.post_process.explode(explosion.strength / 30.0);
+            }
+
+            if let Some(strength) = gameplay.punch() {
+                self.camera.punch(strength);
+            }
+
+            danger = gameplay.danger();
         }
 
-        if self.replay.is_none() {
-            if self.gameplay.game_over() && !self.game_over {
-                self.game_over = true;
-                self.g.imgui_state.game_over_window = true;
-                self.g.imgui_state.replay_score = self.gameplay.score();
+        let dt = self.g.clock.delta();
+        let feed = &self.g.settings.feed;
+        let interval = Duration::from_millis(feed.interval.into());
+        if self.feed.update(dt, feed.enabled, interval) {
+            if let Some(gameplay) = self.scenes.gameplay() {
+                let delay = Duration::from_millis(feed.delay.into());
+                self.feed.send(&gameplay.feed_frame(), &feed.address, delay);
             }
+        }
 
-            if self.g.imgui_state.save_replay {
-                self.g.imgui_state.save_replay = false;
-                let mut path = dirs::data_local_dir().unwrap_or_default();
-                path.push("klocki");
-                path.push("replays");
-                path.push(format!(
-                    "Score {} - {}.klocki",
-                    self.gameplay.score(),
-                    Utc::now().format("%Y%m%d_%H%M%S"),
-                ));
-
-                self.gameplay.replay_data().save(&path);
-                ReplayData::load(&path).unwrap();
+        let reminder = match self.g.settings.gameplay.break_reminder {
+            0 => None,
+            minutes => Some(Duration::from_secs(u64::from(minutes) * 60)),
+        };
+        let gameplay = self.scenes.gameplay();
+        let playing = gameplay.as_ref().map_or(false, |g| g.playing());
+        if self.g.session.update(dt, playing, reminder) {
+            if let Some(gameplay) = gameplay {
+                gameplay.remind_break(self.g.session.played());
             }
         }
 
+        // The layout stays put in capture mode
+        let graphics = &self.g.settings.graphics;
+        if graphics.capture_mode {
+            self.g.settings_state.edit_hud = false;
+        }
+
+        let camera = graphics.camera && !graphics.capture_mode;
+        self.camera.update(ctx, camera, danger);
+
+        self.g.profiler.particles = if self.g.settings.graphics.animated_background {
+            self.particle_animation.count()
+        } else {
+            0
+        };
+
         self.g.imgui_state.update.push(start.elapsed());
+        self.g.profiler.update(start.elapsed());
         Ok(())
     }
 
@@ -187,51 +203,64 @@ impl EventHandler for Game {
 
         graphics::clear(ctx, graphics::WHITE);
 
+        let post_effects = self.g.settings.graphics.post_effects;
+        self.post_process.begin(ctx, &post_effects);
+
         let screen = graphics::screen_coordinates(ctx);
         let ratio = screen.w / screen.h;
 
-        graphics::draw(
-            ctx,
-            &self.background,
-            graphics::DrawParam::new().scale(Vector2::new(
-                if ratio > (21.0 / 9.0) {
-                    ratio / (21.0 / 9.0)
-                } else {
-                    1.0
-                },
-                1.0,
-            )),
-        )?;
-
-        if self.g.settings.graphics.animated_background {
-            self.particle_animation.draw(ctx)?;
+        let settings = &self.g.settings.graphics;
+        if settings.capture_mode && settings.chroma_key {
+            let [r, g, b] = settings.chroma_color;
+            graphics::clear(ctx, Color::from_rgb(r, g, b));
+        } else {
+            graphics::draw(
+                ctx,
+                &self.background,
+                graphics::DrawParam::new().scale(Vector2::new(
+                    if ratio > (21.0 / 9.0) {
+                        ratio / (21.0 / 9.0)
+                    } else {
+                        1.0
+                    },
+                    1.0,
+                )),
+            )?;
+
+            if settings.animated_background {
+                self.particle_animation.draw(ctx)?;
+            }
         }
 
-        let screen = graphics::screen_coordinates(ctx);
-        let position_center = Point2::new(
-            (screen.w - (self.gameplay.stack.width * self.g.settings.gameplay.block_size) as f32)
-                / 2.0,
-            (screen.h - (self.gameplay.stack.height * self.g.settings.gameplay.block_size) as f32)
-                / 2.0,
-        );
-
-        let gameplay = if let Some(replay) = &mut self.replay {
-            &mut replay.gameplay
-        } else {
-            &mut self.gameplay
-        };
+        self.camera.push(ctx)?;
+        self.scenes.draw(ctx, &mut self.g)?;
+        self.camera.pop(ctx)?;
 
-        gameplay.draw(ctx, &self.g, position_center)?;
+        self.post_process.finish(ctx, &post_effects)?;
 
-        self.imgui_wrapper.draw(ctx, &mut self.g);
+        self.imgui_wrapper.draw(ctx, &mut self.g, &mut self.scenes);
 
         self.g.imgui_state.draw.push(start.elapsed());
+        self.g.profiler.draw(start.elapsed());
 
         graphics::present(ctx)?;
+
+        if let Some(gameplay) = self.scenes.gameplay() {
+            if let Some(pressed_at) = gameplay.input_pressed_at() {
+                self.g.profiler.latency(pressed_at.elapsed());
+            }
+        }
+
         Ok(())
     }
 
-    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, _x: f32, _y: f32) {
+    fn mouse_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: MouseButton,
+        _x: f32,
+        _y: f32,
+    ) {
         self.imgui_wrapper.update_mouse_down((
             button == MouseButton::Left,
             button == MouseButton::Right,
@@ -255,25 +284,97 @@ impl EventHandler for Game {
 
     fn mouse_wheel_event(&mut self, _ctx: &mut Context, _x: f32, y: f32) {
         self.imgui_wrapper.update_mouse_scroll(y);
+
+        if !self.imgui_wrapper.want_capture_mouse() {
+            self.g.mouse_wheel += y;
+        }
     }
 
-    fn key_up_event(&mut self, ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods) {
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, btn: Button, _id: GamepadId) {
+        if let Some(button) = GamepadButton::from_button(btn) {
+            self.g.gamepad.button(button, true);
+            self.button_down(ctx, button);
+        }
+    }
+
+    fn gamepad_button_up_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
+        if let Some(button) = GamepadButton::from_button(btn) {
+            self.g.gamepad.button(button, false);
+        }
+    }
+
+    fn gamepad_axis_event(&mut self, ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        // Up is positive
+        if axis == Axis::LeftStickY {
+            self.g.stick = (-value).max(0.0);
+        }
+
+        if let Some(button) = self.g.gamepad.axis(axis, value) {
+            self.button_down(ctx, button);
+        }
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        keymods: KeyMods,
+        _repeat: bool,
+    ) {
+        self.imgui_wrapper.update_key(keycode, keymods, true);
+
+        if !self.imgui_wrapper.want_capture_keyboard() {
+            self.scenes.key_down(&mut self.g, keycode);
+
+            if let Some(gameplay) = self.scenes.gameplay() {
+                gameplay.key_down(&self.g, keycode);
+            }
+        }
+    }
+
+    fn key_up_event(&mut self, ctx: &mut Context, keycode: KeyCode, keymods: KeyMods) {
+        self.imgui_wrapper.update_key(keycode, keymods, false);
+
+        if self.imgui_wrapper.want_capture_keyboard() {
+            return;
+        }
+
         match keycode {
             KeyCode::F11 => self.g.settings.graphics.fullscreen ^= true,
+            KeyCode::F3 => self.g.settings.graphics.profiler ^= true,
             KeyCode::D => self.imgui_wrapper.toggle_window(),
-            KeyCode::Escape => event::quit(ctx),
+            KeyCode::Escape => self.scenes.back(ctx),
             KeyCode::LAlt => self.g.settings.graphics.hide_menu ^= true,
+            KeyCode::F10 => {
+                let graphics = &mut self.g.settings.graphics;
+                graphics.capture_mode ^= true;
+
+                if graphics.capture_mode {
+                    if let Some(region) = self.scenes.gameplay().and_then(|g| g.region()) {
+                        log::info!("Capture mode on, the board is at {:?}", region);
+                    }
+                }
+            }
             _ => (),
         };
     }
 
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        self.imgui_wrapper.update_text(character);
+    }
+
     fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
         self.g.settings.graphics.window_size.0 = width as u32;
         self.g.settings.graphics.window_size.1 = height as u32;
 
         let ratio = width / height;
         let width = 1080.0 * ratio;
-        graphics::set_screen_coordinates(ctx, Rect::new(0.0, 0.0, width, 1080.0))
+        self.g.screen = Rect::new(0.0, 0.0, width, 1080.0);
+        graphics::set_screen_coordinates(ctx, self.g.screen)
             .expect("Unable to change the coordinates");
+
+        self.post_process
+            .resize(ctx)
+            .unwrap_or_else(|e| log::error!("Unable to resize post processing: {:?}", e));
     }
 }
@@ -1,29 +1,61 @@
-use std::{collections::VecDeque, time::Duration};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    mem,
+    time::{Duration, Instant},
+};
 
 use ggez::{
-    graphics::{self, Color, Font, Scale},
-    input::{keyboard::KeyCode, mouse},
+    graphics::{
+        self, Color, DrawMode, DrawParam, Font, Mesh, MeshBuilder, Rect, Scale, Text, TextFragment,
+    },
+    input::{
+        keyboard::{self, KeyCode, KeyMods},
+        mouse,
+    },
     nalgebra::{Point2, Vector2},
-    timer, Context, GameResult,
+    Context, GameResult,
 };
+use imgui::ImString;
+use rand::Rng;
 
 use crate::{
     action::Action,
-    bag::Bag,
+    bag,
     blocks::Blocks,
+    crash,
+    engine::{BoardState, Engine, Event, Snapshot},
+    feed::FeedFrame,
     global::Global,
-    holder::Holder,
-    input::Input,
+    handicap::Handicaps,
+    hints::{self, Hint, Hints},
+    hud::{HudElement, HUD_ELEMENTS},
+    input::{self, ControlScheme, GamepadButton, Input, MouseInput},
+    macros::{Macro, Recorder},
+    metronome::Metronome,
+    mode::{RaceResult, WinCondition},
+    objectives::{Objective, Objectives},
     particles::Explosion,
     piece::Piece,
     popups::Popup,
     popups::Popups,
-    replay::ReplayData,
-    score::Score,
-    stack::{Locked, Stack},
+    replay::{ReplayData, ReplayResults},
+    rules::Rules,
+    shape,
+    stack::GarbageRow,
+    stats::{Clears, Dig},
     utils,
 };
 
+/// Rows of the vanish zone shown while holding the peek key
+const PEEK_ROWS: i32 = 4;
+
+/// Restore a snapshot in practice games, or save it while holding control
+const SNAPSHOT_KEYS: [KeyCode; 4] = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4];
+
+/// Time between the rows a piece sinks by with auto soft drop
+const AUTO_SOFT_DROP_INTERVAL: Duration = Duration::from_millis(50);
+
 #[derive(PartialEq)]
 enum Countdown {
     Waiting,
@@ -32,31 +64,61 @@ enum Countdown {
 }
 
 pub struct Gameplay {
-    interactive: bool,
     input: Input,
+    mouse: MouseInput,
+    position: Option<Point2<f32>>,
+    /// Where the board was drawn last, in screen coordinates
+    region: Option<Rect>,
+    keyboard: bool,
+    /// Board of an opponent whose next pieces and hold are kept secret
+    hidden: bool,
+    practice: bool,
+    casual: bool,
+    controls: Option<&'static ControlScheme>,
+    recorder: Recorder,
+    block_size: Option<i32>,
     action_duration: Duration,
-
-    actions: VecDeque<Action>,
     replay: ReplayData,
 
-    pub stack: Stack,
-    bag: Bag,
-    piece: Piece,
-    piece_visible: bool,
-    holder: Holder,
-    score: Score,
+    pub engine: Engine,
+    events: Vec<Event>,
     popups: Popups,
 
-    game_over: bool,
-    falling: Duration,
-    fall_interval: Duration,
-
-    piece_entering: Option<Duration>,
-
     font: Font,
     blocks: Blocks,
 
     explosion: Option<Explosion>,
+    punch: Option<f32>,
+    attack: i32,
+    /// Every line sent in the game, the attack above is taken by the opponents
+    attack_sent: i32,
+    /// Pieces locked without clearing since the last call, when garbage of the opponents
+    /// can rise under rules waiting for a lock
+    clean_locks: u32,
+    spawned: u32,
+    attack_target: Option<Vector2<f32>>,
+    /// Element dragged in the HUD editor, along with where it was grabbed
+    hud_drag: Option<(HudElement, Vector2<f32>)>,
+    metronome: Metronome,
+    /// Time since the last hard drop of the player
+    since_hard_drop: Option<Duration>,
+    /// Time since the last piece locked, while the stack flashes after locks
+    since_lock: Duration,
+    auto_soft_drop: Duration,
+    auto_pause: bool,
+    idle: Duration,
+    idle_paused: bool,
+    wake: bool,
+    clears: Clears,
+    dig: Dig,
+    objectives: Objectives,
+    win_condition: Option<WinCondition>,
+    won: Option<RaceResult>,
+    /// Time the game ran for, without the pauses
+    time: Duration,
+    snapshots: [Option<Snapshot>; 4],
+    snapshot_key: Option<usize>,
+    hints: Hints,
     countdown: Countdown,
     countdown_switch: Duration,
 }
@@ -67,67 +129,116 @@ impl Gameplay {
         g: &mut Global,
         interactive: bool,
         seed: &[u8; 32],
+        rules: &Rules,
     ) -> GameResult<Gameplay> {
-        let mut input = Input::new();
-        input
-            .bind(KeyCode::Right, Action::MoveRight, true)
-            .bind(KeyCode::Left, Action::MoveLeft, true)
-            .bind(KeyCode::Down, Action::MoveDown, true)
-            .bind(KeyCode::Up, Action::RotateClockwise, false)
-            .bind(KeyCode::X, Action::RotateClockwise, false)
-            .bind(KeyCode::Z, Action::RotateCounterClockwise, false)
-            .bind(KeyCode::Space, Action::HardDrop, false)
-            .bind(KeyCode::LShift, Action::SoftDrop, false)
-            .bind(KeyCode::C, Action::HoldPiece, false)
-            .exclude(KeyCode::Right, KeyCode::Left)
-            .exclude(KeyCode::Left, KeyCode::Right);
-
-        let actions = VecDeque::new();
-        let replay = ReplayData::new(seed);
-
-        let stack = Stack::new(10, 20, 20);
-
-        let mut bag = Bag::new(seed);
-        let piece = Piece::new(bag.pop(), &stack);
-        let holder = Holder::default();
-        let score = Score::default();
-        let popups = Popups::new(ctx)?;
-
-        let font = Font::new(ctx, utils::path(ctx, "fonts/bold.ttf"))?;
-
-        let blocks = Blocks::new(g.settings.tileset(ctx, &g.settings_state)?);
+        let input = Input::from_bindings(&g.settings.input.bindings);
+
+        let replay = ReplayData::new(seed, rules);
+
+        if interactive {
+            crash::start_game(seed, rules, &g.settings);
+        }
+
+        let font = g.assets.font(ctx, "fonts/bold.ttf")?;
+        let popups = Popups::new(font);
+
+        let tileset = g.settings.tileset(ctx, &g.settings_state, &mut g.assets)?;
+        let blocks = Blocks::new(ctx, tileset);
 
         Ok(Gameplay {
-            interactive,
             input,
+            mouse: MouseInput::default(),
+            position: None,
+            region: None,
+            keyboard: true,
+            hidden: false,
+            practice: false,
+            casual: false,
+            controls: None,
+            recorder: Recorder::default(),
+            block_size: None,
             action_duration: Duration::new(0, 0),
-            actions,
             replay,
-            stack,
-            bag,
-            piece,
-            piece_visible: true,
-            holder,
-            score,
+            engine: Engine::new(seed, interactive, rules),
+            events: vec![],
             popups,
-            game_over: false,
-            falling: Duration::new(0, 0),
-            fall_interval: Duration::from_secs(1),
-            piece_entering: None,
             font,
             blocks,
             explosion: None,
+            punch: None,
+            attack: 0,
+            attack_sent: 0,
+            clean_locks: 0,
+            spawned: 1,
+            attack_target: None,
+            hud_drag: None,
+            metronome: Metronome::default(),
+            since_hard_drop: None,
+            since_lock: Duration::new(0, 0),
+            auto_soft_drop: Duration::new(0, 0),
+            auto_pause: false,
+            idle: Duration::new(0, 0),
+            idle_paused: false,
+            wake: false,
+            clears: Clears::default(),
+            dig: Dig::default(),
+            objectives: Objectives::default(),
+            win_condition: None,
+            won: None,
+            time: Duration::new(0, 0),
+            snapshots: Default::default(),
+            snapshot_key: None,
+            hints: Hints::default(),
             countdown: Countdown::Waiting,
             countdown_switch: Duration::new(0, 0),
         })
     }
 
-    fn reset_fall(&mut self) {
-        if self.falling > self.fall_interval {
-            self.falling -= self.fall_interval
-        } else {
-            self.falling = Duration::new(0, 0);
-        }
+    pub fn reset(&mut self, seed: &[u8; 32]) {
+        self.action_duration = Duration::new(0, 0);
+        self.replay = ReplayData::new(seed, self.engine.rules());
+        self.engine.reset(seed);
+        self.recorder.stop();
+        self.metronome.reset();
+        self.since_hard_drop = None;
+        self.since_lock = Duration::new(0, 0);
+        self.auto_soft_drop = Duration::new(0, 0);
+        self.idle = Duration::new(0, 0);
+        self.idle_paused = false;
+        self.wake = false;
+        self.popups.clear();
+        self.explosion = None;
+        self.punch = None;
+        self.attack = 0;
+        self.attack_sent = 0;
+        self.clean_locks = 0;
+        self.spawned = 1;
+        self.clears = Clears::default();
+        self.dig = Dig::default();
+        self.objectives.clear();
+        self.won = None;
+        self.time = Duration::new(0, 0);
+        self.snapshots = Default::default();
+        self.snapshot_key = None;
+        self.hints.dismiss();
+        self.countdown = Countdown::Waiting;
+        self.countdown_switch = Duration::new(0, 0);
+    }
+
+    pub fn skip_countdown(&mut self) {
+        self.countdown = Countdown::Finished;
+    }
+
+    pub fn counting_down(&self) -> bool {
+        self.countdown != Countdown::Finished
+    }
+
+    /// Processes an action instantly, skipping clear and entry delays.
+    /// Returns the number of cleared rows.
+    pub fn skip(&mut self, g: &mut Global, action: Action) -> i32 {
+        let rows = self.engine.skip(&g.settings.gameplay, action);
+        self.engine.events(&mut self.events);
+        rows
     }
 
     pub fn explode(&mut self, color: Color) {
@@ -139,209 +250,550 @@ impl Gameplay {
     }
 
     pub fn action(&mut self, action: Action, immediate: bool) {
-        if immediate {
-            self.actions.push_front(action);
-        } else {
-            self.actions.push_back(action);
+        self.engine.action(action, immediate);
+    }
+
+    /// Presses of the bound keys and buttons since the last call
+    pub fn take_presses(&mut self) -> u32 {
+        self.input.take_presses()
+    }
+
+    /// Gamepad button press, including the stick and d-pad directions
+    pub fn button_down(&mut self, g: &Global, button: GamepadButton) {
+        if !self.keyboard {
+            return;
+        }
+
+        self.input
+            .key_down(button.into(), g.settings.input.low_latency);
+
+        self.idle = Duration::new(0, 0);
+        self.wake = self.idle_paused;
+    }
+
+    pub fn key_down(&mut self, g: &Global, keycode: KeyCode) {
+        if !self.keyboard {
+            return;
+        }
+
+        self.input
+            .key_down(keycode.into(), g.settings.input.low_latency);
+
+        if keycode == KeyCode::Return {
+            self.hints.dismiss();
+        }
+
+        if self.practice {
+            if let Some(slot) = SNAPSHOT_KEYS.iter().position(|&k| k == keycode) {
+                self.snapshot_key = Some(slot);
+            }
+        }
+
+        self.idle = Duration::new(0, 0);
+        self.wake = self.idle_paused;
+
+        if let Some(m) = g.settings.input.macros.iter().find(|m| m.key == keycode) {
+            self.play_macro(m);
         }
     }
 
-    pub fn actions(&mut self, actions: &[Action]) {
-        for &action in actions {
-            self.action(action, false);
+    /// Queues the whole sequence behind any pending actions.
+    /// The queue waits for entering pieces, so sequences spanning several pieces play out in order.
+    fn play_macro(&mut self, m: &Macro) {
+        if !self.practice || self.paused() || self.recorder.recording() {
+            return;
+        }
+
+        log::debug!("Playing macro {} with {} actions", m.name, m.actions.len());
+        for &action in m.actions.iter().filter(|&&a| Macro::recordable(a)) {
+            self.engine.action(action, false);
         }
     }
 
+    /// Saves the game to the slot, or brings it back to what was saved there
+    fn snapshot(&mut self, slot: usize, save: bool) {
+        let text = if save {
+            match self.engine.snapshot() {
+                Some(snapshot) => {
+                    self.snapshots[slot] = Some(snapshot);
+                    format!("Snapshot {} saved", slot + 1)
+                }
+                None => String::from("Unable to save right now"),
+            }
+        } else {
+            match &self.snapshots[slot] {
+                Some(snapshot) if self.engine.restore(snapshot) => {
+                    self.since_hard_drop = None;
+                    format!("Snapshot {} restored", slot + 1)
+                }
+                Some(_) => String::from("Unable to restore right now"),
+                None => format!("Snapshot {} is empty", slot + 1),
+            }
+        };
+
+        let mut popup = Popup::new(Duration::from_secs(1));
+        popup.add(text, Color::new(0.8, 0.9, 1.0, 1.0), 1.5);
+        self.popups.add(popup);
+    }
+
+    /// Macros are only recorded and played back in practice games
+    pub fn set_practice(&mut self, practice: bool) {
+        self.practice = practice;
+    }
+
+    /// Hard drops can only be undone in casual games
+    pub fn set_casual(&mut self, casual: bool) {
+        self.casual = casual;
+    }
+
+    /// Single player games pause themselves when nobody plays, instead of being lost to gravity
+    pub fn set_auto_pause(&mut self, auto_pause: bool) {
+        self.auto_pause = auto_pause;
+    }
+
+    /// Replaces the bindings with the ones from the settings, adjusted by the mode
+    pub fn set_controls(&mut self, g: &Global, controls: Option<&'static ControlScheme>) {
+        let bindings = &g.settings.input.bindings;
+        self.input = match controls {
+            Some(controls) => Input::from_bindings(&controls.apply(bindings)),
+            None => Input::from_bindings(bindings),
+        };
+        self.controls = controls;
+    }
+
+    /// Note left in the replay, shown when playback gets to it
+    pub fn show_marker(&mut self, text: &str) {
+        let mut popup = Popup::new(Duration::from_secs(3));
+        popup.add(text.to_string(), Color::new(1.0, 0.9, 0.4, 1.0), 1.5);
+        self.popups.add(popup);
+    }
+
+    /// Bindings of its own instead of the ones from the settings, for boards sharing the keyboard
+    pub fn set_input(&mut self, input: Input) {
+        self.input = input;
+        self.controls = None;
+    }
+
+    pub fn recording(&self) -> bool {
+        self.recorder.recording()
+    }
+
+    pub fn start_recording(&mut self) {
+        if self.practice {
+            self.recorder.start();
+        }
+    }
+
+    pub fn stop_recording(&mut self) -> Vec<Action> {
+        self.recorder.stop()
+    }
+
+    /// Time of the press behind the latest action, taken once the frame showing it is presented
+    pub fn input_pressed_at(&mut self) -> Option<Instant> {
+        self.input.take_emitted_at()
+    }
+
     pub fn explosion(&mut self) -> Option<Explosion> {
         let result = self.explosion;
         self.explosion = None;
         result
     }
 
+    pub fn punch(&mut self) -> Option<f32> {
+        self.punch.take()
+    }
+
+    /// Garbage lines sent since the last call
+    pub fn take_attack(&mut self) -> i32 {
+        std::mem::replace(&mut self.attack, 0)
+    }
+
+    /// Pieces locked without clearing since the last call
+    pub fn take_clean_locks(&mut self) -> u32 {
+        std::mem::replace(&mut self.clean_locks, 0)
+    }
+
+    /// Offset from the middle of the board to the opponent, where attack numbers fly to
+    pub fn set_attack_target(&mut self, target: Option<Vector2<f32>>) {
+        self.attack_target = target;
+    }
+
+    /// Boards controlled by bots or other players ignore the keyboard
+    pub fn set_keyboard(&mut self, enabled: bool) {
+        self.keyboard = enabled;
+    }
+
+    /// Leaves out the next pieces and the hold, for opponents in ranked matches
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
+
+    /// Overrides the block size from the settings, e.g. when showing many boards at once
+    pub fn set_block_size(&mut self, block_size: Option<i32>) {
+        self.block_size = block_size;
+    }
+
+    fn block_size(&self, g: &Global) -> i32 {
+        self.block_size
+            .unwrap_or_else(|| g.block_size(self.engine.stack.width, self.engine.stack.height))
+    }
+
+    /// How close the stack is to topping out, from 0 to 1
+    pub fn danger(&self) -> f32 {
+        self.engine.danger()
+    }
+
     pub fn replay_data(&self) -> &ReplayData {
         &self.replay
     }
 
+    pub fn clears(&self) -> Clears {
+        self.clears
+    }
+
+    pub fn dig(&self) -> Dig {
+        self.dig
+    }
+
+    /// Objectives shown in the ticker, their progress starts from zero
+    pub fn set_objectives(&mut self, objectives: &[Objective]) {
+        self.objectives.set(objectives);
+    }
+
+    pub fn objectives(&self) -> &Objectives {
+        &self.objectives
+    }
+
+    /// Without a win condition the game only ends by topping out
+    pub fn set_win_condition(&mut self, win_condition: Option<WinCondition>) {
+        self.win_condition = win_condition;
+    }
+
+    pub fn won(&self) -> Option<RaceResult> {
+        self.won
+    }
+
+    /// Either won or lost
+    pub fn finished(&self) -> bool {
+        self.won.is_some() || self.engine.game_over()
+    }
+
     pub fn score(&self) -> i32 {
-        self.score.score()
+        self.engine.score.score()
     }
 
     pub fn game_over(&self) -> bool {
-        self.game_over
+        self.engine.game_over()
+    }
+
+    pub fn insert_garbage(&mut self, rows: &[GarbageRow]) {
+        self.engine.insert_garbage(rows);
+    }
+
+    pub fn board_state(&self) -> BoardState {
+        self.engine.board_state()
+    }
+
+    pub fn export_board(&self) -> String {
+        serde_json::to_string(&self.board_state()).unwrap()
+    }
+
+    pub fn import_board(&mut self, json: &str) -> Result<(), String> {
+        let state: BoardState = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        self.engine.set_board_state(state)
     }
 
     pub fn paused(&self) -> bool {
-        self.game_over || self.countdown != Countdown::Finished || self.stack.blocked()
+        self.engine.blocked() || self.countdown != Countdown::Finished || self.won.is_some()
     }
 
-    fn process_action(&mut self, g: &mut Global, action: Action, sfx: bool) -> bool {
-        match action {
-            Action::HoldPiece => {
-                if let Some(shape) = self.holder.hold(self.piece.shape(), &mut self.bag) {
-                    self.piece = Piece::new(shape, &self.stack);
+    /// Board without the HUD, for cropping the picture when broadcasting
+    pub fn region(&self) -> Option<Rect> {
+        self.region
+    }
+
+    pub fn feed_frame(&self) -> FeedFrame {
+        let engine = &self.engine;
+        let pieces = engine.pieces();
+        let (pps, apm) = FeedFrame::rates(pieces, self.attack_sent, self.replay.duration());
+
+        FeedFrame {
+            score: engine.score.score(),
+            lines: engine.score.lines(),
+            pieces,
+            pps,
+            apm,
+            combo: engine.score.combo(),
+            btb: engine.score.btb(),
+            game_over: engine.game_over(),
+            board: engine.stack.grid()[engine.stack.vanish as usize..].to_vec(),
+            piece: engine.piece.state(),
+            region: self.region.map(|r| [r.x, r.y, r.w, r.h]),
+        }
+    }
+
+    /// Whether a game is actually being played, for the session timer
+    pub fn playing(&self) -> bool {
+        self.engine.interactive()
+            && self.countdown == Countdown::Finished
+            && !self.finished()
+            && !self.idle_paused
+    }
+
+    pub fn remind_break(&mut self, played: Duration) {
+        let mut popup = Popup::new(Duration::from_secs(5));
+        popup.add("Take a break", Color::new(1.0, 0.9, 0.4, 1.0), 3.0);
+        popup.add(
+            format!("Playing for {} minutes", played.as_secs() / 60),
+            Color::new(0.8, 0.9, 1.0, 0.8),
+            1.0,
+        );
+        self.popups.add(popup);
+    }
+
+    /// Stops the clock once the win condition is met, the game freezes from then on
+    fn check_win(&mut self) {
+        let lines = self.engine.score.lines();
+        let win_condition = match self.win_condition {
+            Some(w) if self.won.is_none() && w.met(lines, self.time) => w,
+            _ => return,
+        };
+
+        let result = RaceResult {
+            time: win_condition.elapsed(self.time),
+            pieces: self.engine.pieces(),
+        };
+        self.won = Some(result);
+
+        let summary = if win_condition.race() {
+            utils::race_time(result.time)
+        } else {
+            format!("Score {}", self.engine.score.score())
+        };
+
+        let mut popup = Popup::new(Duration::from_secs(3));
+        popup.add(win_condition.ending(), Color::new(0.4, 1.0, 0.5, 1.0), 3.0);
+        popup.add(summary, Color::new(0.8, 0.9, 1.0, 1.0), 1.5);
+        self.popups.add(popup);
+    }
+
+    fn handle_events(&mut self, g: &mut Global, sfx: bool) {
+        // The buffer is taken out only to satisfy the borrow checker, it keeps its capacity
+        let mut events = mem::take(&mut self.events);
+        self.engine.events(&mut events);
+
+        for &event in &events {
+            if self.objectives.update(&event) > 0 {
+                let mut popup = Popup::new(Duration::from_secs(2));
+                let text = if self.objectives.completed() {
+                    "All objectives complete"
+                } else {
+                    "Objective complete"
+                };
+                popup.add(text, Color::new(0.4, 1.0, 0.5, 1.0), 1.5);
+                self.popups.add(popup);
+            }
+
+            match event {
+                Event::Moved => {
+                    if sfx {
+                        g.sfx.play("move");
+                    }
+                }
+                Event::Rotated => {
+                    if sfx {
+                        g.sfx.play("rotate");
+                    }
+                }
+                Event::RotatedHalf => {
+                    if sfx {
+                        g.sfx.play("rotate180");
+                    }
+                }
+                Event::Held => {
                     if sfx {
                         g.sfx.play("hold");
                     }
-                } else if sfx {
-                    g.sfx.play("holdfail");
                 }
-            }
-            Action::FallPiece => {
-                if !self.piece.shift(0, 1, &self.stack) && self.interactive {
-                    self.action(Action::LockPiece, true);
+                Event::DropUndone => {
+                    if sfx {
+                        g.sfx.play("move");
+                    }
                 }
-            }
-            Action::LockPiece => {
-                match self.stack.lock(
-                    &self.piece,
-                    Duration::from_millis(g.settings.gameplay.clear_delay.into()),
-                ) {
-                    Locked::Collision => {
-                        if self.interactive {
-                            self.action(Action::GameOver, true);
+                Event::HoldFailed => {
+                    if sfx {
+                        g.sfx.play("holdfail");
+                    }
+                }
+                Event::Locked {
+                    rows,
+                    t_spin,
+                    attack,
+                } => {
+                    self.attack += attack;
+                    self.attack_sent += attack;
+                    self.since_lock = Duration::new(0, 0);
+                    if rows == 0 {
+                        self.clean_locks += 1;
+                    }
+                    if let Some(target) = self.attack_target {
+                        if attack > 0 {
+                            self.popups.attack(attack, target);
                         }
                     }
-                    Locked::Success(rows) => {
-                        if rows > 0 {
-                            let t_spin = self.piece.t_spin(&self.stack);
-                            self.score.lock(rows, t_spin);
-                            self.popups.lock(
-                                rows,
-                                t_spin,
-                                self.score.btb(),
-                                self.score.combo(),
-                                g.settings.gameplay.entry_delay.into(),
-                            );
-
-                            let color = if rows == 4 {
-                                Color::new(0.0, 1.0, 1.0, 1.0)
-                            } else if t_spin {
-                                Color::new(1.0, 0.0, 1.0, 1.0)
-                            } else {
-                                Color::new(0.5, 0.5, 0.0, 1.0)
-                            };
-
-                            self.explode(color);
+                    self.clears.add(rows, t_spin);
+                    if t_spin {
+                        self.hints.followed(Hint::TSpin);
+                    }
+                    let stack = &self.engine.stack;
+                    self.dig.add(stack.cleared_garbage(), stack.garbage_rows());
+                    self.check_win();
+
+                    if rows > 0 {
+                        let score = &self.engine.score;
+                        self.popups.lock(
+                            rows,
+                            t_spin,
+                            score.btb(),
+                            score.combo(),
+                            g.settings.gameplay.entry_delay.into(),
+                        );
+
+                        let color = if rows == 4 {
+                            Color::new(0.0, 1.0, 1.0, 1.0)
+                        } else if t_spin {
+                            Color::new(1.0, 0.0, 1.0, 1.0)
                         } else {
-                            self.score.reset_combo();
-                        }
+                            Color::new(0.5, 0.5, 0.0, 1.0)
+                        };
 
-                        if sfx {
-                            match (rows, self.piece.t_spin(&self.stack)) {
-                                (1, false) => g.sfx.play("erase1"),
-                                (2, false) => g.sfx.play("erase2"),
-                                (3, false) => g.sfx.play("erase3"),
-                                (4, false) => g.sfx.play("erase4"),
-                                (0, true) => g.sfx.play("tspin0"),
-                                (1, true) => g.sfx.play("tspin1"),
-                                (2, true) => g.sfx.play("tspin2"),
-                                (3, true) => g.sfx.play("tspin3"),
-                                _ => g.sfx.play("lock"),
-                            }
-                        }
+                        self.explode(color);
 
-                        self.piece_entering = Some(Duration::new(0, 0));
-                        self.piece_visible = false;
-
-                        return false;
+                        if rows == 4 {
+                            self.punch = Some(1.0);
+                        }
                     }
-                };
-            }
-            Action::GameOver => {
-                self.game_over = true;
-                self.stack.game_over();
-                self.explode(Color::new(1.0, 0.0, 0.0, 1.0));
-
-                let mut popup = Popup::new(Duration::from_secs(10));
-                popup.add("Game Over", Color::new(0.9, 0.1, 0.2, 1.0), 4.0);
-                self.popups.add(popup);
 
-                if sfx {
-                    g.sfx.play("gameover");
+                    if sfx {
+                        match (rows, t_spin) {
+                            (1, false) => g.sfx.play("erase1"),
+                            (2, false) => g.sfx.play("erase2"),
+                            (3, false) => g.sfx.play("erase3"),
+                            (4, false) => g.sfx.play("erase4"),
+                            (0, true) => g.sfx.play("tspin0"),
+                            (1, true) => g.sfx.play("tspin1"),
+                            (2, true) => g.sfx.play("tspin2"),
+                            (3, true) => g.sfx.play("tspin3"),
+                            _ => g.sfx.play("lock"),
+                        }
+                    }
                 }
+                Event::Chain {
+                    length,
+                    rows,
+                    attack,
+                } => {
+                    self.attack += attack;
+                    self.attack_sent += attack;
+                    if let Some(target) = self.attack_target {
+                        if attack > 0 {
+                            self.popups.attack(attack, target);
+                        }
+                    }
 
-                return false;
-            }
-            Action::MoveLeft
-            | Action::MoveRight
-            | Action::MoveDown
-            | Action::RotateClockwise
-            | Action::RotateCounterClockwise
-            | Action::SoftDrop
-            | Action::HardDrop => self.process_movement_action(g, action, sfx),
-        };
-
-        true
-    }
+                    let mut popup = Popup::new(Duration::from_secs(2));
+                    popup.add(
+                        format!("{} chain", length),
+                        Color::new(1.0, 0.6, 0.3, 1.0),
+                        4.0,
+                    );
+                    popup.add(
+                        format!("+{} lines", rows),
+                        Color::new(0.8, 0.9, 1.0, 1.0),
+                        1.5,
+                    );
+                    self.popups.add(popup);
+                    self.explode(Color::new(1.0, 0.6, 0.3, 1.0));
 
-    fn process_movement_action(&mut self, g: &mut Global, action: Action, sfx: bool) {
-        match action {
-            Action::MoveRight => {
-                let moved = self.piece.shift(1, 0, &self.stack);
-                if moved && self.piece.touching_floor(&self.stack) {
-                    self.reset_fall();
+                    if sfx {
+                        g.sfx.play("erase4");
+                    }
                 }
+                Event::ZoneStarted => {
+                    let mut popup = Popup::new(Duration::from_secs(2));
+                    popup.add("Zone", Color::new(1.0, 0.9, 0.4, 1.0), 4.0);
+                    self.popups.add(popup);
 
-                if sfx && moved {
-                    g.sfx.play("move");
-                }
-            }
-            Action::MoveLeft => {
-                let moved = self.piece.shift(-1, 0, &self.stack);
-                if moved && self.piece.touching_floor(&self.stack) {
-                    self.reset_fall();
+                    if sfx {
+                        g.sfx.play("levelup");
+                    }
                 }
+                Event::ZoneEnded { rows, attack } => {
+                    self.attack += attack;
+                    self.attack_sent += attack;
+                    if let Some(target) = self.attack_target {
+                        if attack > 0 {
+                            self.popups.attack(attack, target);
+                        }
+                    }
 
-                if sfx && moved {
-                    g.sfx.play("move");
+                    if rows > 0 {
+                        let mut popup = Popup::new(Duration::from_secs(3));
+                        popup.add(
+                            format!("{} lines", rows),
+                            Color::new(1.0, 0.9, 0.4, 1.0),
+                            4.0,
+                        );
+                        self.popups.add(popup);
+
+                        self.explode(Color::new(1.0, 0.9, 0.4, 1.0));
+                        self.punch = Some(1.0);
+
+                        if sfx {
+                            g.sfx.play("erase4");
+                        }
+                    }
                 }
-            }
-            Action::MoveDown => {
-                if self.piece.shift(0, 1, &self.stack) {
-                    self.reset_fall();
+                Event::LevelUp { level, interval } => {
+                    let mut popup = Popup::new(Duration::from_secs(3));
+                    popup.add(
+                        format!("Level {}", level),
+                        Color::new(0.4, 1.0, 0.5, 1.0),
+                        3.0,
+                    );
+                    popup.add("Speed increased!", Color::new(0.8, 0.9, 1.0, 1.0), 1.5);
+                    popup.add(
+                        format!("Falls every {}ms", interval.as_millis()),
+                        Color::new(0.8, 0.9, 1.0, 0.8),
+                        1.0,
+                    );
+                    self.popups.add(popup);
 
                     if sfx {
-                        g.sfx.play("move");
+                        g.sfx.play("levelup");
                     }
                 }
-            }
-            Action::RotateClockwise => {
-                let rotated = self.piece.rotate(true, &self.stack);
-                if rotated && self.piece.touching_floor(&self.stack) {
-                    self.reset_fall();
-                }
+                Event::GameOver => {
+                    self.explode(Color::new(1.0, 0.0, 0.0, 1.0));
 
-                if sfx && rotated {
-                    g.sfx.play("rotate");
-                }
-            }
-            Action::RotateCounterClockwise => {
-                let rotated = self.piece.rotate(false, &self.stack);
-                if rotated && self.piece.touching_floor(&self.stack) {
-                    self.reset_fall();
-                }
+                    let mut popup = Popup::new(Duration::from_secs(10));
+                    popup.add("Game Over", Color::new(0.9, 0.1, 0.2, 1.0), 4.0);
+                    self.popups.add(popup);
 
-                if sfx && rotated {
-                    g.sfx.play("rotate");
-                }
-            }
-            Action::SoftDrop => {
-                let rows = self.piece.fall(&self.stack);
-                if rows > 0 {
-                    self.reset_fall();
-                    self.score.soft_drop(rows);
-                }
-            }
-            Action::HardDrop => {
-                let rows = self.piece.fall(&self.stack);
-                self.score.hard_drop(rows);
+                    self.replay.results = Some(ReplayResults {
+                        score: self.engine.score.score(),
+                        lines: self.engine.score.lines(),
+                        duration: self.replay.duration(),
+                    });
 
-                if self.interactive {
-                    self.action(Action::LockPiece, true);
+                    if sfx {
+                        g.sfx.play("gameover");
+                    }
                 }
             }
-            _ => (),
-        };
+        }
+
+        self.events = events;
     }
 
     pub fn update(&mut self, ctx: &mut Context, g: &mut Global, sfx: bool) -> GameResult {
@@ -350,15 +802,62 @@ impl Gameplay {
         }
 
         if g.imgui_state.debug_t_spin_tower {
-            self.stack.debug_t_spin();
+            self.engine.stack.debug_t_spin();
         }
 
         if g.imgui_state.debug_tetris_tower {
-            self.stack.debug_tetris();
+            self.engine.stack.debug_tetris();
+        }
+
+        self.engine.stack.top_out = g.settings.gameplay.top_out;
+
+        let peek = self.keyboard
+            && !g.imgui_state.capture_keyboard
+            && keyboard::is_key_pressed(ctx, KeyCode::V);
+        self.engine.stack.vanish_rows = if peek {
+            PEEK_ROWS
+        } else {
+            g.settings.gameplay.vanish_rows as i32
+        };
+
+        if g.imgui_state.debug_garbage {
+            let hole = rand::thread_rng().gen_range(0, self.engine.stack.width as usize);
+            self.insert_garbage(&[GarbageRow { hole }]);
+        }
+
+        if g.imgui_state.export_board {
+            g.imgui_state.board_json = ImString::new(self.export_board());
+            g.imgui_state.board_status = ImString::new("Board exported to clipboard");
+            g.imgui_state.copy_board_json = true;
+        }
+
+        if g.imgui_state.override_queue {
+            let status = match shape::parse_sequence(g.imgui_state.queue_override.to_str()) {
+                Ok(sequence) => {
+                    self.engine.bag.push_front(&sequence);
+                    format!("Next {} pieces overridden", sequence.len())
+                }
+                Err(c) => format!("Invalid piece: {}", c),
+            };
+
+            g.imgui_state.queue_status = ImString::new(status);
+        }
+
+        if g.imgui_state.import_board {
+            let status = match self.import_board(g.imgui_state.board_json.to_str()) {
+                Ok(()) => String::from("Board imported"),
+                Err(e) => {
+                    log::error!("Unable to import board: {}", e);
+                    format!("Import failed: {}", e)
+                }
+            };
+
+            g.imgui_state.board_status = ImString::new(status);
         }
 
         if g.settings_state.skin_switched {
-            self.blocks = Blocks::new(g.settings.tileset(ctx, &g.settings_state)?);
+            let tileset = g.settings.tileset(ctx, &g.settings_state, &mut g.assets)?;
+            self.blocks = Blocks::new(ctx, tileset);
         }
 
         if g.imgui_state.debug_click_to_place {
@@ -366,7 +865,7 @@ impl Gameplay {
         }
 
         if self.countdown != Countdown::Finished {
-            self.countdown_switch += timer::delta(ctx);
+            self.countdown_switch += g.clock.delta();
             if self.countdown_switch >= Duration::from_secs(1) {
                 self.countdown_switch = Duration::new(0, 0);
 
@@ -395,70 +894,231 @@ impl Gameplay {
             }
         }
 
+        let block_size = self.block_size(g);
         self.popups.update(
             ctx,
-            (g.settings.gameplay.block_size * self.stack.width) as f32,
-            (g.settings.gameplay.block_size * self.stack.height) as f32,
-            g.settings.gameplay.block_size as f32,
+            g.clock.delta(),
+            (block_size * self.engine.stack.width) as f32,
+            (block_size * self.engine.stack.height) as f32,
+            block_size as f32,
         )?;
 
-        self.stack.update(ctx, g)?;
+        self.engine.stack.update(ctx, g, &mut self.blocks)?;
+
+        if self.engine.pieces() != self.spawned {
+            self.spawned = self.engine.pieces();
+            self.input
+                .spawned(g.settings.input.preserve_das, g.settings.input.arr);
+
+            if g.settings.input.hard_drop_fresh_press {
+                self.input.discard_buffered(Action::HardDrop);
+            }
+        }
+
+        // Presses during line clear and entry delay apply as soon as the next piece enters
+        self.input.set_buffering(
+            g.settings.input.buffer_inputs
+                && self.keyboard
+                && self.countdown == Countdown::Finished
+                && !self.engine.game_over()
+                && (self.engine.stack.blocked() || self.engine.entering()),
+        );
 
         self.input.update(
             ctx,
+            &g.gamepad,
             g.settings.input.das,
             g.settings.input.arr,
-            self.paused() || g.imgui_state.paused || self.piece_entering.is_some(),
+            !self.keyboard
+                || self.paused()
+                || g.imgui_state.paused
+                || g.imgui_state.capture_keyboard
+                || g.settings_state.edit_hud
+                || self.idle_paused
+                || self.engine.entering(),
         );
 
-        if self.paused() || g.imgui_state.paused {
-            return Ok(());
+        // The key waking the game up only charges DAS, so that it doesn't drop the piece
+        if self.wake {
+            self.wake = false;
+            self.idle_paused = false;
+            self.popups.clear();
         }
 
-        let actions = self.input.actions();
-        self.actions(&actions);
+        // The game waits while the HUD is being laid out
+        if self.keyboard && g.settings_state.edit_hud {
+            self.edit_hud(ctx, g);
+            return Ok(());
+        }
+        self.hud_drag = None;
 
-        self.action_duration += timer::delta(ctx);
+        if self.paused() || g.imgui_state.paused || self.idle_paused {
+            return Ok(());
+        }
 
-        if self.piece_entering.is_none() {
-            while let Some(action) = self.actions.pop_front() {
-                self.replay.add(action, self.action_duration);
-                self.action_duration = Duration::new(0, 0);
+        if let Some(slot) = self.snapshot_key.take() {
+            let save = keyboard::is_mod_active(ctx, KeyMods::CTRL);
+            self.snapshot(slot, save);
+        }
 
-                if !self.process_action(g, action, sfx) {
-                    break;
-                }
+        let idle_pause = Duration::from_secs(g.settings.input.idle_pause.into());
+        if self.auto_pause && self.keyboard && idle_pause > Duration::new(0, 0) {
+            self.idle += g.clock.delta();
+            if self.idle >= idle_pause && !self.engine.game_over() {
+                self.idle_paused = true;
+
+                let mut popup = Popup::new(Duration::from_secs(24 * 60 * 60));
+                popup.add("Paused", Color::new(0.8, 0.9, 1.0, 1.0), 4.0);
+                popup.add(
+                    format!("No input for {} seconds", idle_pause.as_secs()),
+                    Color::new(0.8, 0.9, 1.0, 0.8),
+                    1.0,
+                );
+                popup.add("Press any key", Color::new(0.8, 0.9, 1.0, 0.8), 1.0);
+                self.popups.add(popup);
+                return Ok(());
             }
         }
 
-        self.piece.update(ctx, &self.stack);
+        if let Some(since) = self.since_hard_drop.as_mut() {
+            *since += g.clock.delta();
+        }
+        self.since_lock += g.clock.delta();
+
+        if self.keyboard {
+            let block_size = self.block_size(g);
+
+            // Hard drops coming too soon after the previous one are most likely misdrops
+            let interval = Duration::from_millis(g.settings.input.hard_drop_interval.into());
+            let since_hard_drop = &mut self.since_hard_drop;
+            let controls = self.controls;
+            let mut allowed = |action: Action| match *since_hard_drop {
+                _ if controls.map_or(false, |c| !c.allows(action)) => false,
+                Some(since) if action == Action::HardDrop && since < interval => false,
+                _ => {
+                    if action == Action::HardDrop {
+                        *since_hard_drop = Some(Duration::new(0, 0));
+                    }
+                    true
+                }
+            };
 
-        if let Some(entering) = self.piece_entering.as_mut() {
-            *entering += timer::delta(ctx);
+            let zone = g.settings.gameplay.zone;
+            let undo = self.casual && g.settings.gameplay.undo_grace > 0;
+            for action in self.input.actions() {
+                if (action != Action::ActivateZone || zone)
+                    && (action != Action::UndoDrop || undo)
+                    && allowed(action)
+                {
+                    self.hints.action(action);
+                    self.engine.action(action, false);
+                }
+            }
 
-            if *entering >= Duration::from_millis(g.settings.gameplay.entry_delay.into()) {
-                self.piece_entering = None;
-                self.piece_visible = true;
+            if g.settings.input.auto_soft_drop && !self.engine.entering() {
+                self.auto_soft_drop += g.clock.delta();
+                while self.auto_soft_drop >= AUTO_SOFT_DROP_INTERVAL {
+                    self.auto_soft_drop -= AUTO_SOFT_DROP_INTERVAL;
+                    if allowed(Action::MoveDown) {
+                        self.engine.action(Action::MoveDown, false);
+                    }
+                }
+            }
 
-                self.piece = Piece::new(self.bag.pop(), &self.stack);
-                if self.stack.collision(&self.piece) && self.interactive {
-                    self.action(Action::GameOver, true);
-                } else {
-                    self.reset_fall();
-                    self.holder.unlock();
+            let soft_drop = if allowed(Action::MoveDown) {
+                input::analog_soft_drop(g.stick, g.settings.input.analog_soft_drop)
+            } else {
+                1.0
+            };
+            if soft_drop > 1.0 {
+                self.idle = Duration::new(0, 0);
+            }
+            self.engine.set_soft_drop(soft_drop);
+
+            if let Some(position) = self.position {
+                if g.settings.input.mouse
+                    && !g.imgui_state.capture_mouse
+                    && !g.imgui_state.debug_click_to_place
+                    && !self.engine.entering()
+                {
+                    let cells = self.engine.piece.cells();
+                    let column = cells.iter().map(|c| c.0).sum::<i32>() / cells.len() as i32;
+
+                    let pieces = self.engine.pieces();
+                    for action in
+                        self.mouse
+                            .update(ctx, position, block_size, column, pieces, g.mouse_wheel)
+                    {
+                        self.idle = Duration::new(0, 0);
+                        if allowed(action) {
+                            self.hints.action(action);
+                            self.engine.action(action, false);
+                        }
+                    }
                 }
             }
-        } else if self.interactive {
-            if self.piece.locking() > Duration::from_millis(g.settings.gameplay.lock_delay.into()) {
-                self.action(Action::LockPiece, true);
+        }
+
+        if self.keyboard && self.engine.interactive() && g.settings.gameplay.hints {
+            self.show_hints(g);
+        }
+        self.hints.update(g.clock.delta());
+
+        let grace = g.settings.gameplay.undo_grace;
+        self.engine
+            .set_undo_grace(if self.keyboard && self.casual && grace > 0 {
+                Some(Duration::from_millis(grace.into()))
             } else {
-                self.falling += timer::delta(ctx);
+                None
+            });
 
-                if self.falling >= self.fall_interval {
-                    self.falling -= self.fall_interval;
+        let confirm = g.settings.gameplay.confirm_drop;
+        self.engine
+            .set_drop_confirmation(if self.keyboard && confirm > 0 {
+                Some(Duration::from_millis(confirm.into()))
+            } else {
+                None
+            });
 
-                    self.action(Action::FallPiece, true);
-                }
+        self.action_duration += g.clock.delta();
+        self.time += g.clock.delta();
+
+        // The time limit is only checked once the actions of the last frame went through
+        self.check_win();
+        if self.won.is_some() {
+            return Ok(());
+        }
+
+        while let Some(action) = self.engine.pop_action() {
+            if self.replay.checkpoint_due() {
+                self.replay.checkpoint(self.engine.state_hash());
+            }
+            self.replay.add(action, self.action_duration);
+            self.recorder.record(action);
+            if self.engine.interactive() {
+                crash::record_action(action, self.action_duration);
+            }
+            self.action_duration = Duration::new(0, 0);
+
+            let proceed = self.engine.process(&g.settings.gameplay, action);
+            self.handle_events(g, sfx);
+
+            if !proceed || self.won.is_some() {
+                break;
+            }
+        }
+
+        if self.won.is_some() {
+            return Ok(());
+        }
+
+        self.engine.step(&g.settings.gameplay, g.clock.delta());
+
+        let audio = &g.settings.audio;
+        if self.keyboard && self.countdown == Countdown::Finished && !self.engine.game_over() {
+            let beat = self.metronome.update(g.clock.delta(), audio.metronome_bpm);
+            if beat && sfx && audio.metronome.tick() {
+                g.sfx.play("tick");
             }
         }
 
@@ -466,86 +1126,610 @@ impl Gameplay {
     }
 
     pub fn draw(&mut self, ctx: &mut Context, g: &Global, position: Point2<f32>) -> GameResult<()> {
-        let block_size = g.settings.gameplay.block_size;
+        let block_size = self.block_size(g);
+        self.position = Some(position);
+        self.region = Some(Rect::new(
+            position[0],
+            position[1],
+            (block_size * self.engine.stack.width) as f32,
+            (block_size * self.engine.stack.height) as f32,
+        ));
 
         let next_block_size = block_size / 2;
         let holder_block_size = block_size * 3 / 4;
         let ui_color = Color::new(0.8, 0.9, 1.0, 0.8);
         let ui_scale = Scale::uniform(block_size as f32);
 
-        self.holder.draw(
+        let hud = |element| self.hud_position(g, element, position, block_size);
+        let holder_position = hud(HudElement::Hold);
+        let bag_position = hud(HudElement::Next);
+
+        if !self.hidden {
+            self.engine.holder.draw(
+                ctx,
+                holder_position,
+                holder_block_size,
+                ui_color,
+                self.font,
+            )?;
+
+            self.engine
+                .bag
+                .draw(ctx, bag_position, next_block_size, ui_color, self.font)?;
+        }
+
+        self.engine
+            .score
+            .draw(ctx, hud(HudElement::Score), ui_color, self.font, ui_scale)?;
+
+        if g.settings.gameplay.zone {
+            self.draw_zone_meter(ctx, position, block_size)?;
+        }
+
+        if self.keyboard && g.settings.audio.metronome.pulse() {
+            self.draw_pulse(ctx, g, position, block_size)?;
+        }
+
+        if let Some(win_condition) = self.win_condition {
+            self.draw_timer(
+                ctx,
+                hud(HudElement::Timer),
+                win_condition,
+                ui_color,
+                ui_scale,
+            )?;
+        }
+
+        if self.engine.rules().gravity.leveled() {
+            self.draw_level(ctx, hud(HudElement::Level), ui_color, ui_scale)?;
+        }
+
+        self.objectives.draw(
             ctx,
-            position + Vector2::new(-6.0 * holder_block_size as f32, 0.0),
-            &mut self.blocks,
-            holder_block_size,
+            hud(HudElement::Objectives),
+            holder_block_size as f32 * 5.5,
             ui_color,
             self.font,
+            Scale::uniform(block_size as f32 * 0.6),
         )?;
 
-        self.bag.draw(
+        // https://github.com/ggez/ggez/issues/664
+        ggez::graphics::pop_transform(ctx);
+        ggez::graphics::apply_transformations(ctx)?;
+
+        let cached = self.engine.stack.draw(ctx, position, block_size)?;
+
+        let alpha = match self.engine.lock_delay(&g.settings.gameplay) {
+            Some(delay) if delay.as_millis() > 0 => {
+                1.0 - self.engine.piece.locking().as_millis() as f32 / delay.as_millis() as f32
+            }
+            _ => 1.0,
+        };
+
+        // The whole board shares a single batch, which is only refilled when something changes
+        let key = self.batch_key(g, position, block_size, alpha, cached);
+        if !self.blocks.reuse(key) {
+            self.add_blocks(g, position, block_size, alpha, cached);
+            if !self.hidden {
+                self.engine
+                    .holder
+                    .add_blocks(holder_position, &mut self.blocks, holder_block_size);
+                self.engine
+                    .bag
+                    .add_blocks(bag_position, &mut self.blocks, next_block_size);
+            }
+        }
+
+        self.blocks.draw(ctx)?;
+        self.engine
+            .stack
+            .draw_sparks(ctx, position, &self.blocks, block_size)?;
+
+        let handicaps = g.settings.gameplay.handicaps;
+        if self.keyboard && handicaps.any() {
+            self.draw_handicaps(ctx, handicaps, position, block_size)?;
+        }
+
+        self.popups.draw(
             ctx,
-            position + Vector2::new((self.stack.width * block_size) as f32, 0.0),
-            &mut self.blocks,
-            next_block_size,
-            ui_color,
-            self.font,
+            position,
+            (block_size * self.engine.stack.height) as f32,
         )?;
 
-        self.score.draw(
+        let width = (block_size * self.engine.stack.width) as f32;
+        self.hints.draw(
             ctx,
-            position
-                + Vector2::new(
-                    (block_size * self.stack.width) as f32 + next_block_size as f32,
-                    (block_size * self.stack.height) as f32 - ui_scale.y * 3.0,
-                ),
-            ui_color,
+            Point2::new(
+                position[0] + width / 2.0,
+                position[1]
+                    + (block_size * self.engine.stack.height) as f32
+                    + block_size as f32 * 0.5,
+            ),
+            width,
             self.font,
-            ui_scale,
+            Scale::uniform(block_size as f32 * 0.5),
         )?;
 
-        // https://github.com/ggez/ggez/issues/664
-        ggez::graphics::pop_transform(ctx);
-        ggez::graphics::apply_transformations(ctx)?;
+        if g.imgui_state.debug_surface {
+            self.draw_surface(ctx, position, block_size)?;
+        }
 
-        self.stack
-            .draw(ctx, position, &mut self.blocks, block_size)?;
+        if self.keyboard && g.settings_state.edit_hud {
+            self.draw_hud_editor(ctx, g, position, block_size)?;
+        }
 
-        if self.piece_visible && !self.game_over {
-            let alpha = if g.settings.gameplay.lock_delay > 0 {
-                1.0 - self.piece.locking().as_millis() as f32
-                    / g.settings.gameplay.lock_delay as f32
-            } else {
-                1.0
+        Ok(())
+    }
+
+    /// Live time of a timed game, along with what's left to win it
+    fn draw_timer(
+        &self,
+        ctx: &mut Context,
+        position: Point2<f32>,
+        win_condition: WinCondition,
+        color: Color,
+        scale: Scale,
+    ) -> GameResult {
+        let time = self.won.map_or(self.time, |r| r.time);
+        let (label, time) = win_condition.clock(time);
+
+        let mut text = Text::new(TextFragment {
+            text: format!("{}\n", label),
+            color: Some(color),
+            font: Some(self.font),
+            scale: Some(Scale::uniform(scale.x * 1.5)),
+        });
+        text.add(TextFragment::from(format!("{}\n", utils::race_time(time))));
+        if let Some(remaining) = win_condition.remaining(self.engine.score.lines()) {
+            text.add(TextFragment {
+                text: remaining,
+                scale: Some(Scale::uniform(scale.x * 0.6)),
+                ..Default::default()
+            });
+        }
+        text.set_font(self.font, scale);
+
+        graphics::draw(ctx, &text, DrawParam::new().dest(position))
+    }
+
+    /// Current level along with the lines needed for the next one
+    fn draw_level(
+        &self,
+        ctx: &mut Context,
+        position: Point2<f32>,
+        color: Color,
+        scale: Scale,
+    ) -> GameResult {
+        let gravity = &self.engine.rules().gravity;
+        let lines = self.engine.score.lines();
+        let level = gravity.level(lines);
+        let next = gravity.lines.max(1) as i32 * level as i32 - lines;
+
+        let mut text = Text::new(TextFragment {
+            text: "Level\n".into(),
+            color: Some(color),
+            font: Some(self.font),
+            scale: Some(Scale::uniform(scale.x * 1.5)),
+        });
+        text.add(TextFragment::from(format!("{}\n", level)));
+        text.add(TextFragment {
+            text: format!("{} lines to next", next),
+            scale: Some(Scale::uniform(scale.x * 0.6)),
+            ..Default::default()
+        });
+        text.set_font(self.font, scale);
+
+        graphics::draw(ctx, &text, DrawParam::new().dest(position))
+    }
+
+    /// Shows the first hint that applies to the game right now and wasn't seen yet
+    fn show_hints(&mut self, g: &mut Global) {
+        if self.hints.showing() || self.engine.entering() || self.engine.game_over() {
+            return;
+        }
+
+        let controls = self.controls;
+        let bindings = &g.settings.input.bindings;
+        let key = |action| {
+            bindings
+                .iter()
+                .find(|b| b.action == action && controls.map_or(true, |c| c.allows(action)))
+                .map(|b| b.binding.name())
+        };
+
+        let zone = g.settings.gameplay.zone;
+        let seen = &mut g.settings.gameplay.hints_seen;
+        let engine = &self.engine;
+
+        // Checked in order, the later ones wait until the one shown is gone
+        let hint = if !seen.contains(&Hint::HardDrop) {
+            key(Action::HardDrop)
+                .map(|k| (Hint::HardDrop, format!("Press {} to drop the piece", k)))
+        } else {
+            None
+        }
+        .or_else(|| {
+            if seen.contains(&Hint::Hold)
+                || engine.pieces() < 2
+                || !engine.rules().hold
+                || engine.holder.locked()
+            {
+                return None;
+            }
+            key(Action::HoldPiece).map(|k| {
+                (
+                    Hint::Hold,
+                    format!("Press {} to hold the piece for later", k),
+                )
+            })
+        })
+        .or_else(|| {
+            if seen.contains(&Hint::TSpin) || !hints::t_slot(&engine.piece, &engine.stack) {
+                return None;
+            }
+            let text = "T-Spin slot! Drop the T next to it and rotate it in";
+            Some((Hint::TSpin, String::from(text)))
+        })
+        .or_else(|| {
+            if seen.contains(&Hint::Zone) || !zone || !engine.zone.ready() {
+                return None;
+            }
+            key(Action::ActivateZone)
+                .map(|k| (Hint::Zone, format!("Press {} to enter the Zone", k)))
+        });
+
+        if let Some((hint, text)) = hint {
+            self.hints.show(hint, text, seen);
+        }
+    }
+
+    fn hud_position(
+        &self,
+        g: &Global,
+        element: HudElement,
+        position: Point2<f32>,
+        block_size: i32,
+    ) -> Point2<f32> {
+        let stack = &self.engine.stack;
+        position + g.settings.hud.offset(element, stack.width, stack.height) * block_size as f32
+    }
+
+    /// Picks up the element under the cursor and drags it until the button is released
+    fn edit_hud(&mut self, ctx: &mut Context, g: &mut Global) {
+        let position = match self.position {
+            Some(position) => position,
+            None => return,
+        };
+
+        if !mouse::button_pressed(ctx, mouse::MouseButton::Left) || g.imgui_state.capture_mouse {
+            self.hud_drag = None;
+            return;
+        }
+
+        let (width, height) = (self.engine.stack.width, self.engine.stack.height);
+        let cursor = (utils::mouse_position_coords(ctx) - position) / self.block_size(g) as f32;
+
+        match self.hud_drag {
+            Some((element, grab)) => g.settings.hud.set(element, cursor - grab),
+            None => {
+                let hud = &g.settings.hud;
+                self.hud_drag = hud
+                    .element_at(cursor, width, height)
+                    .map(|e| (e, cursor - hud.offset(e, width, height)));
+            }
+        }
+    }
+
+    /// Frames around the elements, so that they can be found even when empty
+    fn draw_hud_editor(
+        &self,
+        ctx: &mut Context,
+        g: &Global,
+        position: Point2<f32>,
+        block_size: i32,
+    ) -> GameResult {
+        let stack = &self.engine.stack;
+        let block_size = block_size as f32;
+        let mut builder = MeshBuilder::new();
+
+        for &element in HUD_ELEMENTS.iter() {
+            let rect = g.settings.hud.rect(element, stack.width, stack.height);
+            let color = match self.hud_drag {
+                Some((e, _)) if e == element => Color::new(1.0, 0.9, 0.4, 0.9),
+                _ => Color::new(0.8, 0.9, 1.0, 0.5),
             };
 
-            self.piece.draw(
-                ctx,
+            let rect = Rect::new(
+                position[0] + rect.x * block_size,
+                position[1] + rect.y * block_size,
+                rect.w * block_size,
+                rect.h * block_size,
+            );
+            builder.rectangle(DrawMode::stroke(2.0), rect, color);
+
+            let text = Text::new(TextFragment {
+                text: element.name().to_string(),
+                color: Some(color),
+                font: Some(self.font),
+                scale: Some(Scale::uniform(block_size * 0.4)),
+            });
+            let dest = Point2::new(rect.x, rect.y - block_size * 0.5);
+            graphics::draw(ctx, &text, DrawParam::new().dest(dest))?;
+        }
+
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())
+    }
+
+    /// Everything that decides how the batch looks, none while the stack is animating
+    fn batch_key(
+        &self,
+        g: &Global,
+        position: Point2<f32>,
+        block_size: i32,
+        alpha: f32,
+        cached: bool,
+    ) -> Option<u64> {
+        if self.engine.stack.animating() {
+            return None;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        self.engine.stack.revision().hash(&mut hasher);
+        self.engine.stack.vanish_rows.hash(&mut hasher);
+        position[0].to_bits().hash(&mut hasher);
+        position[1].to_bits().hash(&mut hasher);
+        block_size.hash(&mut hasher);
+        cached.hash(&mut hasher);
+
+        let piece = self.engine.piece.state();
+        (piece.x, piece.y, piece.rotation, piece.shape as usize).hash(&mut hasher);
+        ((alpha * 64.0) as i32).hash(&mut hasher);
+        self.engine.entering().hash(&mut hasher);
+        self.engine.game_over().hash(&mut hasher);
+        self.engine.drop_armed().hash(&mut hasher);
+
+        let holder = &self.engine.holder;
+        (holder.shape_type().map(|s| s as usize), holder.locked()).hash(&mut hasher);
+        for &shape in self.engine.bag.peek(bag::PREVIEW) {
+            (shape as usize).hash(&mut hasher);
+        }
+
+        let settings = &g.settings.gameplay;
+        (settings.ghost_piece, settings.hold_preview).hash(&mut hasher);
+
+        let stack = &self.engine.stack;
+        for &element in &[HudElement::Hold, HudElement::Next] {
+            let offset = g.settings.hud.offset(element, stack.width, stack.height);
+            (offset.x.to_bits(), offset.y.to_bits()).hash(&mut hasher);
+        }
+
+        Some(hasher.finish())
+    }
+
+    /// Adds the stack, unless it was drawn from its cached layer,
+    /// the active piece, its ghost and the hold preview to the batch
+    fn add_blocks(
+        &mut self,
+        g: &Global,
+        position: Point2<f32>,
+        block_size: i32,
+        alpha: f32,
+        cached: bool,
+    ) {
+        if !cached {
+            self.engine
+                .stack
+                .add_blocks(position, &mut self.blocks, block_size);
+        }
+
+        if !self.engine.entering() && !self.engine.game_over() {
+            self.engine.piece.add(
                 position,
-                self.stack.vanish,
+                self.engine.stack.vanish,
                 &mut self.blocks,
                 block_size,
                 alpha,
-            )?;
+            );
+
+            let drop_row = self.engine.drop_row();
+            if self.engine.drop_armed() {
+                // Landing position waiting for the second press stands out even without the ghost
+                self.engine.piece.add_at(
+                    drop_row,
+                    position,
+                    self.engine.stack.vanish,
+                    &mut self.blocks,
+                    block_size,
+                    0.8,
+                );
+            } else if g.settings.gameplay.ghost_piece > 0 && drop_row > self.engine.piece.y {
+                self.engine.piece.add_at(
+                    drop_row,
+                    position,
+                    self.engine.stack.vanish,
+                    &mut self.blocks,
+                    block_size,
+                    g.settings.gameplay.ghost_piece as f32 / 100.0,
+                );
+            }
 
-            if g.settings.gameplay.ghost_piece > 0 {
-                let mut ghost = self.piece.clone();
-                if ghost.fall(&self.stack) > 0 {
-                    ghost.draw(
-                        ctx,
+            if g.settings.gameplay.hold_preview && !self.hidden && !self.engine.holder.locked() {
+                let shape = self
+                    .engine
+                    .holder
+                    .shape_type()
+                    .or_else(|| self.engine.bag.peek(1).next().cloned());
+
+                if let Some(shape) = shape {
+                    let mut preview = Piece::new(shape, &self.engine.stack);
+                    preview.fall(&self.engine.stack);
+                    preview.add(
                         position,
-                        self.stack.vanish,
+                        self.engine.stack.vanish,
                         &mut self.blocks,
                         block_size,
-                        g.settings.gameplay.ghost_piece as f32 / 100.0,
-                    )?;
+                        0.15,
+                    );
                 }
             }
         }
+    }
 
-        self.popups
-            .draw(ctx, position, (block_size * self.stack.height) as f32)?;
+    /// Covers whatever the handicaps hide, on top of the blocks
+    fn draw_handicaps(
+        &self,
+        ctx: &mut Context,
+        handicaps: Handicaps,
+        position: Point2<f32>,
+        block_size: i32,
+    ) -> GameResult {
+        let stack = &self.engine.stack;
+        let vanish = stack.vanish;
+        let grid = stack.grid();
+
+        let cells = self.engine.piece.cells();
+        let center = cells.iter().fold((0.0, 0.0), |(x, y), &(cx, cy)| {
+            (x + cx as f32 + 0.5, y + (cy - vanish) as f32 + 0.5)
+        });
+        let piece = (center.0 / cells.len() as f32, center.1 / cells.len() as f32);
 
-        Ok(())
+        handicaps.draw(
+            ctx,
+            position,
+            (stack.width, stack.height),
+            block_size,
+            |x, y| {
+                let occupied = grid[(y + vanish) as usize][x as usize] != 0;
+                handicaps.cover((x, y), stack.height, piece, occupied, self.since_lock)
+            },
+        )
+    }
+
+    /// Vertical bar along the left edge of the board, glowing once the zone can be activated
+    fn draw_zone_meter(
+        &self,
+        ctx: &mut Context,
+        position: Point2<f32>,
+        block_size: i32,
+    ) -> GameResult {
+        let zone = &self.engine.zone;
+        let width = block_size as f32 * 0.25;
+        let height = (block_size * self.engine.stack.height) as f32;
+        let filled = height * zone.meter();
+        let x = position[0] - width * 2.0;
+
+        let color = if zone.ready() || zone.active() {
+            Color::new(1.0, 0.9, 0.4, 1.0)
+        } else {
+            Color::new(0.8, 0.9, 1.0, 0.6)
+        };
+
+        let mut builder = MeshBuilder::new();
+        builder.rectangle(
+            DrawMode::fill(),
+            Rect::new(x, position[1], width, height),
+            Color::new(1.0, 1.0, 1.0, 0.1),
+        );
+        if filled > 0.0 {
+            builder.rectangle(
+                DrawMode::fill(),
+                Rect::new(x, position[1] + height - filled, width, filled),
+                color,
+            );
+        }
+
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())
+    }
+
+    /// Frame around the board flashing on every beat of the metronome
+    fn draw_pulse(
+        &self,
+        ctx: &mut Context,
+        g: &Global,
+        position: Point2<f32>,
+        block_size: i32,
+    ) -> GameResult {
+        let pulse = self.metronome.pulse(g.settings.audio.metronome_bpm);
+        if pulse <= 0.0 {
+            return Ok(());
+        }
+
+        let spread = block_size as f32 * 0.15;
+        let rect = Rect::new(
+            position[0] - spread,
+            position[1] - spread,
+            (block_size * self.engine.stack.width) as f32 + spread * 2.0,
+            (block_size * self.engine.stack.height) as f32 + spread * 2.0,
+        );
+
+        let mesh = Mesh::new_rectangle(
+            ctx,
+            DrawMode::stroke(spread),
+            rect,
+            Color::new(1.0, 0.9, 0.4, pulse * 0.8),
+        )?;
+        graphics::draw(ctx, &mesh, DrawParam::new())
+    }
+
+    /// Column heights above the board, holes below it and the bumpiness next to them
+    fn draw_surface(
+        &self,
+        ctx: &mut Context,
+        position: Point2<f32>,
+        block_size: i32,
+    ) -> GameResult {
+        let surface = self.engine.stack.surface();
+        let scale = Scale::uniform(block_size as f32 * 0.6);
+        let bottom = position[1] + (block_size * self.engine.stack.height) as f32;
+
+        let label = |text: String, color: Color| {
+            Text::new(TextFragment {
+                text,
+                color: Some(color),
+                font: Some(self.font),
+                scale: Some(scale),
+            })
+        };
+
+        for (x, (&height, &holes)) in surface.heights.iter().zip(&surface.holes).enumerate() {
+            let center = position[0] + (x as i32 * block_size) as f32 + block_size as f32 / 2.0;
+
+            let text = label(height.to_string(), Color::new(1.0, 1.0, 1.0, 0.9));
+            let dest = Point2::new(
+                center - text.width(ctx) as f32 / 2.0,
+                position[1] - scale.y * 1.5,
+            );
+            graphics::draw(ctx, &text, DrawParam::new().dest(dest))?;
+
+            let color = if holes > 0 {
+                Color::new(1.0, 0.3, 0.3, 0.9)
+            } else {
+                Color::new(1.0, 1.0, 1.0, 0.4)
+            };
+            let text = label(holes.to_string(), color);
+            let dest = Point2::new(
+                center - text.width(ctx) as f32 / 2.0,
+                bottom + scale.y * 0.25,
+            );
+            graphics::draw(ctx, &text, DrawParam::new().dest(dest))?;
+        }
+
+        let text = label(
+            format!(
+                "Height {}  Holes {}  Bumpiness {}",
+                surface.aggregate_height(),
+                surface.total_holes(),
+                surface.bumpiness
+            ),
+            Color::new(0.8, 0.9, 1.0, 0.9),
+        );
+        graphics::draw(
+            ctx,
+            &text,
+            DrawParam::new().dest(Point2::new(position[0], bottom + scale.y * 1.5)),
+        )
     }
 
     fn debug_click_to_place(&mut self, ctx: &mut Context, g: &Global) {
@@ -553,16 +1737,17 @@ impl Gameplay {
             return;
         }
 
+        let block_size = self.block_size(g);
         let mouse = utils::mouse_position_coords(ctx);
         let screen = graphics::screen_coordinates(ctx);
         let position_center = Vector2::new(
-            (screen.w - (self.stack.width * g.settings.gameplay.block_size) as f32) / 2.0,
-            (screen.h - (self.stack.height * g.settings.gameplay.block_size) as f32) / 2.0,
+            (screen.w - (self.engine.stack.width * block_size) as f32) / 2.0,
+            (screen.h - (self.engine.stack.height * block_size) as f32) / 2.0,
         );
 
         let position = mouse - position_center;
-        let x = position.x / g.settings.gameplay.block_size as f32;
-        let y = position.y / g.settings.gameplay.block_size as f32;
+        let x = position.x / block_size as f32;
+        let y = position.y / block_size as f32;
 
         if x < 0.0 || y < 0.0 {
             return;
@@ -571,11 +1756,11 @@ impl Gameplay {
         let x = x as i32;
         let y = y as i32;
 
-        if x >= self.stack.width || y >= self.stack.height {
+        if x >= self.engine.stack.width || y >= self.engine.stack.height {
             return;
         }
 
-        let y = y + self.stack.vanish;
-        self.stack.place_random(x as usize, y as usize);
+        let y = y + self.engine.stack.vanish;
+        self.engine.stack.place_random(x as usize, y as usize);
     }
 }
@@ -1,7 +1,16 @@
+use ggez::graphics::Rect;
+
 use crate::{
+    assets::Assets,
+    clock::Clock,
     imgui_wrapper::ImGuiState,
+    input::GamepadState,
+    profiler::Profiler,
+    session::Session,
     settings::{Settings, SettingsState},
     sfx::Sfx,
+    stats::Stats,
+    utils,
 };
 
 pub struct Global {
@@ -9,6 +18,20 @@ pub struct Global {
     pub settings_state: SettingsState,
     pub sfx: Sfx,
     pub imgui_state: ImGuiState,
+    pub stats: Stats,
+    pub profiler: Profiler,
+    pub assets: Assets,
+    /// Drives everything that affects the outcome of a game
+    pub clock: Clock,
+    /// Play time since the game was started
+    pub session: Session,
+    /// Mouse wheel movement since the last update
+    pub mouse_wheel: f32,
+    /// How far down the left stick of a gamepad is pushed, from 0 to 1
+    pub stick: f32,
+    pub gamepad: GamepadState,
+    /// Screen coordinates, updated when the window is resized
+    pub screen: Rect,
 }
 
 impl Global {
@@ -18,6 +41,24 @@ impl Global {
             settings_state: SettingsState::default(),
             sfx: Sfx::default(),
             imgui_state: ImGuiState::default(),
+            stats: Stats::new(),
+            profiler: Profiler::new(),
+            assets: Assets::default(),
+            clock: Clock::default(),
+            session: Session::default(),
+            mouse_wheel: 0.0,
+            stick: 0.0,
+            gamepad: GamepadState::default(),
+            screen: Rect::new(0.0, 0.0, 1080.0, 1080.0),
+        }
+    }
+
+    /// Block size from the settings, or the largest one that fits the window
+    pub fn block_size(&self, width: i32, height: i32) -> i32 {
+        if self.settings.gameplay.auto_block_size {
+            utils::fit_block_size(self.screen, width, height)
+        } else {
+            self.settings.gameplay.block_size
         }
     }
 }
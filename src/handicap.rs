@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use ggez::{
+    graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect},
+    nalgebra::Point2,
+    Context, GameResult,
+};
+use serde::{Deserialize, Serialize};
+
+/// How long the stack stays visible after a lock when it flashes
+const FLASH_TIME: Duration = Duration::from_millis(1000);
+
+/// Time the flashed stack takes to fade out again
+const FLASH_FADE: Duration = Duration::from_millis(250);
+
+/// Same as the background of the board, so that covered cells look empty
+const COVER_COLOR: (f32, f32, f32) = (0.02, 0.03, 0.04);
+
+/// Challenges making the board harder to see, drawn over the board of the player in any mode
+#[derive(Serialize, Deserialize, Copy, Clone, Default, Debug, PartialEq)]
+#[serde(default)]
+pub struct Handicaps {
+    /// Covers the bottom half of the board
+    pub fog: bool,
+    /// Only the blocks this close to the piece can be seen, 0 disables it
+    pub flashlight: u32,
+    /// Locked blocks show up only for a moment after every lock
+    pub flash: bool,
+}
+
+impl Handicaps {
+    pub fn any(&self) -> bool {
+        self.fog || self.flashlight > 0 || self.flash
+    }
+
+    /// How much of a visible cell is hidden, from 0 to 1. Rows start at the top of the board,
+    /// the piece is given by its center.
+    pub fn cover(
+        &self,
+        cell: (i32, i32),
+        height: i32,
+        piece: (f32, f32),
+        occupied: bool,
+        since_lock: Duration,
+    ) -> f32 {
+        let mut cover: f32 = 0.0;
+
+        if self.fog {
+            let edge = height / 2;
+            if cell.1 >= edge {
+                cover = 1.0;
+            } else if cell.1 == edge - 1 {
+                cover = 0.5;
+            }
+        }
+
+        if self.flashlight > 0 {
+            let dx = cell.0 as f32 + 0.5 - piece.0;
+            let dy = cell.1 as f32 + 0.5 - piece.1;
+            let distance = (dx * dx + dy * dy).sqrt();
+            cover = cover.max((distance - self.flashlight as f32).max(0.0).min(1.0));
+        }
+
+        if self.flash && occupied {
+            let shown = since_lock
+                .checked_sub(FLASH_TIME)
+                .unwrap_or_default()
+                .as_secs_f32();
+            cover = cover.max((shown / FLASH_FADE.as_secs_f32()).min(1.0));
+        }
+
+        cover
+    }
+
+    /// Covers the cells of the board, drawn after the blocks
+    pub fn draw<F>(
+        &self,
+        ctx: &mut Context,
+        position: Point2<f32>,
+        size: (i32, i32),
+        block_size: i32,
+        cover: F,
+    ) -> GameResult
+    where
+        F: Fn(i32, i32) -> f32,
+    {
+        let mut builder = MeshBuilder::new();
+        let mut empty = true;
+
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                let alpha = cover(x, y);
+                if alpha <= 0.0 {
+                    continue;
+                }
+
+                let cell = Rect::new(
+                    position[0] + (x * block_size) as f32,
+                    position[1] + (y * block_size) as f32,
+                    block_size as f32,
+                    block_size as f32,
+                );
+                let (r, g, b) = COVER_COLOR;
+                builder.rectangle(DrawMode::fill(), cell, Color::new(r, g, b, alpha));
+                empty = false;
+            }
+        }
+
+        if empty {
+            return Ok(());
+        }
+
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())
+    }
+}
+
+#[test]
+fn handicaps_test() {
+    let none = Handicaps::default();
+    assert!(!none.any());
+    assert_eq!(
+        none.cover((0, 19), 20, (5.0, 2.0), true, FLASH_TIME * 5),
+        0.0
+    );
+
+    let fog = Handicaps {
+        fog: true,
+        ..Handicaps::default()
+    };
+    assert_eq!(
+        fog.cover((3, 10), 20, (5.0, 2.0), false, Duration::new(0, 0)),
+        1.0
+    );
+    assert_eq!(
+        fog.cover((3, 9), 20, (5.0, 2.0), false, Duration::new(0, 0)),
+        0.5
+    );
+    assert_eq!(
+        fog.cover((3, 8), 20, (5.0, 2.0), false, Duration::new(0, 0)),
+        0.0
+    );
+
+    // Dark everywhere further than the radius from the piece
+    let flashlight = Handicaps {
+        flashlight: 3,
+        ..Handicaps::default()
+    };
+    assert_eq!(
+        flashlight.cover((5, 2), 20, (5.5, 2.5), true, FLASH_TIME),
+        0.0
+    );
+    assert_eq!(
+        flashlight.cover((5, 10), 20, (5.5, 2.5), false, FLASH_TIME),
+        1.0
+    );
+
+    // Only locked blocks hide, once the flash after the lock is over
+    let flash = Handicaps {
+        flash: true,
+        ..Handicaps::default()
+    };
+    assert_eq!(flash.cover((0, 19), 20, (5.0, 2.0), true, FLASH_TIME), 0.0);
+    assert_eq!(
+        flash.cover((0, 19), 20, (5.0, 2.0), true, FLASH_TIME * 2),
+        1.0
+    );
+    assert_eq!(
+        flash.cover((0, 19), 20, (5.0, 2.0), false, FLASH_TIME * 2),
+        0.0
+    );
+    let fading = flash.cover((0, 19), 20, (5.0, 2.0), true, FLASH_TIME + FLASH_FADE / 2);
+    assert!(fading > 0.4 && fading < 0.6);
+}
@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use ggez::{
+    graphics::{self, Align, Color, DrawMode, DrawParam, Font, MeshBuilder, Rect, Scale, Text},
+    nalgebra::Point2,
+    Context, GameResult,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{action::Action, piece::Piece, shape::ShapeType, stack::Stack};
+
+/// Time a hint stays up when it's neither followed nor dismissed
+const HINT_LIFETIME: Duration = Duration::from_secs(10);
+
+/// Tips for new players, each one shown only once, the first time it applies
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum Hint {
+    HardDrop,
+    Hold,
+    TSpin,
+    Zone,
+}
+
+impl Hint {
+    /// Action following the hint, which hides it
+    fn action(self) -> Option<Action> {
+        match self {
+            Hint::HardDrop => Some(Action::HardDrop),
+            Hint::Hold => Some(Action::HoldPiece),
+            Hint::TSpin => None,
+            Hint::Zone => Some(Action::ActivateZone),
+        }
+    }
+}
+
+/// The hint currently shown under the board
+#[derive(Default)]
+pub struct Hints {
+    current: Option<(Hint, String)>,
+    elapsed: Duration,
+}
+
+impl Hints {
+    pub fn showing(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Shows the hint unless it was already seen or another one is up.
+    /// Returns true if it was shown, it then counts as seen.
+    pub fn show(&mut self, hint: Hint, text: String, seen: &mut Vec<Hint>) -> bool {
+        if self.showing() || seen.contains(&hint) {
+            return false;
+        }
+
+        seen.push(hint);
+        self.current = Some((hint, text));
+        self.elapsed = Duration::new(0, 0);
+        true
+    }
+
+    pub fn dismiss(&mut self) {
+        self.current = None;
+    }
+
+    /// Hides the hint once the player does what it says
+    pub fn followed(&mut self, hint: Hint) {
+        if self.current.as_ref().map(|c| c.0) == Some(hint) {
+            self.dismiss();
+        }
+    }
+
+    pub fn action(&mut self, action: Action) {
+        if let Some(hint) = self.current.as_ref().map(|c| c.0) {
+            if hint.action() == Some(action) {
+                self.dismiss();
+            }
+        }
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        self.elapsed += dt;
+        if self.elapsed >= HINT_LIFETIME {
+            self.dismiss();
+        }
+    }
+
+    /// Box with the hint, centered on the given point
+    pub fn draw(
+        &self,
+        ctx: &mut Context,
+        position: Point2<f32>,
+        width: f32,
+        font: Font,
+        scale: Scale,
+    ) -> GameResult {
+        let text = match &self.current {
+            Some((_, text)) => text,
+            None => return Ok(()),
+        };
+
+        let mut hint = Text::new(format!("{}\n<Enter> to dismiss", text));
+        hint.set_font(font, scale)
+            .set_bounds(Point2::new(width, std::f32::INFINITY), Align::Center);
+
+        let height = hint.height(ctx) as f32;
+        let padding = scale.y * 0.3;
+        let area = Rect::new(
+            position[0] - width / 2.0 - padding,
+            position[1] - padding,
+            width + padding * 2.0,
+            height + padding * 2.0,
+        );
+
+        let mut builder = MeshBuilder::new();
+        builder.rectangle(DrawMode::fill(), area, Color::new(0.0, 0.0, 0.0, 0.6));
+        builder.rectangle(DrawMode::stroke(2.0), area, Color::new(0.8, 0.9, 1.0, 0.6));
+
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())?;
+        graphics::draw(
+            ctx,
+            &hint,
+            DrawParam::new()
+                .dest(Point2::new(position[0] - width / 2.0, position[1]))
+                .color(Color::new(0.8, 0.9, 1.0, 1.0)),
+        )
+    }
+}
+
+/// Whether the T piece can spin into a slot clearing lines, tried the way it's usually done,
+/// by dropping it in any rotation and column and turning it once at the bottom
+pub fn t_slot(piece: &Piece, stack: &Stack) -> bool {
+    if piece.shape() != ShapeType::T {
+        return false;
+    }
+
+    for rotation in 0..4 {
+        for shift in -5..=5i32 {
+            let mut dropped = piece.clone();
+            for _ in 0..rotation {
+                dropped.rotate(true, stack);
+            }
+            for _ in 0..shift.abs() {
+                dropped.shift(shift.signum(), 0, stack);
+            }
+            dropped.fall(stack);
+
+            for &clockwise in &[true, false] {
+                let mut spun = dropped.clone();
+                if spun.rotate(clockwise, stack)
+                    && !spun.clone().shift(0, 1, stack)
+                    && spun.t_spin(stack)
+                    && clears(&spun, stack)
+                {
+                    return true;
+                }
+            }
+        }
+    }
+
+    false
+}
+
+fn clears(piece: &Piece, stack: &Stack) -> bool {
+    let cells = piece.cells();
+    let grid = stack.grid();
+
+    cells.iter().any(|&(_, y)| {
+        y >= 0
+            && (0..stack.width)
+                .all(|x| grid[y as usize][x as usize] != 0 || cells.contains(&(x, y)))
+    })
+}
+
+#[test]
+fn hints_test() {
+    let mut hints = Hints::default();
+    let mut seen = vec![];
+
+    assert!(hints.show(Hint::Hold, String::from("Hold"), &mut seen));
+    assert!(!hints.show(Hint::Zone, String::from("Zone"), &mut seen));
+    hints.action(Action::HardDrop);
+    assert!(hints.showing());
+    hints.action(Action::HoldPiece);
+    assert!(!hints.showing());
+
+    // Every hint fires at most once
+    assert!(!hints.show(Hint::Hold, String::from("Hold"), &mut seen));
+    assert!(hints.show(Hint::Zone, String::from("Zone"), &mut seen));
+    hints.update(HINT_LIFETIME);
+    assert!(!hints.showing());
+    assert_eq!(seen, vec![Hint::Hold, Hint::Zone]);
+}
+
+#[test]
+fn t_slot_test() {
+    let mut stack = Stack::new(10, 20, 20);
+    let t = Piece::new(ShapeType::T, &stack);
+    assert!(!t_slot(&t, &stack));
+
+    // T-Spin double slot with an overhang on the left
+    for x in (0..10).filter(|&x| x != 4) {
+        stack.place_random(x, 39);
+    }
+    for x in (0..10).filter(|&x| x < 3 || x > 5) {
+        stack.place_random(x, 38);
+    }
+    stack.place_random(3, 37);
+
+    assert!(t_slot(&t, &stack));
+    assert!(!t_slot(&Piece::new(ShapeType::L, &stack), &stack));
+}
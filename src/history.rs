@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::stack::Stack;
+
+/// How many matches are kept in the history
+const MAX_MATCHES: usize = 100;
+
+/// Rating every player starts from
+pub const START_RATING: f32 = 1500.0;
+
+/// How much a single round can move the rating
+const RATING_FACTOR: f32 = 32.0;
+
+/// Rows of a board as it was when the match ended, without the vanish zone
+pub type FinalBoard = Vec<Vec<usize>>;
+
+pub fn final_board(stack: &Stack) -> FinalBoard {
+    stack.grid()[stack.vanish as usize..].to_vec()
+}
+
+/// Versus match against a single opponent, rematches count as rounds of the same match
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MatchRecord {
+    pub opponent: String,
+    pub online: bool,
+    /// Name of the rule set played
+    pub rules: String,
+    /// Whether the player won each of the rounds, in the order they were played
+    pub rounds: Vec<bool>,
+    /// Boards of the player and of the opponent at the end of the last round
+    pub boards: [FinalBoard; 2],
+    pub date: String,
+    /// Where the match was joined, so that it can be joined again
+    pub address: Option<String>,
+}
+
+impl MatchRecord {
+    pub fn new(
+        opponent: &str,
+        online: bool,
+        rules: &str,
+        won: bool,
+        boards: [FinalBoard; 2],
+    ) -> MatchRecord {
+        MatchRecord {
+            opponent: opponent.to_string(),
+            online,
+            rules: rules.to_string(),
+            rounds: vec![won],
+            boards,
+            date: Local::now().format("%Y-%m-%d %H:%M").to_string(),
+            address: None,
+        }
+    }
+
+    pub fn wins(&self) -> u32 {
+        self.rounds.iter().filter(|&&won| won).count() as u32
+    }
+
+    pub fn losses(&self) -> u32 {
+        self.rounds.len() as u32 - self.wins()
+    }
+}
+
+/// Every round against a single opponent
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeadToHead {
+    pub opponent: String,
+    pub wins: u32,
+    pub losses: u32,
+    /// Rating of the opponent, only online opponents are rated
+    pub rating: Option<f32>,
+    /// Last address the opponent was joined at
+    pub address: Option<String>,
+}
+
+impl HeadToHead {
+    pub fn win_rate(&self) -> f32 {
+        self.wins as f32 / (self.wins + self.losses).max(1) as f32
+    }
+}
+
+/// Chance of the first player winning a round, by the difference of the ratings
+fn expected(rating: f32, other: f32) -> f32 {
+    1.0 / (1.0 + 10f32.powf((other - rating) / 400.0))
+}
+
+/// Finished versus matches, the latest first
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct History {
+    pub matches: Vec<MatchRecord>,
+}
+
+impl History {
+    /// Adds the single round of the record, to the latest match when it's a rematch
+    /// against the same opponent under the same rules
+    pub fn record(&mut self, record: MatchRecord, rematch: bool) {
+        if rematch {
+            if let Some(last) = self.matches.first_mut().filter(|m| {
+                m.opponent == record.opponent
+                    && m.online == record.online
+                    && m.rules == record.rules
+            }) {
+                last.rounds.extend(record.rounds);
+                last.boards = record.boards;
+                last.date = record.date;
+                last.address = record.address.or_else(|| last.address.take());
+                return;
+            }
+        }
+
+        self.matches.insert(0, record);
+        self.matches.truncate(MAX_MATCHES);
+    }
+
+    /// Rating of the player and of every online opponent, going through the rounds
+    /// from the oldest one
+    pub fn ratings(&self) -> (f32, BTreeMap<String, f32>) {
+        let mut rating = START_RATING;
+        let mut opponents = BTreeMap::new();
+
+        for record in self.matches.iter().rev().filter(|m| m.online) {
+            let other = opponents
+                .entry(record.opponent.clone())
+                .or_insert(START_RATING);
+
+            for &won in &record.rounds {
+                let score = if won { 1.0 } else { 0.0 };
+                let change = RATING_FACTOR * (score - expected(rating, *other));
+                rating += change;
+                *other -= change;
+            }
+        }
+
+        (rating, opponents)
+    }
+
+    /// Rounds against every opponent, the most played first
+    pub fn head_to_head(&self) -> Vec<HeadToHead> {
+        let (_, ratings) = self.ratings();
+        let mut all: Vec<HeadToHead> = vec![];
+
+        for record in &self.matches {
+            let id = match all.iter().position(|h| h.opponent == record.opponent) {
+                Some(id) => id,
+                None => {
+                    all.push(HeadToHead {
+                        opponent: record.opponent.clone(),
+                        wins: 0,
+                        losses: 0,
+                        rating: ratings.get(&record.opponent).copied(),
+                        address: None,
+                    });
+                    all.len() - 1
+                }
+            };
+
+            let h = &mut all[id];
+            h.wins += record.wins();
+            h.losses += record.losses();
+            if h.address.is_none() {
+                h.address = record.address.clone();
+            }
+        }
+
+        all.sort_by_key(|h| std::cmp::Reverse(h.wins + h.losses));
+        all
+    }
+
+    /// Online opponents that can be joined again, the closest to the player's rating first
+    pub fn rematch_suggestions(&self) -> Vec<HeadToHead> {
+        let (rating, _) = self.ratings();
+        let mut suggestions: Vec<HeadToHead> = self
+            .head_to_head()
+            .into_iter()
+            .filter(|h| h.rating.is_some() && h.address.is_some())
+            .collect();
+
+        let distance = |h: &HeadToHead| (h.rating.unwrap_or(START_RATING) - rating).abs();
+        suggestions.sort_by(|a, b| {
+            distance(a)
+                .partial_cmp(&distance(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        suggestions
+    }
+}
+
+#[test]
+fn history_test() {
+    let boards = || [vec![vec![0; 10]; 20], vec![vec![0; 10]; 20]];
+    let online = |opponent, won| {
+        let mut record = MatchRecord::new(opponent, true, "Default", won, boards());
+        record.address = Some(format!("{}:7878", opponent));
+        record
+    };
+
+    let mut history = History::default();
+    history.record(online("ada", true), false);
+    history.record(online("ada", false), true);
+    history.record(online("ada", true), true);
+    assert_eq!(history.matches.len(), 1);
+    assert_eq!(history.matches[0].rounds, vec![true, false, true]);
+
+    // A new match starts unless it's a rematch of the latest one
+    history.record(online("bob", false), true);
+    history.record(online("ada", true), false);
+    history.record(
+        MatchRecord::new("Right player", false, "Default", true, boards()),
+        false,
+    );
+    assert_eq!(history.matches.len(), 4);
+
+    let all = history.head_to_head();
+    assert_eq!(all[0].opponent, "ada");
+    assert_eq!((all[0].wins, all[0].losses), (3, 1));
+    assert_eq!(all[0].win_rate(), 0.75);
+    assert!(all
+        .iter()
+        .any(|h| h.opponent == "Right player" && h.rating.is_none()));
+
+    // Winning raises the rating by as much as the opponent's goes down
+    let (rating, opponents) = history.ratings();
+    assert!(rating > START_RATING);
+    assert!(opponents["ada"] < START_RATING && opponents["bob"] > START_RATING);
+    let total: f32 = opponents.values().sum::<f32>() + rating;
+    assert!((total - START_RATING * 3.0).abs() < 0.01);
+
+    let suggestions = history.rematch_suggestions();
+    assert_eq!(suggestions.len(), 2);
+    assert_eq!(suggestions[0].opponent, "bob");
+}
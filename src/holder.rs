@@ -9,9 +9,10 @@ use crate::{
     bag::Bag,
     blocks::Blocks,
     shape::{Shape, ShapeType},
+    utils::Fnv64,
 };
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Holder {
     shape: Option<Shape>,
     locked: bool,
@@ -34,15 +35,32 @@ impl Holder {
         }
     }
 
+    pub fn shape_type(&self) -> Option<ShapeType> {
+        self.shape.as_ref().map(|s| s.shape_type)
+    }
+
+    pub fn set(&mut self, shape_type: Option<ShapeType>) {
+        self.shape = shape_type.map(Shape::new);
+        self.locked = false;
+    }
+
     pub fn unlock(&mut self) {
         self.locked = false;
     }
 
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn hash(&self, hash: &mut Fnv64) {
+        hash.write(self.shape_type().map_or(0, |s| s as u64));
+        hash.write(self.locked as u64);
+    }
+
     pub fn draw(
         &self,
         ctx: &mut Context,
         position: Point2<f32>,
-        blocks: &mut Blocks,
         block_size: i32,
         text_color: Color,
         font: Font,
@@ -59,8 +77,11 @@ impl Holder {
             Align::Center,
         );
 
-        graphics::draw(ctx, &text, DrawParam::new().dest(position))?;
+        graphics::draw(ctx, &text, DrawParam::new().dest(position))
+    }
 
+    /// Adds the held piece below the label to the batch
+    pub fn add_blocks(&self, position: Point2<f32>, blocks: &mut Blocks, block_size: i32) {
         let position = position + Vector2::new(0.0, block_size as f32 * 2.5);
 
         if let Some(shape) = &self.shape {
@@ -69,9 +90,7 @@ impl Holder {
                     block_size as f32 * 3.0 - shape.grids[0].width as f32 * block_size as f32 / 2.0,
                     0.0,
                 );
-            shape.draw(ctx, 0, position, blocks, block_size, 1.0)?;
+            shape.add(0, position, blocks, block_size, 1.0);
         }
-
-        Ok(())
     }
 }
@@ -0,0 +1,146 @@
+use ggez::{graphics::Rect, nalgebra::Vector2};
+use serde::{Deserialize, Serialize};
+
+/// Layout editor grid, in blocks
+const SNAP: f32 = 0.5;
+
+/// Parts of the HUD around the board that can be moved
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HudElement {
+    Hold,
+    Next,
+    Score,
+    Objectives,
+    /// Time of the game in timed modes
+    Timer,
+    /// Current level in games speeding up with it
+    Level,
+}
+
+pub const HUD_ELEMENTS: [HudElement; 6] = [
+    HudElement::Hold,
+    HudElement::Next,
+    HudElement::Score,
+    HudElement::Objectives,
+    HudElement::Timer,
+    HudElement::Level,
+];
+
+impl HudElement {
+    pub fn name(self) -> &'static str {
+        match self {
+            HudElement::Hold => "Hold",
+            HudElement::Next => "Next",
+            HudElement::Score => "Score",
+            HudElement::Objectives => "Objectives",
+            HudElement::Timer => "Timer",
+            HudElement::Level => "Level",
+        }
+    }
+
+    /// Place next to a board of the given size, in blocks from its top left corner
+    fn default_offset(self, width: i32, height: i32) -> (f32, f32) {
+        match self {
+            HudElement::Hold => (-4.5, 0.0),
+            HudElement::Next => (width as f32, 0.0),
+            HudElement::Score => (width as f32 + 0.5, height as f32 - 3.0),
+            HudElement::Objectives => (-4.5, 4.5),
+            HudElement::Timer => (width as f32 + 0.5, height as f32 - 6.0),
+            HudElement::Level => (width as f32 + 0.5, height as f32 - 9.0),
+        }
+    }
+
+    /// Area the element roughly takes, in blocks, used to pick it up in the editor
+    fn size(self) -> (f32, f32) {
+        match self {
+            HudElement::Hold => (4.5, 3.5),
+            HudElement::Next => (3.0, 10.5),
+            HudElement::Score => (4.0, 3.0),
+            HudElement::Objectives => (4.5, 4.0),
+            HudElement::Timer => (4.0, 2.5),
+            HudElement::Level => (4.0, 2.5),
+        }
+    }
+}
+
+/// Custom places of the HUD elements, in blocks from the top left corner of the board,
+/// so that the layout keeps up with the block size. Elements without one stay in their default place.
+#[derive(Serialize, Deserialize, Copy, Clone, Default, Debug, PartialEq)]
+#[serde(default)]
+pub struct HudLayout {
+    pub hold: Option<(f32, f32)>,
+    pub next: Option<(f32, f32)>,
+    pub score: Option<(f32, f32)>,
+    pub objectives: Option<(f32, f32)>,
+    pub timer: Option<(f32, f32)>,
+    pub level: Option<(f32, f32)>,
+}
+
+impl HudLayout {
+    fn slot(&mut self, element: HudElement) -> &mut Option<(f32, f32)> {
+        match element {
+            HudElement::Hold => &mut self.hold,
+            HudElement::Next => &mut self.next,
+            HudElement::Score => &mut self.score,
+            HudElement::Objectives => &mut self.objectives,
+            HudElement::Timer => &mut self.timer,
+            HudElement::Level => &mut self.level,
+        }
+    }
+
+    pub fn offset(&self, element: HudElement, width: i32, height: i32) -> Vector2<f32> {
+        let custom = match element {
+            HudElement::Hold => self.hold,
+            HudElement::Next => self.next,
+            HudElement::Score => self.score,
+            HudElement::Objectives => self.objectives,
+            HudElement::Timer => self.timer,
+            HudElement::Level => self.level,
+        };
+
+        let (x, y) = custom.unwrap_or_else(|| element.default_offset(width, height));
+        Vector2::new(x, y)
+    }
+
+    /// Moves the element, snapping it to the editor grid
+    pub fn set(&mut self, element: HudElement, offset: Vector2<f32>) {
+        let snap = |v: f32| (v / SNAP).round() * SNAP;
+        *self.slot(element) = Some((snap(offset.x), snap(offset.y)));
+    }
+
+    pub fn rect(&self, element: HudElement, width: i32, height: i32) -> Rect {
+        let offset = self.offset(element, width, height);
+        let (w, h) = element.size();
+        Rect::new(offset.x, offset.y, w, h)
+    }
+
+    /// Topmost element under the cursor, given in blocks from the top left corner of the board
+    pub fn element_at(&self, cursor: Vector2<f32>, width: i32, height: i32) -> Option<HudElement> {
+        HUD_ELEMENTS
+            .iter()
+            .rev()
+            .find(|&&e| self.rect(e, width, height).contains([cursor.x, cursor.y]))
+            .copied()
+    }
+}
+
+#[test]
+fn hud_layout_test() {
+    let mut layout = HudLayout::default();
+    assert_eq!(
+        layout.offset(HudElement::Next, 10, 20),
+        Vector2::new(10.0, 0.0)
+    );
+    assert_eq!(
+        layout.element_at(Vector2::new(-2.0, 1.0), 10, 20),
+        Some(HudElement::Hold)
+    );
+
+    layout.set(HudElement::Hold, Vector2::new(12.3, 17.8));
+    assert_eq!(layout.hold, Some((12.5, 18.0)));
+    assert_eq!(layout.element_at(Vector2::new(-2.0, 1.0), 10, 20), None);
+    assert_eq!(
+        layout.element_at(Vector2::new(16.0, 21.0), 10, 20),
+        Some(HudElement::Hold)
+    );
+}
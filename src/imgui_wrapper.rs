@@ -1,15 +1,48 @@
 use std::{
     io::Read,
+    path::PathBuf,
     time::{Duration, Instant},
 };
 
 use gfx_core::{handle::RenderTargetView, memory::Typed};
 use gfx_device_gl;
-use ggez::{event, filesystem, graphics, timer, Context};
-use imgui::{self, im_str, Condition, FontId, FontSource, ImString, StyleColor, Window};
+use ggez::{
+    event::{self, KeyMods},
+    filesystem, graphics,
+    input::keyboard::KeyCode,
+    timer, Context,
+};
+use imgui::{
+    self, im_str, BackendFlags, Condition, ConfigFlags, FontId, FontSource, ImString, Key,
+    NavInput, StyleColor, Window,
+};
 use imgui_gfx_renderer::{Renderer, Shaders};
 
-use crate::{global::Global, utils};
+use crate::{
+    crash,
+    global::Global,
+    input::{GamepadButton, GamepadState},
+    scene::SceneStack,
+    utils,
+};
+
+/// Gamepad buttons navigating the windows, the d-pad and the stick move between the widgets
+const NAV_BUTTONS: [(NavInput, GamepadButton); 14] = [
+    (NavInput::Activate, GamepadButton::South),
+    (NavInput::Cancel, GamepadButton::East),
+    (NavInput::Input, GamepadButton::North),
+    (NavInput::Menu, GamepadButton::West),
+    (NavInput::DpadLeft, GamepadButton::DPadLeft),
+    (NavInput::DpadRight, GamepadButton::DPadRight),
+    (NavInput::DpadUp, GamepadButton::DPadUp),
+    (NavInput::DpadDown, GamepadButton::DPadDown),
+    (NavInput::LStickLeft, GamepadButton::StickLeft),
+    (NavInput::LStickRight, GamepadButton::StickRight),
+    (NavInput::LStickUp, GamepadButton::StickUp),
+    (NavInput::LStickDown, GamepadButton::StickDown),
+    (NavInput::FocusPrev, GamepadButton::LeftBumper),
+    (NavInput::FocusNext, GamepadButton::RightBumper),
+];
 
 #[derive(Default)]
 struct MouseState {
@@ -26,13 +59,23 @@ pub struct ImGuiState {
     pub game_over: bool,
     pub debug_t_spin_tower: bool,
     pub debug_tetris_tower: bool,
+    pub debug_garbage: bool,
+    pub debug_surface: bool,
     pub update_last: Duration,
     pub draw_last: Duration,
     pub update: Vec<Duration>,
     pub draw: Vec<Duration>,
-    pub game_over_window: bool,
-    pub save_replay: bool,
-    pub replay_score: i32,
+    pub capture_keyboard: bool,
+    pub capture_mouse: bool,
+    pub export_board: bool,
+    pub import_board: bool,
+    pub copy_board_json: bool,
+    pub board_json: ImString,
+    pub board_status: ImString,
+    pub override_queue: bool,
+    pub queue_override: ImString,
+    pub queue_status: ImString,
+    pub crash_report: Option<PathBuf>,
 }
 
 pub struct ImGuiWrapper {
@@ -93,6 +136,34 @@ impl ImGuiWrapper {
             }
         };
 
+        {
+            let io = imgui.io_mut();
+            io.config_flags.insert(ConfigFlags::NAV_ENABLE_GAMEPAD);
+            io.backend_flags.insert(BackendFlags::HAS_GAMEPAD);
+
+            io[Key::Tab] = KeyCode::Tab as _;
+            io[Key::LeftArrow] = KeyCode::Left as _;
+            io[Key::RightArrow] = KeyCode::Right as _;
+            io[Key::UpArrow] = KeyCode::Up as _;
+            io[Key::DownArrow] = KeyCode::Down as _;
+            io[Key::PageUp] = KeyCode::PageUp as _;
+            io[Key::PageDown] = KeyCode::PageDown as _;
+            io[Key::Home] = KeyCode::Home as _;
+            io[Key::End] = KeyCode::End as _;
+            io[Key::Insert] = KeyCode::Insert as _;
+            io[Key::Delete] = KeyCode::Delete as _;
+            io[Key::Backspace] = KeyCode::Back as _;
+            io[Key::Space] = KeyCode::Space as _;
+            io[Key::Enter] = KeyCode::Return as _;
+            io[Key::Escape] = KeyCode::Escape as _;
+            io[Key::A] = KeyCode::A as _;
+            io[Key::C] = KeyCode::C as _;
+            io[Key::V] = KeyCode::V as _;
+            io[Key::X] = KeyCode::X as _;
+            io[Key::Y] = KeyCode::Y as _;
+            io[Key::Z] = KeyCode::Z as _;
+        }
+
         let mut renderer = Renderer::init(&mut imgui, &mut *factory, shaders).unwrap();
         renderer
             .reload_font_texture(&mut imgui, &mut *factory)
@@ -160,7 +231,7 @@ impl ImGuiWrapper {
         }
     }
 
-    pub fn draw(&mut self, ctx: &mut Context, g: &mut Global) {
+    pub fn draw(&mut self, ctx: &mut Context, g: &mut Global, scenes: &mut SceneStack) {
         self.update_mouse();
 
         const AVG_FRAMES: usize = 30;
@@ -186,10 +257,25 @@ impl ImGuiWrapper {
         self.imgui.io_mut().display_framebuffer_scale = [dpi_factor, dpi_factor];
         self.imgui.io_mut().delta_time = delta_s;
 
+        // The buttons belong to the piece while playing
+        let playing = scenes.gameplay().map_or(false, |g| g.playing());
+        self.update_gamepad(&g.gamepad, !playing);
+
         let ui = self.imgui.frame();
         {
+            g.imgui_state.capture_keyboard = ui.io().want_capture_keyboard;
+            g.imgui_state.capture_mouse = ui.io().want_capture_mouse;
+
+            if g.imgui_state.copy_board_json {
+                g.imgui_state.copy_board_json = false;
+                ui.set_clipboard_text(&g.imgui_state.board_json);
+            }
+
+            // Nothing but the game is shown in capture mode
+            let capture = g.settings.graphics.capture_mode;
+
             let font_id = ui.push_font(self.regular_font);
-            if self.show_debug_window {
+            if self.show_debug_window && !capture {
                 Window::new(im_str!("Debug"))
                     .size([0.0, 0.0], Condition::Appearing)
                     .position([50.0, 50.0], Condition::Appearing)
@@ -204,6 +290,8 @@ impl ImGuiWrapper {
                             &mut g.imgui_state.debug_click_to_place,
                         );
 
+                        ui.checkbox(im_str!("Surface overlay"), &mut g.imgui_state.debug_surface);
+
                         g.imgui_state.restart = ui.button(im_str!("Restart"), [0.0, 0.0]);
 
                         g.imgui_state.game_over = ui.button(im_str!("Game over"), [0.0, 0.0]);
@@ -214,6 +302,36 @@ impl ImGuiWrapper {
                         g.imgui_state.debug_tetris_tower =
                             ui.button(im_str!("Tetris tower"), [0.0, 0.0]);
 
+                        g.imgui_state.debug_garbage = ui.button(im_str!("Add garbage"), [0.0, 0.0]);
+
+                        ui.separator();
+                        g.imgui_state.export_board = ui.button(im_str!("Export board"), [0.0, 0.0]);
+                        ui.same_line(0.0);
+                        g.imgui_state.import_board = ui.button(im_str!("Import board"), [0.0, 0.0]);
+                        ui.same_line(0.0);
+                        if ui.button(im_str!("Paste"), [0.0, 0.0]) {
+                            if let Some(text) = ui.clipboard_text() {
+                                g.imgui_state.board_json = text;
+                            }
+                        }
+
+                        ui.input_text_multiline(
+                            im_str!("##board_json"),
+                            &mut g.imgui_state.board_json,
+                            [300.0, 80.0],
+                        )
+                        .resize_buffer(true)
+                        .build();
+                        ui.text(&g.imgui_state.board_status);
+
+                        ui.separator();
+                        ui.input_text(im_str!("Next pieces"), &mut g.imgui_state.queue_override)
+                            .resize_buffer(true)
+                            .build();
+                        g.imgui_state.override_queue =
+                            ui.button(im_str!("Override queue"), [0.0, 0.0]);
+                        ui.text(&g.imgui_state.queue_status);
+
                         ui.separator();
                         ui.text(im_str!("Window size: {}x{}", w, h));
 
@@ -230,32 +348,47 @@ impl ImGuiWrapper {
                     });
             }
 
-            if g.imgui_state.game_over_window {
-                let mut opened = true;
-                Window::new(im_str!("Game over"))
-                    .opened(&mut opened)
+            if !capture {
+                scenes.ui(&ui, g);
+            }
+
+            if g.settings.graphics.profiler && !capture {
+                g.profiler.ui(&ui);
+            }
+
+            if g.settings.graphics.session_timer && !capture {
+                g.session.ui(&ui);
+            }
+
+            if let Some(report) = g.imgui_state.crash_report.as_ref().filter(|_| !capture) {
+                let mut dismiss = false;
+                Window::new(im_str!("Crash report"))
                     .resizable(false)
-                    .size([120.0, 180.0], Condition::Appearing)
-                    .position([w / 2.0 - 60.0, h / 3.0 * 2.0], Condition::Appearing)
                     .collapsible(false)
+                    .size([0.0, 0.0], Condition::Appearing)
+                    .position([w / 2.0 - 220.0, h / 3.0], Condition::Appearing)
                     .build(&ui, || {
-                        ui.text(im_str!("Score: {}", g.imgui_state.replay_score));
+                        ui.text(im_str!("Klocki crashed during the last session."));
+                        ui.text(im_str!(
+                            "The seed, inputs and settings were saved to:\n{}",
+                            report.display()
+                        ));
                         ui.separator();
 
-                        g.imgui_state.save_replay = ui.button(im_str!("Save replay"), [0.0, 0.0]);
-
-                        ui.separator();
-                        if g.imgui_state.save_replay || ui.button(im_str!("Close"), [0.0, 0.0]) {
-                            g.imgui_state.game_over_window = false;
+                        if ui.button(im_str!("Open crash folder"), [0.0, 0.0]) {
+                            crash::open_directory();
                         }
+
+                        ui.same_line(0.0);
+                        dismiss = ui.button(im_str!("Dismiss"), [0.0, 0.0]);
                     });
 
-                if !opened {
-                    g.imgui_state.game_over_window = false;
+                if dismiss {
+                    g.imgui_state.crash_report = None;
                 }
             }
 
-            if !g.settings.graphics.hide_menu {
+            if !g.settings.graphics.hide_menu && !capture {
                 if let Some(menu_bar) = ui.begin_main_menu_bar() {
                     if let Some(menu) = ui.begin_menu(im_str!("File"), true) {
                         if imgui::MenuItem::new(im_str!("Quit")).build(&ui) {
@@ -316,6 +449,17 @@ impl ImGuiWrapper {
         self.mouse_state.wheel = 0.0;
     }
 
+    fn update_gamepad(&mut self, gamepad: &GamepadState, navigate: bool) {
+        let io = self.imgui.io_mut();
+        for &(input, button) in NAV_BUTTONS.iter() {
+            io[input] = if navigate && gamepad.pressed(button) {
+                1.0
+            } else {
+                0.0
+            };
+        }
+    }
+
     pub fn update_mouse_pos(&mut self, x: f32, y: f32) {
         self.mouse_state.pos = (x as i32, y as i32);
     }
@@ -328,6 +472,27 @@ impl ImGuiWrapper {
         self.mouse_state.wheel = lines;
     }
 
+    pub fn update_key(&mut self, keycode: KeyCode, keymods: KeyMods, pressed: bool) {
+        let io = self.imgui.io_mut();
+        io.keys_down[keycode as usize] = pressed;
+        io.key_shift = keymods.contains(KeyMods::SHIFT);
+        io.key_ctrl = keymods.contains(KeyMods::CTRL);
+        io.key_alt = keymods.contains(KeyMods::ALT);
+        io.key_super = keymods.contains(KeyMods::LOGO);
+    }
+
+    pub fn update_text(&mut self, character: char) {
+        self.imgui.io_mut().add_input_character(character);
+    }
+
+    pub fn want_capture_keyboard(&self) -> bool {
+        self.imgui.io().want_capture_keyboard
+    }
+
+    pub fn want_capture_mouse(&self) -> bool {
+        self.imgui.io().want_capture_mouse
+    }
+
     pub fn toggle_window(&mut self) {
         self.show_debug_window = !self.show_debug_window;
     }
@@ -1,10 +1,400 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    time::{Duration, Instant},
+    vec::Drain,
+};
 
-use ggez::{self, input::keyboard::KeyCode, timer, Context};
+use ggez::{
+    self,
+    event::{Axis, Button},
+    input::{keyboard::KeyCode, mouse::MouseButton},
+    nalgebra::Point2,
+    timer, Context,
+};
+use serde::{Deserialize, Serialize};
 
-use crate::action::Action;
+use crate::{action::Action, utils};
 
 const MAX_KEYCODES: usize = 161;
+const MAX_MOUSE_BUTTONS: usize = 8;
+const MAX_GAMEPAD_BUTTONS: usize = 20;
+const MAX_BINDINGS: usize = MAX_KEYCODES + MAX_MOUSE_BUTTONS + MAX_GAMEPAD_BUTTONS;
+
+/// Stick deflection ignored as noise of a stick at rest
+const STICK_DEAD_ZONE: f32 = 0.2;
+
+/// Stick deflection at which it counts as a pressed direction
+const STICK_PRESS: f32 = 0.5;
+
+/// Button of a gamepad, the left stick and the d-pad count as four buttons each
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    StickUp,
+    StickDown,
+    StickLeft,
+    StickRight,
+}
+
+impl GamepadButton {
+    pub fn from_button(button: Button) -> Option<GamepadButton> {
+        Some(match button {
+            Button::South => GamepadButton::South,
+            Button::East => GamepadButton::East,
+            Button::North => GamepadButton::North,
+            Button::West => GamepadButton::West,
+            Button::LeftTrigger => GamepadButton::LeftBumper,
+            Button::RightTrigger => GamepadButton::RightBumper,
+            Button::LeftTrigger2 => GamepadButton::LeftTrigger,
+            Button::RightTrigger2 => GamepadButton::RightTrigger,
+            Button::Select => GamepadButton::Select,
+            Button::Start => GamepadButton::Start,
+            Button::LeftThumb => GamepadButton::LeftThumb,
+            Button::RightThumb => GamepadButton::RightThumb,
+            Button::DPadUp => GamepadButton::DPadUp,
+            Button::DPadDown => GamepadButton::DPadDown,
+            Button::DPadLeft => GamepadButton::DPadLeft,
+            Button::DPadRight => GamepadButton::DPadRight,
+            _ => return None,
+        })
+    }
+
+    /// Key the button stands for in the menus navigated with the keyboard
+    pub fn menu_key(self) -> Option<KeyCode> {
+        Some(match self {
+            GamepadButton::DPadUp | GamepadButton::StickUp => KeyCode::Up,
+            GamepadButton::DPadDown | GamepadButton::StickDown => KeyCode::Down,
+            GamepadButton::DPadLeft | GamepadButton::StickLeft => KeyCode::Left,
+            GamepadButton::DPadRight | GamepadButton::StickRight => KeyCode::Right,
+            GamepadButton::LeftBumper => KeyCode::PageUp,
+            GamepadButton::RightBumper => KeyCode::PageDown,
+            GamepadButton::South => KeyCode::Return,
+            GamepadButton::East => KeyCode::Escape,
+            _ => return None,
+        })
+    }
+
+    /// Directions on both ends of an axis, the negative one first
+    fn directions(axis: Axis) -> Option<(GamepadButton, GamepadButton)> {
+        // Up is positive on the vertical axes
+        match axis {
+            Axis::LeftStickX => Some((GamepadButton::StickLeft, GamepadButton::StickRight)),
+            Axis::LeftStickY => Some((GamepadButton::StickDown, GamepadButton::StickUp)),
+            Axis::DPadX => Some((GamepadButton::DPadLeft, GamepadButton::DPadRight)),
+            Axis::DPadY => Some((GamepadButton::DPadDown, GamepadButton::DPadUp)),
+            _ => None,
+        }
+    }
+}
+
+/// Buttons held down on the gamepads, kept up to date by the gamepad events
+#[derive(Default)]
+pub struct GamepadState {
+    pressed: HashSet<GamepadButton>,
+}
+
+impl GamepadState {
+    pub fn button(&mut self, button: GamepadButton, down: bool) {
+        if down {
+            self.pressed.insert(button);
+        } else {
+            self.pressed.remove(&button);
+        }
+    }
+
+    /// Turns an axis into presses of its directions, returns the direction newly pressed
+    pub fn axis(&mut self, axis: Axis, value: f32) -> Option<GamepadButton> {
+        let (negative, positive) = GamepadButton::directions(axis)?;
+        let before = (self.pressed(negative), self.pressed(positive));
+
+        self.button(negative, value <= -STICK_PRESS);
+        self.button(positive, value >= STICK_PRESS);
+
+        if self.pressed(negative) && !before.0 {
+            Some(negative)
+        } else if self.pressed(positive) && !before.1 {
+            Some(positive)
+        } else {
+            None
+        }
+    }
+
+    pub fn pressed(&self, button: GamepadButton) -> bool {
+        self.pressed.contains(&button)
+    }
+}
+
+/// Anything that can be held down to trigger an action
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+impl Binding {
+    fn index(self) -> usize {
+        match self {
+            Binding::Key(keycode) => keycode as usize,
+            Binding::Mouse(button) => {
+                let button = match button {
+                    MouseButton::Left => 0,
+                    MouseButton::Right => 1,
+                    MouseButton::Middle => 2,
+                    MouseButton::Other(n) => 3 + n as usize,
+                };
+
+                MAX_KEYCODES + button.min(MAX_MOUSE_BUTTONS - 1)
+            }
+            Binding::Gamepad(button) => MAX_KEYCODES + MAX_MOUSE_BUTTONS + button as usize,
+        }
+    }
+
+    fn pressed(
+        self,
+        ctx: &Context,
+        pressed_keys: &HashSet<KeyCode>,
+        gamepad: &GamepadState,
+    ) -> bool {
+        match self {
+            Binding::Key(keycode) => pressed_keys.contains(&keycode),
+            Binding::Mouse(button) => ggez::input::mouse::button_pressed(ctx, button),
+            Binding::Gamepad(button) => gamepad.pressed(button),
+        }
+    }
+
+    /// Shown in prompts telling which key to press
+    pub fn name(self) -> String {
+        match self {
+            Binding::Key(keycode) => format!("{:?}", keycode),
+            Binding::Mouse(button) => format!("{:?} mouse button", button),
+            Binding::Gamepad(button) => format!("{:?} on the gamepad", button),
+        }
+    }
+
+    /// First key or button held down, used when rebinding controls
+    pub fn captured(ctx: &Context, gamepad: &GamepadState) -> Option<Binding> {
+        let keys = ggez::input::keyboard::pressed_keys(ctx);
+        if let Some(&keycode) = keys.iter().next() {
+            return Some(Binding::Key(keycode));
+        }
+
+        if let Some(&button) = gamepad.pressed.iter().next() {
+            return Some(Binding::Gamepad(button));
+        }
+
+        [MouseButton::Left, MouseButton::Right, MouseButton::Middle]
+            .iter()
+            .find(|&&b| ggez::input::mouse::button_pressed(ctx, b))
+            .map(|&b| Binding::Mouse(b))
+    }
+}
+
+impl From<KeyCode> for Binding {
+    fn from(keycode: KeyCode) -> Binding {
+        Binding::Key(keycode)
+    }
+}
+
+impl From<MouseButton> for Binding {
+    fn from(button: MouseButton) -> Binding {
+        Binding::Mouse(button)
+    }
+}
+
+impl From<GamepadButton> for Binding {
+    fn from(button: GamepadButton) -> Binding {
+        Binding::Gamepad(button)
+    }
+}
+
+/// Action bound to a key or button, as stored in the settings
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct ActionBinding {
+    pub action: Action,
+    pub binding: Binding,
+}
+
+/// Built-in sets of bindings, starting points for customization
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum ControlPreset {
+    Standard,
+    /// Mirrored, moving with the left hand and rotating with the right one
+    LeftHanded,
+    /// Everything around WASD, best played with auto soft drop
+    OneHanded,
+}
+
+pub const CONTROL_PRESETS: [ControlPreset; 3] = [
+    ControlPreset::Standard,
+    ControlPreset::LeftHanded,
+    ControlPreset::OneHanded,
+];
+
+impl ControlPreset {
+    pub fn name(self) -> &'static str {
+        match self {
+            ControlPreset::Standard => "Standard",
+            ControlPreset::LeftHanded => "Left-handed",
+            ControlPreset::OneHanded => "One-handed",
+        }
+    }
+
+    pub fn bindings(self) -> Vec<ActionBinding> {
+        let keys: &[(KeyCode, Action)] = match self {
+            ControlPreset::Standard => &[
+                (KeyCode::Right, Action::MoveRight),
+                (KeyCode::Left, Action::MoveLeft),
+                (KeyCode::Down, Action::MoveDown),
+                (KeyCode::Up, Action::RotateClockwise),
+                (KeyCode::X, Action::RotateClockwise),
+                (KeyCode::Z, Action::RotateCounterClockwise),
+                (KeyCode::S, Action::Rotate180),
+                (KeyCode::Space, Action::HardDrop),
+                (KeyCode::LShift, Action::SoftDrop),
+                (KeyCode::C, Action::HoldPiece),
+                (KeyCode::Numpad6, Action::MoveRight),
+                (KeyCode::Numpad4, Action::MoveLeft),
+                (KeyCode::Numpad2, Action::MoveDown),
+                (KeyCode::Numpad9, Action::RotateClockwise),
+                (KeyCode::Numpad7, Action::RotateCounterClockwise),
+                (KeyCode::Numpad8, Action::HardDrop),
+                (KeyCode::Numpad0, Action::HoldPiece),
+                (KeyCode::A, Action::ActivateZone),
+                (KeyCode::Back, Action::UndoDrop),
+            ],
+            ControlPreset::LeftHanded => &[
+                (KeyCode::D, Action::MoveRight),
+                (KeyCode::A, Action::MoveLeft),
+                (KeyCode::S, Action::MoveDown),
+                (KeyCode::W, Action::RotateClockwise),
+                (KeyCode::Comma, Action::RotateClockwise),
+                (KeyCode::Period, Action::RotateCounterClockwise),
+                (KeyCode::Slash, Action::Rotate180),
+                (KeyCode::Space, Action::HardDrop),
+                (KeyCode::RShift, Action::SoftDrop),
+                (KeyCode::M, Action::HoldPiece),
+                (KeyCode::L, Action::ActivateZone),
+                (KeyCode::Back, Action::UndoDrop),
+            ],
+            ControlPreset::OneHanded => &[
+                (KeyCode::D, Action::MoveRight),
+                (KeyCode::A, Action::MoveLeft),
+                (KeyCode::S, Action::MoveDown),
+                (KeyCode::E, Action::RotateClockwise),
+                (KeyCode::Q, Action::RotateCounterClockwise),
+                (KeyCode::Z, Action::Rotate180),
+                (KeyCode::W, Action::HardDrop),
+                (KeyCode::LShift, Action::HoldPiece),
+                (KeyCode::R, Action::ActivateZone),
+                (KeyCode::F, Action::UndoDrop),
+            ],
+        };
+
+        // Every preset plays the same on a gamepad
+        let buttons = [
+            (GamepadButton::DPadRight, Action::MoveRight),
+            (GamepadButton::DPadLeft, Action::MoveLeft),
+            (GamepadButton::DPadDown, Action::MoveDown),
+            (GamepadButton::DPadUp, Action::HardDrop),
+            (GamepadButton::StickRight, Action::MoveRight),
+            (GamepadButton::StickLeft, Action::MoveLeft),
+            (GamepadButton::East, Action::RotateClockwise),
+            (GamepadButton::South, Action::RotateCounterClockwise),
+            (GamepadButton::RightTrigger, Action::Rotate180),
+            (GamepadButton::LeftBumper, Action::HoldPiece),
+            (GamepadButton::RightBumper, Action::HoldPiece),
+            (GamepadButton::North, Action::ActivateZone),
+            (GamepadButton::West, Action::UndoDrop),
+        ];
+
+        keys.iter()
+            .map(|&(keycode, action)| ActionBinding {
+                action,
+                binding: Binding::Key(keycode),
+            })
+            .chain(buttons.iter().map(|&(button, action)| ActionBinding {
+                action,
+                binding: Binding::Gamepad(button),
+            }))
+            .collect()
+    }
+}
+
+impl Default for ControlPreset {
+    fn default() -> ControlPreset {
+        ControlPreset::Standard
+    }
+}
+
+/// Actions keys and buttons can be bound to, the rest only come from the game itself
+pub const BINDABLE_ACTIONS: [Action; 11] = [
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::MoveDown,
+    Action::RotateClockwise,
+    Action::RotateCounterClockwise,
+    Action::Rotate180,
+    Action::HardDrop,
+    Action::SoftDrop,
+    Action::HoldPiece,
+    Action::ActivateZone,
+    Action::UndoDrop,
+];
+
+/// Other actions the key or button is bound to, all of them would fire together with the action
+pub fn conflicts(bindings: &[ActionBinding], action: Action, binding: Binding) -> Vec<Action> {
+    bindings
+        .iter()
+        .filter(|b| b.binding == binding && b.action != action)
+        .map(|b| b.action)
+        .collect()
+}
+
+/// Binds the key or button to the action alone, taking it away from any other action
+pub fn rebind(bindings: &mut Vec<ActionBinding>, action: Action, binding: Binding) {
+    bindings.retain(|b| b.binding != binding);
+    bindings.push(ActionBinding { action, binding });
+}
+
+/// Changes a mode makes on top of the bindings from the settings
+pub struct ControlScheme {
+    pub name: &'static str,
+    /// Actions not allowed in the mode, their keys and buttons do nothing
+    pub disabled: &'static [Action],
+    /// Shown while paused, so that nobody wonders why a key stopped working
+    pub prompts: &'static [&'static str],
+}
+
+impl ControlScheme {
+    pub fn allows(&self, action: Action) -> bool {
+        !self.disabled.contains(&action)
+    }
+
+    pub fn apply(&self, bindings: &[ActionBinding]) -> Vec<ActionBinding> {
+        bindings
+            .iter()
+            .filter(|b| self.allows(b.action))
+            .copied()
+            .collect()
+    }
+}
 
 struct KeyBind {
     actions: Vec<Action>,
@@ -14,17 +404,25 @@ struct KeyBind {
 pub struct Input {
     key_activated: Vec<Option<Duration>>,
     key_repeated: Vec<Option<Duration>>,
-    key_binds: HashMap<KeyCode, KeyBind>,
+    key_binds: HashMap<Binding, KeyBind>,
     actions: Vec<Action>,
-    exclusions: HashMap<KeyCode, Vec<KeyCode>>,
+    buffered: Vec<Action>,
+    buffering: bool,
+    exclusions: HashMap<Binding, Vec<Binding>>,
+    ignore: Vec<Binding>,
+    paused: bool,
+    pressed_at: Option<Instant>,
+    emitted_at: Option<Instant>,
+    /// Bound keys and buttons pressed, for counting inputs
+    presses: u32,
 }
 
 impl Input {
     pub fn new() -> Input {
-        let mut key_activated = Vec::with_capacity(MAX_KEYCODES);
-        let mut key_repeated = Vec::with_capacity(MAX_KEYCODES);
+        let mut key_activated = Vec::with_capacity(MAX_BINDINGS);
+        let mut key_repeated = Vec::with_capacity(MAX_BINDINGS);
 
-        for _ in 0..MAX_KEYCODES {
+        for _ in 0..MAX_BINDINGS {
             key_activated.push(None);
             key_repeated.push(None);
         }
@@ -34,15 +432,52 @@ impl Input {
             key_repeated,
             key_binds: HashMap::new(),
             actions: vec![],
+            buffered: vec![],
+            buffering: false,
             exclusions: HashMap::new(),
+            ignore: vec![],
+            paused: false,
+            pressed_at: None,
+            emitted_at: None,
+            presses: 0,
+        }
+    }
+
+    /// Input with every binding from the settings, movement keys exclude their opposites
+    pub fn from_bindings(bindings: &[ActionBinding]) -> Input {
+        let mut input = Input::new();
+
+        for b in bindings {
+            input.bind(b.binding, b.action, b.action.repeats());
         }
+
+        let opposites = [
+            (Action::MoveLeft, Action::MoveRight),
+            (Action::MoveRight, Action::MoveLeft),
+        ];
+
+        for &(action, opposite) in &opposites {
+            for b in bindings.iter().filter(|b| b.action == action) {
+                for o in bindings.iter().filter(|o| o.action == opposite) {
+                    input.exclude(b.binding, o.binding);
+                }
+            }
+        }
+
+        input
     }
 
-    pub fn bind(&mut self, keycode: KeyCode, action: Action, repeat: bool) -> &mut Input {
-        match self.key_binds.get_mut(&keycode) {
+    pub fn bind<B: Into<Binding>>(
+        &mut self,
+        binding: B,
+        action: Action,
+        repeat: bool,
+    ) -> &mut Input {
+        let binding = binding.into();
+        match self.key_binds.get_mut(&binding) {
             None => {
                 self.key_binds.insert(
-                    keycode,
+                    binding,
                     KeyBind {
                         actions: vec![action],
                         repeat,
@@ -57,17 +492,68 @@ impl Input {
         self
     }
 
-    pub fn exclude(&mut self, keycode: KeyCode, excludes: KeyCode) -> &mut Input {
-        if let Some(exclusions) = self.exclusions.get_mut(&keycode) {
+    pub fn exclude<B: Into<Binding>>(&mut self, binding: B, excludes: B) -> &mut Input {
+        let (binding, excludes) = (binding.into(), excludes.into());
+        if let Some(exclusions) = self.exclusions.get_mut(&binding) {
             exclusions.push(excludes);
         } else {
-            self.exclusions.insert(keycode, vec![excludes]);
+            self.exclusions.insert(binding, vec![excludes]);
         }
 
         self
     }
 
-    pub fn update(&mut self, ctx: &Context, das: u32, arr: u32, paused: bool) {
+    /// Keys pressed while paused are remembered and sent once input resumes,
+    /// e.g. during line clear or entry delay
+    pub fn set_buffering(&mut self, buffering: bool) {
+        self.buffering = buffering;
+    }
+
+    /// Key press event, which arrives before the keyboard is polled in the next update.
+    /// With `immediate` the actions are sent right away, so even taps shorter than a frame count.
+    pub fn key_down(&mut self, binding: Binding, immediate: bool) {
+        let bind = match self.key_binds.get(&binding) {
+            Some(bind) => bind,
+            None => return,
+        };
+
+        self.pressed_at = Some(Instant::now());
+
+        let key = binding.index();
+        if !immediate
+            || self.paused
+            || self.key_activated[key].is_some()
+            || self.ignore.contains(&binding)
+        {
+            return;
+        }
+
+        self.key_activated[key] = Some(Duration::new(0, 0));
+        self.actions.extend(&bind.actions);
+        self.emitted_at = self.pressed_at.take();
+        self.presses += 1;
+    }
+
+    /// Presses of bound keys and buttons since the last call, held keys count once
+    pub fn take_presses(&mut self) -> u32 {
+        mem::replace(&mut self.presses, 0)
+    }
+
+    /// Time of the key press behind the latest new action, used to estimate the input latency
+    pub fn take_emitted_at(&mut self) -> Option<Instant> {
+        self.emitted_at.take()
+    }
+
+    pub fn update(
+        &mut self,
+        ctx: &Context,
+        gamepad: &GamepadState,
+        das: u32,
+        arr: u32,
+        paused: bool,
+    ) {
+        self.paused = paused;
+
         let das = Duration::from_millis(das.into());
         let arr = Duration::from_millis(arr.into());
 
@@ -75,35 +561,50 @@ impl Input {
         let zero = Duration::new(0, 0);
         let dt = timer::delta(ctx);
 
-        let mut ignore: Vec<KeyCode> = vec![];
+        if !paused {
+            self.actions.append(&mut self.buffered);
+        }
+
+        self.ignore.clear();
         for exclusion in &self.exclusions {
-            if pressed_keys.contains(&exclusion.0) {
-                ignore.extend(exclusion.1);
+            if exclusion.0.pressed(ctx, pressed_keys, gamepad) {
+                self.ignore.extend(exclusion.1);
             }
         }
 
-        for (keycode, bind) in &self.key_binds {
-            let key = *keycode as usize;
+        for (binding, bind) in &self.key_binds {
+            let key = binding.index();
 
-            if !pressed_keys.contains(keycode) {
+            if !binding.pressed(ctx, pressed_keys, gamepad) {
                 self.key_activated[key] = None;
                 self.key_repeated[key] = None;
                 continue;
             }
 
-            if ignore.contains(keycode) {
+            if self.ignore.contains(binding) {
                 continue;
             }
 
             let mut active = false;
+            let mut instant = false;
 
             match self.key_activated[key].as_mut() {
                 None => {
-                    if paused {
+                    if paused && self.buffering {
+                        // Charge DAS from the moment of the press
+                        self.key_activated[key] = Some(zero);
+                        self.buffered.extend(&bind.actions);
+                        self.presses += 1;
+                    } else if paused {
                         self.key_activated[key] = Some(das);
                     } else {
                         self.key_activated[key] = Some(zero);
+                        self.presses += 1;
                         active = true;
+
+                        if let Some(pressed_at) = self.pressed_at.take() {
+                            self.emitted_at = Some(pressed_at);
+                        }
                     }
                 }
                 Some(key_activated) => {
@@ -115,12 +616,14 @@ impl Input {
                                 if !paused {
                                     self.key_repeated[key] = Some(zero);
                                     active = true;
+                                    instant = arr == zero;
                                 }
                             }
                             Some(key_repeated) => {
                                 *key_repeated += dt;
 
-                                if *key_repeated >= arr {
+                                // With zero ARR the piece is already at the wall
+                                if arr > zero && *key_repeated >= arr {
                                     *key_repeated = zero;
                                     active = true;
                                 }
@@ -130,13 +633,247 @@ impl Input {
                 }
             };
 
-            if active {
+            if instant {
+                self.actions
+                    .extend(bind.actions.iter().map(|a| a.instant()));
+            } else if active {
                 self.actions.extend(&bind.actions);
             }
         }
     }
 
-    pub fn actions(&mut self) -> Vec<Action> {
-        self.actions.drain(..).collect()
+    /// Called when a new piece spawns. Held keys either keep their DAS charge and move
+    /// the new piece right away, or have to charge again from the start.
+    pub fn spawned(&mut self, preserve_das: bool, arr: u32) {
+        for (binding, bind) in &self.key_binds {
+            let key = binding.index();
+            if !bind.repeat || self.key_activated[key].is_none() {
+                continue;
+            }
+
+            if !preserve_das {
+                self.key_activated[key] = Some(Duration::new(0, 0));
+                self.key_repeated[key] = None;
+            } else if let Some(repeated) = self.key_repeated[key].as_mut() {
+                *repeated = Duration::new(0, 0);
+
+                if arr == 0 {
+                    self.actions
+                        .extend(bind.actions.iter().map(|a| a.instant()));
+                } else {
+                    self.actions.extend(&bind.actions);
+                }
+            }
+        }
+    }
+
+    /// Forgets presses of the action made during line clear or entry delay,
+    /// so that it has to be pressed again once the new piece is in play
+    pub fn discard_buffered(&mut self, action: Action) {
+        self.buffered.retain(|&a| a != action);
     }
+
+    /// Takes the pressed actions, the buffer keeps its capacity for the next frame
+    pub fn actions(&mut self) -> Drain<Action> {
+        self.actions.drain(..)
+    }
+}
+
+/// Gravity multiplier of the soft drop for the stick pushed down this far, from 0 to 1.
+/// Grows linearly past the dead zone up to `max` at full deflection, 0 ignores the stick.
+pub fn analog_soft_drop(deflection: f32, max: u32) -> f32 {
+    if max <= 1 || deflection <= STICK_DEAD_ZONE {
+        return 1.0;
+    }
+
+    let amount = ((deflection - STICK_DEAD_ZONE) / (1.0 - STICK_DEAD_ZONE)).min(1.0);
+    1.0 + amount * (max - 1) as f32
+}
+
+/// Alternative control scheme, the piece follows the mouse horizontally,
+/// left and right buttons rotate and the wheel or middle button hard drops
+#[derive(Default)]
+pub struct MouseInput {
+    pressed: [bool; 3],
+    column: Option<i32>,
+    piece: u32,
+    actions: Vec<Action>,
+}
+
+impl MouseInput {
+    /// `origin` is the top left corner of the stack on the screen,
+    /// `piece_column` the column in the middle of the current piece
+    /// and `piece` the number of the current piece
+    pub fn update(
+        &mut self,
+        ctx: &mut Context,
+        origin: Point2<f32>,
+        block_size: i32,
+        piece_column: i32,
+        piece: u32,
+        wheel: f32,
+    ) -> Drain<Action> {
+        let mouse = utils::mouse_position_coords(ctx);
+        let column = ((mouse.x - origin.x) / block_size as f32).floor() as i32;
+
+        // Only follow the mouse when it moves or a new piece enters,
+        // so that a piece blocked on the way doesn't retry every frame
+        if Some(column) != self.column || piece != self.piece {
+            self.column = Some(column);
+            self.piece = piece;
+
+            let shift = column - piece_column;
+            let action = if shift > 0 {
+                Action::MoveRight
+            } else {
+                Action::MoveLeft
+            };
+
+            for _ in 0..shift.abs() {
+                self.actions.push(action);
+            }
+        }
+
+        let buttons = [
+            (MouseButton::Left, Action::RotateCounterClockwise),
+            (MouseButton::Right, Action::RotateClockwise),
+            (MouseButton::Middle, Action::HardDrop),
+        ];
+
+        for (i, &(button, action)) in buttons.iter().enumerate() {
+            let pressed = ggez::input::mouse::button_pressed(ctx, button);
+            if pressed && !self.pressed[i] {
+                self.actions.push(action);
+            }
+            self.pressed[i] = pressed;
+        }
+
+        if wheel.abs() > 0.0 {
+            self.actions.push(Action::HardDrop);
+        }
+
+        self.actions.drain(..)
+    }
+}
+
+#[test]
+fn control_scheme_test() {
+    let scheme = ControlScheme {
+        name: "Test",
+        disabled: &[Action::HardDrop],
+        prompts: &[],
+    };
+
+    let bindings = [
+        ActionBinding {
+            action: Action::HardDrop,
+            binding: Binding::Key(KeyCode::Space),
+        },
+        ActionBinding {
+            action: Action::MoveLeft,
+            binding: Binding::Key(KeyCode::Left),
+        },
+    ];
+
+    assert_eq!(scheme.apply(&bindings), vec![bindings[1]]);
+    assert!(!scheme.allows(Action::HardDrop));
+}
+
+#[test]
+fn control_presets_test() {
+    let actions = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::RotateClockwise,
+        Action::RotateCounterClockwise,
+        Action::Rotate180,
+        Action::HardDrop,
+        Action::HoldPiece,
+    ];
+
+    for preset in &CONTROL_PRESETS {
+        let bindings = preset.bindings();
+        for action in &actions {
+            assert!(bindings.iter().any(|b| b.action == *action));
+        }
+
+        let keys: HashSet<Binding> = bindings.iter().map(|b| b.binding).collect();
+        assert_eq!(keys.len(), bindings.len());
+    }
+}
+
+#[test]
+fn rebind_test() {
+    let mut bindings = ControlPreset::Standard.bindings();
+    let count = bindings.len();
+    let space = Binding::Key(KeyCode::Space);
+
+    assert_eq!(
+        conflicts(&bindings, Action::HoldPiece, space),
+        vec![Action::HardDrop]
+    );
+    assert!(conflicts(&bindings, Action::HardDrop, space).is_empty());
+
+    rebind(&mut bindings, Action::HoldPiece, space);
+    assert_eq!(bindings.len(), count);
+    assert!(conflicts(&bindings, Action::HoldPiece, space).is_empty());
+    assert!(!bindings
+        .iter()
+        .any(|b| b.action == Action::HardDrop && b.binding == space));
+
+    rebind(&mut bindings, Action::HoldPiece, Binding::Key(KeyCode::V));
+    assert_eq!(bindings.len(), count + 1);
+}
+
+#[test]
+fn gamepad_test() {
+    let mut gamepad = GamepadState::default();
+
+    assert_eq!(gamepad.axis(Axis::LeftStickX, -0.3), None);
+    assert_eq!(
+        gamepad.axis(Axis::LeftStickX, -0.8),
+        Some(GamepadButton::StickLeft)
+    );
+    assert_eq!(gamepad.axis(Axis::LeftStickX, -0.9), None);
+    assert!(gamepad.pressed(GamepadButton::StickLeft));
+
+    // Flicking the stick over releases the other direction
+    assert_eq!(
+        gamepad.axis(Axis::LeftStickX, 1.0),
+        Some(GamepadButton::StickRight)
+    );
+    assert!(!gamepad.pressed(GamepadButton::StickLeft));
+
+    assert_eq!(
+        gamepad.axis(Axis::LeftStickY, -1.0),
+        Some(GamepadButton::StickDown)
+    );
+    assert_eq!(gamepad.axis(Axis::RightStickX, 1.0), None);
+
+    gamepad.button(GamepadButton::South, true);
+    assert!(gamepad.pressed(GamepadButton::South));
+    gamepad.button(GamepadButton::South, false);
+    assert!(!gamepad.pressed(GamepadButton::South));
+
+    assert_eq!(GamepadButton::StickUp.menu_key(), Some(KeyCode::Up));
+    assert_eq!(GamepadButton::East.menu_key(), Some(KeyCode::Escape));
+    assert_eq!(GamepadButton::Start.menu_key(), None);
+
+    let last = Binding::Gamepad(GamepadButton::StickRight).index();
+    assert!(last < MAX_BINDINGS);
+    assert!(
+        Binding::Mouse(MouseButton::Other(100)).index()
+            < Binding::Gamepad(GamepadButton::South).index()
+    );
+}
+
+#[test]
+fn analog_soft_drop_test() {
+    assert_eq!(analog_soft_drop(0.1, 20), 1.0);
+    assert_eq!(analog_soft_drop(-1.0, 20), 1.0);
+    assert_eq!(analog_soft_drop(1.0, 20), 20.0);
+    assert_eq!(analog_soft_drop(1.0, 0), 1.0);
+
+    let half = analog_soft_drop(0.6, 21);
+    assert!((half - 11.0).abs() < 0.001);
 }
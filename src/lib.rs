@@ -0,0 +1,61 @@
+//! Game logic shared by the executable and the benchmarks
+
+pub mod action;
+pub mod args;
+pub mod assets;
+pub mod bag;
+pub mod battle;
+pub mod blocks;
+pub mod bot;
+pub mod camera;
+pub mod clear;
+pub mod clock;
+pub mod coach;
+pub mod coop;
+pub mod crash;
+#[cfg(test)]
+mod determinism;
+pub mod drill;
+pub mod engine;
+pub mod feed;
+pub mod finesse;
+#[cfg(test)]
+mod fuzz;
+pub mod game;
+pub mod gameplay;
+pub mod global;
+pub mod handicap;
+pub mod hints;
+pub mod history;
+pub mod holder;
+pub mod hud;
+pub mod imgui_wrapper;
+pub mod input;
+pub mod macros;
+pub mod metronome;
+pub mod miniature;
+pub mod mode;
+pub mod monochrome;
+pub mod music;
+pub mod net;
+pub mod objectives;
+pub mod particles;
+pub mod piece;
+pub mod popups;
+pub mod postprocess;
+pub mod profiler;
+pub mod replay;
+pub mod rotation;
+pub mod rules;
+pub mod scene;
+pub mod scheduler;
+pub mod score;
+pub mod session;
+pub mod settings;
+pub mod sfx;
+pub mod shape;
+pub mod stack;
+pub mod stats;
+pub mod utils;
+pub mod verify;
+pub mod zone;
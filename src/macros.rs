@@ -0,0 +1,97 @@
+use ggez::input::keyboard::KeyCode;
+use serde::{Deserialize, Serialize};
+
+use crate::action::Action;
+
+/// Hotkeys playing back the macros, one macro per key
+pub const MACRO_KEYS: [KeyCode; 4] = [KeyCode::F5, KeyCode::F6, KeyCode::F7, KeyCode::F8];
+
+/// Longest sequence a macro can hold, enough for an opener spanning several pieces
+pub const MAX_ACTIONS: usize = 64;
+
+/// Recorded sequence of player actions, stored with the input settings
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Macro {
+    pub name: String,
+    pub key: KeyCode,
+    pub actions: Vec<Action>,
+}
+
+impl Macro {
+    /// Only actions a player could have pressed are recorded,
+    /// gravity and locking come from the engine during playback as usual
+    pub fn recordable(action: Action) -> bool {
+        match action {
+            Action::FallPiece | Action::LockPiece | Action::GameOver => false,
+            _ => true,
+        }
+    }
+
+    /// First hotkey not taken by any of the macros
+    pub fn free_key(macros: &[Macro]) -> Option<KeyCode> {
+        MACRO_KEYS
+            .iter()
+            .find(|&&k| macros.iter().all(|m| m.key != k))
+            .copied()
+    }
+}
+
+#[derive(Default)]
+pub struct Recorder {
+    actions: Option<Vec<Action>>,
+}
+
+impl Recorder {
+    pub fn start(&mut self) {
+        self.actions = Some(vec![]);
+    }
+
+    pub fn recording(&self) -> bool {
+        self.actions.is_some()
+    }
+
+    pub fn record(&mut self, action: Action) {
+        if let Some(actions) = &mut self.actions {
+            if Macro::recordable(action) && actions.len() < MAX_ACTIONS {
+                actions.push(action);
+            }
+        }
+    }
+
+    /// Recorded actions, empty when nothing was being recorded
+    pub fn stop(&mut self) -> Vec<Action> {
+        self.actions.take().unwrap_or_default()
+    }
+}
+
+#[test]
+fn recorder_test() {
+    let mut recorder = Recorder::default();
+    recorder.record(Action::HardDrop);
+    assert!(!recorder.recording());
+
+    recorder.start();
+    for &action in &[Action::MoveLeft, Action::FallPiece, Action::HardDrop] {
+        recorder.record(action);
+    }
+    recorder.record(Action::LockPiece);
+
+    assert_eq!(recorder.stop(), vec![Action::MoveLeft, Action::HardDrop]);
+    assert!(recorder.stop().is_empty());
+
+    let mut macros = vec![Macro {
+        name: String::from("Opener"),
+        key: KeyCode::F5,
+        actions: vec![],
+    }];
+    assert_eq!(Macro::free_key(&macros), Some(KeyCode::F6));
+
+    for &key in &MACRO_KEYS[1..] {
+        macros.push(Macro {
+            name: String::new(),
+            key,
+            actions: vec![],
+        });
+    }
+    assert_eq!(Macro::free_key(&macros), None);
+}
@@ -1,24 +1,4 @@
-mod action;
-mod bag;
-mod blocks;
-mod game;
-mod gameplay;
-mod global;
-mod holder;
-mod imgui_wrapper;
-mod input;
-mod particles;
-mod piece;
-mod popups;
-mod replay;
-mod score;
-mod settings;
-mod sfx;
-mod shape;
-mod stack;
-mod utils;
-
-use std::{ffi::OsStr, panic, thread};
+use std::{ffi::OsStr, panic, process, thread};
 
 use backtrace::Backtrace;
 use env_logger;
@@ -26,7 +6,14 @@ use ggez::{conf, event, filesystem, graphics, ContextBuilder, GameResult};
 use imgui::ImString;
 use log::{self, LevelFilter};
 
-use crate::{game::Game, global::Global, sfx::Sfx};
+use klocki::{
+    args::{Args, USAGE},
+    crash,
+    game::Game,
+    global::Global,
+    sfx::Sfx,
+    utils, verify,
+};
 
 fn main() {
     std::env::set_var("WINIT_UNIX_BACKEND", "x11");
@@ -51,29 +38,52 @@ fn main() {
             },
         };
 
-        match info.location() {
-            Some(location) => {
-                log::error!(
-                    "thread '{}' panicked at '{}': {}:{}\n{:?}",
-                    thread,
-                    msg,
-                    location.file(),
-                    location.line(),
-                    backtrace
-                );
-            }
-            None => log::error!("thread '{}' panicked at '{}'\n{:?}", thread, msg, backtrace),
-        }
+        let report = match info.location() {
+            Some(location) => format!(
+                "thread '{}' panicked at '{}': {}:{}\n{:?}",
+                thread,
+                msg,
+                location.file(),
+                location.line(),
+                backtrace
+            ),
+            None => format!("thread '{}' panicked at '{}'\n{:?}", thread, msg, backtrace),
+        };
+
+        log::error!("{}", report);
+        crash::dump(&report);
     }));
 
-    if let Some(err) = real_main().err() {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if args.help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    if let Some(path) = &args.verify {
+        process::exit(verify::run(path));
+    }
+
+    if let Some(err) = real_main(&args).err() {
         log::error!("{}", err);
     }
 }
 
-fn real_main() -> GameResult {
+fn real_main(args: &Args) -> GameResult {
     loop {
         let mut g = Global::new();
+        g.imgui_state.crash_report = crash::pending();
+
+        if args.fullscreen {
+            g.settings.graphics.fullscreen = true;
+        }
 
         const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -84,7 +94,7 @@ fn real_main() -> GameResult {
                 conf::WindowSetup::default()
                     .title(&format!("Klocki v{}", VERSION))
                     .samples(g.settings.graphics.multi_sampling)
-                    .vsync(g.settings.graphics.vsync),
+                    .vsync(g.settings.graphics.vsync && !g.settings.input.low_latency),
             )
             .window_mode(
                 conf::WindowMode::default()
@@ -113,6 +123,11 @@ fn real_main() -> GameResult {
             cb = cb.add_zipfile_bytes(include_bytes!("../target/resources.zip").to_vec());
         }
 
+        if let Some(resources) = &args.resources {
+            log::debug!("Adding resources path {:?}", resources);
+            cb = cb.add_resource_path(resources);
+        }
+
         let (ctx, event_loop) = &mut cb.build()?;
 
         graphics::set_window_icon(ctx, Some(utils::path(ctx, "icon.ico")))?;
@@ -137,9 +152,13 @@ fn real_main() -> GameResult {
             .position(|s| s.to_str() == g.settings.gameplay.skin)
             .unwrap_or_default();
 
-        g.sfx = Sfx::load(ctx, g.settings.audio.sfx_volume)?;
+        g.sfx = Sfx::load(
+            ctx,
+            g.settings.audio.sfx_volume,
+            g.settings.audio.sfx_voices,
+        )?;
 
-        let game = &mut Game::new(ctx, g)?;
+        let game = &mut Game::new(ctx, g, args)?;
 
         log::info!("Starting the event loop");
 
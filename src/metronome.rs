@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+pub const MIN_BPM: u32 = 40;
+pub const MAX_BPM: u32 = 240;
+
+/// Part of a beat the border pulse takes to fade out
+const PULSE_LENGTH: f32 = 0.25;
+
+const TICK_SAMPLE_RATE: u32 = 22050;
+const TICK_FREQUENCY: f32 = 1760.0;
+/// 40ms at the sample rate above
+const TICK_SAMPLES: u32 = 882;
+
+/// How the beat is given away, the pulse being an alternative for playing without sound
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum MetronomeMode {
+    Off,
+    Tick,
+    Pulse,
+    Both,
+}
+
+pub const METRONOME_MODES: [MetronomeMode; 4] = [
+    MetronomeMode::Off,
+    MetronomeMode::Tick,
+    MetronomeMode::Pulse,
+    MetronomeMode::Both,
+];
+
+impl MetronomeMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            MetronomeMode::Off => "Off",
+            MetronomeMode::Tick => "Tick",
+            MetronomeMode::Pulse => "Border pulse",
+            MetronomeMode::Both => "Tick and pulse",
+        }
+    }
+
+    pub fn tick(self) -> bool {
+        self == MetronomeMode::Tick || self == MetronomeMode::Both
+    }
+
+    pub fn pulse(self) -> bool {
+        self == MetronomeMode::Pulse || self == MetronomeMode::Both
+    }
+}
+
+impl Default for MetronomeMode {
+    fn default() -> MetronomeMode {
+        MetronomeMode::Off
+    }
+}
+
+/// Keeps the beat for rhythm based stacking practice
+#[derive(Default)]
+pub struct Metronome {
+    elapsed: Duration,
+}
+
+impl Metronome {
+    fn beat(bpm: u32) -> Duration {
+        Duration::from_secs(60) / bpm.max(1)
+    }
+
+    pub fn reset(&mut self) {
+        self.elapsed = Duration::new(0, 0);
+    }
+
+    /// Returns true on every beat
+    pub fn update(&mut self, dt: Duration, bpm: u32) -> bool {
+        let beat = Metronome::beat(bpm);
+        self.elapsed += dt;
+
+        if self.elapsed < beat {
+            return false;
+        }
+
+        self.elapsed = Duration::from_nanos((self.elapsed.as_nanos() % beat.as_nanos()) as u64);
+        true
+    }
+
+    /// From 1 right on the beat down to 0 a moment later
+    pub fn pulse(&self, bpm: u32) -> f32 {
+        let progress = self.elapsed.as_secs_f32() / Metronome::beat(bpm).as_secs_f32();
+        (1.0 - progress / PULSE_LENGTH).max(0.0)
+    }
+}
+
+/// Short fading blip as a WAV file, there is no sample for the tick among the sounds
+pub fn tick_wav() -> Vec<u8> {
+    let data_size = TICK_SAMPLES * 2;
+
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&TICK_SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(TICK_SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+
+    for i in 0..TICK_SAMPLES {
+        let t = i as f32 / TICK_SAMPLE_RATE as f32;
+        let envelope = (1.0 - i as f32 / TICK_SAMPLES as f32).powi(3);
+        let sample = (t * TICK_FREQUENCY * std::f32::consts::PI * 2.0).sin() * envelope;
+        wav.extend_from_slice(&((sample * i16::max_value() as f32 * 0.8) as i16).to_le_bytes());
+    }
+
+    wav
+}
+
+#[test]
+fn metronome_test() {
+    let mut metronome = Metronome::default();
+    assert!(!metronome.update(Duration::from_millis(400), 120));
+    assert!(metronome.update(Duration::from_millis(150), 120));
+    assert!((metronome.pulse(120) - 0.6).abs() < 0.001);
+
+    assert!(!metronome.update(Duration::from_millis(100), 120));
+    assert_eq!(metronome.pulse(120), 0.0);
+
+    let wav = tick_wav();
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(wav.len(), 44 + TICK_SAMPLES as usize * 2);
+}
@@ -0,0 +1,109 @@
+use ggez::{
+    graphics::{self, Color, DrawMode, DrawParam, Image, Mesh, MeshBuilder, Rect},
+    nalgebra::Point2,
+    Context, GameResult,
+};
+
+use crate::{blocks::Blocks, engine::Engine, profiler};
+
+/// Miniatures are drawn at a quarter of the regular block size
+pub const MINIATURE_SCALE: i32 = 4;
+
+/// Cheap view of a board for opponents and thumbnails, without the grid, popups or particles.
+/// All blocks, including the active piece, go through a single sprite batch.
+pub struct Miniature {
+    blocks: Blocks,
+    frame: Option<Mesh>,
+    frame_size: (i32, i32, i32),
+}
+
+impl Miniature {
+    pub fn new(ctx: &mut Context, tileset: Image) -> Miniature {
+        Miniature {
+            blocks: Blocks::new(ctx, tileset),
+            frame: None,
+            frame_size: (0, 0, 0),
+        }
+    }
+
+    /// Block size of a miniature next to a board drawn with the given block size
+    pub fn block_size(block_size: i32) -> i32 {
+        (block_size / MINIATURE_SCALE).max(2)
+    }
+
+    fn frame(&mut self, ctx: &mut Context, width: i32, height: i32, block_size: i32) -> GameResult {
+        if self.frame.is_some() && self.frame_size == (width, height, block_size) {
+            return Ok(());
+        }
+
+        const BACKGROUND_COLOR: Color = Color::new(0.02, 0.03, 0.04, 0.95);
+        const OUTLINE_COLOR: Color = Color::new(0.7, 0.8, 0.9, 0.8);
+
+        let rect = Rect::new(
+            0.0,
+            0.0,
+            (width * block_size) as f32,
+            (height * block_size) as f32,
+        );
+
+        let mesh = MeshBuilder::new()
+            .rectangle(DrawMode::fill(), rect, BACKGROUND_COLOR)
+            .rectangle(DrawMode::stroke(1.0), rect, OUTLINE_COLOR)
+            .build(ctx)?;
+
+        self.frame = Some(mesh);
+        self.frame_size = (width, height, block_size);
+        Ok(())
+    }
+
+    pub fn draw(
+        &mut self,
+        ctx: &mut Context,
+        engine: &Engine,
+        position: Point2<f32>,
+        block_size: i32,
+    ) -> GameResult {
+        let stack = &engine.stack;
+        self.frame(ctx, stack.width, stack.height, block_size)?;
+
+        if let Some(frame) = &self.frame {
+            profiler::count_draw();
+            graphics::draw(ctx, frame, DrawParam::new().dest(position))?;
+        }
+
+        self.blocks.clear();
+
+        let grid = stack.grid();
+        for y in 0..stack.height {
+            for (x, &block) in grid[(stack.vanish + y) as usize].iter().enumerate() {
+                if block != 0 {
+                    let dest = Point2::new(
+                        position[0] + (x as i32 * block_size) as f32,
+                        position[1] + (y * block_size) as f32,
+                    );
+                    self.blocks.add(block, block_size, dest, 1.0);
+                }
+            }
+        }
+
+        if !engine.entering() && !engine.game_over() {
+            let piece = &engine.piece;
+            for (my, row) in piece.grid().grid.iter().enumerate() {
+                for (mx, &block) in row.iter().enumerate() {
+                    let y = piece.y + my as i32 - stack.vanish;
+                    if block == 0 || y < 0 {
+                        continue;
+                    }
+
+                    let dest = Point2::new(
+                        position[0] + ((piece.x + mx as i32) * block_size) as f32,
+                        position[1] + (y * block_size) as f32,
+                    );
+                    self.blocks.add(block, block_size, dest, 1.0);
+                }
+            }
+        }
+
+        self.blocks.draw(ctx)
+    }
+}
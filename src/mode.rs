@@ -0,0 +1,235 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{action::Action, input::ControlScheme, rules::Rules};
+
+const CLASSIC_CONTROLS: ControlScheme = ControlScheme {
+    name: "Classic",
+    disabled: &[Action::HoldPiece, Action::HardDrop],
+    prompts: &["No hold", "No hard drop, soft drop the piece down instead"],
+};
+
+/// What ends the game with a win, instead of only topping out
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum WinCondition {
+    /// Clearing this many lines, the faster the better
+    Lines(i32),
+    /// Surviving until the time runs out, the higher the score the better
+    TimeLimit(Duration),
+}
+
+impl WinCondition {
+    pub fn met(self, lines: i32, time: Duration) -> bool {
+        match self {
+            WinCondition::Lines(target) => lines >= target,
+            WinCondition::TimeLimit(limit) => time >= limit,
+        }
+    }
+
+    /// Play time of a finished game, which doesn't run over the limit
+    pub fn elapsed(self, time: Duration) -> Duration {
+        match self {
+            WinCondition::Lines(_) => time,
+            WinCondition::TimeLimit(limit) => time.min(limit),
+        }
+    }
+
+    /// Games are ranked by time, otherwise by score
+    pub fn race(self) -> bool {
+        match self {
+            WinCondition::Lines(_) => true,
+            WinCondition::TimeLimit(_) => false,
+        }
+    }
+
+    /// Label and time shown in the timer, counting down when there's a limit
+    pub fn clock(self, time: Duration) -> (&'static str, Duration) {
+        match self {
+            WinCondition::Lines(_) => ("Time", time),
+            WinCondition::TimeLimit(limit) => {
+                ("Time left", limit.checked_sub(time).unwrap_or_default())
+            }
+        }
+    }
+
+    /// What is still left to do, shown under the timer
+    pub fn remaining(self, lines: i32) -> Option<String> {
+        match self {
+            WinCondition::Lines(target) => Some(format!("{} lines left", (target - lines).max(0))),
+            WinCondition::TimeLimit(_) => None,
+        }
+    }
+
+    /// What has to be done to win, for the rules card
+    pub fn goal(self) -> String {
+        match self {
+            WinCondition::Lines(target) => format!("Clear {} lines as fast as possible", target),
+            WinCondition::TimeLimit(limit) => format!(
+                "Score as much as possible in {}:{:02}",
+                limit.as_secs() / 60,
+                limit.as_secs() % 60
+            ),
+        }
+    }
+
+    /// Popup and title of the results once the game is over
+    pub fn ending(self) -> &'static str {
+        match self {
+            WinCondition::Lines(_) => "Finished",
+            WinCondition::TimeLimit(_) => "Time's up",
+        }
+    }
+}
+
+/// How fast a game with a win condition was won
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RaceResult {
+    pub time: Duration,
+    pub pieces: u32,
+}
+
+impl RaceResult {
+    /// Pieces per second
+    pub fn pps(&self) -> f32 {
+        match self.time.as_secs_f32() {
+            seconds if seconds > 0.0 => self.pieces as f32 / seconds,
+            _ => 0.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum GameMode {
+    Endless,
+    /// Games with custom rules, where macros can be played back
+    Practice,
+    /// Old school rules, without hold and hard drop
+    Classic,
+    /// Levels speeding up every 10 lines, each scoring more than the last
+    Marathon,
+    /// Clearing 40 lines as fast as possible
+    Sprint,
+    /// Scoring as much as possible in two minutes
+    Ultra,
+    /// Pieces clear by touching others of the same color instead of filling rows
+    Colors,
+}
+
+pub const GAME_MODES: [GameMode; 7] = [
+    GameMode::Endless,
+    GameMode::Practice,
+    GameMode::Classic,
+    GameMode::Marathon,
+    GameMode::Sprint,
+    GameMode::Ultra,
+    GameMode::Colors,
+];
+
+impl GameMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Endless => "endless",
+            GameMode::Practice => "practice",
+            GameMode::Classic => "classic",
+            GameMode::Marathon => "marathon",
+            GameMode::Sprint => "sprint",
+            GameMode::Ultra => "ultra",
+            GameMode::Colors => "colors",
+        }
+    }
+
+    /// Rules the mode starts with, practice games usually come with their own
+    pub fn rules(self) -> Rules {
+        match self {
+            GameMode::Endless | GameMode::Practice | GameMode::Sprint | GameMode::Ultra => {
+                Rules::default()
+            }
+            GameMode::Classic => Rules::classic(),
+            GameMode::Marathon => Rules::marathon(),
+            GameMode::Colors => Rules::colors(),
+        }
+    }
+
+    /// Changes the mode makes to the controls of the player, if any
+    pub fn controls(self) -> Option<&'static ControlScheme> {
+        match self {
+            GameMode::Endless
+            | GameMode::Practice
+            | GameMode::Marathon
+            | GameMode::Sprint
+            | GameMode::Ultra
+            | GameMode::Colors => None,
+            GameMode::Classic => Some(&CLASSIC_CONTROLS),
+        }
+    }
+
+    /// Modes without one go on until the player tops out
+    pub fn win_condition(self) -> Option<WinCondition> {
+        match self {
+            GameMode::Endless
+            | GameMode::Practice
+            | GameMode::Classic
+            | GameMode::Marathon
+            | GameMode::Colors => None,
+            GameMode::Sprint => Some(WinCondition::Lines(40)),
+            GameMode::Ultra => Some(WinCondition::TimeLimit(Duration::from_secs(120))),
+        }
+    }
+
+    /// The rules card of the mode played with the given rules, its goal coming last
+    pub fn summary(self, rules: &Rules) -> Vec<(&'static str, String)> {
+        let goal = match self.win_condition() {
+            Some(win_condition) => win_condition.goal(),
+            None => String::from("Survive as long as possible"),
+        };
+
+        let mut summary = rules.summary();
+        summary.push(("Goal", goal));
+        summary
+    }
+
+    pub fn practice(self) -> bool {
+        self == GameMode::Practice
+    }
+
+    /// Misdrops can be taken back, neither the modes played against others
+    /// nor the ones played against the clock allow that
+    pub fn casual(self) -> bool {
+        match self {
+            GameMode::Endless | GameMode::Practice | GameMode::Classic | GameMode::Colors => true,
+            GameMode::Marathon | GameMode::Sprint | GameMode::Ultra => false,
+        }
+    }
+}
+
+impl Default for GameMode {
+    fn default() -> GameMode {
+        GameMode::Endless
+    }
+}
+
+impl fmt::Display for GameMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for GameMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GameMode, String> {
+        GAME_MODES
+            .iter()
+            .find(|m| m.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<&str> = GAME_MODES.iter().map(|m| m.name()).collect();
+                format!(
+                    "unknown mode '{}', expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
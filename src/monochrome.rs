@@ -0,0 +1,124 @@
+use ggez::{graphics::Image, Context, GameResult};
+
+use crate::blocks::BLOCKS_NUM;
+
+/// Fill of a tile, telling the pieces apart without relying on color
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Pattern {
+    Plain,
+    HorizontalStripes,
+    VerticalStripes,
+    DiagonalStripes,
+    Dots,
+    AntiDiagonalStripes,
+    CrossHatch,
+    Checkers,
+}
+
+/// Pattern and shade of every block id, in the order of the tiles in a skin
+const TILES: [(Pattern, u8); BLOCKS_NUM] = [
+    (Pattern::Plain, 200),
+    (Pattern::HorizontalStripes, 215),
+    (Pattern::VerticalStripes, 150),
+    (Pattern::DiagonalStripes, 185),
+    (Pattern::Dots, 230),
+    (Pattern::AntiDiagonalStripes, 165),
+    (Pattern::CrossHatch, 205),
+    (Pattern::Checkers, 140),
+    // Garbage
+    (Pattern::Plain, 95),
+    (Pattern::Plain, 240),
+];
+
+impl Pattern {
+    /// Whether the pixel is drawn darker
+    fn marked(self, x: usize, y: usize, spacing: usize) -> bool {
+        let width = (spacing / 3).max(1);
+
+        match self {
+            Pattern::Plain => false,
+            Pattern::HorizontalStripes => y % spacing < width,
+            Pattern::VerticalStripes => x % spacing < width,
+            Pattern::DiagonalStripes => (x + y) % spacing < width,
+            Pattern::AntiDiagonalStripes => (x + spacing * 64 - y) % spacing < width,
+            Pattern::CrossHatch => {
+                (x + y) % spacing < width || (x + spacing * 64 - y) % spacing < width
+            }
+            Pattern::Dots => {
+                let center = spacing / 2;
+                let dx = (x % spacing) as i32 - center as i32;
+                let dy = (y % spacing) as i32 - center as i32;
+                (dx * dx + dy * dy) as usize <= width * width
+            }
+            Pattern::Checkers => (x / spacing + y / spacing) % 2 == 0,
+        }
+    }
+}
+
+/// Pixels of a skin with shaded gray tiles, one pattern per piece
+pub fn pixels(size: usize) -> Vec<u8> {
+    let width = size * BLOCKS_NUM;
+    // Grows slower than the tile, so larger blocks fit more of the pattern
+    let spacing = 3 + size / 12;
+    let bevel = (size / 10).max(1);
+    let mut rgba = vec![255u8; width * size * 4];
+
+    for (i, &(pattern, shade)) in TILES.iter().enumerate() {
+        for y in 0..size {
+            for x in 0..size {
+                let mut value = i32::from(shade);
+
+                if x < bevel || y < bevel {
+                    value += 35;
+                } else if x >= size - bevel || y >= size - bevel {
+                    value -= 60;
+                } else if pattern.marked(x, y, spacing) {
+                    value -= 70;
+                }
+
+                let value = value.max(0).min(255) as u8;
+                let pixel = (y * width + i * size + x) * 4;
+                rgba[pixel..pixel + 3].copy_from_slice(&[value, value, value]);
+            }
+        }
+    }
+
+    rgba
+}
+
+/// Skin generated at runtime instead of being loaded from the blocks directory
+pub fn tileset(ctx: &mut Context, size: u16) -> GameResult<Image> {
+    let size = size.max(8);
+    let rgba = pixels(usize::from(size));
+    Image::from_rgba8(ctx, size * BLOCKS_NUM as u16, size, &rgba)
+}
+
+#[test]
+fn monochrome_test() {
+    let size = 24;
+    let rgba = pixels(size);
+    assert_eq!(rgba.len(), size * size * BLOCKS_NUM * 4);
+
+    // Every pixel is gray and opaque
+    assert!(rgba
+        .chunks(4)
+        .all(|p| p[0] == p[1] && p[1] == p[2] && p[3] == 255));
+
+    // The pieces don't look the same
+    let tile = |i: usize| -> Vec<u8> {
+        (0..size)
+            .flat_map(|y| {
+                let start = (y * size * BLOCKS_NUM + i * size) * 4;
+                rgba[start..start + size * 4].to_vec()
+            })
+            .collect()
+    };
+    for a in 1..8 {
+        for b in a + 1..8 {
+            assert_ne!(tile(a), tile(b));
+        }
+    }
+
+    assert!(Pattern::Dots.marked(2, 2, 4));
+    assert!(!Pattern::Dots.marked(0, 0, 4));
+}
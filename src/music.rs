@@ -0,0 +1,94 @@
+use ggez::{
+    audio::{self, SoundSource},
+    Context, GameResult,
+};
+
+use crate::{settings, utils};
+
+/// Soundtrack that fits what the player is currently doing
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Track {
+    /// Menus and results are quiet, whatever played before fades out
+    Silence,
+    Gameplay,
+}
+
+/// Tracks that can be heard, with their paths in the resources
+const TRACKS: [(Track, &str); 1] = [(Track::Gameplay, "chiptronical.ogg")];
+
+struct Channel {
+    track: Track,
+    source: audio::Source,
+    fade: f32,
+}
+
+/// Fades the tracks in and out whenever the requested track changes
+pub struct Music {
+    channels: Vec<Channel>,
+    current: Option<Track>,
+}
+
+impl Music {
+    pub fn new(ctx: &mut Context) -> GameResult<Music> {
+        let mut channels = vec![];
+
+        for &(track, path) in TRACKS.iter() {
+            let mut source = audio::Source::new(ctx, utils::path(ctx, path))?;
+            source.set_repeat(true);
+            channels.push(Channel {
+                track,
+                source,
+                fade: 0.0,
+            });
+        }
+
+        Ok(Music {
+            channels,
+            current: None,
+        })
+    }
+
+    pub fn update(
+        &mut self,
+        ctx: &mut Context,
+        track: Track,
+        audio: &settings::Audio,
+    ) -> GameResult {
+        let dt = utils::dt_f32(ctx);
+        let volume = audio.music_volume as f32 / 100.0;
+
+        let switched = self.current != Some(track);
+        self.current = Some(track);
+
+        for channel in &mut self.channels {
+            if channel.track == track {
+                if switched {
+                    channel.source.play()?;
+                }
+
+                channel.fade = if audio.music_fade_in == 0 {
+                    1.0
+                } else {
+                    (channel.fade + dt * 1000.0 / audio.music_fade_in as f32).min(1.0)
+                };
+            } else if channel.fade > 0.0 {
+                channel.fade = if audio.music_fade_out == 0 {
+                    0.0
+                } else {
+                    (channel.fade - dt * 1000.0 / audio.music_fade_out as f32).max(0.0)
+                };
+
+                if channel.fade == 0.0 {
+                    channel.source.stop();
+                }
+            }
+
+            let channel_volume = volume * channel.fade;
+            if (channel.source.volume() - channel_volume).abs() > 0.001 {
+                channel.source.set_volume(channel_volume);
+            }
+        }
+
+        Ok(())
+    }
+}
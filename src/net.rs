@@ -0,0 +1,940 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    mem,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+use bincode;
+use ggez::{Context, GameResult};
+use rand::{rngs::ThreadRng, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    battle::AttackPool,
+    engine::{Engine, Snapshot},
+    gameplay::Gameplay,
+    global::Global,
+    history::{self, MatchRecord},
+    piece::Piece,
+    replay::{Checkpoint, TimedAction, CHECKPOINT_INTERVAL},
+    rules::Rules,
+    shape::ShapeType,
+    stack::GarbageRow,
+};
+
+/// Longest wait for the host to answer when joining a game
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Wait before trying to join again after the first failed attempt, it doubles with every next one
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(8);
+
+/// Anything longer means the stream is broken, actual messages are tiny
+const MAX_MESSAGE_LENGTH: usize = 1 << 16;
+
+/// What the players send each other during a versus match
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Message {
+    /// Sent by the host once the other player connects, both games start from it.
+    /// Ranked matches come without a seed, each side picks its own and keeps it to itself.
+    Start {
+        seed: Option<[u8; 32]>,
+        rules: Rules,
+        ranked: bool,
+    },
+    /// Action of the sender's board, with the time since the previous one
+    Action(TimedAction),
+    /// Garbage lines sent to the receiver, who picks where the hole goes
+    Attack(i32),
+    /// Garbage queued on the sender's board, so that its copy gets the same.
+    /// When it waits for a lock it went into the board right away, after the locking actions.
+    Garbage { lines: i32, hole: usize },
+    /// Piece entering the sender's board in a ranked match, the opponent doesn't learn it sooner
+    Piece(ShapeType),
+    /// First message over a connection made again after a drop, with how many messages
+    /// of the match the sender got, the rest is sent again.
+    /// Also asks for everything from a checkpoint on again when the boards went out of sync.
+    Resume { received: u64 },
+    /// Name of the sender, sent by both players as the match starts
+    Hello { name: String },
+    /// Hash of the sender's board right before the action with the given index,
+    /// its copy on the other side has to match it
+    Checkpoint(Checkpoint),
+}
+
+/// Hash of the board compared by both sides, in ranked matches the other side
+/// doesn't know the generators of the queue and the garbage holes
+fn checkpoint_hash(engine: &Engine, ranked: bool) -> u64 {
+    if ranked {
+        engine.board_hash()
+    } else {
+        engine.state_hash()
+    }
+}
+
+/// Appends the message prefixed with its length
+fn encode(message: &Message, buffer: &mut Vec<u8>) {
+    let bytes = bincode::serialize(message).unwrap();
+    buffer.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(&bytes);
+}
+
+/// Takes the first complete message out of the buffer, an error means the stream is broken
+fn decode(buffer: &mut Vec<u8>) -> Result<Option<Message>, String> {
+    if buffer.len() < 4 {
+        return Ok(None);
+    }
+
+    let mut length = [0u8; 4];
+    length.copy_from_slice(&buffer[..4]);
+    let length = u32::from_le_bytes(length) as usize;
+
+    if length > MAX_MESSAGE_LENGTH {
+        return Err(format!("message of {} bytes", length));
+    }
+
+    if buffer.len() < 4 + length {
+        return Ok(None);
+    }
+
+    let message = bincode::deserialize(&buffer[4..4 + length]).map_err(|e| format!("{:?}", e));
+    buffer.drain(..4 + length);
+    message.map(Some)
+}
+
+/// Blocks until connected to the host or the timeout runs out
+fn open(address: &str) -> io::Result<TcpStream> {
+    let address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "address not found"))?;
+
+    let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+    log::info!("Connected to {}", address);
+    Ok(stream)
+}
+
+/// Stream of messages to the other player, never blocking the game
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    closed: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Connection> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+
+        Ok(Connection {
+            stream,
+            incoming: vec![],
+            outgoing: vec![],
+            closed: false,
+        })
+    }
+
+    /// Joins a hosted game
+    pub fn connect(address: &str) -> io::Result<Connection> {
+        Connection::new(open(address)?)
+    }
+
+    /// The other player left or the stream broke, nothing more comes through
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
+
+    /// The message goes out with the next poll
+    pub fn send(&mut self, message: &Message) {
+        encode(message, &mut self.outgoing);
+    }
+
+    /// Writes what was sent so far and collects the messages that arrived
+    pub fn poll(&mut self, messages: &mut Vec<Message>) {
+        if self.closed {
+            return;
+        }
+
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return self.close("The other player disconnected"),
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::error!("Unable to send to the other player: {:?}", e);
+                    return self.close("Connection lost");
+                }
+            }
+        }
+
+        let mut chunk = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    self.close("The other player disconnected");
+                    break;
+                }
+                Ok(read) => self.incoming.extend_from_slice(&chunk[..read]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::error!("Unable to receive from the other player: {:?}", e);
+                    self.close("Connection lost");
+                    break;
+                }
+            }
+        }
+
+        loop {
+            match decode(&mut self.incoming) {
+                Ok(Some(message)) => messages.push(message),
+                Ok(None) => break,
+                Err(e) => {
+                    log::error!("Invalid message from the other player: {}", e);
+                    self.close("Connection lost");
+                    break;
+                }
+            }
+        }
+    }
+
+    fn close(&mut self, reason: &str) {
+        log::info!("{}", reason);
+        self.closed = true;
+    }
+}
+
+/// Hosted game waiting for the other player
+pub struct Host {
+    listener: TcpListener,
+}
+
+impl Host {
+    pub fn listen(port: u16) -> io::Result<Host> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        log::info!("Hosting a game on port {}", port);
+
+        Ok(Host { listener })
+    }
+
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    /// The other player, once they join
+    pub fn accept(&self) -> Option<Connection> {
+        match self.listener.accept() {
+            Ok((stream, address)) => {
+                log::info!("{} joined the game", address);
+                match Connection::new(stream) {
+                    Ok(connection) => Some(connection),
+                    Err(e) => {
+                        log::error!("Unable to set up the connection: {:?}", e);
+                        None
+                    }
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => None,
+            Err(e) => {
+                log::error!("Unable to accept the other player: {:?}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Checkpoint at which the board of the other player matched its copy
+struct Verified {
+    /// Action of the other player the checkpoint was taken before
+    action: u32,
+    /// Index of the checkpoint among the messages of the match
+    index: u64,
+    snapshot: Snapshot,
+    /// Pieces of a ranked opponent revealed by then
+    revealed: usize,
+}
+
+/// Board of the other player, played back from the actions they send
+/// with the same timing they were made with
+pub struct RemoteBoard {
+    pub gameplay: Gameplay,
+    /// Messages to play, along with their index among the messages of the match
+    queue: VecDeque<(u64, Message)>,
+    action_duration: Duration,
+    /// Pieces of a ranked opponent sent so far, their seed is never known
+    revealed: Vec<ShapeType>,
+    ranked: bool,
+    /// Last checkpoint that matched and could be gone back to
+    verified: Option<Verified>,
+    /// Action of the other player before which the boards went out of sync
+    desync: Option<u32>,
+}
+
+impl RemoteBoard {
+    fn new(
+        ctx: &mut Context,
+        g: &mut Global,
+        seed: &[u8; 32],
+        rules: &Rules,
+        ranked: bool,
+    ) -> GameResult<RemoteBoard> {
+        let mut gameplay = Gameplay::new(ctx, g, false, seed, rules)?;
+        gameplay.set_keyboard(false);
+        gameplay.set_hidden(ranked);
+
+        Ok(RemoteBoard {
+            gameplay,
+            queue: VecDeque::new(),
+            action_duration: Duration::new(0, 0),
+            revealed: vec![],
+            ranked,
+            verified: None,
+            desync: None,
+        })
+    }
+
+    /// Puts the revealed pieces not played yet at the front of the queue, whatever
+    /// the made up seed of the board fills in after them is never played
+    fn reveal(&mut self, shape: ShapeType) {
+        self.revealed.push(shape);
+        let engine = &mut self.gameplay.engine;
+
+        // The first piece was already taken out when the board was set up
+        if self.revealed.len() == 1 {
+            let mut state = engine.board_state();
+            state.piece = Piece::new(shape, &engine.stack).state();
+            state.queue = vec![];
+            if let Err(e) = engine.set_board_state(state) {
+                log::error!("Unable to place the first piece of the opponent: {}", e);
+            }
+        }
+
+        self.sync_queue();
+    }
+
+    fn sync_queue(&mut self) {
+        if self.revealed.is_empty() {
+            return;
+        }
+
+        let bag = &mut self.gameplay.engine.bag;
+        let next = bag.popped().min(self.revealed.len());
+        bag.set_queue(&self.revealed[next..]);
+    }
+
+    /// Compares the board with the one of the other player, returns false if they differ.
+    /// Boards in the middle of a line clear can't be gone back to, the previous checkpoint
+    /// is kept for them.
+    fn check(&mut self, index: u64, checkpoint: Checkpoint) -> bool {
+        let engine = &self.gameplay.engine;
+        if checkpoint_hash(engine, self.ranked) != checkpoint.hash {
+            log::error!(
+                "The board of the other player went out of sync before action {}",
+                checkpoint.action
+            );
+            self.desync = Some(checkpoint.action);
+            return false;
+        }
+
+        if let Some(snapshot) = engine.snapshot() {
+            self.verified = Some(Verified {
+                action: checkpoint.action,
+                index,
+                snapshot,
+                revealed: self.revealed.len(),
+            });
+        }
+
+        true
+    }
+
+    /// Puts the board back to the last checkpoint that matched, the messages from it on
+    /// have to be played again. Returns the index of the checkpoint and its action.
+    fn rewind(&mut self) -> Option<(u64, u32)> {
+        let verified = self.verified.as_ref()?;
+        if !self.gameplay.engine.rewind(&verified.snapshot) {
+            return None;
+        }
+
+        self.revealed.truncate(verified.revealed);
+        self.queue.clear();
+        self.action_duration = Duration::new(0, 0);
+        self.desync = None;
+        Some((verified.index, verified.action))
+    }
+
+    fn update(&mut self, dt: Duration) {
+        self.action_duration += dt;
+
+        while let Some(&(index, ref message)) = self.queue.front() {
+            // The other side hashed its board once the previous actions went through
+            let queued = self.gameplay.engine.queued_actions();
+            let settled = queued == 0 && !self.gameplay.engine.entering();
+
+            match *message {
+                Message::Action(action) if self.action_duration >= action.duration => {
+                    self.gameplay.action(action.action, false);
+                    self.action_duration -= action.duration;
+                    // Undoing a drop brings back an older queue
+                    self.sync_queue();
+                }
+                Message::Action(_) => break,
+                Message::Garbage { lines, hole } => {
+                    let rows = vec![GarbageRow { hole }; lines.max(0) as usize];
+                    if !self.gameplay.engine.rules().garbage_on_lock {
+                        self.gameplay.engine.queue_garbage(&rows);
+                    } else if queued > 0 {
+                        // It goes in after the locking actions, like on the other side
+                        break;
+                    } else {
+                        self.gameplay.insert_garbage(&rows);
+                    }
+                }
+                Message::Piece(shape) => self.reveal(shape),
+                Message::Checkpoint(_) if !settled => break,
+                Message::Checkpoint(checkpoint) => {
+                    if self.desync.is_none() && !self.check(index, checkpoint) {
+                        break;
+                    }
+                }
+                Message::Start { .. }
+                | Message::Attack(_)
+                | Message::Resume { .. }
+                | Message::Hello { .. } => (),
+            }
+
+            self.queue.pop_front();
+        }
+    }
+}
+
+/// How the connection is made again after it drops
+pub enum Peer {
+    /// Waits for the other player on the port the match was hosted on
+    Host(Host),
+    /// Connects to the host's address again
+    Join(String),
+}
+
+/// Connection being made again after it dropped, both boards stay frozen meanwhile
+struct Reconnect {
+    elapsed: Duration,
+    /// The match is given up on after this long
+    window: Duration,
+    /// Time of the next attempt to join the host, the wait doubles after every failed one
+    next_attempt: Duration,
+    backoff: Duration,
+    attempt: Option<Receiver<io::Result<TcpStream>>>,
+}
+
+impl Reconnect {
+    /// Starts a new connection to the host in the background, as it blocks until connected
+    fn attempt(&mut self, address: &str) {
+        if self.attempt.is_some() || self.elapsed < self.next_attempt {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let address = address.to_string();
+        thread::spawn(move || {
+            // Nobody waits for the result once the match is given up on
+            let _ = sender.send(open(&address));
+        });
+        self.attempt = Some(receiver);
+    }
+
+    /// Stream of a finished attempt, a failed one schedules the next
+    fn attempted(&mut self) -> Option<TcpStream> {
+        let result = match self.attempt.as_ref().map(|r| r.try_recv()) {
+            Some(Ok(result)) => result,
+            Some(Err(TryRecvError::Empty)) | None => return None,
+            Some(Err(TryRecvError::Disconnected)) => Err(io::ErrorKind::Other.into()),
+        };
+
+        self.attempt = None;
+        match result {
+            Ok(stream) => Some(stream),
+            Err(e) => {
+                log::info!(
+                    "Unable to reconnect, trying again in {:?}: {:?}",
+                    self.backoff,
+                    e
+                );
+                self.next_attempt = self.elapsed + self.backoff;
+                self.backoff = (self.backoff * 2).min(MAX_RECONNECT_BACKOFF);
+                None
+            }
+        }
+    }
+}
+
+/// Versus match against another player over the network. Only the actions of the boards
+/// go through, each side simulates the other board on its own.
+/// In ranked matches the boards don't share a seed, so that neither player can work out
+/// the other one's next pieces. Each piece is sent as it enters the board instead.
+pub struct NetMatch {
+    connection: Connection,
+    peer: Peer,
+    pub local: Gameplay,
+    pub remote: RemoteBoard,
+    /// Garbage coming to the local board, which can still be cancelled
+    pool: AttackPool,
+    /// Actions of the local board sent so far
+    sent: usize,
+    /// Number of actions of the local board after which the next checkpoint is sent
+    next_checkpoint: usize,
+    ranked: bool,
+    /// Pieces of the local board sent so far, in ranked matches
+    revealed: usize,
+    /// Every message of the match sent so far, the ones lost with a dropped connection
+    /// go out again once it's back
+    log: Vec<Message>,
+    /// Messages of the match received so far
+    received: u64,
+    /// Index of the next message to arrive, behind the received ones while the messages
+    /// from a checkpoint on come again
+    next: u64,
+    /// Action of the checkpoint the messages come again from,
+    /// whatever arrives before it was sent earlier and is left out
+    resuming: Option<u32>,
+    /// Checkpoint the board of the other player was last put back to after going out of sync
+    rewound: Option<u32>,
+    /// The boards went out of sync and going back to the last checkpoint didn't help
+    desynced: bool,
+    reconnect: Option<Reconnect>,
+    /// The connection dropped and didn't come back in time
+    lost: bool,
+    /// Name of the other player, once it arrives
+    pub opponent: Option<String>,
+    messages: Vec<Message>,
+    rng: ThreadRng,
+}
+
+impl NetMatch {
+    pub fn new(
+        ctx: &mut Context,
+        g: &mut Global,
+        connection: Connection,
+        peer: Peer,
+        seed: &[u8; 32],
+        rules: &Rules,
+        ranked: bool,
+    ) -> GameResult<NetMatch> {
+        let local = Gameplay::new(ctx, g, true, seed, rules)?;
+        let remote_seed = if ranked { [0; 32] } else { *seed };
+
+        let mut net_match = NetMatch {
+            connection,
+            peer,
+            remote: RemoteBoard::new(ctx, g, &remote_seed, rules, ranked)?,
+            pool: AttackPool::new(rules.garbage_on_lock),
+            sent: 0,
+            next_checkpoint: 0,
+            ranked,
+            revealed: 0,
+            log: vec![],
+            received: 0,
+            next: 0,
+            resuming: None,
+            rewound: None,
+            desynced: false,
+            reconnect: None,
+            lost: false,
+            opponent: None,
+            messages: vec![],
+            rng: thread_rng(),
+            local,
+        };
+
+        net_match.send(Message::Hello {
+            name: g.settings.gameplay.player_name.clone(),
+        });
+
+        if ranked {
+            let shape = net_match.local.engine.piece.state().shape;
+            net_match.send(Message::Piece(shape));
+            net_match.revealed = net_match.local.engine.bag.popped();
+        }
+
+        Ok(net_match)
+    }
+
+    /// The connection dropped and the other player didn't come back in time
+    pub fn disconnected(&self) -> bool {
+        self.lost
+    }
+
+    /// The boards went out of sync for good, the match can't go on
+    pub fn desynced(&self) -> bool {
+        self.desynced
+    }
+
+    /// Time left for the other player to come back, while the match is frozen
+    pub fn reconnecting(&self) -> Option<Duration> {
+        self.reconnect
+            .as_ref()
+            .map(|r| r.window.checked_sub(r.elapsed).unwrap_or_default())
+    }
+
+    /// Whether the local player won, once either board tops out
+    pub fn won(&self) -> Option<bool> {
+        if self.local.game_over() {
+            Some(false)
+        } else if self.remote.gameplay.game_over() {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    /// Result of the match for the history, once either board tops out
+    pub fn record(&self) -> Option<MatchRecord> {
+        let won = self.won()?;
+        let opponent = self.opponent.as_ref().map_or("Unknown", |o| o.as_str());
+        let boards = [
+            history::final_board(&self.local.engine.stack),
+            history::final_board(&self.remote.gameplay.engine.stack),
+        ];
+
+        let rules = &self.local.engine.rules().name;
+        let mut record = MatchRecord::new(opponent, true, rules, won, boards);
+        if let Peer::Join(address) = &self.peer {
+            record.address = Some(address.clone());
+        }
+
+        Some(record)
+    }
+
+    /// Garbage waiting to be queued on the local board
+    pub fn forecast(&self) -> Vec<(i32, Option<Duration>)> {
+        self.pool.forecast()
+    }
+
+    fn send(&mut self, message: Message) {
+        self.connection.send(&message);
+        self.log.push(message);
+    }
+
+    /// Messages that arrived along with the start of the match
+    pub fn receive(&mut self, message: Message) {
+        match message {
+            Message::Resume { received } => {
+                // Whatever the other side missed goes out again, then the match goes on
+                let missed = &self.log[(received as usize).min(self.log.len())..];
+                for message in missed {
+                    self.connection.send(message);
+                }
+
+                if self.reconnect.take().is_some() {
+                    log::info!("Reconnected, {} messages sent again", missed.len());
+                }
+                return;
+            }
+            Message::Checkpoint(checkpoint) if self.resuming == Some(checkpoint.action) => {
+                self.resuming = None;
+            }
+            _ if self.resuming.is_some() => return,
+            _ => (),
+        }
+
+        let index = self.next;
+        self.next += 1;
+        // Only the board of the other player went back, the rest came through already
+        let repeated = index < self.received;
+        self.received = self.received.max(self.next);
+
+        match message {
+            Message::Attack(lines) if !repeated && !self.local.game_over() => {
+                let width = self.local.engine.stack.width as usize;
+                self.pool.add(lines, self.rng.gen_range(0, width));
+            }
+            Message::Action(_)
+            | Message::Garbage { .. }
+            | Message::Piece(_)
+            | Message::Checkpoint(_) => self.remote.queue.push_back((index, message)),
+            Message::Hello { name } => self.opponent = Some(name),
+            Message::Attack(_) | Message::Start { .. } | Message::Resume { .. } => (),
+        }
+    }
+
+    /// Puts the board of the other player back to the last checkpoint that matched
+    /// and asks for the messages from it on, returns false if there's none to go back to
+    fn resume(&mut self) -> bool {
+        let (index, action) = match self.remote.rewind() {
+            Some(checkpoint) => checkpoint,
+            None => return false,
+        };
+
+        self.next = index;
+        self.resuming = Some(action);
+        self.connection.send(&Message::Resume { received: index });
+        true
+    }
+
+    /// Gives up on the match if going back to the last checkpoint that matched
+    /// doesn't get the boards in sync again
+    fn resync(&mut self) {
+        match self.remote.verified.as_ref().map(|v| v.action) {
+            Some(action) if Some(action) != self.rewound && self.resume() => {
+                log::info!("Going back to the checkpoint before action {}", action);
+                self.rewound = Some(action);
+            }
+            _ => {
+                log::error!("Unable to get the boards back in sync, giving up on the match");
+                self.desynced = true;
+            }
+        }
+    }
+
+    /// Freezes the match until the connection is back, or gives up after the window
+    fn drop_connection(&mut self, g: &Global) {
+        let window = Duration::from_secs(g.settings.gameplay.reconnect_window.into());
+        if window == Duration::new(0, 0) {
+            self.lost = true;
+            return;
+        }
+
+        log::info!(
+            "Connection dropped, waiting {:?} for it to come back",
+            window
+        );
+        self.reconnect = Some(Reconnect {
+            elapsed: Duration::new(0, 0),
+            window,
+            next_attempt: Duration::new(0, 0),
+            backoff: RECONNECT_BACKOFF,
+            attempt: None,
+        });
+    }
+
+    fn update_reconnect(&mut self, dt: Duration) {
+        let reconnect = match &mut self.reconnect {
+            Some(reconnect) => reconnect,
+            None => return,
+        };
+
+        reconnect.elapsed += dt;
+        if reconnect.elapsed >= reconnect.window {
+            log::info!("The other player didn't come back, giving up on the match");
+            self.reconnect = None;
+            self.lost = true;
+            return;
+        }
+
+        if !self.connection.closed() {
+            return;
+        }
+
+        let connection = match &self.peer {
+            Peer::Host(host) => host.accept(),
+            Peer::Join(address) => {
+                reconnect.attempt(address);
+                reconnect
+                    .attempted()
+                    .and_then(|stream| Connection::new(stream).ok())
+            }
+        };
+
+        // Each side tells the other how far it got, the match goes on once both know.
+        // The board of the other player goes back to the last checkpoint that matched,
+        // as whatever came after it could have been cut off.
+        if let Some(connection) = connection {
+            self.connection = connection;
+            if !self.resume() {
+                self.next = self.received;
+                self.resuming = None;
+                self.connection.send(&Message::Resume {
+                    received: self.received,
+                });
+            }
+        }
+    }
+
+    pub fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        if self.desynced {
+            return Ok(());
+        }
+
+        let dt = g.clock.delta();
+
+        let mut messages = mem::replace(&mut self.messages, vec![]);
+        self.connection.poll(&mut messages);
+        for message in messages.drain(..) {
+            self.receive(message);
+        }
+        self.messages = messages;
+
+        if self.connection.closed() && self.reconnect.is_none() && !self.lost {
+            self.drop_connection(g);
+        }
+
+        if self.reconnect.is_some() {
+            self.update_reconnect(dt);
+            return Ok(());
+        }
+
+        // Garbage is announced before the actions it comes in between
+        self.pool.update(dt);
+        while let Some((lines, hole)) = self.pool.pop_ready() {
+            let rows = vec![GarbageRow { hole }; lines as usize];
+            self.local.engine.queue_garbage(&rows);
+            self.send(Message::Garbage { lines, hole });
+        }
+
+        let upcoming: Vec<ShapeType> = self.local.engine.bag.peek(14).cloned().collect();
+        let popped = self.local.engine.bag.popped();
+
+        // With nothing waiting to be processed this is the board right before the first
+        // action of the frame, the other side hashes its copy at the same point
+        let engine = &self.local.engine;
+        let settled = engine.queued_actions() == 0 && !engine.entering();
+        let checkpoint = if settled && self.sent >= self.next_checkpoint {
+            Some(Checkpoint {
+                action: self.sent as u32,
+                hash: checkpoint_hash(engine, self.ranked),
+            })
+        } else {
+            None
+        };
+
+        self.local.update(ctx, g, true)?;
+
+        // Pieces go out before the actions playing them
+        if self.ranked {
+            let now = self.local.engine.bag.popped();
+            for i in self.revealed.max(popped)..now {
+                if let Some(&shape) = upcoming.get(i - popped) {
+                    self.send(Message::Piece(shape));
+                }
+            }
+            self.revealed = self.revealed.max(now);
+        }
+
+        let actions: Vec<TimedAction> = self
+            .local
+            .replay_data()
+            .actions
+            .iter()
+            .skip(self.sent)
+            .cloned()
+            .collect();
+        // A checkpoint goes out along with the action it was taken before
+        if let Some(checkpoint) = checkpoint.filter(|_| !actions.is_empty()) {
+            self.send(Message::Checkpoint(checkpoint));
+            self.next_checkpoint = self.sent + CHECKPOINT_INTERVAL;
+        }
+
+        self.sent += actions.len();
+        for action in actions {
+            self.send(Message::Action(action));
+        }
+
+        let attack = self.pool.cancel(self.local.take_attack());
+        if attack > 0 {
+            self.send(Message::Attack(attack));
+        }
+
+        // The copy on the other side puts it in after the same actions
+        if self.local.take_clean_locks() > 0 {
+            for (lines, hole) in self.pool.lock() {
+                let rows = vec![GarbageRow { hole }; lines as usize];
+                self.local.insert_garbage(&rows);
+                self.send(Message::Garbage { lines, hole });
+            }
+        }
+
+        self.remote.update(dt);
+        if self.remote.desync.is_some() {
+            self.resync();
+        }
+        self.remote.gameplay.update(ctx, g, false)?;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn message_test() {
+    use crate::action::Action;
+
+    let messages = vec![
+        Message::Start {
+            seed: None,
+            rules: Rules::default(),
+            ranked: true,
+        },
+        Message::Action(TimedAction {
+            action: Action::HardDrop,
+            duration: Duration::from_millis(250),
+        }),
+        Message::Attack(4),
+        Message::Garbage { lines: 2, hole: 3 },
+        Message::Piece(ShapeType::T),
+        Message::Resume { received: 12 },
+        Message::Hello {
+            name: String::from("Player"),
+        },
+        Message::Checkpoint(Checkpoint {
+            action: 100,
+            hash: 0x1234,
+        }),
+    ];
+
+    let mut buffer = vec![];
+    for message in &messages {
+        encode(message, &mut buffer);
+    }
+
+    // Messages come through only once they arrive in full
+    let mut stream = buffer.split_off(5);
+    assert_eq!(decode(&mut buffer), Ok(None));
+    buffer.append(&mut stream);
+
+    for message in &messages {
+        assert_eq!(decode(&mut buffer).as_ref(), Ok(&Some(message.clone())));
+    }
+    assert!(buffer.is_empty());
+
+    let mut broken = vec![255, 255, 255, 255, 0];
+    assert!(decode(&mut broken).is_err());
+}
+
+#[test]
+fn reconnect_test() {
+    let mut reconnect = Reconnect {
+        elapsed: Duration::from_secs(1),
+        window: Duration::from_secs(30),
+        next_attempt: Duration::new(0, 0),
+        backoff: RECONNECT_BACKOFF,
+        attempt: None,
+    };
+
+    // Every failed attempt waits twice as long before the next one, up to the limit
+    let mut waits = vec![];
+    for _ in 0..6 {
+        let (sender, receiver) = mpsc::channel();
+        sender
+            .send(Err(io::ErrorKind::ConnectionRefused.into()))
+            .unwrap();
+        reconnect.attempt = Some(receiver);
+
+        assert!(reconnect.attempted().is_none());
+        assert!(reconnect.attempt.is_none());
+        waits.push((reconnect.next_attempt - reconnect.elapsed).as_millis());
+    }
+    assert_eq!(waits, vec![500, 1000, 2000, 4000, 8000, 8000]);
+
+    // Nothing happens until the attempt finishes
+    let (_sender, receiver) = mpsc::channel::<io::Result<TcpStream>>();
+    reconnect.attempt = Some(receiver);
+    assert!(reconnect.attempted().is_none());
+    assert!(reconnect.attempt.is_some());
+}
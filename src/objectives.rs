@@ -0,0 +1,231 @@
+use ggez::{
+    graphics::{
+        self, Color, DrawMode, DrawParam, Font, MeshBuilder, Rect, Scale, Text, TextFragment,
+    },
+    nalgebra::Point2,
+    Context, GameResult,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::engine::Event;
+
+/// What counts towards an objective
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum Goal {
+    /// Every cleared line
+    Lines,
+    /// Clears of exactly this many rows, T-Spins only count towards T-Spin goals
+    Clear { rows: i32, t_spin: bool },
+    /// Every locked piece
+    Pieces,
+    /// Lines sent as garbage
+    Attack,
+}
+
+impl Goal {
+    fn name(self) -> &'static str {
+        match self {
+            Goal::Lines => "Lines",
+            Goal::Clear { rows, t_spin } => match (rows, t_spin) {
+                (0, true) => "T-Spins",
+                (1, true) => "T-Spin singles",
+                (2, true) => "T-Spin doubles",
+                (_, true) => "T-Spin triples",
+                (1, false) => "Singles",
+                (2, false) => "Doubles",
+                (3, false) => "Triples",
+                _ => "Tetrises",
+            },
+            Goal::Pieces => "Pieces",
+            Goal::Attack => "Attack",
+        }
+    }
+
+    /// Progress made by a single event
+    fn progress(self, event: &Event) -> u32 {
+        let (rows, t_spin, attack) = match *event {
+            Event::Locked {
+                rows,
+                t_spin,
+                attack,
+            } => (rows, t_spin, attack),
+            // Chains only add lines and attack, the piece was already counted
+            Event::Chain { rows, attack, .. } => {
+                return match self {
+                    Goal::Lines => rows.max(0) as u32,
+                    Goal::Attack => attack.max(0) as u32,
+                    Goal::Clear { .. } | Goal::Pieces => 0,
+                };
+            }
+            _ => return 0,
+        };
+
+        match self {
+            Goal::Lines => rows.max(0) as u32,
+            Goal::Clear { rows: r, t_spin: t } => (r == rows && t == t_spin) as u32,
+            Goal::Pieces => 1,
+            Goal::Attack => attack.max(0) as u32,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct Objective {
+    pub goal: Goal,
+    pub target: u32,
+    #[serde(skip)]
+    pub progress: u32,
+}
+
+impl Objective {
+    pub fn new(goal: Goal, target: u32) -> Objective {
+        Objective {
+            goal,
+            target,
+            progress: 0,
+        }
+    }
+
+    pub fn done(&self) -> bool {
+        self.progress >= self.target
+    }
+
+    pub fn label(&self) -> String {
+        format!(
+            "{} {}/{}",
+            self.goal.name(),
+            self.progress.min(self.target),
+            self.target
+        )
+    }
+}
+
+/// Objectives of a mission, tutorial or campaign stage, shown as a ticker next to the board
+#[derive(Default)]
+pub struct Objectives {
+    objectives: Vec<Objective>,
+}
+
+impl Objectives {
+    pub fn set(&mut self, objectives: &[Objective]) {
+        self.objectives = objectives.to_vec();
+        for o in &mut self.objectives {
+            o.progress = 0;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.objectives.clear();
+    }
+
+    pub fn objectives(&self) -> &[Objective] {
+        &self.objectives
+    }
+
+    /// Returns the number of objectives completed by this event
+    pub fn update(&mut self, event: &Event) -> usize {
+        let mut completed = 0;
+        for o in self.objectives.iter_mut().filter(|o| !o.done()) {
+            o.progress += o.goal.progress(event);
+            if o.done() {
+                completed += 1;
+            }
+        }
+
+        completed
+    }
+
+    /// Whether there were any objectives and all of them are done
+    pub fn completed(&self) -> bool {
+        !self.objectives.is_empty() && self.objectives.iter().all(Objective::done)
+    }
+
+    /// Label and progress bar for every objective, one below the other
+    pub fn draw(
+        &self,
+        ctx: &mut Context,
+        position: Point2<f32>,
+        width: f32,
+        color: Color,
+        font: Font,
+        scale: Scale,
+    ) -> GameResult {
+        if self.objectives.is_empty() {
+            return Ok(());
+        }
+
+        let bar_height = scale.y * 0.3;
+        let mut builder = MeshBuilder::new();
+        let mut y = position[1];
+
+        for o in &self.objectives {
+            let label_color = if o.done() {
+                Color::new(0.4, 1.0, 0.5, color.a)
+            } else {
+                color
+            };
+
+            let text = Text::new(TextFragment {
+                text: o.label(),
+                color: Some(label_color),
+                font: Some(font),
+                scale: Some(scale),
+            });
+            graphics::draw(
+                ctx,
+                &text,
+                DrawParam::new().dest(Point2::new(position[0], y)),
+            )?;
+            y += scale.y * 1.1;
+
+            let fraction = o.progress.min(o.target) as f32 / o.target.max(1) as f32;
+            let background = Rect::new(position[0], y, width, bar_height);
+            builder.rectangle(
+                DrawMode::fill(),
+                background,
+                Color::new(1.0, 1.0, 1.0, 0.15),
+            );
+            if fraction > 0.0 {
+                let bar = Rect::new(position[0], y, width * fraction, bar_height);
+                builder.rectangle(DrawMode::fill(), bar, label_color);
+            }
+            y += bar_height + scale.y * 0.6;
+        }
+
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())
+    }
+}
+
+#[test]
+fn objectives_test() {
+    let mut objectives = Objectives::default();
+    assert!(!objectives.completed());
+
+    objectives.set(&[
+        Objective::new(
+            Goal::Clear {
+                rows: 2,
+                t_spin: true,
+            },
+            2,
+        ),
+        Objective::new(Goal::Lines, 3),
+    ]);
+
+    let lock = |rows, t_spin| Event::Locked {
+        rows,
+        t_spin,
+        attack: 0,
+    };
+
+    assert_eq!(objectives.update(&lock(2, false)), 0);
+    assert_eq!(objectives.update(&lock(2, true)), 1);
+    assert_eq!(objectives.objectives()[0].label(), "T-Spin doubles 1/2");
+    assert!(!objectives.completed());
+
+    assert_eq!(objectives.update(&Event::Moved), 0);
+    assert_eq!(objectives.update(&lock(2, true)), 1);
+    assert!(objectives.completed());
+    assert_eq!(objectives.objectives()[1].label(), "Lines 3/3");
+}
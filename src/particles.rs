@@ -1,11 +1,15 @@
+use std::time::Duration;
+
 use ggez::{
-    graphics::{self, Color, DrawMode, DrawParam, MeshBuilder},
-    nalgebra::{self, Point2, Vector2},
+    graphics::{
+        self, spritebatch::SpriteBatch, Color, DrawMode, DrawParam, Image, MeshBuilder, Rect,
+    },
+    nalgebra::{Point2, Vector2},
     timer, Context, GameResult,
 };
 use rand_distr::{Distribution, Normal, Uniform};
 
-use crate::utils;
+use crate::{blocks::Blocks, profiler, utils};
 
 #[derive(Copy, Clone)]
 pub struct Explosion {
@@ -14,29 +18,82 @@ pub struct Explosion {
     pub strength: f32,
 }
 
-struct Particle {
-    position: Point2<f32>,
-    speed: Vector2<f32>,
-    starting_speed: Vector2<f32>,
-    size: f32,
-    color: Color,
-    starting_color: Color,
+/// Resolution of the circle texture shared by all background particles
+const CIRCLE_SIZE: u16 = 64;
+
+/// Background particles stored as a structure of arrays in a pool allocated up front,
+/// so that every update pass is a tight loop over plain floats
+/// and the whole animation is drawn with a single sprite batch
+pub struct ParticleAnimation {
+    x: Vec<f32>,
+    y: Vec<f32>,
+    vx: Vec<f32>,
+    vy: Vec<f32>,
+    start_vx: Vec<f32>,
+    start_vy: Vec<f32>,
+    size: Vec<f32>,
+    r: Vec<f32>,
+    g: Vec<f32>,
+    b: Vec<f32>,
+    shade: Vec<f32>,
+
+    batch: SpriteBatch,
+    max_speed: f32,
+    width: f32,
+    height: f32,
+    explosion: Option<Explosion>,
 }
 
-impl Particle {
-    pub fn new(position: Point2<f32>, speed: Vector2<f32>, size: f32, color: Color) -> Particle {
-        Particle {
-            position,
-            speed,
-            starting_speed: speed.abs(),
-            size,
-            color,
-            starting_color: color,
+impl ParticleAnimation {
+    pub fn new(
+        ctx: &mut Context,
+        particles: usize,
+        max_speed: f32,
+    ) -> GameResult<ParticleAnimation> {
+        let rect = graphics::screen_coordinates(ctx);
+
+        let mut animation = ParticleAnimation {
+            x: Vec::with_capacity(particles),
+            y: Vec::with_capacity(particles),
+            vx: Vec::with_capacity(particles),
+            vy: Vec::with_capacity(particles),
+            start_vx: Vec::with_capacity(particles),
+            start_vy: Vec::with_capacity(particles),
+            size: Vec::with_capacity(particles),
+            r: Vec::with_capacity(particles),
+            g: Vec::with_capacity(particles),
+            b: Vec::with_capacity(particles),
+            shade: Vec::with_capacity(particles),
+            batch: SpriteBatch::new(ParticleAnimation::circle(ctx)?),
+            max_speed,
+            width: rect.w,
+            height: rect.h,
+            explosion: None,
+        };
+
+        animation.random_plane(particles);
+        Ok(animation)
+    }
+
+    /// White circle with a soft edge, tinted per particle when drawing
+    fn circle(ctx: &mut Context) -> GameResult<Image> {
+        let size = usize::from(CIRCLE_SIZE);
+        let radius = size as f32 / 2.0;
+        let mut rgba = vec![255u8; size * size * 4];
+
+        for y in 0..size {
+            for x in 0..size {
+                let dx = x as f32 + 0.5 - radius;
+                let dy = y as f32 + 0.5 - radius;
+                let coverage = clamp(radius - (dx * dx + dy * dy).sqrt(), 0.0, 1.0);
+                rgba[(y * size + x) * 4 + 3] = (coverage * 255.0) as u8;
+            }
         }
+
+        Image::from_rgba8(ctx, CIRCLE_SIZE, CIRCLE_SIZE, &rgba)
     }
 
-    fn random_plane(n: usize, max_x: f32, max_y: f32) -> Vec<Particle> {
-        let mut particles: Vec<Particle> = vec![];
+    fn random_plane(&mut self, n: usize) {
         let mut rng = rand::thread_rng();
 
         let uniform_x = Uniform::new(0.01, 0.99);
@@ -57,43 +114,27 @@ impl Particle {
                 1.0
             };
 
-            let speed =
-                Vector2::new(uniform_vx.sample(&mut rng), uniform_vy.sample(&mut rng)) * direction;
-
-            let size = clamp(normal_size.sample(&mut rng), 1.0, 5.0);
-
-            let position = Point2::new(
-                uniform_x.sample(&mut rng) * max_x,
-                uniform_y.sample(&mut rng) * max_y,
-            );
-
+            let vx = uniform_vx.sample(&mut rng) * direction;
+            let vy = uniform_vy.sample(&mut rng) * direction;
             let c = clamp(uniform_color.sample(&mut rng), 0.1, 1.0);
-            let color = Color::new(c, c, c, c);
 
-            particles.push(Particle::new(position, speed, size, color));
+            self.x.push(uniform_x.sample(&mut rng) * self.width);
+            self.y.push(uniform_y.sample(&mut rng) * self.height);
+            self.vx.push(vx);
+            self.vy.push(vy);
+            self.start_vx.push(vx.abs());
+            self.start_vy.push(vy.abs());
+            self.size
+                .push(clamp(normal_size.sample(&mut rng), 1.0, 5.0));
+            self.r.push(c);
+            self.g.push(c);
+            self.b.push(c);
+            self.shade.push(c);
         }
-
-        particles
     }
-}
 
-pub struct ParticleAnimation {
-    particles: Vec<Particle>,
-    max_speed: f32,
-    width: f32,
-    height: f32,
-    explosion: Option<Explosion>,
-}
-
-impl ParticleAnimation {
-    pub fn new(particles: usize, max_speed: f32, width: f32, height: f32) -> ParticleAnimation {
-        ParticleAnimation {
-            particles: Particle::random_plane(particles, width, height),
-            max_speed,
-            width,
-            height,
-            explosion: None,
-        }
+    pub fn count(&self) -> usize {
+        self.x.len()
     }
 
     pub fn explode(&mut self, explosion: Explosion) {
@@ -106,132 +147,226 @@ impl ParticleAnimation {
         self.height = rect.h;
 
         let dt = utils::dt_f32(ctx);
-        let pos = utils::mouse_position_coords(ctx);
-
-        for particle in &mut self.particles {
-            let speed = dt * self.max_speed;
-            particle.position += particle.speed * speed;
-
-            if particle.position[0] < particle.size {
-                particle.position[0] = particle.size;
-                particle.speed[0] = -particle.speed[0];
-            } else if particle.position[0] > self.width - particle.size {
-                particle.position[0] = self.width - particle.size;
-                particle.speed[0] = -particle.speed[0];
-            }
+        let mouse = utils::mouse_position_coords(ctx);
+        let n = self.count();
 
-            if particle.position[1] < particle.size {
-                particle.position[1] = particle.size;
-                particle.speed[1] = -particle.speed[1];
-            } else if particle.position[1] > self.height - particle.size {
-                particle.position[1] = self.height - particle.size;
-                particle.speed[1] = -particle.speed[1];
-            }
-
-            if let Some(explosion) = self.explosion {
-                let mut direction = Vector2::new(0.0, 0.0);
+        let speed = dt * self.max_speed;
+        for i in 0..n {
+            self.x[i] += self.vx[i] * speed;
+            self.y[i] += self.vy[i] * speed;
+        }
 
-                if particle.position[0] < explosion.position[0] {
-                    direction[0] = -1.0;
-                } else {
-                    direction[0] = 1.0;
-                }
+        bounce(&mut self.x, &mut self.vx, &self.size, self.width);
+        bounce(&mut self.y, &mut self.vy, &self.size, self.height);
 
-                if particle.position[1] < explosion.position[1] {
-                    direction[1] = -1.0;
-                } else {
-                    direction[1] = 1.0;
-                }
+        if let Some(explosion) = self.explosion.take() {
+            let strength = explosion.strength / 2.0;
+            for i in 0..n {
+                self.vx[i] += away(self.x[i], explosion.position[0]) * self.start_vx[i] * strength;
+                self.vy[i] += away(self.y[i], explosion.position[1]) * self.start_vy[i] * strength;
+            }
 
-                particle.speed[0] +=
-                    direction[0] * particle.starting_speed[0] / 2.0 * explosion.strength;
-                particle.speed[1] +=
-                    direction[1] * particle.starting_speed[1] / 2.0 * explosion.strength;
+            scale(&mut self.r, explosion.color.r);
+            scale(&mut self.g, explosion.color.g);
+            scale(&mut self.b, explosion.color.b);
+        }
 
-                particle.color.r *= explosion.color.r;
-                particle.color.g *= explosion.color.g;
-                particle.color.b *= explosion.color.b;
-            }
+        const MOUSE_THRESHOLD: f32 = 200.0;
 
-            const MOUSE_THRESHOLD: f32 = 200.0;
+        // The mouse starts in the corner, don't scatter everything right at the start
+        let mouse_active = timer::time_since_start(ctx).as_millis() >= 1000;
 
-            let distance = if timer::time_since_start(ctx).as_millis() < 1000 {
-                MOUSE_THRESHOLD
+        for i in 0..n {
+            let dx = self.x[i] - mouse[0];
+            let dy = self.y[i] - mouse[1];
+            let distance = if mouse_active {
+                (dx * dx + dy * dy).sqrt()
             } else {
-                nalgebra::distance(&pos, &particle.position)
+                MOUSE_THRESHOLD
             };
 
             if distance < MOUSE_THRESHOLD {
-                let mut direction = Vector2::new(0.0, 0.0);
-
-                if particle.position[0] < pos[0] {
-                    direction[0] = -1.0;
-                } else {
-                    direction[0] = 1.0;
-                }
-
-                if particle.position[1] < pos[1] {
-                    direction[1] = -1.0;
-                } else {
-                    direction[1] = 1.0;
-                }
-
-                particle.speed +=
-                    direction * dt * (MOUSE_THRESHOLD - distance).powf(2.0) / MOUSE_THRESHOLD;
+                let push = dt * (MOUSE_THRESHOLD - distance).powf(2.0) / MOUSE_THRESHOLD;
+                self.vx[i] += away(self.x[i], mouse[0]) * push;
+                self.vy[i] += away(self.y[i], mouse[1]) * push;
             } else {
-                if particle.speed[0].abs() > particle.starting_speed[0] {
-                    particle.speed[0] -= particle.speed[0] / 2.0 * dt;
+                if self.vx[i].abs() > self.start_vx[i] {
+                    self.vx[i] -= self.vx[i] / 2.0 * dt;
                 }
 
-                if particle.speed[1].abs() > particle.starting_speed[1] {
-                    particle.speed[1] -= particle.speed[1] / 2.0 * dt;
+                if self.vy[i].abs() > self.start_vy[i] {
+                    self.vy[i] -= self.vy[i] / 2.0 * dt;
                 }
             }
+        }
 
-            fn recover_color(a: &mut f32, b: &mut f32, dt: f32) {
-                const THRESHOLD: f32 = 0.01;
-                const SECONDS: f32 = 10.0;
+        recover_color(&mut self.r, &self.shade, dt);
+        recover_color(&mut self.g, &self.shade, dt);
+        recover_color(&mut self.b, &self.shade, dt);
 
-                if *a - *b > THRESHOLD {
-                    *a -= dt / SECONDS;
-                } else if *a - *b < THRESHOLD {
-                    *a += dt / SECONDS;
-                }
-            }
+        const MAX_SPEED: f32 = 75.0;
+        for v in self.vx.iter_mut().chain(self.vy.iter_mut()) {
+            clamp_mut(v, -MAX_SPEED, MAX_SPEED);
+        }
 
-            recover_color(&mut particle.color.r, &mut particle.starting_color.r, dt);
-            recover_color(&mut particle.color.g, &mut particle.starting_color.g, dt);
-            recover_color(&mut particle.color.b, &mut particle.starting_color.b, dt);
+        Ok(())
+    }
 
-            const MAX_SPEED: f32 = 75.0;
-            clamp_mut(&mut particle.speed[0], -MAX_SPEED, MAX_SPEED);
-            clamp_mut(&mut particle.speed[1], -MAX_SPEED, MAX_SPEED);
+    pub fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.batch.clear();
+
+        let radius = f32::from(CIRCLE_SIZE) / 2.0;
+        for i in 0..self.count() {
+            let scale = self.size[i] / radius;
+            self.batch.add(
+                DrawParam::new()
+                    .dest(Point2::new(
+                        self.x[i] - self.size[i],
+                        self.y[i] - self.size[i],
+                    ))
+                    .scale(Vector2::new(scale, scale))
+                    .color(Color::new(self.r[i], self.g[i], self.b[i], self.shade[i])),
+            );
         }
 
-        if self.explosion.is_some() {
-            self.explosion = None;
+        profiler::count_draw();
+        graphics::draw(ctx, &self.batch, DrawParam::new())
+    }
+}
+
+/// Keeps the particles inside the screen, reversing their speed at the edges
+fn bounce(position: &mut [f32], speed: &mut [f32], size: &[f32], max: f32) {
+    for ((p, v), &s) in position.iter_mut().zip(speed.iter_mut()).zip(size) {
+        if *p < s {
+            *p = s;
+            *v = -*v;
+        } else if *p > max - s {
+            *p = max - s;
+            *v = -*v;
         }
+    }
+}
 
-        Ok(())
+/// Direction pointing away from the source
+fn away(position: f32, source: f32) -> f32 {
+    if position < source {
+        -1.0
+    } else {
+        1.0
+    }
+}
+
+fn scale(values: &mut [f32], factor: f32) {
+    for value in values {
+        *value *= factor;
     }
+}
 
-    pub fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        let mut mesh_circles = MeshBuilder::new();
+/// Slowly brings the tinted colors back to the original shade
+fn recover_color(colors: &mut [f32], shade: &[f32], dt: f32) {
+    const THRESHOLD: f32 = 0.01;
+    const SECONDS: f32 = 10.0;
+
+    for (a, &b) in colors.iter_mut().zip(shade) {
+        if *a - b > THRESHOLD {
+            *a -= dt / SECONDS;
+        } else if *a - b < THRESHOLD {
+            *a += dt / SECONDS;
+        }
+    }
+}
+
+struct Spark {
+    block_id: usize,
+    position: Point2<f32>,
+    speed: Vector2<f32>,
+    size: f32,
+    visible: Duration,
+    lifetime: Duration,
+}
+
+/// Small bursts of block-colored particles, positioned in stack cell units
+#[derive(Default)]
+pub struct Sparks {
+    sparks: Vec<Spark>,
+}
+
+impl Sparks {
+    pub fn burst(&mut self, x: i32, y: i32, block_id: usize, count: usize) {
+        let mut rng = rand::thread_rng();
+
+        let uniform_offset = Uniform::new(0.1, 0.9);
+        let uniform_vx = Uniform::new(-4.0, 4.0);
+        let normal_vy = Normal::new(-6.0, 3.0).unwrap();
+        let uniform_size = Uniform::new(0.06, 0.16);
+        let uniform_lifetime = Uniform::new(300, 700);
+
+        for _ in 0..count {
+            self.sparks.push(Spark {
+                block_id,
+                position: Point2::new(
+                    x as f32 + uniform_offset.sample(&mut rng),
+                    y as f32 + uniform_offset.sample(&mut rng),
+                ),
+                speed: Vector2::new(uniform_vx.sample(&mut rng), normal_vy.sample(&mut rng)),
+                size: uniform_size.sample(&mut rng),
+                visible: Duration::new(0, 0),
+                lifetime: Duration::from_millis(uniform_lifetime.sample(&mut rng)),
+            });
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.sparks.clear();
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        let dt_f32 = dt.as_secs_f32();
+        let g_force = Vector2::new(0.0, 20.0) * dt_f32;
+
+        for spark in &mut self.sparks {
+            spark.speed += g_force;
+            spark.position += spark.speed * dt_f32;
+            spark.visible += dt;
+        }
+
+        self.sparks.retain(|spark| spark.visible < spark.lifetime);
+    }
+
+    pub fn draw(
+        &self,
+        ctx: &mut Context,
+        position: Point2<f32>,
+        blocks: &Blocks,
+        block_size: i32,
+    ) -> GameResult {
+        if self.sparks.is_empty() {
+            return Ok(());
+        }
+
+        let block_size = block_size as f32;
+        let mut mesh = MeshBuilder::new();
 
-        for i in 0..self.particles.len() {
-            let particle = &self.particles[i];
+        for spark in &self.sparks {
+            let mut color = blocks.color(spark.block_id);
+            color.a = 1.0 - spark.visible.as_secs_f32() / spark.lifetime.as_secs_f32();
 
-            mesh_circles.circle(
+            let size = spark.size * block_size;
+            mesh.rectangle(
                 DrawMode::fill(),
-                particle.position,
-                particle.size,
-                0.1,
-                particle.color,
+                Rect::new(
+                    spark.position[0] * block_size - size / 2.0,
+                    spark.position[1] * block_size - size / 2.0,
+                    size,
+                    size,
+                ),
+                color,
             );
         }
 
-        let mesh_circles = mesh_circles.build(ctx)?;
-        graphics::draw(ctx, &mesh_circles, DrawParam::new())?;
+        let mesh = mesh.build(ctx)?;
+        profiler::count_draw();
+        graphics::draw(ctx, &mesh, DrawParam::new().dest(position))?;
 
         Ok(())
     }
@@ -1,9 +1,11 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
-use ggez::{self, nalgebra::Point2, timer, Context, GameResult};
+use ggez::nalgebra::Point2;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     blocks::Blocks,
+    rotation::{self, RotationSystem},
     shape::{Shape, ShapeGrid, ShapeType},
     stack::Stack,
 };
@@ -15,12 +17,22 @@ pub enum Movement {
     Rotate,
 }
 
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct PieceState {
+    pub shape: ShapeType,
+    pub x: i32,
+    pub y: i32,
+    pub rotation: usize,
+}
+
 #[derive(Clone)]
 pub struct Piece {
     shape: Shape,
     pub x: i32,
     pub y: i32,
     rotation: usize,
+    /// Rotation system turning the piece, the one of the rules it's played by
+    system: Arc<dyn RotationSystem>,
     last_movement: Movement,
     locking: Duration,
 }
@@ -32,6 +44,7 @@ impl Piece {
             x: 0,
             y: 0,
             rotation: 0,
+            system: rotation::srs(),
             last_movement: Movement::None,
             locking: Duration::new(0, 0),
         };
@@ -40,9 +53,34 @@ impl Piece {
         piece
     }
 
-    pub fn update(&mut self, ctx: &mut Context, stack: &Stack) {
+    pub fn from_state(state: PieceState) -> Piece {
+        Piece {
+            shape: Shape::new(state.shape),
+            x: state.x,
+            y: state.y,
+            rotation: state.rotation % 4,
+            system: rotation::srs(),
+            last_movement: Movement::None,
+            locking: Duration::new(0, 0),
+        }
+    }
+
+    pub fn state(&self) -> PieceState {
+        PieceState {
+            shape: self.shape.shape_type,
+            x: self.x,
+            y: self.y,
+            rotation: self.rotation,
+        }
+    }
+
+    pub fn set_system(&mut self, system: Arc<dyn RotationSystem>) {
+        self.system = system;
+    }
+
+    pub fn update(&mut self, dt: Duration, stack: &Stack) {
         if self.collision(0, 1, stack) {
-            self.locking += timer::delta(ctx);
+            self.locking += dt;
         }
     }
 
@@ -52,8 +90,8 @@ impl Piece {
         }
 
         // Position of the center tile
-        let x = self.x as usize + 1;
-        let y = self.y as usize + 1;
+        let x = (self.x + 1) as usize;
+        let y = (self.y + 1) as usize;
 
         let mut occupied = 0;
 
@@ -102,30 +140,43 @@ impl Piece {
     }
 
     pub fn rotate(&mut self, clockwise: bool, stack: &Stack) -> bool {
-        if self.shape() == ShapeType::O {
-            return false;
-        }
+        self.rotate_with(clockwise, true, stack)
+    }
 
-        let kicks = self.shape.kicks[self.rotation];
-        let last_rotation = self.rotation;
-        let mut rotated = false;
+    /// Rotates the piece, trying the wall kicks only when they are enabled
+    pub fn rotate_with(&mut self, clockwise: bool, kicks: bool, stack: &Stack) -> bool {
+        self.turn(if clockwise { 1 } else { 3 }, kicks, stack)
+    }
 
-        let kicks = if clockwise { kicks.0 } else { kicks.1 };
+    pub fn rotate_180(&mut self, stack: &Stack) -> bool {
+        self.rotate_180_with(true, stack)
+    }
 
-        if clockwise && self.rotation == 3 {
-            self.rotation = 0;
-        } else if clockwise {
-            self.rotation += 1;
-        } else if self.rotation == 0 {
-            self.rotation = 3;
-        } else {
-            self.rotation -= 1;
+    /// Turns the piece around at once, with kicks of its own instead of two quarter turns
+    pub fn rotate_180_with(&mut self, kicks: bool, stack: &Stack) -> bool {
+        self.turn(2, kicks, stack)
+    }
+
+    /// Turns the piece clockwise by the quarters the way its rotation system does
+    fn turn(&mut self, quarters: usize, kicks: bool, stack: &Stack) -> bool {
+        let system = Arc::clone(&self.system);
+        let shape = self.shape();
+        let (last_rotation, x, y) = (self.rotation, self.x, self.y);
+        let rotation = system.turn(shape, last_rotation, quarters);
+        if rotation == last_rotation {
+            return false;
         }
 
+        let offset = system.offset(shape, last_rotation, rotation);
+        let mut rotated = false;
+        self.rotation = rotation;
+        self.x += offset.0;
+        self.y += offset.1;
+
         if !stack.collision(&self) {
             rotated = true;
-        } else {
-            for kick in &kicks {
+        } else if kicks {
+            for kick in system.kicks(shape, last_rotation, rotation) {
                 if self.shift(kick.0, kick.1, stack) {
                     rotated = true;
                     break;
@@ -138,6 +189,8 @@ impl Piece {
             self.clear_locking();
         } else {
             self.rotation = last_rotation;
+            self.x = x;
+            self.y = y;
         }
 
         rotated
@@ -157,6 +210,18 @@ impl Piece {
         rows
     }
 
+    /// Moves the piece straight down to a row it can fall to, see `Engine::drop_row`
+    pub fn drop_to(&mut self, row: i32) -> i32 {
+        let rows = row - self.y;
+        if rows > 0 {
+            self.y = row;
+            self.last_movement = Movement::Shift;
+            self.clear_locking();
+        }
+
+        rows.max(0)
+    }
+
     pub fn clear_locking(&mut self) {
         self.locking = Duration::new(0, 0);
     }
@@ -173,30 +238,56 @@ impl Piece {
         &self.shape.grids[self.rotation]
     }
 
+    /// Positions of all four blocks of the piece on the stack
+    pub fn cells(&self) -> [(i32, i32); 4] {
+        let grid = self.grid();
+        let mut cells = [(0, 0); 4];
+        let mut i = 0;
+
+        for my in 0..grid.height {
+            for mx in 0..grid.width {
+                if grid.grid[(my + grid.offset_y) as usize][(mx + grid.offset_x) as usize] != 0 {
+                    cells[i] = (self.x + grid.offset_x + mx, self.y + grid.offset_y + my);
+                    i += 1;
+                }
+            }
+        }
+
+        cells
+    }
+
     pub fn shape(&self) -> ShapeType {
         self.shape.shape_type
     }
 
-    pub fn draw(
+    pub fn add(
         &self,
-        ctx: &mut Context,
         position: Point2<f32>,
         vanish: i32,
         blocks: &mut Blocks,
         block_size: i32,
         alpha: f32,
-    ) -> GameResult {
-        blocks.clear();
+    ) {
+        self.add_at(self.y, position, vanish, blocks, block_size, alpha);
+    }
 
+    /// Adds the piece as if it was moved to another row, e.g. the ghost
+    pub fn add_at(
+        &self,
+        row: i32,
+        position: Point2<f32>,
+        vanish: i32,
+        blocks: &mut Blocks,
+        block_size: i32,
+        alpha: f32,
+    ) {
         let position = Point2::new(
             position[0] + (self.x * block_size) as f32,
-            position[1] + ((self.y - vanish) * block_size) as f32,
+            position[1] + ((row - vanish) * block_size) as f32,
         );
 
         self.shape
-            .draw(ctx, self.rotation, position, blocks, block_size, alpha)?;
-
-        Ok(())
+            .add(self.rotation, position, blocks, block_size, alpha);
     }
 
     fn collision(&mut self, x: i32, y: i32, stack: &Stack) -> bool {
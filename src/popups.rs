@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::{borrow::Cow, time::Duration};
 
 use ggez::{
     conf::NumSamples,
@@ -7,8 +7,6 @@ use ggez::{
     timer, Context, GameResult,
 };
 
-use crate::utils;
-
 #[derive(Default)]
 pub struct Popup {
     canvas: Option<Canvas>,
@@ -21,7 +19,7 @@ pub struct Popup {
 }
 
 struct PopupFragment {
-    text: String,
+    text: Cow<'static, str>,
     color: Color,
     scale: f32,
 }
@@ -34,9 +32,10 @@ impl Popup {
         }
     }
 
-    pub fn add(&mut self, text: &str, color: Color, scale: f32) {
+    /// Static labels are borrowed, only formatted ones like the combo counter own their text
+    pub fn add<T: Into<Cow<'static, str>>>(&mut self, text: T, color: Color, scale: f32) {
         self.fragments.push(PopupFragment {
-            text: String::from(text),
+            text: text.into(),
             color,
             scale,
         });
@@ -61,13 +60,13 @@ impl Popup {
 
         for f in self.fragments.drain(..) {
             text.add(
-                TextFragment::from(f.text.clone())
+                TextFragment::from(f.text.as_ref())
                     .color(f.color)
                     .scale(Scale::uniform(scale * f.scale)),
             );
 
             shadow.add(
-                TextFragment::from(f.text)
+                TextFragment::from(f.text.into_owned())
                     .color(graphics::BLACK)
                     .scale(Scale::uniform(scale * f.scale)),
             );
@@ -144,21 +143,65 @@ impl Popup {
     }
 }
 
+/// Number of garbage lines flying from the board towards the opponent
+struct AttackPopup {
+    lines: i32,
+    target: Vector2<f32>,
+    visible: Duration,
+}
+
+impl AttackPopup {
+    const LIFETIME: Duration = Duration::from_millis(800);
+
+    fn ratio(&self) -> f32 {
+        (self.visible.as_secs_f32() / AttackPopup::LIFETIME.as_secs_f32()).min(1.0)
+    }
+
+    fn draw(&self, ctx: &mut Context, origin: Point2<f32>, font: Font, scale: f32) -> GameResult {
+        let ratio = self.ratio();
+
+        // Ease out, so that the number leaves the board quickly and slows down near the target
+        let travel = 1.0 - (1.0 - ratio).powi(3);
+        let alpha = if ratio > 0.75 {
+            (1.0 - ratio) * 4.0
+        } else {
+            1.0
+        };
+
+        let text = Text::new(TextFragment {
+            text: format!("+{}", self.lines),
+            color: Some(Color::new(1.0, 0.3, 0.2, alpha)),
+            font: Some(font),
+            scale: Some(Scale::uniform(scale * (3.0 - ratio))),
+        });
+
+        let size = Vector2::new(text.width(ctx) as f32, text.height(ctx) as f32);
+        let dest = origin + self.target * travel - size / 2.0;
+        graphics::draw(ctx, &text, DrawParam::new().dest(dest))
+    }
+}
+
 pub struct Popups {
     active_popup: Option<Popup>,
     fading_popups: Vec<Popup>,
+    attacks: Vec<AttackPopup>,
     font: Font,
     just_created: bool,
+    size: Vector2<f32>,
+    scale: f32,
 }
 
 impl Popups {
-    pub fn new(ctx: &mut Context) -> GameResult<Popups> {
-        Ok(Popups {
+    pub fn new(font: Font) -> Popups {
+        Popups {
             active_popup: None,
             fading_popups: vec![],
-            font: Font::new(ctx, utils::path(ctx, "fonts/bold.ttf"))?,
+            attacks: vec![],
+            font,
             just_created: true,
-        })
+            size: Vector2::new(0.0, 0.0),
+            scale: 0.0,
+        }
     }
 
     pub fn add(&mut self, popup: Popup) {
@@ -173,6 +216,21 @@ impl Popups {
         }
     }
 
+    pub fn clear(&mut self) {
+        self.active_popup = None;
+        self.fading_popups.clear();
+        self.attacks.clear();
+    }
+
+    /// Sends the number of garbage lines from the middle of the board by the target offset
+    pub fn attack(&mut self, lines: i32, target: Vector2<f32>) {
+        self.attacks.push(AttackPopup {
+            lines,
+            target,
+            visible: Duration::new(0, 0),
+        });
+    }
+
     pub fn lock(&mut self, rows: i32, t_spin: bool, btb: bool, combo: Option<i32>, delay: u64) {
         let mut lifetime = delay;
         if lifetime < 750 {
@@ -208,7 +266,7 @@ impl Popups {
                 }
 
                 popup.add(
-                    &format!("{} combo\n", combo),
+                    format!("{} combo\n", combo),
                     Color::new(1.0, 1.0, 1.0 - rank, 1.0),
                     rank + 1.0,
                 );
@@ -218,8 +276,16 @@ impl Popups {
         self.add(popup);
     }
 
-    pub fn update(&mut self, ctx: &mut Context, width: f32, height: f32, scale: f32) -> GameResult {
-        let dt = timer::delta(ctx);
+    pub fn update(
+        &mut self,
+        ctx: &mut Context,
+        dt: Duration,
+        width: f32,
+        height: f32,
+        scale: f32,
+    ) -> GameResult {
+        self.size = Vector2::new(width, height);
+        self.scale = scale;
 
         if self.just_created {
             self.just_created = false;
@@ -251,6 +317,12 @@ impl Popups {
 
         self.fading_popups.retain(|p| p.visible < p.lifetime);
 
+        for a in self.attacks.iter_mut() {
+            a.visible += dt;
+        }
+
+        self.attacks.retain(|a| a.visible < AttackPopup::LIFETIME);
+
         Ok(())
     }
 
@@ -267,6 +339,11 @@ impl Popups {
             p.draw(ctx, position, height)?;
         }
 
+        let origin = position + self.size / 2.0;
+        for a in &self.attacks {
+            a.draw(ctx, origin, self.font, self.scale)?;
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,136 @@
+use gfx::{self, *};
+use ggez::{
+    graphics::{self, Canvas, DrawParam, Shader},
+    nalgebra::Vector2,
+    Context, GameResult,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+gfx_defines! {
+    constant Effect {
+        resolution: [f32; 2] = "u_Resolution",
+        time: f32 = "u_Time",
+        strength: f32 = "u_Strength",
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Default)]
+#[serde(default)]
+pub struct PostEffects {
+    pub scanlines: bool,
+    pub bloom: bool,
+    pub aberration: bool,
+}
+
+impl PostEffects {
+    pub fn any(&self) -> bool {
+        self.scanlines || self.bloom || self.aberration
+    }
+}
+
+pub struct PostProcess {
+    canvases: [Canvas; 2],
+    scanlines: Shader<Effect>,
+    bloom: Shader<Effect>,
+    aberration: Shader<Effect>,
+    effect: Effect,
+}
+
+impl PostProcess {
+    pub fn new(ctx: &mut Context) -> GameResult<PostProcess> {
+        let (w, h) = graphics::drawable_size(ctx);
+        let effect = Effect {
+            resolution: [w, h],
+            time: 0.0,
+            strength: 0.0,
+        };
+
+        Ok(PostProcess {
+            canvases: [
+                Canvas::with_window_size(ctx)?,
+                Canvas::with_window_size(ctx)?,
+            ],
+            scanlines: PostProcess::shader(ctx, "scanlines", effect)?,
+            bloom: PostProcess::shader(ctx, "bloom", effect)?,
+            aberration: PostProcess::shader(ctx, "aberration", effect)?,
+            effect,
+        })
+    }
+
+    fn shader(ctx: &mut Context, name: &str, effect: Effect) -> GameResult<Shader<Effect>> {
+        let vertex = utils::path(ctx, "shaders/basic_150.glslv");
+        let pixel = utils::path(ctx, &format!("shaders/{}_150.glslf", name));
+        Shader::new(ctx, vertex, pixel, effect, "Effect", None)
+    }
+
+    pub fn resize(&mut self, ctx: &mut Context) -> GameResult {
+        let (w, h) = graphics::drawable_size(ctx);
+        self.effect.resolution = [w, h];
+        self.canvases = [
+            Canvas::with_window_size(ctx)?,
+            Canvas::with_window_size(ctx)?,
+        ];
+
+        Ok(())
+    }
+
+    pub fn explode(&mut self, strength: f32) {
+        self.effect.strength = self.effect.strength.max(strength);
+    }
+
+    pub fn update(&mut self, ctx: &mut Context) {
+        let dt = utils::dt_f32(ctx);
+        self.effect.time += dt;
+        self.effect.strength = (self.effect.strength - dt * 2.0).max(0.0);
+    }
+
+    /// Redirects all further drawing into an offscreen canvas
+    pub fn begin(&mut self, ctx: &mut Context, effects: &PostEffects) {
+        if effects.any() {
+            graphics::set_canvas(ctx, Some(&self.canvases[0]));
+            graphics::clear(ctx, graphics::BLACK);
+        }
+    }
+
+    /// Runs every enabled effect, the last one drawing onto the screen
+    pub fn finish(&mut self, ctx: &mut Context, effects: &PostEffects) -> GameResult {
+        if !effects.any() {
+            return Ok(());
+        }
+
+        let mut passes: Vec<&Shader<Effect>> = vec![];
+        if effects.bloom {
+            passes.push(&self.bloom);
+        }
+        if effects.aberration {
+            passes.push(&self.aberration);
+        }
+        if effects.scanlines {
+            passes.push(&self.scanlines);
+        }
+
+        let screen = graphics::screen_coordinates(ctx);
+        let (w, h) = graphics::drawable_size(ctx);
+        let param = DrawParam::new().scale(Vector2::new(screen.w / w, screen.h / h));
+
+        let last = passes.len() - 1;
+        for (i, shader) in passes.into_iter().enumerate() {
+            let source = i % 2;
+
+            if i == last {
+                graphics::set_canvas(ctx, None);
+            } else {
+                graphics::set_canvas(ctx, Some(&self.canvases[1 - source]));
+                graphics::clear(ctx, graphics::BLACK);
+            }
+
+            let _lock = graphics::use_shader(ctx, shader);
+            shader.send(ctx, self.effect)?;
+            graphics::draw(ctx, &self.canvases[source], param)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::VecDeque,
+    mem,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use imgui::{im_str, Condition, Ui, Window};
+
+/// How many frames are kept for the graph
+const HISTORY: usize = 240;
+
+/// How many key presses are averaged for the latency estimate
+const LATENCY_SAMPLES: usize = 16;
+
+static DRAW_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Called next to every batch, mesh and canvas draw of the board and the background
+pub fn count_draw() {
+    DRAW_CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[derive(Copy, Clone, Default)]
+struct Frame {
+    total: Duration,
+    update: Duration,
+    draw: Duration,
+    draw_calls: usize,
+}
+
+/// Frame times of the last few seconds, shown in the performance HUD
+pub struct Profiler {
+    frames: VecDeque<Frame>,
+    current: Frame,
+    last_frame: Instant,
+    latencies: VecDeque<Duration>,
+    pub particles: usize,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            frames: VecDeque::with_capacity(HISTORY),
+            current: Frame::default(),
+            last_frame: Instant::now(),
+            latencies: VecDeque::with_capacity(LATENCY_SAMPLES),
+            particles: 0,
+        }
+    }
+
+    pub fn update(&mut self, duration: Duration) {
+        self.current.update += duration;
+    }
+
+    /// Finishes the frame, the draw is the last thing that happens in it
+    pub fn draw(&mut self, duration: Duration) {
+        let now = Instant::now();
+
+        let mut frame = mem::replace(&mut self.current, Frame::default());
+        frame.draw = duration;
+        frame.total = now - self.last_frame;
+        frame.draw_calls = DRAW_CALLS.swap(0, Ordering::Relaxed);
+        self.last_frame = now;
+
+        if self.frames.len() == HISTORY {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+    }
+
+    /// Time from a key press to presenting the frame with its result.
+    /// The display itself adds its own delay on top, so this is only a lower bound.
+    pub fn latency(&mut self, duration: Duration) {
+        if self.latencies.len() == LATENCY_SAMPLES {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(duration);
+    }
+
+    pub fn ui(&self, ui: &Ui) {
+        let last = match self.frames.back() {
+            Some(last) => *last,
+            None => return,
+        };
+
+        let count = self.frames.len() as u32;
+        let average = self.frames.iter().map(|f| f.total).sum::<Duration>() / count;
+        let worst = self
+            .frames
+            .iter()
+            .map(|f| f.total)
+            .max()
+            .unwrap_or_default();
+
+        let [w, _] = ui.io().display_size;
+        Window::new(im_str!("Performance"))
+            .resizable(false)
+            .collapsible(false)
+            .size([280.0, 0.0], Condition::Always)
+            .position([w - 300.0, 40.0], Condition::Appearing)
+            .build(ui, || {
+                ui.text(im_str!("Frame:  {:.1?}", last.total));
+                ui.text(im_str!("Update: {:.1?}", last.update));
+                ui.text(im_str!("Draw:   {:.1?}", last.draw));
+                ui.text(im_str!("Draw calls: {}", last.draw_calls));
+                ui.text(im_str!("Particles:  {}", self.particles));
+                if !self.latencies.is_empty() {
+                    let latency =
+                        self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32;
+                    ui.text(im_str!("Input latency: {:.1?}", latency));
+                }
+                ui.separator();
+
+                let times: Vec<f32> = self
+                    .frames
+                    .iter()
+                    .map(|f| f.total.as_secs_f32() * 1000.0)
+                    .collect();
+                let overlay = im_str!("avg {:.1?} / max {:.1?}", average, worst);
+                ui.plot_lines(im_str!("##frames"), &times)
+                    .overlay_text(&overlay)
+                    .scale_min(0.0)
+                    .scale_max(50.0)
+                    .graph_size([264.0, 60.0])
+                    .build();
+            });
+    }
+}
@@ -2,71 +2,271 @@ use std::{
     collections::VecDeque,
     fs,
     io::{Read, Write},
-    path::Path,
-    time::Duration,
+    mem,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
+use chrono::Utc;
+use dirs;
 use flate2::{read::GzDecoder, write::GzEncoder, Compression};
-use ggez::{timer, Context, GameResult};
-use serde::{Deserialize, Serialize};
+use ggez::{Context, GameResult};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::{action::Action, gameplay::Gameplay, global::Global};
+use crate::{
+    action::Action,
+    clock::{Clock, TICK},
+    gameplay::Gameplay,
+    global::Global,
+    rules::Rules,
+};
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub struct TimedAction {
-    action: Action,
-    duration: Duration,
+    pub action: Action,
+    pub duration: Duration,
+}
+
+/// Final results of the recorded game, used to verify the replay
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct ReplayResults {
+    pub score: i32,
+    pub lines: i32,
+    pub duration: Duration,
+}
+
+/// Actions between two checkpoints
+pub const CHECKPOINT_INTERVAL: usize = 100;
+
+/// State hash taken right before the action with the given index,
+/// shows where a replay stops playing back the same
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub action: u32,
+    pub hash: u64,
+}
+
+/// Playback speeds to pick from, whole multiples so that the ticks stay whole
+pub const SPEEDS: [u32; 3] = [1, 2, 4];
+
+/// Note left at some point of the replay, e.g. by a coach
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Marker {
+    pub time: Duration,
+    pub text: String,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+/// Starts every replay file with a version, older replays start right away with the seed
+const REPLAY_MAGIC: [u8; 8] = *b"KLOCKIRP";
+
+/// Version of the layout of `ReplayFile`, to be bumped whenever it changes.
+/// Replays of any other version are rejected instead of being misread.
+const REPLAY_VERSION: u32 = 1;
+
+#[derive(Clone)]
 pub struct ReplayData {
     pub seed: [u8; 32],
     pub actions: VecDeque<TimedAction>,
+    pub results: Option<ReplayResults>,
+    pub rules: Rules,
+    pub checkpoints: Vec<Checkpoint>,
+    /// Sorted by time
+    pub markers: Vec<Marker>,
+}
+
+/// Replay as saved after the version. The rules are kept as TOML,
+/// so that new rules don't change the layout and old replays get their defaults.
+#[derive(Serialize, Deserialize)]
+struct ReplayFile {
+    seed: [u8; 32],
+    actions: VecDeque<TimedAction>,
+    results: Option<ReplayResults>,
+    rules: String,
+    checkpoints: Vec<Checkpoint>,
+    markers: Vec<Marker>,
+}
+
+/// Replays saved before the version and the rules were stored
+#[derive(Deserialize)]
+struct StandardReplayData {
+    seed: [u8; 32],
+    actions: VecDeque<TimedAction>,
+    results: Option<ReplayResults>,
+}
+
+/// Replays saved before the results were stored
+#[derive(Deserialize)]
+struct LegacyReplayData {
+    seed: [u8; 32],
+    actions: VecDeque<TimedAction>,
 }
 
 impl ReplayData {
-    pub fn new(seed: &[u8; 32]) -> ReplayData {
+    pub fn new(seed: &[u8; 32], rules: &Rules) -> ReplayData {
         let mut seed_clone = [0; 32];
         seed_clone.clone_from_slice(seed);
 
         ReplayData {
             actions: VecDeque::new(),
             seed: seed_clone,
+            results: None,
+            rules: rules.clone(),
+            checkpoints: vec![],
+            markers: vec![],
         }
     }
 
+    pub fn directory() -> PathBuf {
+        let mut path = dirs::data_local_dir().unwrap_or_default();
+        path.push("klocki");
+        path.push("replays");
+        path
+    }
+
+    /// New file in the replays directory, named after what the replay holds and when it was made
+    pub fn new_path(label: &str) -> PathBuf {
+        let mut path = ReplayData::directory();
+        path.push(format!(
+            "{} - {}.klocki",
+            label,
+            Utc::now().format("%Y%m%d_%H%M%S")
+        ));
+        path
+    }
+
+    /// Replay files in the directory, the newest first
+    pub fn list(directory: &Path) -> Vec<PathBuf> {
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::error!("Unable to list replays in {:?}: {:?}", directory, e);
+                return vec![];
+            }
+        };
+
+        let mut replays: Vec<(PathBuf, Option<SystemTime>)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().map_or(false, |e| e == "klocki"))
+            .map(|entry| {
+                let modified = entry.metadata().and_then(|m| m.modified()).ok();
+                (entry.path(), modified)
+            })
+            .collect();
+
+        replays.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| b.0.cmp(&a.0)));
+        replays.into_iter().map(|(path, _)| path).collect()
+    }
+
     pub fn add(&mut self, action: Action, duration: Duration) {
         self.actions.push_back(TimedAction { action, duration });
     }
 
-    pub fn current_duration(&self) -> Option<Duration> {
-        if let Some(action) = self.actions.get(0) {
-            Some(action.duration)
-        } else {
-            None
-        }
+    /// True when the next action should be preceded by a checkpoint
+    pub fn checkpoint_due(&self) -> bool {
+        self.actions.len() % CHECKPOINT_INTERVAL == 0
     }
 
-    pub fn pop_action(&mut self) -> Action {
-        if let Some(action) = self.actions.pop_front() {
-            action.action
-        } else {
-            Action::GameOver
+    pub fn checkpoint(&mut self, hash: u64) {
+        self.checkpoints.push(Checkpoint {
+            action: self.actions.len() as u32,
+            hash,
+        });
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.actions.iter().map(|a| a.duration).sum()
+    }
+
+    /// Markers at the same time keep the order they were added in
+    pub fn add_marker(&mut self, time: Duration, text: String) {
+        let index = self.markers.iter().take_while(|m| m.time <= time).count();
+        self.markers.insert(index, Marker { time, text });
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, String> {
+        let file = ReplayFile {
+            seed: self.seed,
+            actions: self.actions.clone(),
+            results: self.results,
+            rules: toml::to_string(&self.rules).map_err(|e| format!("Invalid rules: {}", e))?,
+            checkpoints: self.checkpoints.clone(),
+            markers: self.markers.clone(),
+        };
+
+        let mut bytes = REPLAY_MAGIC.to_vec();
+        bytes.extend_from_slice(&REPLAY_VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(&file).map_err(|e| format!("{:?}", e))?);
+        Ok(bytes)
+    }
+
+    /// Replays saved before the version was stored only load when they are
+    /// exactly one of the layouts without rules, which play by the default ones
+    fn decode(bytes: &[u8]) -> Result<ReplayData, String> {
+        if !bytes.starts_with(&REPLAY_MAGIC) {
+            return decode_exact::<StandardReplayData>(bytes)
+                .map(|standard| ReplayData {
+                    seed: standard.seed,
+                    actions: standard.actions,
+                    results: standard.results,
+                    rules: Rules::default(),
+                    checkpoints: vec![],
+                    markers: vec![],
+                })
+                .or_else(|_| {
+                    decode_exact::<LegacyReplayData>(bytes).map(|legacy| ReplayData {
+                        seed: legacy.seed,
+                        actions: legacy.actions,
+                        results: None,
+                        rules: Rules::default(),
+                        checkpoints: vec![],
+                        markers: vec![],
+                    })
+                })
+                .map_err(|_| String::from("Unknown replay layout without a version"));
+        }
+
+        let bytes = &bytes[REPLAY_MAGIC.len()..];
+        if bytes.len() < 4 {
+            return Err(String::from("Replay version is missing"));
+        }
+
+        let mut version = [0; 4];
+        version.copy_from_slice(&bytes[..4]);
+        let version = u32::from_le_bytes(version);
+        if version != REPLAY_VERSION {
+            return Err(format!("Unsupported replay version {}", version));
         }
+
+        let file: ReplayFile = decode_exact(&bytes[4..])?;
+        let rules = toml::from_str(&file.rules).map_err(|e| format!("Invalid rules: {}", e))?;
+
+        Ok(ReplayData {
+            seed: file.seed,
+            actions: file.actions,
+            results: file.results,
+            rules,
+            checkpoints: file.checkpoints,
+            markers: file.markers,
+        })
     }
 
     pub fn save(&self, path: &Path) {
-        let mut writer = GzEncoder::new(Vec::new(), Compression::best());
-        let bytes = bincode::serialize(&self).unwrap();
-        writer.write_all(&bytes).unwrap();
-
-        if let Err(e) = fs::write(path, writer.finish().unwrap()) {
-            log::error!("Unable to save replay: {:?}", e)
-        } else {
-            log::info!("Saved replay in {:?}", path);
+        match self.try_save(path) {
+            Ok(()) => log::info!("Saved replay in {:?}", path),
+            Err(e) => log::error!("Unable to save replay: {}", e),
         }
     }
 
+    /// Never panics, so that it can be used from the panic hook
+    pub fn try_save(&self, path: &Path) -> Result<(), String> {
+        let mut writer = GzEncoder::new(Vec::new(), Compression::best());
+        writer
+            .write_all(&self.encode()?)
+            .map_err(|e| format!("{:?}", e))?;
+        let bytes = writer.finish().map_err(|e| format!("{:?}", e))?;
+        fs::write(path, bytes).map_err(|e| format!("{:?}", e))
+    }
+
     pub fn load(path: &Path) -> Option<ReplayData> {
         match fs::read(path) {
             Err(e) => log::error!("Unable to load replay: {:?}", e),
@@ -76,17 +276,13 @@ impl ReplayData {
 
                 match reader.read_to_end(&mut bytes) {
                     Err(e) => log::error!("Unable to decompress replay: {:?}", e),
-                    Ok(_) => {
-                        let replay_data: Result<ReplayData, _> = bincode::deserialize(&bytes);
-
-                        match replay_data {
-                            Err(e) => log::error!("Unable to deserialize replay: {:?}", e),
-                            Ok(replay_data) => {
-                                log::info!("Loaded replay from {:?}", path,);
-                                return Some(replay_data);
-                            }
+                    Ok(_) => match ReplayData::decode(&bytes) {
+                        Err(e) => log::error!("Unable to deserialize replay: {}", e),
+                        Ok(replay_data) => {
+                            log::info!("Loaded replay from {:?}", path,);
+                            return Some(replay_data);
                         }
-                    }
+                    },
                 }
             }
         }
@@ -95,31 +291,299 @@ impl ReplayData {
     }
 }
 
+/// Decodes a value taking up all of the bytes, anything left over means another layout
+fn decode_exact<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    let mut reader = bytes;
+    let value = bincode::deserialize_from(&mut reader).map_err(|e| format!("{:?}", e))?;
+
+    if reader.is_empty() {
+        Ok(value)
+    } else {
+        Err(format!("{} bytes left over", reader.len()))
+    }
+}
+
+#[derive(Default)]
+pub struct Timeline {
+    pub length: f32,
+    pub position: f32,
+    pub density: Vec<f32>,
+    pub bookmarks: Vec<(f32, i32)>,
+    /// Seconds and text of every marker
+    pub markers: Vec<(f32, String)>,
+    /// Every second of the replay, with the ones holding a marker raised
+    pub marker_lane: Vec<f32>,
+    pub seek: Option<f32>,
+}
+
+impl Timeline {
+    pub fn set_markers(&mut self, markers: &[Marker]) {
+        self.markers = markers
+            .iter()
+            .map(|m| (m.time.as_secs_f32(), m.text.clone()))
+            .collect();
+
+        self.marker_lane = vec![0.0; self.length as usize + 1];
+        for &(time, _) in &self.markers {
+            if let Some(second) = self.marker_lane.get_mut(time as usize) {
+                *second = 1.0;
+            }
+        }
+    }
+}
+
 pub struct Replay {
     replay_data: ReplayData,
+    position: usize,
+    elapsed: Duration,
     action_duration: Duration,
+    /// Markers already shown during playback
+    markers_shown: usize,
+    /// Time of the playback, running faster or slower than the real one
+    clock: Clock,
+    speed: u32,
+    paused: bool,
     pub gameplay: Gameplay,
 }
 
 impl Replay {
     pub fn new(ctx: &mut Context, g: &mut Global, replay_data: ReplayData) -> GameResult<Replay> {
+        let gameplay = Gameplay::new(ctx, g, false, &replay_data.seed, &replay_data.rules)?;
+
         Ok(Replay {
-            gameplay: Gameplay::new(ctx, g, false, &replay_data.seed)?,
             replay_data,
+            position: 0,
+            elapsed: Duration::new(0, 0),
             action_duration: Duration::new(0, 0),
+            markers_shown: 0,
+            clock: Clock::default(),
+            speed: 1,
+            paused: false,
+            gameplay,
         })
     }
 
-    pub fn update(&mut self, ctx: &mut Context) {
-        self.action_duration += timer::delta(ctx);
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: u32) {
+        self.speed = speed.max(1);
+    }
 
-        while let Some(duration) = self.replay_data.current_duration() {
-            if self.action_duration >= duration {
-                self.gameplay.action(self.replay_data.pop_action(), false);
-                self.action_duration = Duration::new(0, 0);
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Plays on by the frame time times the speed. The gameplay runs on the clock
+    /// of the playback, so that its animations keep up with the actions.
+    pub fn play(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        let ticks = if self.paused {
+            0
+        } else {
+            (g.clock.delta().as_nanos() / TICK.as_nanos()) as u32 * self.speed
+        };
+        self.clock.advance(ticks);
+
+        mem::swap(&mut g.clock, &mut self.clock);
+        if !self.gameplay.paused() && !g.imgui_state.paused {
+            self.update(g.clock.delta());
+        }
+        let result = self.gameplay.update(ctx, g, true);
+        mem::swap(&mut g.clock, &mut self.clock);
+
+        result
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        self.action_duration += dt;
+
+        while let Some(action) = self.replay_data.actions.get(self.position) {
+            if self.action_duration >= action.duration {
+                self.gameplay.action(action.action, false);
+                self.elapsed += action.duration;
+                self.action_duration -= action.duration;
+                self.position += 1;
             } else {
                 break;
             }
         }
+
+        let elapsed = self.elapsed();
+        while let Some(marker) = self.replay_data.markers.get(self.markers_shown) {
+            if marker.time > elapsed {
+                break;
+            }
+
+            self.gameplay.show_marker(&marker.text);
+            self.markers_shown += 1;
+        }
+    }
+
+    pub fn replay_data(&self) -> &ReplayData {
+        &self.replay_data
+    }
+
+    /// Leaves a note at the current moment, it counts as already shown
+    pub fn add_marker(&mut self, text: String) {
+        let elapsed = self.elapsed();
+        self.replay_data.add_marker(elapsed, text);
+        self.markers_shown = self.markers_before(elapsed, true);
+    }
+
+    fn markers_before(&self, time: Duration, inclusive: bool) -> usize {
+        self.replay_data
+            .markers
+            .iter()
+            .take_while(|m| m.time < time || (inclusive && m.time == time))
+            .count()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        if self.position < self.replay_data.actions.len() {
+            self.elapsed + self.action_duration
+        } else {
+            self.elapsed
+        }
     }
+
+    pub fn seek(&mut self, g: &mut Global, target: Duration) {
+        self.gameplay.reset(&self.replay_data.seed);
+        self.gameplay.skip_countdown();
+
+        self.position = 0;
+        self.elapsed = Duration::new(0, 0);
+
+        while let Some(action) = self.replay_data.actions.get(self.position) {
+            if self.elapsed + action.duration > target {
+                break;
+            }
+
+            self.gameplay.skip(g, action.action);
+            self.elapsed += action.duration;
+            self.position += 1;
+        }
+
+        self.action_duration = target - self.elapsed;
+        self.markers_shown = self.markers_before(target, false);
+    }
+
+    pub fn timeline(&mut self, g: &mut Global) -> Timeline {
+        let length = self.replay_data.duration().as_secs_f32();
+        let mut density = vec![0.0; length as usize + 1];
+        let mut bookmarks = vec![];
+
+        self.gameplay.reset(&self.replay_data.seed);
+        let mut elapsed = Duration::new(0, 0);
+
+        for action in &self.replay_data.actions {
+            elapsed += action.duration;
+            let seconds = elapsed.as_secs_f32();
+
+            match action.action {
+                Action::FallPiece | Action::LockPiece | Action::GameOver => (),
+                _ => density[seconds as usize] += 1.0,
+            }
+
+            let rows = self.gameplay.skip(g, action.action);
+            if rows > 0 {
+                bookmarks.push((seconds, rows));
+            }
+        }
+
+        self.gameplay.reset(&self.replay_data.seed);
+
+        let mut timeline = Timeline {
+            length,
+            position: 0.0,
+            density,
+            bookmarks,
+            markers: vec![],
+            marker_lane: vec![],
+            seek: None,
+        };
+        timeline.set_markers(&self.replay_data.markers);
+        timeline
+    }
+}
+
+#[test]
+fn save_load_test() {
+    let mut directory = std::env::temp_dir();
+    directory.push(format!("klocki-replays-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+
+    let mut replay_data = ReplayData::new(&[7; 32], &Rules::default());
+    replay_data.add(Action::MoveLeft, Duration::from_millis(250));
+    replay_data.add(Action::HardDrop, Duration::from_millis(400));
+    replay_data.add_marker(Duration::from_millis(650), String::from("Nice"));
+
+    let mut path = directory.clone();
+    path.push("game.klocki");
+    replay_data.save(&path);
+    fs::write(directory.join("notes.txt"), "not a replay").unwrap();
+
+    assert_eq!(ReplayData::list(&directory), vec![path.clone()]);
+
+    let loaded = ReplayData::load(&path).unwrap();
+    assert_eq!(loaded.seed, replay_data.seed);
+    assert_eq!(loaded.actions, replay_data.actions);
+    assert_eq!(loaded.markers, replay_data.markers);
+    assert_eq!(loaded.duration(), Duration::from_millis(650));
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn replay_version_test() {
+    let rules = Rules {
+        cascade: true,
+        ..Rules::marathon()
+    };
+    let mut replay_data = ReplayData::new(&[3; 32], &rules);
+    replay_data.add(Action::HardDrop, Duration::from_millis(100));
+
+    let mut bytes = replay_data.encode().unwrap();
+    let decoded = ReplayData::decode(&bytes).unwrap();
+    assert_eq!(decoded.rules, rules);
+    assert_eq!(decoded.actions, replay_data.actions);
+
+    // Versions from the future and leftovers of other layouts don't load
+    bytes[REPLAY_MAGIC.len()] += 1;
+    assert!(ReplayData::decode(&bytes).is_err());
+
+    let standard =
+        bincode::serialize(&([3u8; 32], &replay_data.actions, None::<ReplayResults>)).unwrap();
+    let decoded = ReplayData::decode(&standard).unwrap();
+    assert_eq!(decoded.rules, Rules::default());
+    assert_eq!(decoded.actions, replay_data.actions);
+
+    let mut trailing = standard.clone();
+    trailing.push(0);
+    assert!(ReplayData::decode(&trailing).is_err());
+}
+
+#[test]
+fn markers_test() {
+    let mut replay_data = ReplayData::new(&[0; 32], &Rules::default());
+    replay_data.add_marker(Duration::from_secs(5), String::from("Should have held"));
+    replay_data.add_marker(Duration::from_secs(2), String::from("Misdrop here"));
+    replay_data.add_marker(Duration::from_secs(5), String::from("Too late"));
+
+    let texts: Vec<&str> = replay_data.markers.iter().map(|m| &m.text[..]).collect();
+    assert_eq!(texts, vec!["Misdrop here", "Should have held", "Too late"]);
+
+    let mut timeline = Timeline {
+        length: 6.0,
+        ..Timeline::default()
+    };
+    timeline.set_markers(&replay_data.markers);
+    assert_eq!(
+        timeline.marker_lane,
+        vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0]
+    );
 }
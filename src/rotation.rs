@@ -0,0 +1,409 @@
+use std::{io::Read, sync::Arc};
+
+use ggez::{filesystem, Context};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use toml;
+
+use crate::{shape::ShapeType, utils};
+
+pub type Kick = [(i32, i32); 4];
+pub type Kicks = [(Kick, Kick); 4];
+pub type Kick180 = [(i32, i32); 5];
+
+/// Offsets tried for a half turn from each rotation, the same for every piece
+static KICKS_180: [Kick180; 4] = [
+    [(0, -1), (1, -1), (-1, -1), (1, 0), (-1, 0)],
+    [(1, 0), (1, -2), (1, -1), (0, -2), (0, -1)],
+    [(0, 1), (-1, 1), (1, 1), (-1, 0), (1, 0)],
+    [(-1, 0), (-1, -2), (-1, -1), (0, -2), (0, -1)],
+];
+
+static KICKS_JLSTZ: Kicks = [
+    (
+        [(-1, 0), (-1, -1), (0, 2), (-1, 2)],
+        [(1, 0), (1, -1), (0, 2), (1, 2)],
+    ),
+    (
+        [(1, 0), (1, 1), (0, -2), (1, -2)],
+        [(1, 0), (1, 1), (0, -2), (1, -2)],
+    ),
+    (
+        [(1, 0), (1, -1), (0, 2), (1, 2)],
+        [(-1, 0), (-1, -1), (0, 2), (-1, 2)],
+    ),
+    (
+        [(-1, 0), (-1, 1), (0, -2), (-1, -2)],
+        [(-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    ),
+];
+
+static KICKS_I: Kicks = [
+    (
+        [(-2, 0), (1, 0), (-2, 1), (1, -2)],
+        [(-1, 0), (2, 0), (-1, -2), (2, 1)],
+    ),
+    (
+        [(-1, 0), (2, 0), (-1, -2), (2, 1)],
+        [(2, 0), (-1, 0), (2, -1), (-1, 2)],
+    ),
+    (
+        [(2, 0), (-1, 0), (2, -1), (-1, 2)],
+        [(1, 0), (-2, 0), (1, 2), (-2, -1)],
+    ),
+    (
+        [(1, 0), (-2, 0), (1, 2), (-2, -1)],
+        [(-2, 0), (1, 0), (-2, 1), (1, -2)],
+    ),
+];
+
+/// File in the resources with the kick table of the custom rotation
+const KICK_TABLE_PATH: &str = "kicks.toml";
+
+/// Offsets further than this would let pieces jump through walls and over the stack
+const MAX_KICK_DISTANCE: i32 = 3;
+
+/// Most offsets tried for a single turn
+const MAX_KICKS: usize = 8;
+
+lazy_static! {
+    static ref SRS: Arc<dyn RotationSystem> = Arc::new(Srs);
+}
+
+/// A step to the right and then to the left, all that the arcade games try
+static KICKS_ARS: [(i32, i32); 2] = [(1, 0), (-1, 0)];
+
+/// Decides where a piece ends up when it turns, the piece takes care of the collisions.
+/// Rotations are the ones of the shape grids, turns go clockwise.
+pub trait RotationSystem: Send + Sync {
+    /// Rotation after turning the given number of quarters, the same one when the piece
+    /// doesn't turn that way
+    fn turn(&self, shape: ShapeType, rotation: usize, quarters: usize) -> usize;
+
+    /// Move of the piece keeping it in place while its grid changes
+    fn offset(&self, _shape: ShapeType, _from: usize, _to: usize) -> (i32, i32) {
+        (0, 0)
+    }
+
+    /// Moves tried in order when the turned piece doesn't fit
+    fn kicks(&self, shape: ShapeType, from: usize, to: usize) -> &[(i32, i32)];
+}
+
+/// Pieces with only two distinct rotations, which flip between the first two
+fn two_sided(shape: ShapeType) -> bool {
+    shape == ShapeType::I || shape == ShapeType::S || shape == ShapeType::Z
+}
+
+/// Rotations of the older games, where I, S and Z flip between two states
+fn flip(shape: ShapeType, rotation: usize, quarters: usize) -> usize {
+    if shape == ShapeType::O {
+        rotation
+    } else if two_sided(shape) {
+        if quarters % 2 == 0 {
+            rotation
+        } else {
+            (rotation + 1) % 2
+        }
+    } else {
+        (rotation + quarters) % 4
+    }
+}
+
+/// Super Rotation System of the guideline games
+pub struct Srs;
+
+/// Shared SRS, turning pieces created without any rules
+pub fn srs() -> Arc<dyn RotationSystem> {
+    Arc::clone(&SRS)
+}
+
+impl RotationSystem for Srs {
+    fn turn(&self, shape: ShapeType, rotation: usize, quarters: usize) -> usize {
+        if shape == ShapeType::O {
+            rotation
+        } else {
+            (rotation + quarters) % 4
+        }
+    }
+
+    fn kicks(&self, shape: ShapeType, from: usize, to: usize) -> &[(i32, i32)] {
+        let kicks = if shape == ShapeType::I {
+            &KICKS_I
+        } else {
+            &KICKS_JLSTZ
+        };
+
+        if to == (from + 1) % 4 {
+            &kicks[from].0
+        } else if to == (from + 3) % 4 {
+            &kicks[from].1
+        } else {
+            &KICKS_180[from]
+        }
+    }
+}
+
+/// Arika rotation of the TGM games, pieces lying flat rest on the bottom of their box
+pub struct Ars;
+
+impl Ars {
+    /// Where the grid of the rotation sits compared to the guideline one
+    fn position(shape: ShapeType, rotation: usize) -> (i32, i32) {
+        match (shape, rotation) {
+            (ShapeType::I, _) | (ShapeType::O, _) => (0, 0),
+            (_, 0) => (0, 1),
+            (ShapeType::S, 1) | (ShapeType::Z, 1) => (-1, 0),
+            _ => (0, 0),
+        }
+    }
+}
+
+impl RotationSystem for Ars {
+    fn turn(&self, shape: ShapeType, rotation: usize, quarters: usize) -> usize {
+        flip(shape, rotation, quarters)
+    }
+
+    fn offset(&self, shape: ShapeType, from: usize, to: usize) -> (i32, i32) {
+        let from = Ars::position(shape, from);
+        let to = Ars::position(shape, to);
+        (to.0 - from.0, to.1 - from.1)
+    }
+
+    fn kicks(&self, shape: ShapeType, _from: usize, _to: usize) -> &[(i32, i32)] {
+        if shape == ShapeType::I {
+            &[]
+        } else {
+            &KICKS_ARS
+        }
+    }
+}
+
+/// Rotation of the NES game, the piece turns where it is or not at all
+pub struct Classic;
+
+impl RotationSystem for Classic {
+    fn turn(&self, shape: ShapeType, rotation: usize, quarters: usize) -> usize {
+        flip(shape, rotation, quarters)
+    }
+
+    fn kicks(&self, _shape: ShapeType, _from: usize, _to: usize) -> &[(i32, i32)] {
+        &[]
+    }
+}
+
+/// Offsets tried for each of the turns, with a list for every rotation the piece turns from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KickSet {
+    pub clockwise: Vec<Vec<(i32, i32)>>,
+    pub counter_clockwise: Vec<Vec<(i32, i32)>>,
+    pub half: Vec<Vec<(i32, i32)>>,
+}
+
+impl KickSet {
+    fn from_kicks(kicks: &Kicks) -> KickSet {
+        KickSet {
+            clockwise: kicks.iter().map(|k| k.0.to_vec()).collect(),
+            counter_clockwise: kicks.iter().map(|k| k.1.to_vec()).collect(),
+            half: KICKS_180.iter().map(|k| k.to_vec()).collect(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (turn, lists) in &[
+            ("clockwise", &self.clockwise),
+            ("counter_clockwise", &self.counter_clockwise),
+            ("half", &self.half),
+        ] {
+            if lists.len() != 4 {
+                return Err(format!(
+                    "{} has {} lists of offsets instead of one for each of the 4 rotations",
+                    turn,
+                    lists.len()
+                ));
+            }
+
+            for (rotation, offsets) in lists.iter().enumerate() {
+                if offsets.len() > MAX_KICKS {
+                    return Err(format!(
+                        "{} from rotation {} has more than {} offsets",
+                        turn, rotation, MAX_KICKS
+                    ));
+                }
+
+                if let Some(&(x, y)) = offsets
+                    .iter()
+                    .find(|(x, y)| x.abs() > MAX_KICK_DISTANCE || y.abs() > MAX_KICK_DISTANCE)
+                {
+                    return Err(format!(
+                        "{} from rotation {} kicks by ({}, {}), further than {} blocks",
+                        turn, rotation, x, y, MAX_KICK_DISTANCE
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Kick offsets read from a file, pieces turn the same way as in SRS
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KickTable {
+    pub name: String,
+    /// Offsets of the J, L, S, T and Z pieces
+    pub jlstz: KickSet,
+    pub i: KickSet,
+}
+
+impl KickTable {
+    /// The same offsets as the built-in SRS
+    pub fn srs() -> KickTable {
+        KickTable {
+            name: String::from("SRS"),
+            jlstz: KickSet::from_kicks(&KICKS_JLSTZ),
+            i: KickSet::from_kicks(&KICKS_I),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<KickTable, String> {
+        let table: KickTable = toml::from_str(contents).map_err(|e| e.to_string())?;
+        table.jlstz.validate().map_err(|e| format!("jlstz.{}", e))?;
+        table.i.validate().map_err(|e| format!("i.{}", e))?;
+        Ok(table)
+    }
+
+    /// Reads the table shipped in the resources, so that it can be changed
+    /// without rebuilding the game
+    pub fn load(ctx: &mut Context) -> Result<KickTable, String> {
+        let mut file =
+            filesystem::open(ctx, utils::path(ctx, KICK_TABLE_PATH)).map_err(|e| e.to_string())?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)
+            .map_err(|e| e.to_string())?;
+        KickTable::parse(&contents)
+    }
+}
+
+impl RotationSystem for KickTable {
+    fn turn(&self, shape: ShapeType, rotation: usize, quarters: usize) -> usize {
+        Srs.turn(shape, rotation, quarters)
+    }
+
+    fn kicks(&self, shape: ShapeType, from: usize, to: usize) -> &[(i32, i32)] {
+        let set = if shape == ShapeType::I {
+            &self.i
+        } else {
+            &self.jlstz
+        };
+
+        if to == (from + 1) % 4 {
+            &set.clockwise[from]
+        } else if to == (from + 3) % 4 {
+            &set.counter_clockwise[from]
+        } else {
+            &set.half[from]
+        }
+    }
+}
+
+#[test]
+fn rotation_system_test() {
+    assert_eq!(Srs.turn(ShapeType::S, 1, 1), 2);
+    assert_eq!(Srs.turn(ShapeType::T, 0, 3), 3);
+    assert_eq!(Srs.turn(ShapeType::O, 0, 1), 0);
+    assert_eq!(Srs.kicks(ShapeType::T, 0, 1), &KICKS_JLSTZ[0].0);
+    assert_eq!(Srs.kicks(ShapeType::I, 2, 1), &KICKS_I[2].1);
+    assert_eq!(Srs.kicks(ShapeType::L, 3, 1).len(), 5);
+
+    // S, Z and I flip between two states both ways, and a half turn leaves them as they are
+    for system in &[&Ars as &dyn RotationSystem, &Classic] {
+        assert_eq!(system.turn(ShapeType::S, 0, 1), 1);
+        assert_eq!(system.turn(ShapeType::S, 1, 1), 0);
+        assert_eq!(system.turn(ShapeType::Z, 0, 3), 1);
+        assert_eq!(system.turn(ShapeType::I, 1, 2), 1);
+        assert_eq!(system.turn(ShapeType::J, 1, 2), 3);
+    }
+
+    // Flat pieces stay on the same floor when turning upside down
+    assert_eq!(Ars.offset(ShapeType::T, 0, 2), (0, -1));
+    assert_eq!(Ars.offset(ShapeType::S, 0, 1), (-1, -1));
+    assert_eq!(Ars.offset(ShapeType::I, 0, 1), (0, 0));
+    assert!(Ars.kicks(ShapeType::I, 0, 1).is_empty());
+    assert!(Classic.kicks(ShapeType::T, 0, 1).is_empty());
+
+    // The table shipped in the resources is a copy of SRS to start experimenting from
+    let srs = KickTable::srs();
+    let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("resources");
+    path.push(KICK_TABLE_PATH);
+    let shipped = std::fs::read_to_string(path).unwrap();
+    assert_eq!(KickTable::parse(&shipped), Ok(srs.clone()));
+    for &(from, to) in &[(0, 1), (1, 0), (3, 1), (2, 3)] {
+        assert_eq!(
+            srs.kicks(ShapeType::Z, from, to),
+            Srs.kicks(ShapeType::Z, from, to)
+        );
+        assert_eq!(
+            srs.kicks(ShapeType::I, from, to),
+            Srs.kicks(ShapeType::I, from, to)
+        );
+    }
+
+    let mut table = srs.clone();
+    table.jlstz.half.pop();
+    assert!(KickTable::parse(&toml::to_string(&table).unwrap()).is_err());
+    let mut table = srs;
+    table.i.clockwise[2].push((0, -5));
+    assert!(KickTable::parse(&toml::to_string(&table).unwrap()).is_err());
+    assert!(KickTable::parse("name = \"Empty\"").is_err());
+
+    use crate::{
+        piece::Piece,
+        rules::{RotationRule, Rules},
+        stack::Stack,
+    };
+    let stack = Stack::new(10, 20, 20);
+
+    // Turning an S twice brings it back to the same cells
+    let mut piece = Piece::new(ShapeType::S, &stack);
+    piece.set_system(Arc::new(Ars));
+    let cells = piece.cells();
+    assert!(piece.rotate(true, &stack));
+    assert!(piece.rotate(true, &stack));
+    assert_eq!(piece.cells(), cells);
+    assert!(!piece.rotate_180(&stack));
+
+    // Pushed against the wall, only the guideline rotation kicks the T out
+    for &(rotation, rotated) in &[(RotationRule::Srs, true), (RotationRule::Classic, false)] {
+        let rules = Rules {
+            rotation,
+            ..Rules::default()
+        };
+        let mut piece = Piece::new(ShapeType::T, &stack);
+        piece.set_system(rules.rotation_system());
+        piece.rotate(true, &stack);
+        while piece.shift(-1, 0, &stack) {}
+        assert_eq!(piece.rotate(true, &stack), rotated);
+    }
+
+    // Custom rules kick by their own table, like SRS when they come without one
+    let mut table = KickTable::srs();
+    table.jlstz.clockwise[0].clear();
+    let custom = Rules {
+        rotation: RotationRule::Custom,
+        kick_table: Some(table),
+        ..Rules::default()
+    };
+    assert!(custom
+        .rotation_system()
+        .kicks(ShapeType::T, 0, 1)
+        .is_empty());
+    let custom = Rules {
+        kick_table: None,
+        ..custom
+    };
+    assert_eq!(
+        custom.rotation_system().kicks(ShapeType::T, 0, 1),
+        Srs.kicks(ShapeType::T, 0, 1)
+    );
+}
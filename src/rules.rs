@@ -0,0 +1,518 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use dirs;
+use serde::{Deserialize, Serialize};
+use toml;
+
+use crate::{
+    bag,
+    clear::{ClearDetector, ColorMatch, LineClear},
+    rotation::{self, Ars, Classic, KickTable, RotationSystem},
+    shape::ShapeType,
+};
+
+/// How the upcoming pieces are picked
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum Randomizer {
+    /// Every 7 pieces contain each shape exactly once
+    Bag,
+    /// Every piece is picked independently
+    Random,
+    /// The sequence of the rules over and over, for drilling particular shapes
+    Loop,
+}
+
+pub const RANDOMIZERS: [Randomizer; 3] = [Randomizer::Bag, Randomizer::Random, Randomizer::Loop];
+
+/// Longest sequence repeated by the loop randomizer
+const MAX_SEQUENCE: usize = 21;
+
+impl Randomizer {
+    pub fn name(self) -> &'static str {
+        match self {
+            Randomizer::Bag => "7-bag",
+            Randomizer::Random => "Random",
+            Randomizer::Loop => "Piece loop",
+        }
+    }
+}
+
+/// What makes blocks clear
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum ClearRule {
+    /// Full rows
+    Lines,
+    /// Groups of blocks of the same color, every cleared group counts as a line
+    Colors,
+}
+
+pub const CLEAR_RULES: [ClearRule; 2] = [ClearRule::Lines, ClearRule::Colors];
+
+/// Connected blocks of one color needed for a clear, one more than a single piece
+const COLOR_MATCH: usize = 5;
+
+impl ClearRule {
+    pub fn name(self) -> &'static str {
+        match self {
+            ClearRule::Lines => "Lines",
+            ClearRule::Colors => "Color match",
+        }
+    }
+
+    pub fn detector(self) -> Box<dyn ClearDetector> {
+        match self {
+            ClearRule::Lines => Box::new(LineClear),
+            ClearRule::Colors => Box::new(ColorMatch { min: COLOR_MATCH }),
+        }
+    }
+}
+
+/// How pieces turn and where they are kicked to
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum RotationRule {
+    /// Super Rotation System of the guideline games
+    Srs,
+    /// Arika rotation of the TGM games
+    Ars,
+    /// NES rotation, which never kicks
+    Classic,
+    /// Kick table of the rules, see `Rules::kick_table`
+    Custom,
+}
+
+pub const ROTATION_RULES: [RotationRule; 4] = [
+    RotationRule::Srs,
+    RotationRule::Ars,
+    RotationRule::Classic,
+    RotationRule::Custom,
+];
+
+impl RotationRule {
+    pub fn name(self) -> &'static str {
+        match self {
+            RotationRule::Srs => "SRS",
+            RotationRule::Ars => "ARS",
+            RotationRule::Classic => "Classic",
+            RotationRule::Custom => "Custom",
+        }
+    }
+}
+
+/// Fall interval of every level in milliseconds, from the guideline curve,
+/// the last one stays for the rest of the game
+const GUIDELINE_GRAVITY: [u32; 15] = [
+    1000, 793, 618, 473, 355, 262, 190, 135, 94, 64, 43, 28, 18, 11, 7,
+];
+
+/// Fall interval shrinking with the number of cleared lines
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Gravity {
+    /// Fall interval at the start of the game, in milliseconds
+    pub start: u32,
+    /// How much faster the piece falls every level, in milliseconds
+    pub step: u32,
+    /// Lines needed to advance a level
+    pub lines: u32,
+    /// Shortest fall interval, in milliseconds
+    pub min: u32,
+    /// Levels follow the guideline curve instead of speeding up by the step
+    pub guideline: bool,
+    /// Line clears score times the level
+    pub multiplier: bool,
+}
+
+impl Default for Gravity {
+    fn default() -> Gravity {
+        Gravity {
+            start: 1000,
+            step: 0,
+            lines: 10,
+            min: 50,
+            guideline: false,
+            multiplier: false,
+        }
+    }
+}
+
+impl Gravity {
+    /// Starts from 1
+    pub fn level(&self, lines: i32) -> u32 {
+        lines.max(0) as u32 / self.lines.max(1) + 1
+    }
+
+    pub fn interval(&self, lines: i32) -> Duration {
+        let level = self.level(lines) - 1;
+        let interval = if self.guideline {
+            let last = GUIDELINE_GRAVITY.len() - 1;
+            GUIDELINE_GRAVITY[(level as usize).min(last)].max(self.min)
+        } else {
+            self.start
+                .saturating_sub(level.saturating_mul(self.step))
+                .max(self.min.min(self.start))
+        };
+
+        Duration::from_millis(interval.into())
+    }
+
+    /// Whether the levels make any difference, otherwise they're not worth showing
+    pub fn leveled(&self) -> bool {
+        self.step > 0 || self.guideline || self.multiplier
+    }
+
+    /// Multiplier of the line clear scores
+    pub fn multiplier(&self, lines: i32) -> i32 {
+        if self.multiplier {
+            self.level(lines) as i32
+        } else {
+            1
+        }
+    }
+
+    /// How the speed changes over the game, in a few words
+    pub fn describe(&self) -> String {
+        let mut curve = if self.guideline {
+            format!("Guideline curve, a level every {} lines", self.lines)
+        } else if self.step > 0 {
+            format!(
+                "{}ms per row, {}ms faster every {} lines down to {}ms",
+                self.start,
+                self.step,
+                self.lines,
+                self.min.min(self.start)
+            )
+        } else {
+            format!("{}ms per row throughout", self.start)
+        };
+
+        if self.multiplier {
+            curve.push_str(", clears score times the level");
+        }
+        curve
+    }
+}
+
+/// Garbage rising on its own, regardless of any opponents
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Garbage {
+    /// Pieces between garbage rises, 0 disables it
+    pub interval: u32,
+    /// Rows rising at once
+    pub rows: u32,
+    /// Chance of the hole moving between consecutive rows, in percent
+    pub messiness: u32,
+}
+
+impl Default for Garbage {
+    fn default() -> Garbage {
+        Garbage {
+            interval: 0,
+            rows: 1,
+            messiness: 100,
+        }
+    }
+}
+
+/// Full rule set of a game, which can be saved as a named preset
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Rules {
+    pub name: String,
+    pub width: i32,
+    pub height: i32,
+    pub randomizer: Randomizer,
+    /// Pieces repeated by the loop randomizer, e.g. SZSZ
+    pub sequence: String,
+    pub rotation: RotationRule,
+    pub kicks: bool,
+    pub hold: bool,
+    /// Floating groups of blocks fall on their own after clears, possibly clearing more rows
+    pub cascade: bool,
+    pub clear: ClearRule,
+    /// Garbage sent by opponents enters the board only as a piece locks without clearing,
+    /// instead of once it waited its time in the queue
+    pub garbage_on_lock: bool,
+    pub gravity: Gravity,
+    pub garbage: Garbage,
+    /// Offsets of the custom rotation, kept with the rules so that replays and opponents
+    /// kick the same way. Without a table the custom rotation kicks like SRS.
+    pub kick_table: Option<KickTable>,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules {
+            name: String::from("Standard"),
+            width: 10,
+            height: 20,
+            randomizer: Randomizer::Bag,
+            sequence: String::from("T"),
+            rotation: RotationRule::Srs,
+            kicks: true,
+            hold: true,
+            cascade: false,
+            clear: ClearRule::Lines,
+            garbage_on_lock: false,
+            gravity: Gravity::default(),
+            garbage: Garbage::default(),
+            kick_table: None,
+        }
+    }
+}
+
+impl Rules {
+    /// Pieces picked at random, no hold and no wall kicks
+    pub fn classic() -> Rules {
+        Rules {
+            name: String::from("Classic"),
+            randomizer: Randomizer::Random,
+            kicks: false,
+            hold: false,
+            ..Rules::default()
+        }
+    }
+
+    /// Levels every 10 lines along the guideline speed curve, scoring more with every level
+    pub fn marathon() -> Rules {
+        Rules {
+            name: String::from("Marathon"),
+            gravity: Gravity {
+                min: 0,
+                guideline: true,
+                multiplier: true,
+                ..Gravity::default()
+            },
+            ..Rules::default()
+        }
+    }
+
+    /// Pieces clear by touching others of their color, blocks stick together when falling
+    pub fn colors() -> Rules {
+        Rules {
+            name: String::from("Colors"),
+            clear: ClearRule::Colors,
+            ..Rules::default()
+        }
+    }
+
+    pub fn directory() -> PathBuf {
+        let mut path = dirs::data_local_dir().unwrap_or_default();
+        path.push("klocki");
+        path.push("presets");
+        path
+    }
+
+    fn path(name: &str) -> PathBuf {
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == ' ' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+
+        let mut path = Rules::directory();
+        path.push(format!("{}.toml", name.trim()));
+        path
+    }
+
+    pub fn save(&self) {
+        let directory = Rules::directory();
+        if let Err(e) = fs::create_dir_all(&directory) {
+            log::error!("Unable to create directory {:?}: {:?}", &directory, e);
+            return;
+        }
+
+        let path = Rules::path(&self.name);
+        let toml = toml::to_string(self).unwrap();
+
+        if let Err(e) = fs::write(&path, toml) {
+            log::error!("Unable to save preset: {:?}", e);
+        } else {
+            log::info!("Saved preset to: {:?}", &path);
+        }
+    }
+
+    pub fn load(path: &Path) -> Option<Rules> {
+        let contents = fs::read_to_string(path).ok()?;
+
+        match toml::from_str(&contents) {
+            Ok(rules) => Some(rules),
+            Err(e) => {
+                log::error!("Error while reading preset {:?}: {:?}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Every saved preset, sorted by name
+    pub fn presets() -> Vec<Rules> {
+        let mut presets: Vec<Rules> = match fs::read_dir(Rules::directory()) {
+            Err(_) => vec![],
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().map_or(false, |e| e == "toml"))
+                .filter_map(|p| Rules::load(&p))
+                .collect(),
+        };
+
+        presets.sort_by(|a, b| a.name.cmp(&b.name));
+        presets
+    }
+
+    /// Labels and values of the rules card shown before a game starts
+    pub fn summary(&self) -> Vec<(&'static str, String)> {
+        let rotation = format!(
+            "{} {} wall kicks",
+            self.rotation.name(),
+            if self.kicks { "with" } else { "without" }
+        );
+
+        let randomizer = match self.randomizer {
+            Randomizer::Loop => format!("{} of {}", self.randomizer.name(), self.sequence),
+            randomizer => String::from(randomizer.name()),
+        };
+
+        let mut clear = String::from(self.clear.name());
+        if self.cascade {
+            clear.push_str(", loose blocks fall after clears");
+        }
+
+        let mut summary = vec![
+            ("Board", format!("{}x{}", self.width, self.height)),
+            ("Rotation", rotation),
+            ("Randomizer", randomizer),
+            ("Gravity", self.gravity.describe()),
+            ("Hold", String::from(if self.hold { "On" } else { "Off" })),
+            ("Preview", format!("{} pieces", bag::PREVIEW)),
+            ("Clears", clear),
+        ];
+
+        if self.garbage.interval > 0 {
+            summary.push((
+                "Garbage",
+                format!(
+                    "{} rows every {} pieces",
+                    self.garbage.rows, self.garbage.interval
+                ),
+            ));
+        }
+
+        if self.garbage_on_lock {
+            summary.push((
+                "Incoming garbage",
+                String::from("Rises as a piece locks without clearing"),
+            ));
+        }
+
+        summary
+    }
+
+    /// Keeps the board big enough to spawn pieces
+    pub fn clamp(&mut self) {
+        self.width = self.width.max(4).min(20);
+        self.height = self.height.max(4).min(40);
+        self.gravity.lines = self.gravity.lines.max(1);
+        self.garbage.messiness = self.garbage.messiness.min(100);
+
+        let sequence: String = self
+            .sequence
+            .chars()
+            .filter_map(ShapeType::from_char)
+            .take(MAX_SEQUENCE)
+            .map(|shape| format!("{:?}", shape))
+            .collect();
+        self.sequence = if sequence.is_empty() {
+            String::from("T")
+        } else {
+            sequence
+        };
+    }
+
+    /// Pieces of the loop randomizer, skipping anything that isn't a piece
+    pub fn sequence(&self) -> Vec<ShapeType> {
+        self.sequence
+            .chars()
+            .filter_map(ShapeType::from_char)
+            .collect()
+    }
+
+    /// Rotation system turning the pieces
+    pub fn rotation_system(&self) -> Arc<dyn RotationSystem> {
+        match self.rotation {
+            RotationRule::Srs => rotation::srs(),
+            RotationRule::Ars => Arc::new(Ars),
+            RotationRule::Classic => Arc::new(Classic),
+            RotationRule::Custom => match &self.kick_table {
+                Some(table) => Arc::new(table.clone()),
+                None => rotation::srs(),
+            },
+        }
+    }
+}
+
+#[test]
+fn rules_test() {
+    let mut rules = Rules::default();
+    assert_eq!(Duration::from_secs(1), rules.gravity.interval(150));
+
+    rules.gravity.step = 100;
+    assert_eq!(Duration::from_millis(800), rules.gravity.interval(25));
+    assert_eq!(3, rules.gravity.level(25));
+    assert_eq!(Duration::from_millis(50), rules.gravity.interval(1000));
+    assert_eq!(1, rules.gravity.multiplier(1000));
+
+    let marathon = Rules::marathon().gravity;
+    assert_eq!(Duration::from_millis(1000), marathon.interval(9));
+    assert_eq!(Duration::from_millis(793), marathon.interval(10));
+    assert_eq!(Duration::from_millis(7), marathon.interval(1000));
+    assert_eq!(3, marathon.multiplier(25));
+
+    rules.name = String::from("Wide: fast");
+    rules.width = 30;
+    rules.sequence = String::from("sz sz!");
+    rules.clamp();
+    assert_eq!(20, rules.width);
+    assert_eq!("SZSZ", rules.sequence);
+    assert_eq!(4, rules.sequence().len());
+
+    let toml = toml::to_string(&rules).unwrap();
+    assert_eq!(rules, toml::from_str::<Rules>(&toml).unwrap());
+
+    rules.rotation = RotationRule::Custom;
+    rules.kick_table = Some(KickTable::srs());
+    let toml = toml::to_string(&rules).unwrap();
+    assert_eq!(rules, toml::from_str::<Rules>(&toml).unwrap());
+    assert!(Rules::path(&rules.name).ends_with("Wide_ fast.toml"));
+
+    let summary = Rules::classic().summary();
+    assert!(summary.contains(&("Hold", String::from("Off"))));
+    assert!(summary.contains(&("Rotation", String::from("SRS without wall kicks"))));
+    let other = Rules {
+        rotation: RotationRule::Ars,
+        randomizer: Randomizer::Loop,
+        sequence: String::from("SZ"),
+        ..Rules::default()
+    }
+    .summary();
+    assert!(other.contains(&("Rotation", String::from("ARS with wall kicks"))));
+    assert!(other.contains(&("Randomizer", String::from("Piece loop of SZ"))));
+    assert!(!summary.iter().any(|(label, _)| *label == "Garbage"));
+    assert!(!summary
+        .iter()
+        .any(|(label, _)| *label == "Incoming garbage"));
+    assert_eq!(
+        "Guideline curve, a level every 10 lines, clears score times the level",
+        Rules::marathon().gravity.describe()
+    );
+}
@@ -0,0 +1,260 @@
+use std::time::Duration;
+
+use ggez::{
+    graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect, Scale, Text, TextFragment},
+    nalgebra::Point2,
+    Context, GameResult,
+};
+use imgui::{im_str, Condition, Ui, Window};
+use rand::{thread_rng, RngCore};
+
+use crate::{
+    battle::{Battle, Board},
+    gameplay::Gameplay,
+    global::Global,
+    miniature::Miniature,
+    music::Track,
+    rules::Rules,
+    scene::{MenuScene, Scene, Transition},
+};
+
+const TEAM_NAMES: [&str; 2] = ["Blue team", "Red team"];
+
+enum BattleAction {
+    Rematch,
+    Menu,
+}
+
+/// Two teams of two, the player and a bot against two bots.
+/// Only the player's board is drawn in full, the bots are shown as miniatures.
+pub struct BattleScene {
+    battle: Battle,
+    miniature: Miniature,
+    action: Option<BattleAction>,
+}
+
+impl BattleScene {
+    pub fn new(ctx: &mut Context, g: &mut Global) -> GameResult<BattleScene> {
+        let mut seed = [0u8; 32];
+        thread_rng().fill_bytes(&mut seed);
+
+        // Everyone gets the same pieces
+        let rules = Rules::default();
+        let gameplay = Gameplay::new(ctx, g, true, &seed, &rules)?;
+        let mut battle = Battle::new(vec![Board { gameplay, team: 0 }]);
+
+        // The teammate first, then both opponents
+        for &(team, delay) in &[(0, 160), (1, 140), (1, 180)] {
+            battle.add_bot(team, &seed, &rules, Duration::from_millis(delay));
+        }
+
+        let tileset = g.settings.tileset(ctx, &g.settings_state, &mut g.assets)?;
+
+        Ok(BattleScene {
+            battle,
+            miniature: Miniature::new(ctx, tileset),
+            action: None,
+        })
+    }
+}
+
+impl Scene for BattleScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        self.battle.update(ctx, g)?;
+
+        Ok(match self.action.take() {
+            Some(BattleAction::Rematch) => Transition::Replace(Box::new(BattleScene::new(ctx, g)?)),
+            Some(BattleAction::Menu) => Transition::Switch(Box::new(MenuScene::new())),
+            None => Transition::None,
+        })
+    }
+
+    fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        let screen = graphics::screen_coordinates(ctx);
+
+        let stack = &self.battle.boards[0].gameplay.engine.stack;
+        let (width, height) = (stack.width, stack.height);
+        let block_size = ((screen.w / (width as f32 * 3.2)).min(screen.h * 0.7 / height as f32)
+            as i32)
+            .min(g.block_size(width, height));
+        let mini_size = Miniature::block_size(block_size);
+
+        let x = screen.w * 0.4 - (width * block_size) as f32 / 2.0;
+        let y = (screen.h - (height * block_size) as f32) / 2.0;
+        let player = Point2::new(x, y);
+
+        // The teammate sits left of the hold box, the opponents right of the next queue
+        let mini_y = (screen.h - (height * mini_size) as f32) / 2.0;
+        let mini_width = (width * mini_size) as f32;
+        let gap = (mini_size * 2) as f32;
+        let left = x - block_size as f32 * 5.0 - mini_width - gap;
+        let right = x + (width * block_size) as f32 + block_size as f32 * 4.0 + gap;
+        let positions = [
+            player,
+            Point2::new(left, mini_y),
+            Point2::new(right, mini_y),
+            Point2::new(right + mini_width + gap, mini_y),
+        ];
+
+        // Attack numbers fly to the first opponent
+        let gameplay = &mut self.battle.boards[0].gameplay;
+        gameplay.set_block_size(Some(block_size));
+        gameplay.set_attack_target(Some(positions[2] - player));
+        gameplay.draw(ctx, g, player)?;
+
+        for (engine, &position) in self.battle.bots().zip(&positions[1..]) {
+            self.miniature.draw(ctx, engine, position, mini_size)?;
+        }
+
+        let below = Point2::new(
+            x,
+            y + (height * block_size) as f32 + block_size as f32 * 0.2,
+        );
+        let board = &self.battle.boards[0];
+        draw_forecast(
+            ctx,
+            &board.gameplay,
+            self.battle.forecast(board.team),
+            below,
+            (width * block_size) as f32,
+            block_size as f32,
+        )?;
+
+        let label_y = (mini_y - block_size as f32 * 1.5).min(y - block_size as f32 * 2.5);
+        for (team, name) in TEAM_NAMES.iter().enumerate() {
+            let pending = self.battle.pending(team);
+            let label = if pending > 0 {
+                format!("{} (+{})", name, pending)
+            } else {
+                name.to_string()
+            };
+
+            let text = Text::new(TextFragment {
+                text: label,
+                scale: Some(Scale::uniform(block_size as f32 * 1.2)),
+                ..Default::default()
+            });
+
+            let center = if team == 0 {
+                x + (width * block_size) as f32 / 2.0
+            } else {
+                right + mini_width + gap / 2.0
+            };
+            let x = center - text.width(ctx) as f32 / 2.0;
+            graphics::draw(ctx, &text, DrawParam::new().dest(Point2::new(x, label_y)))?;
+        }
+
+        Ok(())
+    }
+
+    fn ui(&mut self, ui: &Ui, _g: &mut Global) {
+        let winner = match self.battle.winner() {
+            Some(winner) => winner,
+            None => return,
+        };
+
+        let [w, h] = ui.io().display_size;
+        Window::new(im_str!("Battle over"))
+            .resizable(false)
+            .collapsible(false)
+            .size([200.0, 0.0], Condition::Appearing)
+            .position([w / 2.0 - 100.0, h / 3.0 * 2.0], Condition::Appearing)
+            .build(ui, || {
+                ui.text(im_str!("{} wins!", TEAM_NAMES[winner]));
+                ui.separator();
+
+                if ui.button(im_str!("Rematch"), [170.0, 0.0]) {
+                    self.action = Some(BattleAction::Rematch);
+                }
+
+                if ui.button(im_str!("Menu"), [170.0, 0.0]) {
+                    self.action = Some(BattleAction::Menu);
+                }
+            });
+    }
+
+    fn gameplay(&mut self) -> Option<&mut Gameplay> {
+        Some(&mut self.battle.boards[0].gameplay)
+    }
+
+    fn music(&self) -> Track {
+        Track::Gameplay
+    }
+}
+
+/// Strip under the board with the incoming garbage, one segment per batch
+/// with the seconds left until it's queued. Queued garbage, and incoming garbage
+/// under rules waiting for a lock, rises on the next lock.
+pub(super) fn draw_forecast(
+    ctx: &mut Context,
+    gameplay: &Gameplay,
+    forecast: Vec<(i32, Option<Duration>)>,
+    position: Point2<f32>,
+    width: f32,
+    block_size: f32,
+) -> GameResult {
+    let queued = gameplay.engine.pending_garbage() as i32;
+    let mut batches: Vec<(i32, Option<Duration>)> = vec![];
+    if queued > 0 {
+        batches.push((queued, None));
+    }
+    batches.extend(forecast);
+
+    let lines: i32 = batches.iter().map(|b| b.0).sum();
+    if lines == 0 {
+        return Ok(());
+    }
+
+    // Lines get narrower once they don't fit under the board
+    let line_width = (block_size * 0.8).min(width / lines as f32);
+    let height = block_size * 0.6;
+    let mut builder = MeshBuilder::new();
+    let mut labels = vec![];
+    let mut x = position[0];
+
+    for &(lines, eta) in &batches {
+        let segment = Rect::new(x, position[1], line_width * lines as f32, height);
+
+        // Redder the sooner it arrives
+        let urgency = eta.map_or(1.0, |t| 1.0 - t.as_secs_f32().min(1.0));
+        let color = Color::new(0.9, 0.6 - urgency * 0.5, 0.2, 0.8);
+        builder.rectangle(DrawMode::fill(), segment, color);
+        builder.rectangle(
+            DrawMode::stroke(1.0),
+            segment,
+            Color::new(0.0, 0.0, 0.0, 0.8),
+        );
+
+        let label = match eta {
+            Some(eta) => format!("{:.1}s", eta.as_secs_f32()),
+            None => String::from("lock"),
+        };
+        labels.push((label, segment));
+        x += segment.w;
+    }
+
+    let mesh = builder.build(ctx)?;
+    graphics::draw(ctx, &mesh, DrawParam::new())?;
+
+    for (label, segment) in labels {
+        let text = Text::new(TextFragment {
+            text: label,
+            scale: Some(Scale::uniform(height * 0.8)),
+            ..Default::default()
+        });
+
+        // Labels only go on segments wide enough to hold them
+        let text_width = text.width(ctx) as f32;
+        if text_width > segment.w {
+            continue;
+        }
+
+        let dest = Point2::new(
+            segment.x + (segment.w - text_width) / 2.0,
+            segment.y + height * 0.1,
+        );
+        graphics::draw(ctx, &text, DrawParam::new().dest(dest))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use ggez::{Context, GameResult};
+use imgui::{im_str, Condition, Ui, Window};
+
+use crate::{
+    action::Action,
+    global::Global,
+    input::{self, Binding, BINDABLE_ACTIONS},
+    scene::{Scene, Transition},
+};
+
+enum ControlsAction {
+    Capture(Action),
+    Unbind(Action, Binding),
+    Replace,
+    Cancel,
+    Reset,
+}
+
+/// Waiting for a key or button to bind to the action
+enum Capture {
+    /// Whatever was held when binding started, the click on the button at least, goes up first
+    Releasing(Action),
+    Waiting(Action),
+}
+
+/// Key or button already bound to other actions, waiting for the player to decide
+struct Conflict {
+    action: Action,
+    binding: Binding,
+    others: Vec<Action>,
+}
+
+/// Every action with its keys and buttons, which can be rebound by pressing new ones
+pub struct ControlsScene {
+    capture: Option<Capture>,
+    conflict: Option<Conflict>,
+    action: Option<ControlsAction>,
+}
+
+impl ControlsScene {
+    pub fn new() -> ControlsScene {
+        ControlsScene {
+            capture: None,
+            conflict: None,
+            action: None,
+        }
+    }
+
+    fn bind(&mut self, g: &mut Global, action: Action, binding: Binding) {
+        let bindings = &mut g.settings.input.bindings;
+        if bindings
+            .iter()
+            .any(|b| b.action == action && b.binding == binding)
+        {
+            return;
+        }
+
+        let others = input::conflicts(bindings, action, binding);
+        if others.is_empty() {
+            input::rebind(bindings, action, binding);
+            g.settings.save();
+        } else {
+            self.conflict = Some(Conflict {
+                action,
+                binding,
+                others,
+            });
+        }
+    }
+}
+
+impl Scene for ControlsScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        match self.action.take() {
+            Some(ControlsAction::Capture(action)) => {
+                self.conflict = None;
+                self.capture = Some(Capture::Releasing(action));
+            }
+            Some(ControlsAction::Unbind(action, binding)) => {
+                g.settings
+                    .input
+                    .bindings
+                    .retain(|b| b.action != action || b.binding != binding);
+                g.settings.save();
+            }
+            Some(ControlsAction::Replace) => {
+                if let Some(conflict) = self.conflict.take() {
+                    input::rebind(
+                        &mut g.settings.input.bindings,
+                        conflict.action,
+                        conflict.binding,
+                    );
+                    g.settings.save();
+                }
+            }
+            Some(ControlsAction::Cancel) => self.conflict = None,
+            Some(ControlsAction::Reset) => {
+                self.capture = None;
+                self.conflict = None;
+                g.settings.input.bindings = g.settings.input.preset.bindings();
+                g.settings.save();
+            }
+            None => (),
+        }
+
+        let captured = Binding::captured(ctx, &g.gamepad);
+        match self.capture {
+            Some(Capture::Releasing(action)) if captured.is_none() => {
+                self.capture = Some(Capture::Waiting(action));
+            }
+            Some(Capture::Waiting(action)) => {
+                if let Some(binding) = captured {
+                    self.capture = None;
+                    self.bind(g, action, binding);
+                }
+            }
+            _ => (),
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, _ctx: &mut Context, _g: &mut Global) -> GameResult {
+        Ok(())
+    }
+
+    fn ui(&mut self, ui: &Ui, g: &mut Global) {
+        let [w, h] = ui.io().display_size;
+        let action = &mut self.action;
+        let capturing = match self.capture {
+            Some(Capture::Releasing(a)) | Some(Capture::Waiting(a)) => Some(a),
+            None => None,
+        };
+        let conflict = &self.conflict;
+        let bindings = &g.settings.input.bindings;
+
+        Window::new(im_str!("Controls"))
+            .resizable(false)
+            .collapsible(false)
+            .size([560.0, 460.0], Condition::Always)
+            .position([w / 2.0 - 280.0, h / 2.0 - 230.0], Condition::Always)
+            .build(ui, || {
+                for (i, &bindable) in BINDABLE_ACTIONS.iter().enumerate() {
+                    let id = ui.push_id(i as i32);
+                    ui.text(im_str!("{}", bindable.name()));
+                    ui.same_line(190.0);
+
+                    if capturing == Some(bindable) {
+                        ui.text_colored(
+                            [1.0, 0.9, 0.4, 1.0],
+                            im_str!("Press a key or button, Escape to cancel"),
+                        );
+                        id.pop(ui);
+                        continue;
+                    }
+
+                    for (j, b) in bindings.iter().filter(|b| b.action == bindable).enumerate() {
+                        let id = ui.push_id(j as i32);
+                        // Keys firing more than one action are marked
+                        let label = if input::conflicts(bindings, bindable, b.binding).is_empty() {
+                            im_str!("{} x", b.binding.name())
+                        } else {
+                            im_str!("{} (conflict) x", b.binding.name())
+                        };
+                        if ui.small_button(&label) {
+                            *action = Some(ControlsAction::Unbind(bindable, b.binding));
+                        }
+                        ui.same_line(0.0);
+                        id.pop(ui);
+                    }
+
+                    if ui.small_button(im_str!("+")) {
+                        *action = Some(ControlsAction::Capture(bindable));
+                    }
+                    id.pop(ui);
+                }
+
+                ui.separator();
+                if let Some(conflict) = conflict {
+                    let others: Vec<&str> = conflict.others.iter().map(|a| a.name()).collect();
+                    ui.text_colored(
+                        [1.0, 0.4, 0.4, 1.0],
+                        im_str!(
+                            "{} is already bound to {}",
+                            conflict.binding.name(),
+                            others.join(", ")
+                        ),
+                    );
+                    if ui.button(
+                        &im_str!("Bind to {} instead", conflict.action.name()),
+                        [250.0, 0.0],
+                    ) {
+                        *action = Some(ControlsAction::Replace);
+                    }
+                    ui.same_line(0.0);
+                    if ui.button(im_str!("Keep as it is"), [170.0, 0.0]) {
+                        *action = Some(ControlsAction::Cancel);
+                    }
+                    ui.separator();
+                }
+
+                ui.text(im_str!("Click a binding to remove it"));
+                if ui.button(
+                    &im_str!("Reset to {} preset", g.settings.input.preset.name()),
+                    [250.0, 0.0],
+                ) {
+                    *action = Some(ControlsAction::Reset);
+                }
+            });
+    }
+
+    /// Escape cancels the binding in progress before leaving
+    fn back(&mut self) -> Transition {
+        if self.capture.take().is_some() {
+            Transition::None
+        } else {
+            Transition::Pop
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use ggez::{graphics, nalgebra::Point2, Context, GameResult};
+use imgui::{im_str, Condition, Ui, Window};
+use rand::{thread_rng, RngCore};
+
+use crate::{
+    coop::Coop,
+    global::Global,
+    music::Track,
+    scene::{MenuScene, Scene, Transition},
+};
+
+enum CoopAction {
+    PlayAgain,
+    Menu,
+}
+
+pub struct CoopScene {
+    coop: Coop,
+    action: Option<CoopAction>,
+}
+
+impl CoopScene {
+    pub fn new(ctx: &mut Context, g: &mut Global) -> GameResult<CoopScene> {
+        let mut seed = [0u8; 32];
+        thread_rng().fill_bytes(&mut seed);
+
+        Ok(CoopScene {
+            coop: Coop::new(ctx, g, &seed)?,
+            action: None,
+        })
+    }
+}
+
+impl Scene for CoopScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        self.coop.update(ctx, g)?;
+
+        Ok(match self.action.take() {
+            Some(CoopAction::PlayAgain) => Transition::Replace(Box::new(CoopScene::new(ctx, g)?)),
+            Some(CoopAction::Menu) => Transition::Switch(Box::new(MenuScene::new())),
+            None => Transition::None,
+        })
+    }
+
+    fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        let screen = graphics::screen_coordinates(ctx);
+        let stack = &self.coop.stack;
+        let block_size = g.block_size(stack.width, stack.height);
+
+        let position = Point2::new(
+            (screen.w - (stack.width * block_size) as f32) / 2.0,
+            (screen.h - (stack.height * block_size) as f32) / 2.0,
+        );
+
+        self.coop.draw(ctx, g, position)
+    }
+
+    fn ui(&mut self, ui: &Ui, _g: &mut Global) {
+        if !self.coop.game_over() {
+            return;
+        }
+
+        let [w, h] = ui.io().display_size;
+        Window::new(im_str!("Game over"))
+            .resizable(false)
+            .collapsible(false)
+            .size([180.0, 0.0], Condition::Appearing)
+            .position([w / 2.0 - 90.0, h / 3.0 * 2.0], Condition::Appearing)
+            .build(ui, || {
+                ui.text(im_str!("Team score: {}", self.coop.score.score()));
+                ui.separator();
+
+                if ui.button(im_str!("Play again"), [150.0, 0.0]) {
+                    self.action = Some(CoopAction::PlayAgain);
+                }
+
+                if ui.button(im_str!("Menu"), [150.0, 0.0]) {
+                    self.action = Some(CoopAction::Menu);
+                }
+            });
+    }
+
+    fn music(&self) -> Track {
+        Track::Gameplay
+    }
+}
@@ -0,0 +1,275 @@
+use ggez::{Context, GameResult};
+use imgui::{im_str, ComboBox, Condition, ImStr, ImString, Slider, Ui, Window};
+
+use crate::{
+    global::Global,
+    mode::GameMode,
+    rotation::KickTable,
+    rules::{Randomizer, RotationRule, Rules, CLEAR_RULES, RANDOMIZERS, ROTATION_RULES},
+    scene::{PlayScene, Scene, Transition},
+};
+
+enum CustomAction {
+    Load(usize),
+    LoadKicks,
+    Save,
+    Play,
+}
+
+/// Editor for the full rule set, presets are stored as files so they can be shared
+pub struct CustomScene {
+    rules: Rules,
+    name: ImString,
+    sequence: ImString,
+    presets: Vec<Rules>,
+    preset_id: usize,
+    randomizer_id: usize,
+    rotation_id: usize,
+    clear_id: usize,
+    action: Option<CustomAction>,
+}
+
+impl CustomScene {
+    pub fn new() -> CustomScene {
+        let rules = Rules::default();
+
+        CustomScene {
+            name: ImString::new(rules.name.clone()),
+            sequence: ImString::new(rules.sequence.clone()),
+            rules,
+            presets: Rules::presets(),
+            preset_id: 0,
+            randomizer_id: 0,
+            rotation_id: 0,
+            clear_id: 0,
+            action: None,
+        }
+    }
+
+    fn rules(&self) -> Rules {
+        let mut rules = self.rules.clone();
+        rules.name = self.name.to_string();
+        rules.randomizer = RANDOMIZERS[self.randomizer_id];
+        rules.sequence = self.sequence.to_string();
+        rules.rotation = ROTATION_RULES[self.rotation_id];
+        rules.clear = CLEAR_RULES[self.clear_id];
+        if rules.rotation != RotationRule::Custom {
+            rules.kick_table = None;
+        }
+        rules.clamp();
+        rules
+    }
+
+    fn custom_rotation(&self) -> bool {
+        ROTATION_RULES[self.rotation_id] == RotationRule::Custom
+    }
+
+    /// Reads the kick table of the custom rotation from the resources,
+    /// the rules keep the one they had when it can't be read
+    fn load_kicks(&mut self, ctx: &mut Context) {
+        match KickTable::load(ctx) {
+            Ok(table) => {
+                log::info!("Loaded the {} kick table", table.name);
+                self.rules.kick_table = Some(table);
+            }
+            Err(e) => log::error!("Unable to load the kick table: {}", e),
+        }
+    }
+}
+
+impl Scene for CustomScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        // The table goes into the rules, so that replays and presets kick the same way later
+        let playing = match self.action {
+            Some(CustomAction::Save) | Some(CustomAction::Play) => true,
+            _ => false,
+        };
+        if playing && self.custom_rotation() && self.rules.kick_table.is_none() {
+            self.load_kicks(ctx);
+        }
+
+        Ok(match self.action.take() {
+            Some(CustomAction::Load(id)) => {
+                if let Some(rules) = self.presets.get(id) {
+                    self.rules = rules.clone();
+                    self.name = ImString::new(rules.name.clone());
+                    self.sequence = ImString::new(rules.sequence.clone());
+                    self.randomizer_id = RANDOMIZERS
+                        .iter()
+                        .position(|&r| r == rules.randomizer)
+                        .unwrap_or(0);
+                    self.rotation_id = ROTATION_RULES
+                        .iter()
+                        .position(|&r| r == rules.rotation)
+                        .unwrap_or(0);
+                    self.clear_id = CLEAR_RULES
+                        .iter()
+                        .position(|&c| c == rules.clear)
+                        .unwrap_or(0);
+                }
+                Transition::None
+            }
+            Some(CustomAction::LoadKicks) => {
+                self.load_kicks(ctx);
+                Transition::None
+            }
+            Some(CustomAction::Save) => {
+                let rules = self.rules();
+                rules.save();
+                self.presets = Rules::presets();
+                self.preset_id = self
+                    .presets
+                    .iter()
+                    .position(|p| p.name == rules.name)
+                    .unwrap_or(0);
+                Transition::None
+            }
+            Some(CustomAction::Play) => {
+                let rules = self.rules();
+                Transition::Replace(Box::new(PlayScene::new(
+                    ctx,
+                    g,
+                    GameMode::Practice,
+                    None,
+                    rules,
+                )?))
+            }
+            None => Transition::None,
+        })
+    }
+
+    fn draw(&mut self, _ctx: &mut Context, _g: &mut Global) -> GameResult {
+        Ok(())
+    }
+
+    fn ui(&mut self, ui: &Ui, _g: &mut Global) {
+        let [w, h] = ui.io().display_size;
+
+        Window::new(im_str!("Custom game"))
+            .resizable(false)
+            .collapsible(false)
+            .size([340.0, 0.0], Condition::Always)
+            .position([w / 2.0 - 170.0, h / 6.0], Condition::Always)
+            .build(ui, || {
+                if !self.presets.is_empty() {
+                    let names: Vec<ImString> = self
+                        .presets
+                        .iter()
+                        .map(|p| ImString::new(p.name.clone()))
+                        .collect();
+                    let names: Vec<&ImStr> = names.iter().map(|n| n.as_ref()).collect();
+                    ComboBox::new(im_str!("Preset")).build_simple_string(
+                        ui,
+                        &mut self.preset_id,
+                        &names,
+                    );
+
+                    ui.same_line(0.0);
+                    if ui.small_button(im_str!("Load")) {
+                        self.action = Some(CustomAction::Load(self.preset_id));
+                    }
+                    ui.separator();
+                }
+
+                ui.input_text(im_str!("Name"), &mut self.name)
+                    .resize_buffer(true)
+                    .build();
+
+                ui.text("Board");
+                Slider::new(im_str!("Width"), 4..=20).build(ui, &mut self.rules.width);
+                Slider::new(im_str!("Height"), 4..=40).build(ui, &mut self.rules.height);
+                ui.separator();
+
+                ui.text("Gravity");
+                let gravity = &mut self.rules.gravity;
+                Slider::new(im_str!("Start interval"), 16..=2000).build(ui, &mut gravity.start);
+                Slider::new(im_str!("Speed up per level"), 0..=200).build(ui, &mut gravity.step);
+                Slider::new(im_str!("Lines per level"), 1..=50).build(ui, &mut gravity.lines);
+                Slider::new(im_str!("Minimum interval"), 0..=1000).build(ui, &mut gravity.min);
+                ui.checkbox(im_str!("Guideline speed curve"), &mut gravity.guideline);
+                ui.checkbox(im_str!("Score times level"), &mut gravity.multiplier);
+                ui.separator();
+
+                ui.text("Pieces");
+                let randomizers: Vec<ImString> = RANDOMIZERS
+                    .iter()
+                    .map(|r| ImString::new(r.name()))
+                    .collect();
+                let randomizers: Vec<&ImStr> = randomizers.iter().map(|r| r.as_ref()).collect();
+                ComboBox::new(im_str!("Randomizer")).build_simple_string(
+                    ui,
+                    &mut self.randomizer_id,
+                    &randomizers,
+                );
+                if RANDOMIZERS[self.randomizer_id] == Randomizer::Loop {
+                    ui.input_text(im_str!("Sequence"), &mut self.sequence)
+                        .resize_buffer(true)
+                        .build();
+                    ui.text_colored(
+                        [0.6, 0.6, 0.6, 1.0],
+                        im_str!("Repeated over and over, e.g. SZSZ, or T alone"),
+                    );
+                }
+                let rotations: Vec<ImString> = ROTATION_RULES
+                    .iter()
+                    .map(|r| ImString::new(r.name()))
+                    .collect();
+                let rotations: Vec<&ImStr> = rotations.iter().map(|r| r.as_ref()).collect();
+                ComboBox::new(im_str!("Rotation")).build_simple_string(
+                    ui,
+                    &mut self.rotation_id,
+                    &rotations,
+                );
+                if self.custom_rotation() {
+                    match &self.rules.kick_table {
+                        Some(table) => ui.text(im_str!("Kick table: {}", table.name)),
+                        None => ui.text("Kick table: read from kicks.toml"),
+                    }
+
+                    ui.same_line(0.0);
+                    if ui.small_button(im_str!("Reload")) {
+                        self.action = Some(CustomAction::LoadKicks);
+                    }
+                }
+                ui.checkbox(im_str!("Wall kicks"), &mut self.rules.kicks);
+                ui.checkbox(im_str!("Hold"), &mut self.rules.hold);
+                ui.separator();
+
+                ui.text("Clears");
+                let clear_rules: Vec<ImString> = CLEAR_RULES
+                    .iter()
+                    .map(|c| ImString::new(c.name()))
+                    .collect();
+                let clear_rules: Vec<&ImStr> = clear_rules.iter().map(|c| c.as_ref()).collect();
+                ComboBox::new(im_str!("Clear rule")).build_simple_string(
+                    ui,
+                    &mut self.clear_id,
+                    &clear_rules,
+                );
+                ui.checkbox(im_str!("Cascade gravity"), &mut self.rules.cascade);
+                ui.separator();
+
+                ui.text("Garbage");
+                let garbage = &mut self.rules.garbage;
+                Slider::new(im_str!("Every N pieces"), 0..=50).build(ui, &mut garbage.interval);
+                Slider::new(im_str!("Rows"), 1..=10).build(ui, &mut garbage.rows);
+                Slider::new(im_str!("Messiness"), 0..=100)
+                    .display_format(im_str!("%d%%"))
+                    .build(ui, &mut garbage.messiness);
+                ui.checkbox(
+                    im_str!("Opponents' garbage waits for a lock"),
+                    &mut self.rules.garbage_on_lock,
+                );
+                ui.separator();
+
+                if ui.button(im_str!("Save preset"), [150.0, 0.0]) {
+                    self.action = Some(CustomAction::Save);
+                }
+
+                ui.same_line(0.0);
+                if ui.button(im_str!("Play"), [150.0, 0.0]) {
+                    self.action = Some(CustomAction::Play);
+                }
+            });
+    }
+}
@@ -0,0 +1,266 @@
+use std::time::Duration;
+
+use ggez::{
+    graphics::{self, DrawParam, Scale, Text, TextFragment},
+    nalgebra::Point2,
+    Context, GameResult,
+};
+use imgui::{im_str, Condition, Ui, Window};
+
+use crate::{
+    battle::AttackPool,
+    drill::{Script, ScriptedOpponent},
+    gameplay::Gameplay,
+    global::Global,
+    music::Track,
+    rules::Rules,
+    scene::{self, battle::draw_forecast, MenuScene, Scene, Transition},
+    stack::GarbageRow,
+    utils,
+};
+
+/// Attacks of the script listed next to the board
+const UPCOMING: usize = 5;
+
+enum DrillAction {
+    Start(usize),
+    Retry,
+    Scripts,
+    Menu,
+}
+
+/// The player's board against a script, with the attacks to come shown where the opponent sits
+struct Drill {
+    script: usize,
+    gameplay: Gameplay,
+    opponent: ScriptedOpponent,
+    pool: AttackPool,
+    survived: Duration,
+}
+
+impl Drill {
+    fn new(
+        ctx: &mut Context,
+        g: &mut Global,
+        script: usize,
+        scripts: &[Script],
+    ) -> GameResult<Drill> {
+        // Pieces follow the script's seed too, so that every try is the same
+        let mut seed = [0u8; 32];
+        seed[..8].copy_from_slice(&scripts[script].seed.to_le_bytes());
+
+        let rules = Rules::default();
+        let gameplay = Gameplay::new(ctx, g, true, &seed, &rules)?;
+        let opponent = ScriptedOpponent::new(scripts[script].clone(), rules.width as usize);
+
+        Ok(Drill {
+            script,
+            gameplay,
+            opponent,
+            pool: AttackPool::new(rules.garbage_on_lock),
+            survived: Duration::new(0, 0),
+        })
+    }
+
+    /// The script sent everything it had and all of it landed
+    fn cleared(&self) -> bool {
+        self.opponent.finished()
+            && self.pool.pending() == 0
+            && self.gameplay.engine.pending_garbage() == 0
+    }
+
+    fn over(&self) -> bool {
+        self.gameplay.game_over() || self.cleared()
+    }
+
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        self.gameplay.update(ctx, g, true)?;
+
+        if self.over() || self.gameplay.paused() {
+            return Ok(());
+        }
+
+        let dt = g.clock.delta();
+        self.survived += dt;
+        self.opponent.update(dt, &mut self.pool);
+
+        let attack = self.gameplay.take_attack();
+        if attack > 0 {
+            self.pool.cancel(attack);
+        }
+
+        self.pool.update(dt);
+        while let Some((lines, hole)) = self.pool.pop_ready() {
+            let rows = vec![GarbageRow { hole }; lines as usize];
+            self.gameplay.engine.queue_garbage(&rows);
+        }
+
+        if self.gameplay.take_clean_locks() > 0 {
+            for (lines, hole) in self.pool.lock() {
+                let rows = vec![GarbageRow { hole }; lines as usize];
+                self.gameplay.insert_garbage(&rows);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Practice against scripted garbage patterns, to drill defending against a rhythm of attacks.
+/// Scripts are read from the drills directory, next to a few built-in ones.
+pub struct DrillScene {
+    scripts: Vec<Script>,
+    drill: Option<Drill>,
+    action: Option<DrillAction>,
+}
+
+impl DrillScene {
+    pub fn new() -> DrillScene {
+        DrillScene {
+            scripts: Script::all(),
+            drill: None,
+            action: None,
+        }
+    }
+}
+
+impl Scene for DrillScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        if let Some(drill) = &mut self.drill {
+            drill.update(ctx, g)?;
+        }
+
+        Ok(match self.action.take() {
+            Some(DrillAction::Start(script)) => {
+                self.drill = Some(Drill::new(ctx, g, script, &self.scripts)?);
+                Transition::None
+            }
+            Some(DrillAction::Retry) => {
+                if let Some(script) = self.drill.as_ref().map(|d| d.script) {
+                    self.drill = Some(Drill::new(ctx, g, script, &self.scripts)?);
+                }
+                Transition::None
+            }
+            Some(DrillAction::Scripts) => {
+                self.drill = None;
+                self.scripts = Script::all();
+                Transition::None
+            }
+            Some(DrillAction::Menu) => Transition::Switch(Box::new(MenuScene::new())),
+            None => Transition::None,
+        })
+    }
+
+    fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        let drill = match &mut self.drill {
+            Some(drill) => drill,
+            None => return Ok(()),
+        };
+
+        let stack = &drill.gameplay.engine.stack;
+        let (width, height) = (stack.width, stack.height);
+        let (block_size, positions) = scene::side_by_side(ctx, g, width, height);
+
+        drill.gameplay.set_block_size(Some(block_size));
+        drill.gameplay.draw(ctx, g, positions[0])?;
+
+        let below = Point2::new(
+            positions[0][0],
+            positions[0][1] + (height * block_size) as f32 + block_size as f32 * 0.2,
+        );
+        draw_forecast(
+            ctx,
+            &drill.gameplay,
+            drill.pool.forecast(),
+            below,
+            (width * block_size) as f32,
+            block_size as f32,
+        )?;
+
+        let mut lines = vec![drill.opponent.name().to_string(), String::new()];
+        for (attack, time) in drill.opponent.upcoming(UPCOMING) {
+            lines.push(format!("{} in {:.1}s", attack, time.as_secs_f32()));
+        }
+        if drill.opponent.finished() {
+            lines.push(String::from("Nothing left to send"));
+        }
+
+        let text = Text::new(TextFragment {
+            text: lines.join("\n"),
+            scale: Some(Scale::uniform(block_size as f32 * 1.2)),
+            ..Default::default()
+        });
+        graphics::draw(ctx, &text, DrawParam::new().dest(positions[1]))
+    }
+
+    fn ui(&mut self, ui: &Ui, _g: &mut Global) {
+        let [w, h] = ui.io().display_size;
+        let action = &mut self.action;
+        let scripts = &self.scripts;
+
+        let drill = match &self.drill {
+            Some(drill) => drill,
+            None => {
+                Window::new(im_str!("Garbage drill"))
+                    .resizable(false)
+                    .collapsible(false)
+                    .size([300.0, 0.0], Condition::Always)
+                    .position([w / 2.0 - 150.0, h / 2.0 - 150.0], Condition::Always)
+                    .build(ui, || {
+                        for (i, script) in scripts.iter().enumerate() {
+                            let id = ui.push_id(i as i32);
+                            if ui.button(&im_str!("{}", script.name), [270.0, 0.0]) {
+                                *action = Some(DrillAction::Start(i));
+                            }
+                            id.pop(ui);
+                        }
+
+                        ui.separator();
+                        ui.text(im_str!("More scripts are read from"));
+                        ui.text(im_str!("{}", Script::directory().display()));
+                    });
+                return;
+            }
+        };
+
+        if !drill.over() {
+            return;
+        }
+
+        let title = if drill.cleared() {
+            im_str!("Drill cleared")
+        } else {
+            im_str!("Drill over")
+        };
+
+        Window::new(title)
+            .resizable(false)
+            .collapsible(false)
+            .size([200.0, 0.0], Condition::Appearing)
+            .position([w / 2.0 - 100.0, h / 3.0 * 2.0], Condition::Appearing)
+            .build(ui, || {
+                ui.text(im_str!("Survived: {}", utils::race_time(drill.survived)));
+                ui.separator();
+
+                if ui.button(im_str!("Retry"), [170.0, 0.0]) {
+                    *action = Some(DrillAction::Retry);
+                }
+
+                if ui.button(im_str!("Other script"), [170.0, 0.0]) {
+                    *action = Some(DrillAction::Scripts);
+                }
+
+                if ui.button(im_str!("Menu"), [170.0, 0.0]) {
+                    *action = Some(DrillAction::Menu);
+                }
+            });
+    }
+
+    fn gameplay(&mut self) -> Option<&mut Gameplay> {
+        self.drill.as_mut().map(|d| &mut d.gameplay)
+    }
+
+    fn music(&self) -> Track {
+        Track::Gameplay
+    }
+}
@@ -0,0 +1,280 @@
+use std::time::Duration;
+
+use ggez::{
+    graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect},
+    nalgebra::Point2,
+    Context, GameResult,
+};
+use imgui::{im_str, Condition, Ui, Window};
+
+use crate::{
+    engine::BoardState,
+    finesse::{self, Placement},
+    gameplay::Gameplay,
+    global::Global,
+    music::Track,
+    piece::Piece,
+    rules::Rules,
+    scene::{Scene, Transition},
+    shape::{self, ShapeType},
+};
+
+/// Slowest placements listed as the ones to work on
+const WEAK_SPOTS: usize = 5;
+
+enum FinesseAction {
+    Shape(ShapeType),
+    Select(usize),
+}
+
+/// Result of the last try
+struct Try {
+    placement: usize,
+    time: Duration,
+    inputs: u32,
+    hit: bool,
+}
+
+/// The same piece over and over, each time to be placed where the outline shows,
+/// going through every placement of the piece
+pub struct FinesseScene {
+    gameplay: Gameplay,
+    placements: Vec<Placement>,
+    current: usize,
+    time: Duration,
+    inputs: u32,
+    last: Option<Try>,
+    action: Option<FinesseAction>,
+}
+
+impl FinesseScene {
+    pub fn new(ctx: &mut Context, g: &mut Global) -> GameResult<FinesseScene> {
+        let rules = Rules {
+            name: String::from("Finesse"),
+            hold: false,
+            ..Rules::default()
+        };
+
+        let mut gameplay = Gameplay::new(ctx, g, true, &[0; 32], &rules)?;
+        gameplay.skip_countdown();
+
+        let mut scene = FinesseScene {
+            gameplay,
+            placements: vec![],
+            current: 0,
+            time: Duration::new(0, 0),
+            inputs: 0,
+            last: None,
+            action: None,
+        };
+        scene.set_shape(ShapeType::T);
+
+        Ok(scene)
+    }
+
+    fn set_shape(&mut self, shape: ShapeType) {
+        self.placements = finesse::placements(shape, &self.gameplay.engine.stack);
+        self.current = 0;
+        self.last = None;
+        self.respawn();
+    }
+
+    /// Empties the board and puts the piece back at the spawn
+    fn respawn(&mut self) {
+        let shape = self.placements[self.current].shape;
+        let stack = &self.gameplay.engine.stack;
+        let grid = vec![vec![0; stack.width as usize]; (stack.height + stack.vanish) as usize];
+
+        let state = BoardState {
+            grid,
+            piece: Piece::new(shape, stack).state(),
+            hold: None,
+            queue: vec![shape; 7],
+        };
+
+        if let Err(e) = self.gameplay.engine.set_board_state(state) {
+            log::error!("Unable to set up the finesse drill: {}", e);
+        }
+
+        self.gameplay.take_presses();
+        self.time = Duration::new(0, 0);
+        self.inputs = 0;
+    }
+
+    /// Cells of the piece locked on the board, none while it's still moving
+    fn locked(&self) -> Vec<(i32, i32)> {
+        let mut cells = vec![];
+        for (y, row) in self.gameplay.engine.stack.grid().iter().enumerate() {
+            for (x, &block) in row.iter().enumerate() {
+                if block != 0 {
+                    cells.push((x as i32, y as i32));
+                }
+            }
+        }
+
+        cells
+    }
+}
+
+impl Scene for FinesseScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        match self.action.take() {
+            Some(FinesseAction::Shape(shape)) => self.set_shape(shape),
+            Some(FinesseAction::Select(current)) => {
+                self.current = current;
+                self.respawn();
+            }
+            None => (),
+        }
+
+        self.gameplay.update(ctx, g, true)?;
+
+        if !self.gameplay.paused() {
+            self.time += g.clock.delta();
+        }
+        self.inputs += self.gameplay.take_presses();
+
+        let cells = self.locked();
+        if cells.is_empty() {
+            return Ok(Transition::None);
+        }
+
+        let placement = &self.placements[self.current];
+        let hit = cells == placement.cells;
+        self.last = Some(Try {
+            placement: self.current,
+            time: self.time,
+            inputs: self.inputs,
+            hit,
+        });
+
+        // Misses are tried again, only the placements made count towards the times
+        if hit {
+            g.stats.finesse.entry(placement.key()).or_default().add(
+                self.time,
+                self.inputs,
+                placement.optimal,
+            );
+            g.stats.save();
+
+            self.current = (self.current + 1) % self.placements.len();
+        }
+
+        self.respawn();
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        let screen = graphics::screen_coordinates(ctx);
+        let stack = &self.gameplay.engine.stack;
+        let (width, height, vanish) = (stack.width, stack.height, stack.vanish);
+
+        let block_size = g.block_size(width, height);
+        let position = Point2::new(
+            (screen.w - (width * block_size) as f32) / 2.0,
+            (screen.h - (height * block_size) as f32) / 2.0,
+        );
+
+        self.gameplay.set_block_size(Some(block_size));
+        self.gameplay.draw(ctx, g, position)?;
+
+        let mut builder = MeshBuilder::new();
+        for &(x, y) in &self.placements[self.current].cells {
+            let cell = Rect::new(
+                position[0] + (x * block_size) as f32,
+                position[1] + ((y - vanish) * block_size) as f32,
+                block_size as f32,
+                block_size as f32,
+            );
+            builder.rectangle(DrawMode::stroke(3.0), cell, Color::new(1.0, 0.9, 0.2, 0.9));
+        }
+
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())
+    }
+
+    fn ui(&mut self, ui: &Ui, g: &mut Global) {
+        let [w, h] = ui.io().display_size;
+        let action = &mut self.action;
+        let placements = &self.placements;
+        let current = self.current;
+        let last = &self.last;
+        let placement = &placements[current];
+
+        Window::new(im_str!("Finesse drill"))
+            .resizable(false)
+            .collapsible(false)
+            .size([260.0, 0.0], Condition::Appearing)
+            .position([w - 280.0, h / 2.0 - 200.0], Condition::Appearing)
+            .build(ui, || {
+                for (i, &shape) in shape::all_shape_types().iter().enumerate() {
+                    if i > 0 {
+                        ui.same_line(0.0);
+                    }
+
+                    let label = if shape == placement.shape {
+                        im_str!("[{:?}]", shape)
+                    } else {
+                        im_str!("{:?}", shape)
+                    };
+                    if ui.small_button(&label) {
+                        *action = Some(FinesseAction::Shape(shape));
+                    }
+                }
+
+                ui.separator();
+                ui.text(im_str!("Placement {} of {}", current + 1, placements.len()));
+                ui.text(im_str!("{}", placement.label()));
+                ui.text(im_str!("Fewest inputs: {}", placement.optimal));
+
+                if let Some(last) = last {
+                    ui.separator();
+                    let optimal = placements[last.placement].optimal;
+                    if !last.hit {
+                        ui.text_colored([1.0, 0.4, 0.4, 1.0], im_str!("Missed, try again"));
+                    } else if last.inputs > optimal {
+                        ui.text_colored(
+                            [1.0, 0.8, 0.3, 1.0],
+                            im_str!("{} inputs, {} needed", last.inputs, optimal),
+                        );
+                    } else {
+                        ui.text_colored(
+                            [0.4, 1.0, 0.4, 1.0],
+                            im_str!("{} inputs, perfect", last.inputs),
+                        );
+                    }
+                    ui.text(im_str!("Time: {:.2}s", last.time.as_secs_f32()));
+                }
+
+                let weak = finesse::weak_spots(placements, &g.stats.finesse);
+                if weak.is_empty() {
+                    return;
+                }
+
+                ui.separator();
+                ui.text(im_str!("Slowest placements"));
+                for (placement, record) in weak.iter().take(WEAK_SPOTS) {
+                    let id = ui.push_id(placement.index as i32);
+                    let label = im_str!(
+                        "{:.2}s {} ({}/{} faults)",
+                        record.average().as_secs_f32(),
+                        placement.label(),
+                        record.faults,
+                        record.tries
+                    );
+                    if ui.small_button(&label) {
+                        *action = Some(FinesseAction::Select(placement.index));
+                    }
+                    id.pop(ui);
+                }
+            });
+    }
+
+    fn gameplay(&mut self) -> Option<&mut Gameplay> {
+        Some(&mut self.gameplay)
+    }
+
+    fn music(&self) -> Track {
+        Track::Gameplay
+    }
+}
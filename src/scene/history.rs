@@ -0,0 +1,159 @@
+use ggez::{
+    graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect},
+    nalgebra::Point2,
+    Context, GameResult,
+};
+use imgui::{im_str, Condition, Ui, Window};
+
+use crate::{
+    global::Global,
+    history::{FinalBoard, START_RATING},
+    scene::{Scene, Transition},
+};
+
+/// Finished versus matches with the record against every opponent,
+/// the final boards of the selected match are drawn next to the list
+pub struct HistoryScene {
+    selected: Option<usize>,
+}
+
+impl HistoryScene {
+    pub fn new() -> HistoryScene {
+        HistoryScene { selected: None }
+    }
+
+    fn draw_board(
+        builder: &mut MeshBuilder,
+        board: &FinalBoard,
+        position: Point2<f32>,
+        block_size: f32,
+    ) {
+        let width = board.first().map_or(0, |row| row.len()) as f32;
+        let frame = Rect::new(
+            position[0],
+            position[1],
+            width * block_size,
+            board.len() as f32 * block_size,
+        );
+        builder.rectangle(DrawMode::fill(), frame, Color::new(0.02, 0.03, 0.04, 0.95));
+        builder.rectangle(DrawMode::stroke(1.0), frame, Color::new(0.7, 0.8, 0.9, 0.8));
+
+        for (y, row) in board.iter().enumerate() {
+            for (x, &block) in row.iter().enumerate() {
+                if block == 0 {
+                    continue;
+                }
+
+                let cell = Rect::new(
+                    position[0] + x as f32 * block_size,
+                    position[1] + y as f32 * block_size,
+                    block_size - 1.0,
+                    block_size - 1.0,
+                );
+                builder.rectangle(DrawMode::fill(), cell, Color::new(0.6, 0.65, 0.7, 1.0));
+            }
+        }
+    }
+}
+
+impl Scene for HistoryScene {
+    fn update(&mut self, _ctx: &mut Context, _g: &mut Global) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        let record = match self.selected.and_then(|i| g.stats.history.matches.get(i)) {
+            Some(record) => record,
+            None => return Ok(()),
+        };
+
+        let screen = graphics::screen_coordinates(ctx);
+        let rows = record.boards[0].len().max(1) as f32;
+        let block_size = (screen.h * 0.6 / rows).floor();
+
+        let mut builder = MeshBuilder::new();
+        for (i, board) in record.boards.iter().enumerate() {
+            let position = Point2::new(
+                screen.w / 2.0 + 20.0 + i as f32 * block_size * 11.0,
+                screen.h / 2.0 - rows * block_size / 2.0,
+            );
+            HistoryScene::draw_board(&mut builder, board, position, block_size);
+        }
+
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())
+    }
+
+    fn ui(&mut self, ui: &Ui, g: &mut Global) {
+        let [w, h] = ui.io().display_size;
+        let history = &g.stats.history;
+        let selected = &mut self.selected;
+        let (rating, _) = history.ratings();
+
+        Window::new(im_str!("Match history"))
+            .resizable(false)
+            .collapsible(false)
+            .size([360.0, h * 0.8], Condition::Always)
+            .position([w / 2.0 - 380.0, h * 0.1], Condition::Always)
+            .build(ui, || {
+                if history.matches.is_empty() {
+                    ui.text(im_str!("No versus matches played yet"));
+                    return;
+                }
+
+                ui.text(im_str!("Online rating: {:.0}", rating));
+                ui.separator();
+
+                ui.text(im_str!("Head-to-head"));
+                for head in history.head_to_head() {
+                    ui.text(im_str!(
+                        "{}: {}-{}, {:.0}% won",
+                        head.opponent,
+                        head.wins,
+                        head.losses,
+                        head.win_rate() * 100.0
+                    ));
+                    if let Some(other) = head.rating {
+                        ui.same_line(260.0);
+                        ui.text_colored([0.6, 0.6, 0.6, 1.0], im_str!("{:.0}", other));
+                    }
+                }
+                ui.separator();
+
+                ui.text(im_str!("Matches"));
+                for (i, record) in history.matches.iter().enumerate() {
+                    let id = ui.push_id(i as i32);
+                    let label = im_str!(
+                        "{} {} {}-{} {}{}",
+                        if *selected == Some(i) { ">" } else { " " },
+                        record.date,
+                        record.wins(),
+                        record.losses(),
+                        record.opponent,
+                        if record.online { "" } else { " (local)" }
+                    );
+                    if ui.small_button(&label) {
+                        *selected = Some(i);
+                    }
+
+                    if *selected == Some(i) {
+                        ui.text_colored(
+                            [0.6, 0.6, 0.6, 1.0],
+                            im_str!(
+                                "{} rules, {} rounds, yours on the left",
+                                record.rules,
+                                record.rounds.len()
+                            ),
+                        );
+                    }
+                    id.pop(ui);
+                }
+
+                ui.separator();
+                ui.text_colored(
+                    [0.6, 0.6, 0.6, 1.0],
+                    im_str!("Everyone starts at {:.0}", START_RATING),
+                );
+            });
+    }
+}
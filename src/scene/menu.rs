@@ -0,0 +1,171 @@
+use ggez::{input::keyboard::KeyCode, Context, GameResult};
+use imgui::{im_str, Condition, Ui, Window};
+
+use crate::{
+    global::Global,
+    mode::GameMode,
+    scene::{
+        BattleScene, ControlsScene, CoopScene, CustomScene, DrillScene, FinesseScene, HistoryScene,
+        OnlineScene, OptionsScene, PlayScene, ReplaysScene, Scene, StatsScene, TournamentScene,
+        Transition, VersusScene,
+    },
+};
+
+enum MenuAction {
+    Mode(GameMode),
+    Custom,
+    Battle,
+    Versus,
+    Online,
+    Coop,
+    Drill,
+    Finesse,
+    Tournament,
+    Replays,
+    Stats,
+    History,
+    Controls,
+    Options,
+    Quit,
+}
+
+pub struct MenuScene {
+    action: Option<MenuAction>,
+}
+
+impl MenuScene {
+    pub fn new() -> MenuScene {
+        MenuScene { action: None }
+    }
+}
+
+impl Scene for MenuScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        Ok(match self.action.take() {
+            Some(MenuAction::Mode(mode)) => {
+                Transition::Push(Box::new(PlayScene::new(ctx, g, mode, None, mode.rules())?))
+            }
+            Some(MenuAction::Custom) => Transition::Push(Box::new(CustomScene::new())),
+            Some(MenuAction::Battle) => Transition::Push(Box::new(BattleScene::new(ctx, g)?)),
+            Some(MenuAction::Versus) => Transition::Push(Box::new(VersusScene::new(ctx, g)?)),
+            Some(MenuAction::Online) => Transition::Push(Box::new(OnlineScene::new(g))),
+            Some(MenuAction::Coop) => Transition::Push(Box::new(CoopScene::new(ctx, g)?)),
+            Some(MenuAction::Drill) => Transition::Push(Box::new(DrillScene::new())),
+            Some(MenuAction::Finesse) => Transition::Push(Box::new(FinesseScene::new(ctx, g)?)),
+            Some(MenuAction::Tournament) => Transition::Push(Box::new(TournamentScene::new())),
+            Some(MenuAction::Replays) => Transition::Push(Box::new(ReplaysScene::new())),
+            Some(MenuAction::Stats) => Transition::Push(Box::new(StatsScene::new())),
+            Some(MenuAction::History) => Transition::Push(Box::new(HistoryScene::new())),
+            Some(MenuAction::Controls) => Transition::Push(Box::new(ControlsScene::new())),
+            Some(MenuAction::Options) => Transition::Push(Box::new(OptionsScene::new(ctx, g)?)),
+            Some(MenuAction::Quit) => Transition::Quit,
+            None => Transition::None,
+        })
+    }
+
+    fn draw(&mut self, _ctx: &mut Context, _g: &mut Global) -> GameResult {
+        Ok(())
+    }
+
+    fn ui(&mut self, ui: &Ui, _g: &mut Global) {
+        let [w, h] = ui.io().display_size;
+
+        Window::new(im_str!("Klocki"))
+            .resizable(false)
+            .collapsible(false)
+            .size([200.0, 0.0], Condition::Always)
+            .position([w / 2.0 - 100.0, h / 2.0 - 150.0], Condition::Always)
+            .build(ui, || {
+                if ui.button(im_str!("Play"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Mode(GameMode::default()));
+                }
+
+                if ui.button(im_str!("Classic"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Mode(GameMode::Classic));
+                }
+
+                if ui.button(im_str!("Marathon"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Mode(GameMode::Marathon));
+                }
+
+                if ui.button(im_str!("Sprint 40 lines"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Mode(GameMode::Sprint));
+                }
+
+                if ui.button(im_str!("Ultra 2 minutes"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Mode(GameMode::Ultra));
+                }
+
+                if ui.button(im_str!("Color match"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Mode(GameMode::Colors));
+                }
+
+                if ui.button(im_str!("Custom game"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Custom);
+                }
+
+                if ui.button(im_str!("2v2 Battle"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Battle);
+                }
+
+                if ui.button(im_str!("1v1 Versus"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Versus);
+                }
+
+                if ui.button(im_str!("Online versus"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Online);
+                }
+
+                if ui.button(im_str!("Co-op"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Coop);
+                }
+
+                if ui.button(im_str!("Garbage drill"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Drill);
+                }
+
+                if ui.button(im_str!("Finesse drill"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Finesse);
+                }
+
+                if ui.button(im_str!("Tournament"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Tournament);
+                }
+
+                if ui.button(im_str!("Replays"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Replays);
+                }
+
+                if ui.button(im_str!("Statistics"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Stats);
+                }
+
+                if ui.button(im_str!("Match history"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::History);
+                }
+
+                if ui.button(im_str!("Controls"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Controls);
+                }
+
+                if ui.button(im_str!("Options"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Options);
+                }
+
+                if ui.button(im_str!("Quit"), [170.0, 0.0]) {
+                    self.action = Some(MenuAction::Quit);
+                }
+            });
+    }
+
+    /// O opens the options without the mouse
+    fn key_down(&mut self, _g: &mut Global, keycode: KeyCode) {
+        if keycode == KeyCode::O {
+            self.action = Some(MenuAction::Options);
+        }
+    }
+
+    fn back(&mut self) -> Transition {
+        Transition::Quit
+    }
+}
@@ -0,0 +1,305 @@
+mod battle;
+mod controls;
+mod coop;
+mod custom;
+mod drill;
+mod finesse;
+mod history;
+mod menu;
+mod online;
+mod options;
+mod play;
+mod replays;
+mod results;
+mod stats;
+mod tournament;
+mod versus;
+mod watch;
+
+use std::time::Duration;
+
+use ggez::{
+    event,
+    graphics::{self, Color, DrawMode, DrawParam, Mesh},
+    input::keyboard::KeyCode,
+    nalgebra::Point2,
+    timer, Context, GameResult,
+};
+use imgui::Ui;
+
+use crate::{gameplay::Gameplay, global::Global, input::GamepadButton, music::Track};
+
+pub use self::{
+    battle::BattleScene, controls::ControlsScene, coop::CoopScene, custom::CustomScene,
+    drill::DrillScene, finesse::FinesseScene, history::HistoryScene, menu::MenuScene,
+    online::OnlineScene, options::OptionsScene, play::PlayScene, replays::ReplaysScene,
+    results::ResultsScene, stats::StatsScene, tournament::TournamentScene, versus::VersusScene,
+    watch::WatchScene,
+};
+
+const TRANSITION_DURATION: Duration = Duration::from_millis(200);
+
+pub enum Transition {
+    None,
+    Push(Box<dyn Scene>),
+    Pop,
+    Replace(Box<dyn Scene>),
+    Switch(Box<dyn Scene>),
+    Quit,
+}
+
+pub trait Scene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition>;
+
+    fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult;
+
+    fn ui(&mut self, _ui: &Ui, _g: &mut Global) {}
+
+    fn gameplay(&mut self) -> Option<&mut Gameplay> {
+        None
+    }
+
+    /// Transition requested when the player presses escape
+    fn back(&mut self) -> Transition {
+        Transition::Pop
+    }
+
+    fn music(&self) -> Track {
+        Track::Silence
+    }
+
+    /// Keys pressed while the scene is on top, used by menus navigated with the keyboard
+    fn key_down(&mut self, _g: &mut Global, _keycode: KeyCode) {}
+
+    /// Overlays are drawn and updated on top of the scene below them
+    fn overlay(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Style {
+    Fade,
+    Slide(f32),
+}
+
+struct Animation {
+    style: Style,
+    elapsed: Duration,
+    pending: Option<Transition>,
+}
+
+impl Animation {
+    /// Goes from 0 to 1 while leaving the old scene and back to 0 while entering the new one
+    fn progress(&self) -> f32 {
+        let half = TRANSITION_DURATION.as_secs_f32();
+        let elapsed = self.elapsed.as_secs_f32();
+
+        if elapsed < half {
+            elapsed / half
+        } else {
+            (2.0 - elapsed / half).max(0.0)
+        }
+    }
+}
+
+pub struct SceneStack {
+    scenes: Vec<Box<dyn Scene>>,
+    animation: Option<Animation>,
+}
+
+impl SceneStack {
+    pub fn new(root: Box<dyn Scene>) -> SceneStack {
+        SceneStack {
+            scenes: vec![root],
+            animation: None,
+        }
+    }
+
+    fn visible(&self) -> usize {
+        let mut first = self.scenes.len() - 1;
+        while first > 0 && self.scenes[first].overlay() {
+            first -= 1;
+        }
+
+        first
+    }
+
+    fn start(&mut self, ctx: &mut Context, transition: Transition) {
+        let style = match transition {
+            Transition::None => return,
+            Transition::Quit => {
+                event::quit(ctx);
+                return;
+            }
+            Transition::Push(_) => Style::Slide(-1.0),
+            Transition::Pop => Style::Slide(1.0),
+            Transition::Replace(_) | Transition::Switch(_) => Style::Fade,
+        };
+
+        self.animation = Some(Animation {
+            style,
+            elapsed: Duration::new(0, 0),
+            pending: Some(transition),
+        });
+    }
+
+    fn apply(&mut self, transition: Transition) {
+        match transition {
+            Transition::Push(scene) => self.scenes.push(scene),
+            Transition::Pop => {
+                self.scenes.pop();
+            }
+            Transition::Replace(scene) => {
+                self.scenes.pop();
+                self.scenes.push(scene);
+            }
+            Transition::Switch(scene) => {
+                self.scenes.clear();
+                self.scenes.push(scene);
+            }
+            Transition::None | Transition::Quit => (),
+        }
+
+        if self.scenes.is_empty() {
+            self.scenes.push(Box::new(MenuScene::new()));
+        }
+    }
+
+    pub fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        if let Some(animation) = &mut self.animation {
+            animation.elapsed += timer::delta(ctx);
+
+            if animation.elapsed >= TRANSITION_DURATION {
+                if let Some(transition) = animation.pending.take() {
+                    self.apply(transition);
+                }
+            }
+
+            if self.animation.as_ref().unwrap().elapsed >= TRANSITION_DURATION * 2 {
+                self.animation = None;
+            }
+
+            return Ok(());
+        }
+
+        let top = self.scenes.len() - 1;
+        for i in self.visible()..top {
+            self.scenes[i].update(ctx, g)?;
+        }
+
+        let transition = self.scenes[top].update(ctx, g)?;
+        self.start(ctx, transition);
+
+        Ok(())
+    }
+
+    pub fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        let top = self.scenes.len() - 1;
+        for i in self.visible()..top {
+            self.scenes[i].draw(ctx, g)?;
+        }
+
+        let screen = graphics::screen_coordinates(ctx);
+
+        match self
+            .animation
+            .as_ref()
+            .map(|a| (a.style, a.progress(), a.pending.is_some()))
+        {
+            Some((Style::Slide(direction), progress, leaving)) => {
+                let direction = if leaving { direction } else { -direction };
+                let offset = Point2::new(direction * progress * screen.w, 0.0);
+
+                graphics::push_transform(ctx, None);
+                graphics::mul_transform(ctx, DrawParam::new().dest(offset).to_matrix());
+                graphics::apply_transformations(ctx)?;
+                self.scenes[top].draw(ctx, g)?;
+                graphics::pop_transform(ctx);
+                graphics::apply_transformations(ctx)?;
+            }
+            Some((Style::Fade, progress, _)) => {
+                self.scenes[top].draw(ctx, g)?;
+
+                let rect = Mesh::new_rectangle(
+                    ctx,
+                    DrawMode::fill(),
+                    screen,
+                    Color::new(0.0, 0.0, 0.0, progress),
+                )?;
+                graphics::draw(ctx, &rect, DrawParam::new())?;
+            }
+            None => self.scenes[top].draw(ctx, g)?,
+        }
+
+        Ok(())
+    }
+
+    pub fn ui(&mut self, ui: &Ui, g: &mut Global) {
+        if self.animation.is_none() {
+            self.scenes.last_mut().unwrap().ui(ui, g);
+        }
+    }
+
+    pub fn back(&mut self, ctx: &mut Context) {
+        if self.animation.is_none() {
+            let transition = self.scenes.last_mut().unwrap().back();
+            self.start(ctx, transition);
+        }
+    }
+
+    pub fn key_down(&mut self, g: &mut Global, keycode: KeyCode) {
+        if self.animation.is_none() {
+            self.scenes.last_mut().unwrap().key_down(g, keycode);
+        }
+    }
+
+    /// Gamepad buttons navigate the menus like the keys they stand for,
+    /// going back only works outside of the gameplay where the buttons may be bound
+    pub fn button_down(&mut self, ctx: &mut Context, g: &mut Global, button: GamepadButton) {
+        match button.menu_key() {
+            Some(KeyCode::Escape) => {
+                if self.gameplay().is_none() {
+                    self.back(ctx);
+                }
+            }
+            Some(keycode) => self.key_down(g, keycode),
+            None => (),
+        }
+    }
+
+    pub fn music(&self) -> Track {
+        self.scenes.last().unwrap().music()
+    }
+
+    /// Gameplay of the topmost scene that has one
+    pub fn gameplay(&mut self) -> Option<&mut Gameplay> {
+        self.scenes.iter_mut().rev().find_map(|s| s.gameplay())
+    }
+}
+
+/// Block size and places of two boards of the given size next to each other,
+/// with room for the hold box and the next queue on both sides of every board
+fn side_by_side(ctx: &Context, g: &Global, width: i32, height: i32) -> (i32, [Point2<f32>; 2]) {
+    let screen = graphics::screen_coordinates(ctx);
+    let block_size = ((screen.w / 2.0 / (width as f32 + 10.0)).min(screen.h * 0.75 / height as f32)
+        as i32)
+        .min(g.block_size(width, height));
+
+    let y = (screen.h - (height * block_size) as f32) / 2.0;
+    let board = |center: f32| Point2::new(center - (width * block_size) as f32 / 2.0, y);
+
+    (block_size, [board(screen.w * 0.25), board(screen.w * 0.75)])
+}
+
+/// Draws the gameplay in the middle of the screen
+fn draw_centered(ctx: &mut Context, g: &Global, gameplay: &mut Gameplay) -> GameResult {
+    let screen = graphics::screen_coordinates(ctx);
+    let block_size = g.block_size(gameplay.engine.stack.width, gameplay.engine.stack.height);
+    let position = Point2::new(
+        (screen.w - (gameplay.engine.stack.width * block_size) as f32) / 2.0,
+        (screen.h - (gameplay.engine.stack.height * block_size) as f32) / 2.0,
+    );
+
+    gameplay.draw(ctx, g, position)
+}
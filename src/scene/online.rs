@@ -0,0 +1,414 @@
+use ggez::{
+    graphics::{self, DrawParam, Scale, Text, TextFragment},
+    nalgebra::Point2,
+    Context, GameResult,
+};
+use imgui::{im_str, Condition, ImString, Ui, Window};
+use rand::{thread_rng, RngCore};
+
+use crate::{
+    gameplay::Gameplay,
+    global::Global,
+    history::START_RATING,
+    music::Track,
+    net::{Connection, Host, Message, NetMatch, Peer},
+    rules::Rules,
+    scene::{self, battle::draw_forecast, MenuScene, Scene, Transition},
+};
+
+const DEFAULT_PORT: u16 = 7878;
+
+const PLAYER_NAMES: [&str; 2] = ["You", "Opponent"];
+
+/// Opponents from the history offered to be joined again
+const REMATCH_SUGGESTIONS: usize = 3;
+
+fn random_seed() -> [u8; 32] {
+    let mut seed = [0u8; 32];
+    thread_rng().fill_bytes(&mut seed);
+    seed
+}
+
+enum State {
+    Setup,
+    /// Waiting for the other player to join
+    Hosting(Host),
+    /// Waiting for the host to start the match
+    Joined(Connection),
+    Playing(NetMatch),
+}
+
+enum OnlineAction {
+    Host,
+    Join,
+    /// Joins an opponent from the history again
+    Rejoin(String),
+    Cancel,
+    Menu,
+}
+
+/// Versus match against another player over TCP, one of them hosts and the other one joins
+pub struct OnlineScene {
+    state: State,
+    address: ImString,
+    port: i32,
+    /// Hosted matches hide the next pieces and the hold of the opponent
+    ranked: bool,
+    name: ImString,
+    /// The result of the current match went into the history already
+    recorded: bool,
+    /// Opponent of the previous match, playing them again continues that match
+    last_opponent: Option<String>,
+    error: Option<String>,
+    action: Option<OnlineAction>,
+}
+
+impl OnlineScene {
+    pub fn new(g: &Global) -> OnlineScene {
+        OnlineScene {
+            state: State::Setup,
+            address: ImString::new(format!("127.0.0.1:{}", DEFAULT_PORT)),
+            port: i32::from(DEFAULT_PORT),
+            ranked: false,
+            name: ImString::new(g.settings.gameplay.player_name.as_str()),
+            recorded: false,
+            last_opponent: None,
+            error: None,
+            action: None,
+        }
+    }
+
+    fn start(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        match std::mem::replace(&mut self.state, State::Setup) {
+            State::Hosting(host) => match host.accept() {
+                Some(mut connection) => {
+                    let seed = random_seed();
+                    let rules = Rules::default();
+
+                    // Ranked boards don't share the seed, the other player picks their own
+                    connection.send(&Message::Start {
+                        seed: if self.ranked { None } else { Some(seed) },
+                        rules: rules.clone(),
+                        ranked: self.ranked,
+                    });
+                    let peer = Peer::Host(host);
+                    let net_match =
+                        NetMatch::new(ctx, g, connection, peer, &seed, &rules, self.ranked)?;
+                    self.state = State::Playing(net_match);
+                    self.recorded = false;
+                }
+                None => self.state = State::Hosting(host),
+            },
+            State::Joined(mut connection) => {
+                let mut messages = vec![];
+                connection.poll(&mut messages);
+
+                let start = messages.iter().position(|m| match m {
+                    Message::Start { .. } => true,
+                    _ => false,
+                });
+
+                match start {
+                    Some(start) => {
+                        let mut rest = messages.split_off(start + 1);
+                        let (seed, rules, ranked) = match messages.pop() {
+                            Some(Message::Start {
+                                seed,
+                                rules,
+                                ranked,
+                            }) => (seed.unwrap_or_else(random_seed), rules, ranked),
+                            _ => unreachable!(),
+                        };
+
+                        let peer = Peer::Join(self.address.to_str().to_string());
+                        let mut net_match =
+                            NetMatch::new(ctx, g, connection, peer, &seed, &rules, ranked)?;
+                        for message in rest.drain(..) {
+                            net_match.receive(message);
+                        }
+                        self.state = State::Playing(net_match);
+                        self.recorded = false;
+                    }
+                    None if connection.closed() => {
+                        self.error = Some(String::from("The host left"));
+                    }
+                    None => self.state = State::Joined(connection),
+                }
+            }
+            state => self.state = state,
+        }
+
+        Ok(())
+    }
+
+    /// The name is kept for the next matches too
+    fn save_name(&self, g: &mut Global) {
+        let name = self.name.to_str().trim();
+        if !name.is_empty() && name != g.settings.gameplay.player_name {
+            g.settings.gameplay.player_name = name.to_string();
+            g.settings.save();
+        }
+    }
+
+    /// Puts the finished match into the history, once
+    fn record(&mut self, g: &mut Global) {
+        let record = match &self.state {
+            State::Playing(net_match) if !self.recorded => net_match.record(),
+            _ => None,
+        };
+
+        if let Some(record) = record {
+            let rematch = self.last_opponent.as_ref() == Some(&record.opponent);
+            self.last_opponent = Some(record.opponent.clone());
+            self.recorded = true;
+
+            g.stats.history.record(record, rematch);
+            g.stats.save();
+        }
+    }
+}
+
+impl Scene for OnlineScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        match self.action.take() {
+            Some(OnlineAction::Host) => {
+                self.save_name(g);
+                match Host::listen(self.port.max(1).min(65535) as u16) {
+                    Ok(host) => {
+                        self.error = None;
+                        self.state = State::Hosting(host);
+                    }
+                    Err(e) => self.error = Some(format!("Unable to host: {}", e)),
+                }
+            }
+            Some(OnlineAction::Join) => {
+                self.save_name(g);
+                match Connection::connect(self.address.to_str()) {
+                    Ok(connection) => {
+                        self.error = None;
+                        self.state = State::Joined(connection);
+                    }
+                    Err(e) => self.error = Some(format!("Unable to join: {}", e)),
+                }
+            }
+            Some(OnlineAction::Rejoin(address)) => {
+                self.address = ImString::new(address);
+                self.action = Some(OnlineAction::Join);
+                return Ok(Transition::None);
+            }
+            Some(OnlineAction::Cancel) => self.state = State::Setup,
+            Some(OnlineAction::Menu) => return Ok(Transition::Switch(Box::new(MenuScene::new()))),
+            None => (),
+        }
+
+        match &mut self.state {
+            State::Playing(net_match) => net_match.update(ctx, g)?,
+            _ => self.start(ctx, g)?,
+        }
+        self.record(g);
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        let net_match = match &mut self.state {
+            State::Playing(net_match) => net_match,
+            _ => return Ok(()),
+        };
+
+        let stack = &net_match.local.engine.stack;
+        let (width, height) = (stack.width, stack.height);
+        let (block_size, positions) = scene::side_by_side(ctx, g, width, height);
+
+        for (i, gameplay) in [&mut net_match.local, &mut net_match.remote.gameplay]
+            .iter_mut()
+            .enumerate()
+        {
+            gameplay.set_block_size(Some(block_size));
+            gameplay.set_attack_target(Some(positions[1 - i] - positions[i]));
+            gameplay.draw(ctx, g, positions[i])?;
+        }
+
+        let below = Point2::new(
+            positions[0][0],
+            positions[0][1] + (height * block_size) as f32 + block_size as f32 * 0.2,
+        );
+        draw_forecast(
+            ctx,
+            &net_match.local,
+            net_match.forecast(),
+            below,
+            (width * block_size) as f32,
+            block_size as f32,
+        )?;
+
+        for (i, position) in positions.iter().enumerate() {
+            let name = match &net_match.opponent {
+                Some(opponent) if i == 1 => opponent.clone(),
+                _ => PLAYER_NAMES[i].to_string(),
+            };
+            let text = Text::new(TextFragment {
+                text: name,
+                scale: Some(Scale::uniform(block_size as f32 * 1.2)),
+                ..Default::default()
+            });
+            let x = position[0] + ((width * block_size) as f32 - text.width(ctx) as f32) / 2.0;
+            let label = Point2::new(x, position[1] - block_size as f32 * 2.5);
+            graphics::draw(ctx, &text, DrawParam::new().dest(label))?;
+        }
+
+        Ok(())
+    }
+
+    fn ui(&mut self, ui: &Ui, g: &mut Global) {
+        let [w, h] = ui.io().display_size;
+
+        if let State::Playing(net_match) = &self.state {
+            if let Some(left) = net_match.reconnecting() {
+                Window::new(im_str!("Connection dropped"))
+                    .resizable(false)
+                    .collapsible(false)
+                    .size([260.0, 0.0], Condition::Always)
+                    .position([w / 2.0 - 130.0, h / 3.0 * 2.0], Condition::Always)
+                    .build(ui, || {
+                        ui.text(im_str!("Reconnecting, the match is paused"));
+                        ui.text(im_str!("Giving up in {}s", left.as_secs() + 1));
+                    });
+                return;
+            }
+
+            let title = match net_match.won() {
+                Some(true) => im_str!("You win!"),
+                Some(false) => im_str!("You lose"),
+                None if net_match.desynced() => im_str!("Boards out of sync"),
+                None if net_match.disconnected() => im_str!("Connection lost"),
+                None => return,
+            };
+
+            let opponent = net_match.opponent.as_ref();
+            let head_to_head = g
+                .stats
+                .history
+                .head_to_head()
+                .into_iter()
+                .find(|head| Some(&head.opponent) == opponent);
+
+            Window::new(title)
+                .resizable(false)
+                .collapsible(false)
+                .size([200.0, 0.0], Condition::Appearing)
+                .position([w / 2.0 - 100.0, h / 3.0 * 2.0], Condition::Appearing)
+                .build(ui, || {
+                    if let Some(head) = &head_to_head {
+                        ui.text(im_str!("Against {}", head.opponent));
+                        ui.text(im_str!("{} wins, {} losses", head.wins, head.losses));
+                        ui.separator();
+                    }
+
+                    if ui.button(im_str!("Play again"), [170.0, 0.0]) {
+                        self.action = Some(OnlineAction::Cancel);
+                    }
+
+                    if ui.button(im_str!("Menu"), [170.0, 0.0]) {
+                        self.action = Some(OnlineAction::Menu);
+                    }
+                });
+
+            return;
+        }
+
+        let suggestions = g.stats.history.rematch_suggestions();
+
+        Window::new(im_str!("Online versus"))
+            .resizable(false)
+            .collapsible(false)
+            .size([320.0, 0.0], Condition::Appearing)
+            .position([w / 2.0 - 160.0, h / 2.0 - 100.0], Condition::Appearing)
+            .build(ui, || {
+                match &self.state {
+                    State::Hosting(host) => {
+                        let port = host.address().map_or(0, |a| a.port());
+                        ui.text(im_str!("Waiting for the other player on port {}", port));
+                    }
+                    State::Joined(_) => ui.text(im_str!("Waiting for the host to start")),
+                    _ => {
+                        ui.input_text(im_str!("Name"), &mut self.name)
+                            .resize_buffer(true)
+                            .build();
+                        ui.separator();
+
+                        ui.input_int(im_str!("Port"), &mut self.port).build();
+                        ui.checkbox(
+                            im_str!("Ranked when hosting, next pieces hidden"),
+                            &mut self.ranked,
+                        );
+                        if ui.button(im_str!("Host"), [290.0, 0.0]) {
+                            self.action = Some(OnlineAction::Host);
+                        }
+                        ui.separator();
+
+                        ui.input_text(im_str!("Address"), &mut self.address)
+                            .resize_buffer(true)
+                            .build();
+                        if ui.button(im_str!("Join"), [290.0, 0.0]) {
+                            self.action = Some(OnlineAction::Join);
+                        }
+
+                        if !suggestions.is_empty() {
+                            ui.separator();
+                            ui.text(im_str!("Rematch, the closest ratings first"));
+                        }
+                        for (i, head) in suggestions.iter().take(REMATCH_SUGGESTIONS).enumerate() {
+                            let id = ui.push_id(i as i32);
+                            let label = im_str!(
+                                "{} ({:.0}, {}-{})",
+                                head.opponent,
+                                head.rating.unwrap_or(START_RATING),
+                                head.wins,
+                                head.losses
+                            );
+                            if ui.button(&label, [290.0, 0.0]) {
+                                if let Some(address) = &head.address {
+                                    self.action = Some(OnlineAction::Rejoin(address.clone()));
+                                }
+                            }
+                            id.pop(ui);
+                        }
+                    }
+                }
+
+                if let Some(error) = &self.error {
+                    ui.separator();
+                    ui.text_colored([1.0, 0.4, 0.4, 1.0], &ImString::new(error.as_str()));
+                }
+
+                ui.separator();
+                match self.state {
+                    State::Setup => {
+                        if ui.button(im_str!("Menu"), [290.0, 0.0]) {
+                            self.action = Some(OnlineAction::Menu);
+                        }
+                    }
+                    _ => {
+                        if ui.button(im_str!("Cancel"), [290.0, 0.0]) {
+                            self.action = Some(OnlineAction::Cancel);
+                        }
+                    }
+                }
+            });
+    }
+
+    fn gameplay(&mut self) -> Option<&mut Gameplay> {
+        match &mut self.state {
+            State::Playing(net_match) => Some(&mut net_match.local),
+            _ => None,
+        }
+    }
+
+    fn music(&self) -> Track {
+        match self.state {
+            State::Playing(_) => Track::Gameplay,
+            _ => Track::Silence,
+        }
+    }
+}
@@ -0,0 +1,589 @@
+use ggez::{
+    graphics::{self, Color, DrawMode, DrawParam, Font, Mesh, Rect, Scale, Text, TextFragment},
+    input::keyboard::KeyCode,
+    nalgebra::Point2,
+    Context, GameResult,
+};
+
+use crate::{
+    engine::LOCK_MODES,
+    global::Global,
+    input::CONTROL_PRESETS,
+    metronome::{MAX_BPM, METRONOME_MODES, MIN_BPM},
+    scene::{Scene, Transition},
+    settings::Settings,
+    stack::BORDER_STYLES,
+};
+
+enum Value {
+    Toggle {
+        get: fn(&Settings) -> bool,
+        set: fn(&mut Settings, bool),
+    },
+    Number {
+        get: fn(&Settings) -> i32,
+        set: fn(&mut Settings, i32),
+        min: i32,
+        max: i32,
+        step: i32,
+    },
+    Choice {
+        get: fn(&Settings) -> usize,
+        set: fn(&mut Settings, usize),
+        count: usize,
+        name: fn(usize) -> &'static str,
+    },
+}
+
+struct Item {
+    label: &'static str,
+    value: Value,
+}
+
+impl Item {
+    fn toggle(
+        label: &'static str,
+        get: fn(&Settings) -> bool,
+        set: fn(&mut Settings, bool),
+    ) -> Item {
+        Item {
+            label,
+            value: Value::Toggle { get, set },
+        }
+    }
+
+    fn number(
+        label: &'static str,
+        get: fn(&Settings) -> i32,
+        set: fn(&mut Settings, i32),
+        (min, max, step): (i32, i32, i32),
+    ) -> Item {
+        Item {
+            label,
+            value: Value::Number {
+                get,
+                set,
+                min,
+                max,
+                step,
+            },
+        }
+    }
+
+    fn text(&self, settings: &Settings) -> String {
+        match &self.value {
+            Value::Toggle { get, .. } => String::from(if get(settings) { "On" } else { "Off" }),
+            Value::Number { get, .. } => get(settings).to_string(),
+            Value::Choice {
+                get, count, name, ..
+            } => String::from(name(get(settings) % count)),
+        }
+    }
+
+    /// Changes the value by one step in the direction, returns true if it changed
+    fn change(&self, settings: &mut Settings, direction: i32) -> bool {
+        match &self.value {
+            Value::Toggle { get, set } => {
+                set(settings, !get(settings));
+                true
+            }
+            Value::Number {
+                get,
+                set,
+                min,
+                max,
+                step,
+            } => {
+                let value = get(settings);
+                let changed = (value + direction * step).max(*min).min(*max);
+                set(settings, changed);
+                changed != value
+            }
+            Value::Choice {
+                get, set, count, ..
+            } => {
+                let n = *count as i32;
+                let value = (get(settings) as i32 + direction).rem_euclid(n);
+                set(settings, value as usize);
+                true
+            }
+        }
+    }
+}
+
+struct Tab {
+    name: &'static str,
+    items: Vec<Item>,
+}
+
+fn tabs() -> Vec<Tab> {
+    vec![
+        Tab {
+            name: "Gameplay",
+            items: vec![
+                Item::number(
+                    "Ghost piece",
+                    |s| s.gameplay.ghost_piece as i32,
+                    |s, v| s.gameplay.ghost_piece = v as u32,
+                    (0, 100, 10),
+                ),
+                Item::toggle(
+                    "Hold preview",
+                    |s| s.gameplay.hold_preview,
+                    |s, v| s.gameplay.hold_preview = v,
+                ),
+                Item::number(
+                    "Entry delay",
+                    |s| s.gameplay.entry_delay as i32,
+                    |s, v| s.gameplay.entry_delay = v as u32,
+                    (0, 500, 10),
+                ),
+                Item::number(
+                    "Lock delay",
+                    |s| s.gameplay.lock_delay as i32,
+                    |s, v| s.gameplay.lock_delay = v as u32,
+                    (0, 1000, 50),
+                ),
+                Item {
+                    label: "Lock mode",
+                    value: Value::Choice {
+                        get: |s| {
+                            LOCK_MODES
+                                .iter()
+                                .position(|&m| m == s.gameplay.lock_mode)
+                                .unwrap_or(0)
+                        },
+                        set: |s, v| s.gameplay.lock_mode = LOCK_MODES[v],
+                        count: LOCK_MODES.len(),
+                        name: |i| LOCK_MODES[i].name(),
+                    },
+                },
+                Item::number(
+                    "Clear delay",
+                    |s| s.gameplay.clear_delay as i32,
+                    |s, v| s.gameplay.clear_delay = v as u32,
+                    (0, 500, 10),
+                ),
+                Item::toggle(
+                    "Delay per clear size",
+                    |s| s.gameplay.clear_delay_per_size,
+                    |s, v| s.gameplay.clear_delay_per_size = v,
+                ),
+                Item::number(
+                    "Misdrop undo window",
+                    |s| s.gameplay.undo_grace as i32,
+                    |s, v| s.gameplay.undo_grace = v as u32,
+                    (0, 1000, 50),
+                ),
+                Item::number(
+                    "Hard drop confirmation",
+                    |s| s.gameplay.confirm_drop as i32,
+                    |s, v| s.gameplay.confirm_drop = v as u32,
+                    (0, 3000, 250),
+                ),
+                Item::toggle(
+                    "Monochrome blocks",
+                    |s| s.gameplay.monochrome,
+                    |s, v| s.gameplay.monochrome = v,
+                ),
+                Item::toggle(
+                    "Stack outline",
+                    |s| s.gameplay.stack_outline,
+                    |s, v| s.gameplay.stack_outline = v,
+                ),
+                Item::number(
+                    "Vanish rows",
+                    |s| s.gameplay.vanish_rows as i32,
+                    |s, v| s.gameplay.vanish_rows = v as u32,
+                    (0, 4, 1),
+                ),
+                Item::toggle(
+                    "Zone meter",
+                    |s| s.gameplay.zone,
+                    |s, v| s.gameplay.zone = v,
+                ),
+                Item::toggle(
+                    "Tutorial hints",
+                    |s| s.gameplay.hints,
+                    |s, v| s.gameplay.hints = v,
+                ),
+                Item::number(
+                    "Break reminder (minutes)",
+                    |s| s.gameplay.break_reminder as i32,
+                    |s, v| s.gameplay.break_reminder = v as u32,
+                    (0, 120, 15),
+                ),
+                Item::toggle(
+                    "Save replays",
+                    |s| s.gameplay.save_replays,
+                    |s, v| s.gameplay.save_replays = v,
+                ),
+                Item::number(
+                    "Reconnect window (seconds)",
+                    |s| s.gameplay.reconnect_window as i32,
+                    |s, v| s.gameplay.reconnect_window = v as u32,
+                    (0, 120, 5),
+                ),
+            ],
+        },
+        Tab {
+            name: "Controls",
+            items: vec![
+                Item::number(
+                    "DAS",
+                    |s| s.input.das as i32,
+                    |s, v| s.input.das = v as u32,
+                    (0, 500, 1),
+                ),
+                Item::number(
+                    "ARR",
+                    |s| s.input.arr as i32,
+                    |s, v| s.input.arr = v as u32,
+                    (0, 500, 1),
+                ),
+                Item::toggle(
+                    "Keep DAS between pieces",
+                    |s| s.input.preserve_das,
+                    |s, v| s.input.preserve_das = v,
+                ),
+                Item::toggle(
+                    "Buffer inputs during delays",
+                    |s| s.input.buffer_inputs,
+                    |s, v| s.input.buffer_inputs = v,
+                ),
+                Item::toggle(
+                    "Hard drop needs a fresh press",
+                    |s| s.input.hard_drop_fresh_press,
+                    |s, v| s.input.hard_drop_fresh_press = v,
+                ),
+                Item::number(
+                    "Hard drop interval",
+                    |s| s.input.hard_drop_interval as i32,
+                    |s, v| s.input.hard_drop_interval = v as u32,
+                    (0, 500, 10),
+                ),
+                Item::toggle(
+                    "Auto soft drop",
+                    |s| s.input.auto_soft_drop,
+                    |s, v| s.input.auto_soft_drop = v,
+                ),
+                Item::number(
+                    "Analog soft drop",
+                    |s| s.input.analog_soft_drop as i32,
+                    |s, v| s.input.analog_soft_drop = v as u32,
+                    (0, 40, 5),
+                ),
+                Item::number(
+                    "Pause when idle",
+                    |s| s.input.idle_pause as i32,
+                    |s, v| s.input.idle_pause = v as u32,
+                    (0, 120, 5),
+                ),
+                Item {
+                    label: "Controls preset",
+                    value: Value::Choice {
+                        get: |s| {
+                            CONTROL_PRESETS
+                                .iter()
+                                .position(|&p| p == s.input.preset)
+                                .unwrap_or(0)
+                        },
+                        set: |s, v| {
+                            s.input.preset = CONTROL_PRESETS[v];
+                            s.input.bindings = s.input.preset.bindings();
+                        },
+                        count: CONTROL_PRESETS.len(),
+                        name: |i| CONTROL_PRESETS[i].name(),
+                    },
+                },
+                Item::toggle("Mouse control", |s| s.input.mouse, |s, v| s.input.mouse = v),
+                Item::toggle(
+                    "Low latency mode",
+                    |s| s.input.low_latency,
+                    |s, v| s.input.low_latency = v,
+                ),
+            ],
+        },
+        Tab {
+            name: "Audio",
+            items: vec![
+                Item::number(
+                    "Music",
+                    |s| s.audio.music_volume as i32,
+                    |s, v| s.audio.music_volume = v as u32,
+                    (0, 100, 5),
+                ),
+                Item::number(
+                    "SFX",
+                    |s| s.audio.sfx_volume as i32,
+                    |s, v| s.audio.sfx_volume = v as u32,
+                    (0, 100, 5),
+                ),
+                Item::number(
+                    "SFX voices",
+                    |s| s.audio.sfx_voices as i32,
+                    |s, v| s.audio.sfx_voices = v as u32,
+                    (1, 32, 1),
+                ),
+                Item::number(
+                    "Music fade in",
+                    |s| s.audio.music_fade_in as i32,
+                    |s, v| s.audio.music_fade_in = v as u32,
+                    (0, 3000, 100),
+                ),
+                Item::number(
+                    "Music fade out",
+                    |s| s.audio.music_fade_out as i32,
+                    |s, v| s.audio.music_fade_out = v as u32,
+                    (0, 3000, 100),
+                ),
+                Item {
+                    label: "Metronome",
+                    value: Value::Choice {
+                        get: |s| {
+                            METRONOME_MODES
+                                .iter()
+                                .position(|&m| m == s.audio.metronome)
+                                .unwrap_or(0)
+                        },
+                        set: |s, v| s.audio.metronome = METRONOME_MODES[v],
+                        count: METRONOME_MODES.len(),
+                        name: |i| METRONOME_MODES[i].name(),
+                    },
+                },
+                Item::number(
+                    "Metronome BPM",
+                    |s| s.audio.metronome_bpm as i32,
+                    |s, v| s.audio.metronome_bpm = v as u32,
+                    (MIN_BPM as i32, MAX_BPM as i32, 5),
+                ),
+            ],
+        },
+        Tab {
+            name: "Video",
+            items: vec![
+                Item::toggle(
+                    "Fullscreen",
+                    |s| s.graphics.fullscreen,
+                    |s, v| s.graphics.fullscreen = v,
+                ),
+                Item::toggle(
+                    "Fit blocks to window",
+                    |s| s.gameplay.auto_block_size,
+                    |s, v| s.gameplay.auto_block_size = v,
+                ),
+                Item::number(
+                    "Block size",
+                    |s| s.gameplay.block_size,
+                    |s, v| s.gameplay.block_size = v,
+                    (24, 43, 1),
+                ),
+                Item {
+                    label: "Border",
+                    value: Value::Choice {
+                        get: |s| {
+                            BORDER_STYLES
+                                .iter()
+                                .position(|&b| b == s.graphics.border)
+                                .unwrap_or(0)
+                        },
+                        set: |s, v| s.graphics.border = BORDER_STYLES[v],
+                        count: BORDER_STYLES.len(),
+                        name: |i| BORDER_STYLES[i].name(),
+                    },
+                },
+                Item::toggle(
+                    "Stack grid",
+                    |s| s.gameplay.stack_grid,
+                    |s, v| s.gameplay.stack_grid = v,
+                ),
+                Item::toggle(
+                    "Animated background",
+                    |s| s.graphics.animated_background,
+                    |s, v| s.graphics.animated_background = v,
+                ),
+                Item::toggle(
+                    "Camera",
+                    |s| s.graphics.camera,
+                    |s, v| s.graphics.camera = v,
+                ),
+                Item::toggle(
+                    "Performance HUD",
+                    |s| s.graphics.profiler,
+                    |s, v| s.graphics.profiler = v,
+                ),
+                Item::toggle(
+                    "Capture mode",
+                    |s| s.graphics.capture_mode,
+                    |s, v| s.graphics.capture_mode = v,
+                ),
+                Item::toggle(
+                    "Chroma key background",
+                    |s| s.graphics.chroma_key,
+                    |s, v| s.graphics.chroma_key = v,
+                ),
+                Item::toggle(
+                    "Overlay feed",
+                    |s| s.feed.enabled,
+                    |s, v| s.feed.enabled = v,
+                ),
+                Item::number(
+                    "Overlay feed delay",
+                    |s| s.feed.delay as i32,
+                    |s, v| s.feed.delay = v as u32,
+                    (0, 10000, 500),
+                ),
+                Item::toggle(
+                    "Session timer",
+                    |s| s.graphics.session_timer,
+                    |s, v| s.graphics.session_timer = v,
+                ),
+                Item::toggle(
+                    "Scanlines",
+                    |s| s.graphics.post_effects.scanlines,
+                    |s, v| s.graphics.post_effects.scanlines = v,
+                ),
+                Item::toggle(
+                    "Bloom",
+                    |s| s.graphics.post_effects.bloom,
+                    |s, v| s.graphics.post_effects.bloom = v,
+                ),
+                Item::toggle(
+                    "Chromatic aberration",
+                    |s| s.graphics.post_effects.aberration,
+                    |s, v| s.graphics.post_effects.aberration = v,
+                ),
+            ],
+        },
+    ]
+}
+
+/// Settings menu drawn by the game itself, so it can be used without the mouse
+pub struct OptionsScene {
+    tabs: Vec<Tab>,
+    tab: usize,
+    selected: usize,
+    font: Font,
+}
+
+impl OptionsScene {
+    pub fn new(ctx: &mut Context, g: &mut Global) -> GameResult<OptionsScene> {
+        Ok(OptionsScene {
+            tabs: tabs(),
+            tab: 0,
+            selected: 0,
+            font: g.assets.font(ctx, "fonts/bold.ttf")?,
+        })
+    }
+
+    fn switch_tab(&mut self, direction: i32) {
+        let n = self.tabs.len() as i32;
+        self.tab = (self.tab as i32 + direction).rem_euclid(n) as usize;
+        self.selected = 0;
+    }
+
+    fn text(&self, text: &str, color: Color, size: f32) -> Text {
+        Text::new(TextFragment {
+            text: String::from(text),
+            color: Some(color),
+            font: Some(self.font),
+            scale: Some(Scale::uniform(size)),
+        })
+    }
+}
+
+impl Scene for OptionsScene {
+    fn update(&mut self, _ctx: &mut Context, _g: &mut Global) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        let screen = graphics::screen_coordinates(ctx);
+        let normal = Color::new(0.8, 0.9, 1.0, 0.8);
+        let highlight = Color::new(1.0, 1.0, 1.0, 1.0);
+        let dimmed = Color::new(0.8, 0.9, 1.0, 0.4);
+
+        let width = 900.0;
+        let left = (screen.w - width) / 2.0;
+        let mut y = screen.h / 5.0;
+
+        let mut x = left;
+        for (i, tab) in self.tabs.iter().enumerate() {
+            let color = if i == self.tab { highlight } else { dimmed };
+            let text = self.text(tab.name, color, 56.0);
+            graphics::draw(ctx, &text, DrawParam::new().dest(Point2::new(x, y)))?;
+            x += text.width(ctx) as f32 + 48.0;
+        }
+
+        y += 100.0;
+        let row = 56.0;
+
+        for (i, item) in self.tabs[self.tab].items.iter().enumerate() {
+            let selected = i == self.selected;
+            if selected {
+                let rect = Rect::new(left - 16.0, y - 6.0, width + 32.0, row);
+                let mesh = Mesh::new_rectangle(
+                    ctx,
+                    DrawMode::fill(),
+                    rect,
+                    Color::new(0.3, 0.4, 0.6, 0.5),
+                )?;
+                graphics::draw(ctx, &mesh, DrawParam::new())?;
+            }
+
+            let color = if selected { highlight } else { normal };
+            let label = self.text(item.label, color, 40.0);
+            graphics::draw(ctx, &label, DrawParam::new().dest(Point2::new(left, y)))?;
+
+            let value = self.text(&format!("< {} >", item.text(&g.settings)), color, 40.0);
+            let value_x = left + width - value.width(ctx) as f32;
+            graphics::draw(ctx, &value, DrawParam::new().dest(Point2::new(value_x, y)))?;
+
+            y += row;
+        }
+
+        let hint = self.text(
+            "Up/Down select   Left/Right change   Q/E switch tab   Esc back",
+            dimmed,
+            28.0,
+        );
+        let hint_x = (screen.w - hint.width(ctx) as f32) / 2.0;
+        graphics::draw(
+            ctx,
+            &hint,
+            DrawParam::new().dest(Point2::new(hint_x, screen.h - 80.0)),
+        )?;
+
+        Ok(())
+    }
+
+    fn key_down(&mut self, g: &mut Global, keycode: KeyCode) {
+        let items = self.tabs[self.tab].items.len();
+
+        let direction = match keycode {
+            KeyCode::Up => {
+                self.selected = (self.selected + items - 1) % items;
+                return;
+            }
+            KeyCode::Down => {
+                self.selected = (self.selected + 1) % items;
+                return;
+            }
+            KeyCode::Q | KeyCode::PageUp => {
+                self.switch_tab(-1);
+                return;
+            }
+            KeyCode::E | KeyCode::PageDown | KeyCode::Tab => {
+                self.switch_tab(1);
+                return;
+            }
+            KeyCode::Left => -1,
+            KeyCode::Right | KeyCode::Return | KeyCode::Space => 1,
+            _ => return,
+        };
+
+        if self.tabs[self.tab].items[self.selected].change(&mut g.settings, direction) {
+            g.settings.save();
+        }
+    }
+}
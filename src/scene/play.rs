@@ -0,0 +1,246 @@
+use ggez::{Context, GameResult};
+use imgui::{im_str, Condition, ImString, Ui, Window};
+use rand::{thread_rng, RngCore};
+
+use crate::{
+    gameplay::Gameplay,
+    global::Global,
+    macros::Macro,
+    mode::GameMode,
+    music::Track,
+    rules::Rules,
+    scene::{self, ResultsScene, Scene, Transition},
+    stats::GameRecord,
+    utils,
+};
+
+enum MacroAction {
+    Record,
+    Stop,
+    Delete(usize),
+}
+
+pub struct PlayScene {
+    gameplay: Gameplay,
+    mode: GameMode,
+    rules: Rules,
+    game_over: bool,
+    macro_name: ImString,
+    macro_action: Option<MacroAction>,
+}
+
+impl PlayScene {
+    /// Starts a game with a random seed unless one is given
+    pub fn new(
+        ctx: &mut Context,
+        g: &mut Global,
+        mode: GameMode,
+        seed: Option<[u8; 32]>,
+        rules: Rules,
+    ) -> GameResult<PlayScene> {
+        let seed = seed.unwrap_or_else(|| {
+            let mut seed = [0u8; 32];
+            thread_rng().fill_bytes(&mut seed);
+            seed
+        });
+
+        log::info!(
+            "Starting {} game with {} rules and seed {}",
+            mode,
+            rules.name,
+            utils::seed_code(&seed)
+        );
+
+        let mut gameplay = Gameplay::new(ctx, g, true, &seed, &rules)?;
+        gameplay.set_practice(mode.practice());
+        gameplay.set_casual(mode.casual());
+        gameplay.set_auto_pause(true);
+        gameplay.set_controls(g, mode.controls());
+        gameplay.set_win_condition(mode.win_condition());
+
+        Ok(PlayScene {
+            gameplay,
+            mode,
+            rules,
+            game_over: false,
+            macro_name: ImString::with_capacity(32),
+            macro_action: None,
+        })
+    }
+
+    fn update_macros(&mut self, g: &mut Global) {
+        match self.macro_action.take() {
+            Some(MacroAction::Record) => self.gameplay.start_recording(),
+            Some(MacroAction::Stop) => {
+                let actions = self.gameplay.stop_recording();
+                let macros = &mut g.settings.input.macros;
+
+                match Macro::free_key(macros) {
+                    Some(key) if !actions.is_empty() => {
+                        let name = match self.macro_name.to_str().trim() {
+                            "" => format!("Macro {:?}", key),
+                            name => String::from(name),
+                        };
+
+                        log::info!("Recorded macro {} with {} actions", name, actions.len());
+                        macros.push(Macro { name, key, actions });
+                        self.macro_name.clear();
+                        g.settings.save();
+                    }
+                    Some(_) => log::warn!("Nothing recorded, the macro was discarded"),
+                    None => log::warn!("Every macro key is taken, the macro was discarded"),
+                }
+            }
+            Some(MacroAction::Delete(id)) => {
+                if id < g.settings.input.macros.len() {
+                    g.settings.input.macros.remove(id);
+                    g.settings.save();
+                }
+            }
+            None => (),
+        }
+    }
+}
+
+impl Scene for PlayScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        if g.imgui_state.restart {
+            *self = PlayScene::new(ctx, g, self.mode, None, self.rules.clone())?;
+        }
+
+        self.update_macros(g);
+        self.gameplay.update(ctx, g, true)?;
+
+        if self.gameplay.finished() && !self.game_over {
+            self.game_over = true;
+
+            let won = self.gameplay.won();
+            let duration = match won {
+                Some(result) => result.time,
+                None => self.gameplay.replay_data().duration(),
+            };
+            g.stats.record(GameRecord::new(
+                self.mode,
+                self.gameplay.score(),
+                self.gameplay.engine.score.lines(),
+                duration,
+                self.gameplay.clears(),
+            ));
+            g.stats.save();
+
+            let mut results = ResultsScene::new(
+                self.mode,
+                self.gameplay.score(),
+                self.gameplay.dig(),
+                won,
+                self.gameplay.replay_data(),
+            );
+            if g.settings.gameplay.save_replays {
+                results.save_replay();
+            }
+            return Ok(Transition::Push(Box::new(results)));
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        scene::draw_centered(ctx, g, &mut self.gameplay)
+    }
+
+    fn ui(&mut self, ui: &Ui, g: &mut Global) {
+        let [w, h] = ui.io().display_size;
+
+        // The rules card, while the countdown runs and whenever the game is paused
+        if self.gameplay.counting_down() || g.imgui_state.paused {
+            let title = if g.imgui_state.paused {
+                im_str!("Paused")
+            } else {
+                im_str!("Rules")
+            };
+
+            Window::new(title)
+                .resizable(false)
+                .collapsible(false)
+                .size([460.0, 0.0], Condition::Always)
+                .position([w / 2.0 - 230.0, h / 2.0 - 140.0], Condition::Always)
+                .build(ui, || {
+                    ui.text(im_str!("{} - {} rules", self.mode, self.rules.name));
+                    ui.separator();
+                    for (label, value) in self.mode.summary(&self.rules) {
+                        ui.text(im_str!("{}:", label));
+                        ui.same_line(100.0);
+                        ui.text(im_str!("{}", value));
+                    }
+
+                    if let Some(controls) = self.mode.controls() {
+                        ui.separator();
+                        ui.text(im_str!("{} controls", controls.name));
+                        for prompt in controls.prompts {
+                            ui.bullet_text(&ImString::new(*prompt));
+                        }
+                    }
+                });
+        }
+
+        if !self.mode.practice() {
+            return;
+        }
+
+        let recording = self.gameplay.recording();
+        let macro_name = &mut self.macro_name;
+        let mut macro_action = None;
+
+        Window::new(im_str!("Macros"))
+            .size([280.0, 0.0], Condition::Appearing)
+            .position([w - 330.0, 80.0], Condition::Appearing)
+            .build(ui, || {
+                for (i, m) in g.settings.input.macros.iter().enumerate() {
+                    let id = ui.push_id(i as i32);
+                    if ui.small_button(im_str!("Delete")) {
+                        macro_action = Some(MacroAction::Delete(i));
+                    }
+                    ui.same_line(0.0);
+                    ui.text(im_str!(
+                        "{:?}: {} ({} actions)",
+                        m.key,
+                        m.name,
+                        m.actions.len()
+                    ));
+                    id.pop(ui);
+                }
+
+                ui.separator();
+                if recording {
+                    ui.text(im_str!("Recording..."));
+                    if ui.button(im_str!("Stop"), [0.0, 0.0]) {
+                        macro_action = Some(MacroAction::Stop);
+                    }
+                } else if Macro::free_key(&g.settings.input.macros).is_some() {
+                    ui.input_text(im_str!("Name"), macro_name)
+                        .resize_buffer(true)
+                        .build();
+                    if ui.button(im_str!("Record"), [0.0, 0.0]) {
+                        macro_action = Some(MacroAction::Record);
+                    }
+                } else {
+                    ui.text(im_str!("Delete a macro to record a new one"));
+                }
+
+                ui.separator();
+                ui.text(im_str!("Snapshots: <Ctrl+1-4> save, <1-4> restore"));
+            });
+
+        if macro_action.is_some() {
+            self.macro_action = macro_action;
+        }
+    }
+
+    fn gameplay(&mut self) -> Option<&mut Gameplay> {
+        Some(&mut self.gameplay)
+    }
+
+    fn music(&self) -> Track {
+        Track::Gameplay
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use ggez::{Context, GameResult};
+use imgui::{im_str, Condition, Ui, Window};
+
+use crate::{
+    global::Global,
+    replay::ReplayData,
+    scene::{Scene, Transition, WatchScene},
+};
+
+enum ReplaysAction {
+    Watch(usize),
+    Refresh,
+}
+
+/// Saved replays to pick one for watching
+pub struct ReplaysScene {
+    paths: Vec<PathBuf>,
+    action: Option<ReplaysAction>,
+}
+
+impl ReplaysScene {
+    pub fn new() -> ReplaysScene {
+        ReplaysScene {
+            paths: ReplayData::list(&ReplayData::directory()),
+            action: None,
+        }
+    }
+}
+
+impl Scene for ReplaysScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        Ok(match self.action.take() {
+            Some(ReplaysAction::Watch(id)) => {
+                let path = &self.paths[id];
+                match ReplayData::load(path) {
+                    Some(replay_data) => {
+                        let mut scene = WatchScene::new(ctx, g, replay_data)?;
+                        scene.set_path(Some(path.clone()));
+                        Transition::Push(Box::new(scene))
+                    }
+                    None => Transition::None,
+                }
+            }
+            Some(ReplaysAction::Refresh) => {
+                self.paths = ReplayData::list(&ReplayData::directory());
+                Transition::None
+            }
+            None => Transition::None,
+        })
+    }
+
+    fn draw(&mut self, _ctx: &mut Context, _g: &mut Global) -> GameResult {
+        Ok(())
+    }
+
+    fn ui(&mut self, ui: &Ui, _g: &mut Global) {
+        let [w, h] = ui.io().display_size;
+
+        Window::new(im_str!("Replays"))
+            .resizable(false)
+            .collapsible(false)
+            .size([400.0, 420.0], Condition::Always)
+            .position([w / 2.0 - 200.0, h / 2.0 - 210.0], Condition::Always)
+            .build(ui, || {
+                if ui.small_button(im_str!("Refresh")) {
+                    self.action = Some(ReplaysAction::Refresh);
+                }
+                ui.separator();
+
+                if self.paths.is_empty() {
+                    ui.text(im_str!("No replays saved yet"));
+                }
+
+                for (i, path) in self.paths.iter().enumerate() {
+                    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                    let id = ui.push_id(i as i32);
+                    if ui.button(&im_str!("{}", name), [370.0, 0.0]) {
+                        self.action = Some(ReplaysAction::Watch(i));
+                    }
+                    id.pop(ui);
+                }
+            });
+    }
+}
@@ -0,0 +1,162 @@
+use std::path::PathBuf;
+
+use ggez::{Context, GameResult};
+use imgui::{im_str, Condition, ImString, Ui, Window};
+
+use crate::{
+    coach,
+    global::Global,
+    mode::{GameMode, RaceResult},
+    replay::ReplayData,
+    scene::{MenuScene, PlayScene, Scene, Transition, WatchScene},
+    stats::Dig,
+    utils,
+};
+
+enum ResultsAction {
+    SaveReplay,
+    WatchReplay,
+    Review,
+    PlayAgain,
+    Menu,
+    Close,
+}
+
+pub struct ResultsScene {
+    mode: GameMode,
+    score: i32,
+    dig: Dig,
+    won: Option<RaceResult>,
+    replay_data: ReplayData,
+    /// Where the replay was saved, if it was
+    path: Option<PathBuf>,
+    action: Option<ResultsAction>,
+}
+
+impl ResultsScene {
+    pub fn new(
+        mode: GameMode,
+        score: i32,
+        dig: Dig,
+        won: Option<RaceResult>,
+        replay_data: &ReplayData,
+    ) -> ResultsScene {
+        ResultsScene {
+            mode,
+            score,
+            dig,
+            won,
+            replay_data: replay_data.clone(),
+            path: None,
+            action: None,
+        }
+    }
+
+    /// Saves the replay right away instead of leaving it to the player
+    pub fn save_replay(&mut self) {
+        let path = ReplayData::new_path(&format!("Score {}", self.score));
+        self.replay_data.save(&path);
+        self.path = Some(path);
+    }
+}
+
+impl Scene for ResultsScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        Ok(match self.action.take() {
+            Some(ResultsAction::SaveReplay) => {
+                self.save_replay();
+                Transition::None
+            }
+            Some(ResultsAction::WatchReplay) => {
+                let mut scene = WatchScene::new(ctx, g, self.replay_data.clone())?;
+                scene.set_path(self.path.clone());
+                Transition::Switch(Box::new(scene))
+            }
+            Some(ResultsAction::Review) => {
+                let mistakes = coach::review(&self.replay_data);
+                let mut scene = WatchScene::new(ctx, g, self.replay_data.clone())?;
+                scene.set_path(self.path.clone());
+                scene.set_mistakes(mistakes);
+                Transition::Switch(Box::new(scene))
+            }
+            Some(ResultsAction::PlayAgain) => {
+                let rules = self.replay_data.rules.clone();
+                Transition::Switch(Box::new(PlayScene::new(ctx, g, self.mode, None, rules)?))
+            }
+            Some(ResultsAction::Menu) => Transition::Switch(Box::new(MenuScene::new())),
+            Some(ResultsAction::Close) => Transition::Pop,
+            None => Transition::None,
+        })
+    }
+
+    fn draw(&mut self, _ctx: &mut Context, _g: &mut Global) -> GameResult {
+        Ok(())
+    }
+
+    fn ui(&mut self, ui: &Ui, _g: &mut Global) {
+        let [w, h] = ui.io().display_size;
+
+        let title = match (self.won, self.mode.win_condition()) {
+            (Some(_), Some(win_condition)) => ImString::new(win_condition.ending()),
+            _ => ImString::new("Game over"),
+        };
+
+        let mut opened = true;
+        Window::new(&title)
+            .opened(&mut opened)
+            .resizable(false)
+            .collapsible(false)
+            .size([180.0, 0.0], Condition::Appearing)
+            .position([w / 2.0 - 90.0, h / 3.0 * 2.0], Condition::Appearing)
+            .build(ui, || {
+                if let Some(won) = self.won {
+                    ui.text(im_str!("Time: {}", utils::race_time(won.time)));
+                    ui.text(im_str!("Pieces: {}", won.pieces));
+                    ui.text(im_str!("PPS: {:.2}", won.pps()));
+                    ui.separator();
+                }
+
+                ui.text(im_str!("Score: {}", self.score));
+
+                // Only games that had garbage on the board
+                if self.dig.pieces > 0 {
+                    ui.separator();
+                    ui.text(im_str!("Downstacking: {}", self.dig.grade()));
+                    ui.text(im_str!("Garbage cleared: {}", self.dig.lines));
+                    ui.text(im_str!("Per piece: {:.2}", self.dig.efficiency()));
+                    ui.text(im_str!("Wasted pieces: {}", self.dig.wasted));
+                }
+
+                ui.separator();
+
+                if self.path.is_none() && ui.button(im_str!("Save replay"), [150.0, 0.0]) {
+                    self.action = Some(ResultsAction::SaveReplay);
+                }
+
+                if ui.button(im_str!("Watch replay"), [150.0, 0.0]) {
+                    self.action = Some(ResultsAction::WatchReplay);
+                }
+
+                if ui.button(im_str!("Review mistakes"), [150.0, 0.0]) {
+                    self.action = Some(ResultsAction::Review);
+                }
+
+                ui.separator();
+                if ui.button(im_str!("Play again"), [150.0, 0.0]) {
+                    self.action = Some(ResultsAction::PlayAgain);
+                }
+
+                if ui.button(im_str!("Menu"), [150.0, 0.0]) {
+                    self.action = Some(ResultsAction::Menu);
+                }
+            });
+
+        if !opened {
+            self.action = Some(ResultsAction::Close);
+        }
+    }
+
+    fn overlay(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,216 @@
+use std::f32::consts::PI;
+
+use ggez::{
+    graphics::{self, Color, DrawMode, DrawParam, MeshBuilder},
+    nalgebra::Point2,
+    Context, GameResult,
+};
+use imgui::{im_str, Condition, ImString, Ui, Window};
+
+use crate::{
+    global::Global,
+    mode::GAME_MODES,
+    scene::{Scene, Transition},
+    stats::Clears,
+    utils,
+};
+
+const SLICE_COLORS: [[f32; 3]; 5] = [
+    [0.4, 0.6, 0.9],
+    [0.3, 0.8, 0.5],
+    [0.9, 0.8, 0.3],
+    [0.0, 0.9, 0.9],
+    [0.8, 0.3, 0.9],
+];
+
+#[derive(Copy, Clone, PartialEq)]
+enum Tab {
+    Overview,
+    Bests,
+    Clears,
+    Recent,
+}
+
+const TABS: [(Tab, &str); 4] = [
+    (Tab::Overview, "Overview"),
+    (Tab::Bests, "Bests"),
+    (Tab::Clears, "Clears"),
+    (Tab::Recent, "Recent"),
+];
+
+fn format_duration(secs: u64) -> String {
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+pub struct StatsScene {
+    tab: Tab,
+}
+
+impl StatsScene {
+    pub fn new() -> StatsScene {
+        StatsScene { tab: Tab::Overview }
+    }
+
+    fn draw_pie(
+        ctx: &mut Context,
+        clears: &Clears,
+        center: Point2<f32>,
+        radius: f32,
+    ) -> GameResult {
+        let total = clears.total();
+        if total == 0 {
+            return Ok(());
+        }
+
+        let mut builder = MeshBuilder::new();
+        let mut angle = -PI / 2.0;
+
+        for (i, &(_, count)) in clears.breakdown().iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+
+            let sweep = count as f32 / total as f32 * 2.0 * PI;
+            let segments = ((sweep / (2.0 * PI) * 64.0) as usize).max(2);
+
+            let mut points = vec![center];
+            for s in 0..=segments {
+                let a = angle + sweep * s as f32 / segments as f32;
+                points.push(Point2::new(
+                    center.x + radius * a.cos(),
+                    center.y + radius * a.sin(),
+                ));
+            }
+
+            let [r, g, b] = SLICE_COLORS[i];
+            builder.polygon(DrawMode::fill(), &points, Color::new(r, g, b, 1.0))?;
+            angle += sweep;
+        }
+
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())
+    }
+}
+
+impl Scene for StatsScene {
+    fn update(&mut self, _ctx: &mut Context, _g: &mut Global) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        if self.tab == Tab::Clears {
+            let screen = graphics::screen_coordinates(ctx);
+            let center = Point2::new(screen.w / 2.0 - 200.0, screen.h / 2.0);
+            StatsScene::draw_pie(ctx, &g.stats.clears, center, screen.h / 5.0)?;
+        }
+
+        Ok(())
+    }
+
+    fn ui(&mut self, ui: &Ui, g: &mut Global) {
+        let [w, h] = ui.io().display_size;
+        let stats = &g.stats;
+
+        Window::new(im_str!("Statistics"))
+            .resizable(false)
+            .collapsible(false)
+            .size([400.0, 0.0], Condition::Always)
+            .position([w / 2.0, h / 4.0], Condition::Always)
+            .build(ui, || {
+                for (i, &(tab, name)) in TABS.iter().enumerate() {
+                    if i > 0 {
+                        ui.same_line(0.0);
+                    }
+
+                    let label = if tab == self.tab {
+                        ImString::new(format!("[{}]", name))
+                    } else {
+                        ImString::new(name)
+                    };
+
+                    if ui.button(&label, [85.0, 0.0]) {
+                        self.tab = tab;
+                    }
+                }
+                ui.separator();
+
+                match self.tab {
+                    Tab::Overview => {
+                        ui.text(im_str!("Games played: {}", stats.played));
+                        ui.text(im_str!("Lines cleared: {}", stats.lines));
+                        ui.text(im_str!(
+                            "Time played: {}",
+                            format_duration(stats.time.as_secs())
+                        ));
+
+                        if stats.played > 0 {
+                            let average: i32 = stats.recent.iter().map(|r| r.score).sum::<i32>()
+                                / stats.recent.len().max(1) as i32;
+                            ui.text(im_str!("Average recent score: {}", average));
+                        }
+                    }
+                    Tab::Bests => {
+                        for &mode in GAME_MODES.iter() {
+                            match stats.best(mode) {
+                                Some(best) if mode.win_condition().map_or(false, |w| w.race()) => {
+                                    ui.text(im_str!(
+                                        "{}: {}, {} lines ({})",
+                                        mode,
+                                        utils::race_time(best.duration),
+                                        best.lines,
+                                        best.date
+                                    ))
+                                }
+                                Some(best) => ui.text(im_str!(
+                                    "{}: {} points, {} lines ({})",
+                                    mode,
+                                    best.score,
+                                    best.lines,
+                                    best.date
+                                )),
+                                None => ui.text(im_str!("{}: not played yet", mode)),
+                            }
+                        }
+                    }
+                    Tab::Clears => {
+                        let total = stats.clears.total();
+                        if total == 0 {
+                            ui.text(im_str!("No lines cleared yet"));
+                        }
+
+                        for (i, &(name, count)) in stats.clears.breakdown().iter().enumerate() {
+                            let [r, g, b] = SLICE_COLORS[i];
+                            let percent = if total > 0 {
+                                count as f32 / total as f32 * 100.0
+                            } else {
+                                0.0
+                            };
+
+                            ui.text_colored(
+                                [r, g, b, 1.0],
+                                im_str!("{}: {} ({:.1}%)", name, count, percent),
+                            );
+                        }
+                    }
+                    Tab::Recent => {
+                        if stats.recent.is_empty() {
+                            ui.text(im_str!("No games played yet"));
+                        }
+
+                        ui.columns(4, im_str!("recent"), false);
+                        for game in stats.recent.iter().take(20) {
+                            ui.text(&game.date);
+                            ui.next_column();
+                            ui.text(im_str!("{}", game.mode));
+                            ui.next_column();
+                            ui.text(im_str!("{}", game.score));
+                            ui.next_column();
+                            ui.text(format_duration(game.duration.as_secs()));
+                            ui.next_column();
+                        }
+                        ui.columns(1, im_str!(""), false);
+                    }
+                }
+            });
+    }
+}
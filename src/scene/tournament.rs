@@ -0,0 +1,484 @@
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use ggez::{
+    graphics::{self, Color, DrawMode, DrawParam, Mesh, Rect, Scale, Text, TextFragment},
+    nalgebra::Point2,
+    Context, GameResult,
+};
+use imgui::{im_str, ComboBox, Condition, ImStr, ImString, Slider, Ui, Window};
+use rand::{thread_rng, RngCore};
+
+use crate::{
+    gameplay::Gameplay,
+    global::Global,
+    mode::{GameMode, WinCondition, GAME_MODES},
+    music::Track,
+    scene::{self, MenuScene, Scene, Transition},
+    utils,
+};
+
+const MAX_PLAYERS: usize = 8;
+
+/// Outcome of a single turn, races only have a time when they were finished
+#[derive(Copy, Clone, Debug)]
+struct Turn {
+    score: i32,
+    time: Option<Duration>,
+}
+
+/// Players taking turns on the same seeds, every player plays each round once
+struct Tournament {
+    mode: GameMode,
+    players: Vec<String>,
+    seeds: Vec<[u8; 32]>,
+    turns: Vec<Vec<Turn>>,
+}
+
+impl Tournament {
+    fn new(mode: GameMode, players: Vec<String>, rounds: usize) -> Tournament {
+        let seeds = (0..rounds)
+            .map(|_| {
+                let mut seed = [0u8; 32];
+                thread_rng().fill_bytes(&mut seed);
+                seed
+            })
+            .collect();
+
+        Tournament {
+            mode,
+            turns: vec![vec![]; players.len()],
+            players,
+            seeds,
+        }
+    }
+
+    /// Round and player whose turn is next, turns go around the table each round
+    fn next_turn(&self) -> Option<(usize, usize)> {
+        let round = self.turns.iter().map(|t| t.len()).min()?;
+        if round >= self.seeds.len() {
+            return None;
+        }
+
+        let player = self.turns.iter().position(|t| t.len() == round)?;
+        Some((round, player))
+    }
+
+    /// Turns are ranked by time in races, otherwise by score
+    fn race(&self) -> bool {
+        self.mode.win_condition().map_or(false, WinCondition::race)
+    }
+
+    fn total(&self, player: usize) -> i32 {
+        self.turns[player].iter().map(|t| t.score).sum()
+    }
+
+    /// Races left unfinished and the time of the finished ones
+    fn total_time(&self, player: usize) -> (usize, Duration) {
+        let turns = &self.turns[player];
+        let unfinished = turns.iter().filter(|t| t.time.is_none()).count();
+        let time = turns.iter().filter_map(|t| t.time).sum();
+        (unfinished, time)
+    }
+
+    fn turn_text(&self, turn: &Turn) -> String {
+        if !self.race() {
+            return turn.score.to_string();
+        }
+
+        turn.time
+            .map_or_else(|| String::from("DNF"), utils::race_time)
+    }
+
+    fn total_text(&self, player: usize) -> String {
+        if !self.race() {
+            return self.total(player).to_string();
+        }
+
+        match self.total_time(player) {
+            (0, time) => utils::race_time(time),
+            (unfinished, time) => format!("{} ({} DNF)", utils::race_time(time), unfinished),
+        }
+    }
+
+    /// Players sorted from the best total, the fewest unfinished races
+    /// and then the shortest time in races
+    fn standings(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.players.len()).collect();
+        if self.race() {
+            order.sort_by_key(|&p| self.total_time(p));
+        } else {
+            order.sort_by_key(|&p| -self.total(p));
+        }
+        order
+    }
+}
+
+enum TournamentAction {
+    AddPlayer,
+    RemovePlayer(usize),
+    Start,
+    PlayTurn,
+    Menu,
+}
+
+pub struct TournamentScene {
+    names: Vec<ImString>,
+    rounds: i32,
+    mode_id: usize,
+    tournament: Option<Rc<RefCell<Tournament>>>,
+    action: Option<TournamentAction>,
+}
+
+impl TournamentScene {
+    pub fn new() -> TournamentScene {
+        TournamentScene {
+            names: vec![ImString::new("Player 1"), ImString::new("Player 2")],
+            rounds: 3,
+            mode_id: 0,
+            tournament: None,
+            action: None,
+        }
+    }
+
+    fn setup_ui(&mut self, ui: &Ui) {
+        let [w, h] = ui.io().display_size;
+
+        Window::new(im_str!("Tournament"))
+            .resizable(false)
+            .collapsible(false)
+            .size([300.0, 0.0], Condition::Always)
+            .position([w / 2.0 - 150.0, h / 4.0], Condition::Always)
+            .build(ui, || {
+                let modes: Vec<ImString> = GAME_MODES
+                    .iter()
+                    .map(|m| ImString::new(m.to_string()))
+                    .collect();
+                let modes: Vec<&ImStr> = modes.iter().map(|m| m.as_ref()).collect();
+                ComboBox::new(im_str!("Mode")).build_simple_string(ui, &mut self.mode_id, &modes);
+
+                Slider::new(im_str!("Rounds"), 1..=10).build(ui, &mut self.rounds);
+                ui.separator();
+
+                for (i, name) in self.names.iter_mut().enumerate() {
+                    let id = ui.push_id(i as i32);
+                    ui.input_text(im_str!(""), name).resize_buffer(true).build();
+
+                    if i >= 2 {
+                        ui.same_line(0.0);
+                        if ui.small_button(im_str!("Remove")) {
+                            self.action = Some(TournamentAction::RemovePlayer(i));
+                        }
+                    }
+                    id.pop(ui);
+                }
+
+                if self.names.len() < MAX_PLAYERS && ui.button(im_str!("Add player"), [0.0, 0.0]) {
+                    self.action = Some(TournamentAction::AddPlayer);
+                }
+
+                ui.separator();
+                if ui.button(im_str!("Start"), [270.0, 0.0]) {
+                    self.action = Some(TournamentAction::Start);
+                }
+            });
+    }
+
+    fn standings_ui(&mut self, ui: &Ui, tournament: &Tournament) {
+        let [w, h] = ui.io().display_size;
+        let next = tournament.next_turn();
+
+        Window::new(im_str!("Standings"))
+            .resizable(false)
+            .collapsible(false)
+            .size([360.0, 0.0], Condition::Always)
+            .position([w / 2.0 - 180.0, h / 2.0 + 80.0], Condition::Always)
+            .build(ui, || {
+                ui.text(im_str!(
+                    "{} - {} rounds",
+                    tournament.mode,
+                    tournament.seeds.len()
+                ));
+                ui.separator();
+
+                ui.columns(3, im_str!("standings"), false);
+                for (place, &p) in tournament.standings().iter().enumerate() {
+                    ui.text(im_str!("{}. {}", place + 1, tournament.players[p]));
+                    ui.next_column();
+
+                    let turns: Vec<String> = tournament.turns[p]
+                        .iter()
+                        .map(|t| tournament.turn_text(t))
+                        .collect();
+                    ui.text(turns.join(" / "));
+                    ui.next_column();
+
+                    ui.text(tournament.total_text(p));
+                    ui.next_column();
+                }
+                ui.columns(1, im_str!(""), false);
+                ui.separator();
+
+                match next {
+                    Some((round, player)) => {
+                        ui.text(im_str!(
+                            "Round {}: {} is up",
+                            round + 1,
+                            tournament.players[player]
+                        ));
+
+                        if ui.button(im_str!("Play turn"), [330.0, 0.0]) {
+                            self.action = Some(TournamentAction::PlayTurn);
+                        }
+                    }
+                    None => {
+                        if ui.button(im_str!("Menu"), [330.0, 0.0]) {
+                            self.action = Some(TournamentAction::Menu);
+                        }
+                    }
+                }
+            });
+    }
+
+    fn draw_podium(&self, ctx: &mut Context, tournament: &Tournament) -> GameResult {
+        let screen = graphics::screen_coordinates(ctx);
+        let standings = tournament.standings();
+
+        let width = 200.0;
+        let base = screen.h / 2.0 + 40.0;
+
+        // Second place on the left, winner in the middle, third on the right
+        let steps = [(1, -1.0, 160.0), (0, 0.0, 240.0), (2, 1.0, 100.0)];
+        for &(place, offset, height) in &steps {
+            let player = match standings.get(place) {
+                Some(&player) => player,
+                None => continue,
+            };
+
+            let x = screen.w / 2.0 + offset * width - width / 2.0;
+            let rect = Rect::new(x, base - height, width - 10.0, height);
+            let color = match place {
+                0 => Color::from_rgb(255, 215, 0),
+                1 => Color::from_rgb(192, 192, 192),
+                _ => Color::from_rgb(205, 127, 50),
+            };
+
+            let mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), rect, color)?;
+            graphics::draw(ctx, &mesh, DrawParam::new())?;
+
+            let text = Text::new(TextFragment {
+                text: format!(
+                    "{}\n{}",
+                    tournament.players[player],
+                    tournament.total_text(player)
+                ),
+                scale: Some(Scale::uniform(32.0)),
+                ..Default::default()
+            });
+
+            let position = Point2::new(
+                x + (width - 10.0 - text.width(ctx) as f32) / 2.0,
+                base - height - text.height(ctx) as f32 - 10.0,
+            );
+            graphics::draw(ctx, &text, DrawParam::new().dest(position))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Scene for TournamentScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        Ok(match self.action.take() {
+            Some(TournamentAction::AddPlayer) => {
+                let name = format!("Player {}", self.names.len() + 1);
+                self.names.push(ImString::new(name));
+                Transition::None
+            }
+            Some(TournamentAction::RemovePlayer(i)) => {
+                self.names.remove(i);
+                Transition::None
+            }
+            Some(TournamentAction::Start) => {
+                let players = self.names.iter().map(|n| n.to_string()).collect();
+                let mode = GAME_MODES[self.mode_id];
+                let tournament = Tournament::new(mode, players, self.rounds as usize);
+                self.tournament = Some(Rc::new(RefCell::new(tournament)));
+                Transition::None
+            }
+            Some(TournamentAction::PlayTurn) => match &self.tournament {
+                Some(tournament) => {
+                    Transition::Push(Box::new(TurnScene::new(ctx, g, Rc::clone(tournament))?))
+                }
+                None => Transition::None,
+            },
+            Some(TournamentAction::Menu) => Transition::Switch(Box::new(MenuScene::new())),
+            None => Transition::None,
+        })
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _g: &mut Global) -> GameResult {
+        if let Some(tournament) = &self.tournament {
+            let tournament = tournament.borrow();
+            if tournament.next_turn().is_none() {
+                self.draw_podium(ctx, &tournament)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ui(&mut self, ui: &Ui, _g: &mut Global) {
+        match self.tournament.clone() {
+            Some(tournament) => self.standings_ui(ui, &tournament.borrow()),
+            None => self.setup_ui(ui),
+        }
+    }
+}
+
+/// A single turn of the tournament, records the score and returns to the standings
+struct TurnScene {
+    tournament: Rc<RefCell<Tournament>>,
+    gameplay: Gameplay,
+    player: usize,
+    recorded: bool,
+    finished: bool,
+}
+
+impl TurnScene {
+    fn new(
+        ctx: &mut Context,
+        g: &mut Global,
+        tournament: Rc<RefCell<Tournament>>,
+    ) -> GameResult<TurnScene> {
+        let (round, player) = tournament.borrow().next_turn().unwrap();
+        let seed = tournament.borrow().seeds[round];
+        let mode = tournament.borrow().mode;
+
+        log::info!(
+            "Tournament round {} for {} with seed {}",
+            round + 1,
+            tournament.borrow().players[player],
+            utils::seed_code(&seed)
+        );
+
+        let mut gameplay = Gameplay::new(ctx, g, true, &seed, &mode.rules())?;
+        gameplay.set_controls(g, mode.controls());
+        gameplay.set_win_condition(mode.win_condition());
+
+        Ok(TurnScene {
+            gameplay,
+            tournament,
+            player,
+            recorded: false,
+            finished: false,
+        })
+    }
+
+    fn turn(&self) -> Turn {
+        Turn {
+            score: self.gameplay.score(),
+            time: self.gameplay.won().map(|r| r.time),
+        }
+    }
+
+    fn record(&mut self) {
+        if !self.recorded {
+            self.recorded = true;
+            let turn = self.turn();
+            self.tournament.borrow_mut().turns[self.player].push(turn);
+        }
+    }
+}
+
+impl Scene for TurnScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        self.gameplay.update(ctx, g, true)?;
+
+        if self.finished {
+            self.record();
+            return Ok(Transition::Pop);
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        scene::draw_centered(ctx, g, &mut self.gameplay)
+    }
+
+    fn ui(&mut self, ui: &Ui, _g: &mut Global) {
+        if !self.gameplay.finished() {
+            return;
+        }
+
+        let [w, h] = ui.io().display_size;
+        let name = self.tournament.borrow().players[self.player].clone();
+        let result = self.tournament.borrow().turn_text(&self.turn());
+
+        Window::new(im_str!("Turn over"))
+            .resizable(false)
+            .collapsible(false)
+            .size([200.0, 0.0], Condition::Appearing)
+            .position([w / 2.0 - 100.0, h / 3.0 * 2.0], Condition::Appearing)
+            .build(ui, || {
+                ui.text(im_str!("{}: {}", name, result));
+                ui.separator();
+
+                if ui.button(im_str!("Continue"), [170.0, 0.0]) {
+                    self.finished = true;
+                }
+            });
+    }
+
+    fn gameplay(&mut self) -> Option<&mut Gameplay> {
+        Some(&mut self.gameplay)
+    }
+
+    fn music(&self) -> Track {
+        Track::Gameplay
+    }
+
+    /// Leaving early still counts the score reached so far
+    fn back(&mut self) -> Transition {
+        self.record();
+        Transition::Pop
+    }
+}
+
+#[test]
+fn tournament_turns_test() {
+    let players = vec!["a".to_string(), "b".to_string()];
+    let mut tournament = Tournament::new(GameMode::default(), players, 2);
+
+    let mut turns = vec![];
+    while let Some((round, player)) = tournament.next_turn() {
+        turns.push((round, player));
+        tournament.turns[player].push(Turn {
+            score: (round * 10 + player) as i32,
+            time: None,
+        });
+    }
+
+    assert_eq!(turns, vec![(0, 0), (0, 1), (1, 0), (1, 1)]);
+    assert_eq!(tournament.standings(), vec![1, 0]);
+}
+
+#[test]
+fn tournament_race_standings_test() {
+    let players = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+    let mut tournament = Tournament::new(GameMode::Sprint, players, 2);
+
+    let times = [[Some(60), Some(70)], [Some(50), None], [Some(65), Some(64)]];
+    for (player, times) in times.iter().enumerate() {
+        for time in times {
+            tournament.turns[player].push(Turn {
+                score: 1000 * player as i32,
+                time: time.map(Duration::from_secs),
+            });
+        }
+    }
+
+    // The fastest total wins, unfinished races rank last whatever the score
+    assert_eq!(tournament.standings(), vec![2, 0, 1]);
+    assert_eq!(tournament.total_text(1), "0:50.00 (1 DNF)");
+}
@@ -0,0 +1,203 @@
+use ggez::{
+    graphics::{self, DrawParam, Scale, Text, TextFragment},
+    input::keyboard::KeyCode,
+    nalgebra::Point2,
+    Context, GameResult,
+};
+use imgui::{im_str, Condition, Ui, Window};
+use rand::{thread_rng, RngCore};
+
+use crate::{
+    action::Action,
+    battle::{Battle, Board},
+    gameplay::Gameplay,
+    global::Global,
+    history::{self, MatchRecord},
+    input::{ActionBinding, Binding, Input},
+    music::Track,
+    rules::Rules,
+    scene::{self, battle::draw_forecast, MenuScene, Scene, Transition},
+};
+
+const PLAYER_NAMES: [&str; 2] = ["Left player", "Right player"];
+
+/// Keys of both players, one on each side of the keyboard
+const PLAYER_KEYS: [&[(KeyCode, Action)]; 2] = [
+    &[
+        (KeyCode::D, Action::MoveRight),
+        (KeyCode::A, Action::MoveLeft),
+        (KeyCode::S, Action::MoveDown),
+        (KeyCode::W, Action::RotateClockwise),
+        (KeyCode::Q, Action::RotateCounterClockwise),
+        (KeyCode::Space, Action::HardDrop),
+        (KeyCode::E, Action::HoldPiece),
+        (KeyCode::R, Action::Rotate180),
+    ],
+    &[
+        (KeyCode::Right, Action::MoveRight),
+        (KeyCode::Left, Action::MoveLeft),
+        (KeyCode::Down, Action::MoveDown),
+        (KeyCode::Up, Action::RotateClockwise),
+        (KeyCode::RControl, Action::RotateCounterClockwise),
+        (KeyCode::Return, Action::HardDrop),
+        (KeyCode::RShift, Action::HoldPiece),
+        (KeyCode::Slash, Action::Rotate180),
+    ],
+];
+
+enum VersusAction {
+    Rematch,
+    Menu,
+}
+
+/// Two players on one keyboard, clears send garbage to the other board
+pub struct VersusScene {
+    battle: Battle,
+    /// Started with a rematch, its result goes to the same match of the history
+    rematch: bool,
+    recorded: bool,
+    action: Option<VersusAction>,
+}
+
+impl VersusScene {
+    pub fn new(ctx: &mut Context, g: &mut Global) -> GameResult<VersusScene> {
+        let mut seed = [0u8; 32];
+        thread_rng().fill_bytes(&mut seed);
+
+        let mut boards = vec![];
+        for (team, keys) in PLAYER_KEYS.iter().enumerate() {
+            // Both players get the same pieces
+            let mut gameplay = Gameplay::new(ctx, g, true, &seed, &Rules::default())?;
+
+            let bindings: Vec<ActionBinding> = keys
+                .iter()
+                .map(|&(keycode, action)| ActionBinding {
+                    action,
+                    binding: Binding::Key(keycode),
+                })
+                .collect();
+            gameplay.set_input(Input::from_bindings(&bindings));
+
+            boards.push(Board { gameplay, team });
+        }
+
+        Ok(VersusScene {
+            battle: Battle::new(boards),
+            rematch: false,
+            recorded: false,
+            action: None,
+        })
+    }
+
+    /// Puts the result into the history once the match is over, as seen by the left player
+    fn record(&mut self, g: &mut Global) {
+        let winner = match self.battle.winner() {
+            Some(winner) if !self.recorded => winner,
+            _ => return,
+        };
+
+        let boards = &self.battle.boards;
+        let record = MatchRecord::new(
+            PLAYER_NAMES[1],
+            false,
+            &boards[0].gameplay.engine.rules().name,
+            winner == 0,
+            [
+                history::final_board(&boards[0].gameplay.engine.stack),
+                history::final_board(&boards[1].gameplay.engine.stack),
+            ],
+        );
+
+        g.stats.history.record(record, self.rematch);
+        g.stats.save();
+        self.recorded = true;
+    }
+}
+
+impl Scene for VersusScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        self.battle.update(ctx, g)?;
+        self.record(g);
+
+        Ok(match self.action.take() {
+            Some(VersusAction::Rematch) => {
+                let mut scene = VersusScene::new(ctx, g)?;
+                scene.rematch = true;
+                Transition::Replace(Box::new(scene))
+            }
+            Some(VersusAction::Menu) => Transition::Switch(Box::new(MenuScene::new())),
+            None => Transition::None,
+        })
+    }
+
+    fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        let stack = &self.battle.boards[0].gameplay.engine.stack;
+        let (width, height) = (stack.width, stack.height);
+        let (block_size, positions) = scene::side_by_side(ctx, g, width, height);
+
+        for (i, board) in self.battle.boards.iter_mut().enumerate() {
+            board.gameplay.set_block_size(Some(block_size));
+            board
+                .gameplay
+                .set_attack_target(Some(positions[1 - i] - positions[i]));
+            board.gameplay.draw(ctx, g, positions[i])?;
+        }
+
+        for (i, position) in positions.iter().enumerate() {
+            let below = Point2::new(
+                position[0],
+                position[1] + (height * block_size) as f32 + block_size as f32 * 0.2,
+            );
+            let board = &self.battle.boards[i];
+            draw_forecast(
+                ctx,
+                &board.gameplay,
+                self.battle.forecast(board.team),
+                below,
+                (width * block_size) as f32,
+                block_size as f32,
+            )?;
+
+            let text = Text::new(TextFragment {
+                text: PLAYER_NAMES[i].to_string(),
+                scale: Some(Scale::uniform(block_size as f32 * 1.2)),
+                ..Default::default()
+            });
+            let x = position[0] + ((width * block_size) as f32 - text.width(ctx) as f32) / 2.0;
+            let label = Point2::new(x, position[1] - block_size as f32 * 2.5);
+            graphics::draw(ctx, &text, DrawParam::new().dest(label))?;
+        }
+
+        Ok(())
+    }
+
+    fn ui(&mut self, ui: &Ui, _g: &mut Global) {
+        let winner = match self.battle.winner() {
+            Some(winner) => winner,
+            None => return,
+        };
+
+        let [w, h] = ui.io().display_size;
+        Window::new(im_str!("Versus over"))
+            .resizable(false)
+            .collapsible(false)
+            .size([200.0, 0.0], Condition::Appearing)
+            .position([w / 2.0 - 100.0, h / 3.0 * 2.0], Condition::Appearing)
+            .build(ui, || {
+                ui.text(im_str!("{} wins!", PLAYER_NAMES[winner]));
+                ui.separator();
+
+                if ui.button(im_str!("Rematch"), [170.0, 0.0]) {
+                    self.action = Some(VersusAction::Rematch);
+                }
+
+                if ui.button(im_str!("Menu"), [170.0, 0.0]) {
+                    self.action = Some(VersusAction::Menu);
+                }
+            });
+    }
+
+    fn music(&self) -> Track {
+        Track::Gameplay
+    }
+}
@@ -0,0 +1,263 @@
+use std::{path::PathBuf, time::Duration};
+
+use ggez::{input::keyboard::KeyCode, Context, GameResult};
+use imgui::{im_str, Condition, ImString, Slider, Ui, Window};
+
+use crate::{
+    coach::Mistake,
+    gameplay::Gameplay,
+    global::Global,
+    music::Track,
+    replay::{Replay, ReplayData, Timeline, SPEEDS},
+    scene::{self, Scene, Transition},
+};
+
+pub struct WatchScene {
+    replay: Replay,
+    timeline: Timeline,
+    /// File the replay came from, where the markers are saved
+    path: Option<PathBuf>,
+    marker: ImString,
+    add_marker: bool,
+    save: bool,
+    /// Placements found by the coach, listed for review
+    mistakes: Vec<Mistake>,
+    /// Second to jump to
+    jump: i32,
+    toggle_pause: bool,
+    speed: Option<u32>,
+}
+
+impl WatchScene {
+    pub fn new(
+        ctx: &mut Context,
+        g: &mut Global,
+        replay_data: ReplayData,
+    ) -> GameResult<WatchScene> {
+        let mut replay = Replay::new(ctx, g, replay_data)?;
+        let timeline = replay.timeline(g);
+
+        Ok(WatchScene {
+            replay,
+            timeline,
+            path: None,
+            marker: ImString::with_capacity(64),
+            add_marker: false,
+            save: false,
+            mistakes: vec![],
+            jump: 0,
+            toggle_pause: false,
+            speed: None,
+        })
+    }
+
+    /// Seeks by some seconds from where the playback is
+    fn skip(&mut self, seconds: f32) {
+        let position = (self.timeline.position + seconds).max(0.0);
+        self.timeline.seek = Some(position.min(self.timeline.length));
+    }
+
+    pub fn set_mistakes(&mut self, mistakes: Vec<Mistake>) {
+        self.mistakes = mistakes;
+    }
+
+    pub fn set_path(&mut self, path: Option<PathBuf>) {
+        self.path = path;
+    }
+
+    /// Writes the replay back with its markers, replays that weren't saved yet get a new file
+    fn save(&mut self) {
+        let path = self
+            .path
+            .get_or_insert_with(|| ReplayData::new_path("Replay"));
+
+        self.replay.replay_data().save(path);
+    }
+}
+
+impl Scene for WatchScene {
+    fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult<Transition> {
+        if let Some(seek) = self.timeline.seek.take() {
+            self.replay.seek(g, Duration::from_secs_f32(seek));
+        }
+
+        if self.toggle_pause {
+            self.toggle_pause = false;
+            self.replay.set_paused(!self.replay.paused());
+        }
+
+        if let Some(speed) = self.speed.take() {
+            self.replay.set_speed(speed);
+        }
+
+        self.replay.play(ctx, g)?;
+
+        if self.add_marker && !self.marker.to_str().trim().is_empty() {
+            self.replay
+                .add_marker(self.marker.to_str().trim().to_string());
+            self.timeline
+                .set_markers(&self.replay.replay_data().markers);
+            self.marker.clear();
+        }
+        self.add_marker = false;
+
+        if self.save {
+            self.save = false;
+            self.save();
+        }
+
+        self.timeline.position = self.replay.elapsed().as_secs_f32();
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+        scene::draw_centered(ctx, g, &mut self.replay.gameplay)
+    }
+
+    fn ui(&mut self, ui: &Ui, _g: &mut Global) {
+        let [w, h] = ui.io().display_size;
+        let timeline = &mut self.timeline;
+        let marker = &mut self.marker;
+        let add_marker = &mut self.add_marker;
+        let save = &mut self.save;
+        let mistakes = &self.mistakes;
+        let jump = &mut self.jump;
+        let toggle_pause = &mut self.toggle_pause;
+        let requested_speed = &mut self.speed;
+        let (paused, speed) = (self.replay.paused(), self.replay.speed());
+
+        Window::new(im_str!("Replay"))
+            .size([520.0, 0.0], Condition::Appearing)
+            .position([w / 2.0 - 260.0, h - 240.0], Condition::Appearing)
+            .build(ui, || {
+                ui.plot_histogram(im_str!("##density"), &timeline.density)
+                    .graph_size([490.0, 60.0])
+                    .build();
+                ui.plot_histogram(im_str!("##markers"), &timeline.marker_lane)
+                    .graph_size([490.0, 8.0])
+                    .scale_min(0.0)
+                    .scale_max(1.0)
+                    .build();
+
+                let mut position = timeline.position;
+                let id = ui.push_id(im_str!("position"));
+                if Slider::new(im_str!(""), 0.0..=timeline.length)
+                    .display_format(im_str!("%.1f s"))
+                    .build(ui, &mut position)
+                {
+                    timeline.seek = Some(position);
+                }
+                id.pop(ui);
+
+                let label = if paused {
+                    im_str!("Play")
+                } else {
+                    im_str!("Pause")
+                };
+                if ui.small_button(label) {
+                    *toggle_pause = true;
+                }
+
+                for &s in SPEEDS.iter() {
+                    ui.same_line(0.0);
+                    let label = if s == speed {
+                        im_str!("[{}x]", s)
+                    } else {
+                        im_str!("{}x", s)
+                    };
+                    if ui.small_button(&label) {
+                        *requested_speed = Some(s);
+                    }
+                }
+
+                ui.input_int(im_str!("seconds"), jump).build();
+                ui.same_line(0.0);
+                if ui.small_button(im_str!("Jump")) {
+                    let second = (*jump).max(0) as f32;
+                    timeline.seek = Some(second.min(timeline.length));
+                }
+
+                ui.separator();
+                ui.text(im_str!("Line clears"));
+
+                for (i, &(time, rows)) in timeline.bookmarks.iter().enumerate() {
+                    if i % 8 != 0 {
+                        ui.same_line(0.0);
+                    }
+
+                    let id = ui.push_id(i as i32);
+                    let label = im_str!("{:.0}s x{}", time, rows);
+                    if ui.small_button(&label) {
+                        timeline.seek = Some(time);
+                    }
+                    id.pop(ui);
+                }
+
+                ui.separator();
+                ui.text(im_str!("Markers"));
+
+                for (i, (time, text)) in timeline.markers.iter().enumerate() {
+                    let id = ui.push_id(-1 - i as i32);
+                    let label = im_str!("{:.1}s {}", time, text);
+                    if ui.small_button(&label) {
+                        // Slightly before, so that the marker pops up again
+                        timeline.seek = Some((time - 0.5).max(0.0));
+                    }
+                    id.pop(ui);
+                }
+
+                ui.input_text(im_str!("##marker"), marker)
+                    .resize_buffer(true)
+                    .build();
+                ui.same_line(0.0);
+                if ui.small_button(im_str!("Add marker")) {
+                    *add_marker = true;
+                }
+                ui.same_line(0.0);
+                if ui.small_button(im_str!("Save")) {
+                    *save = true;
+                }
+            });
+
+        if mistakes.is_empty() {
+            return;
+        }
+
+        Window::new(im_str!("Review"))
+            .size([220.0, 0.0], Condition::Appearing)
+            .position([w / 2.0 + 280.0, h - 240.0], Condition::Appearing)
+            .build(ui, || {
+                ui.text(im_str!("Mistakes: {}", mistakes.len()));
+                ui.separator();
+
+                for (i, mistake) in mistakes.iter().enumerate() {
+                    let time = mistake.time.as_secs_f32();
+                    let id = ui.push_id(i as i32);
+                    let label = im_str!("{:.1}s {}", time, mistake.description());
+                    if ui.small_button(&label) {
+                        // Shortly before the lock, to see the piece being placed
+                        timeline.seek = Some((time - 1.5).max(0.0));
+                    }
+                    id.pop(ui);
+                }
+            });
+    }
+
+    fn gameplay(&mut self) -> Option<&mut Gameplay> {
+        Some(&mut self.replay.gameplay)
+    }
+
+    fn key_down(&mut self, _g: &mut Global, keycode: KeyCode) {
+        match keycode {
+            KeyCode::Space => self.toggle_pause = true,
+            KeyCode::Left => self.skip(-5.0),
+            KeyCode::Right => self.skip(5.0),
+            _ => (),
+        }
+    }
+
+    fn music(&self) -> Track {
+        Track::Gameplay
+    }
+}
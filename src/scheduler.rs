@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+
+use crate::{
+    bot::Bot,
+    engine::{Engine, Event},
+    rules::Rules,
+    settings,
+};
+
+/// Bot playing a board without any graphics or audio
+pub struct Board {
+    pub engine: Engine,
+    bot: Bot,
+    events: Vec<Event>,
+}
+
+impl Board {
+    fn update(&mut self, settings: &settings::Gameplay, dt: Duration) {
+        self.events.clear();
+        if self.engine.game_over() {
+            return;
+        }
+
+        if let Some(action) = self.bot.update(&self.engine, dt) {
+            self.engine.action(action, false);
+        }
+
+        while let Some(action) = self.engine.pop_action() {
+            if !self.engine.process(settings, action) {
+                break;
+            }
+        }
+
+        // Nobody watches the animations, so cleared rows disappear right away
+        self.engine.stack.skip_animations();
+        self.engine.step(settings, dt);
+        self.engine.events(&mut self.events);
+    }
+
+    /// Events of the last tick
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Garbage sent during the last tick
+    pub fn attack(&self) -> i32 {
+        self.events
+            .iter()
+            .map(|e| match *e {
+                Event::Locked { attack, .. }
+                | Event::Chain { attack, .. }
+                | Event::ZoneEnded { attack, .. } => attack,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Pieces locked without clearing during the last tick
+    pub fn clean_locks(&self) -> u32 {
+        self.events
+            .iter()
+            .filter(|e| match e {
+                Event::Locked { rows: 0, .. } => true,
+                _ => false,
+            })
+            .count() as u32
+    }
+}
+
+/// Updates many headless boards every tick, spread over a thread pool.
+/// Boards only depend on their seeds, so the results don't depend on the number of threads.
+pub struct Scheduler {
+    boards: Vec<Board>,
+    settings: settings::Gameplay,
+    pool: Option<ThreadPool>,
+}
+
+impl Scheduler {
+    /// Zero threads picks one per CPU core
+    pub fn new(threads: usize) -> Scheduler {
+        let pool = match ThreadPoolBuilder::new().num_threads(threads).build() {
+            Ok(pool) => Some(pool),
+            Err(e) => {
+                log::error!(
+                    "Unable to create the thread pool, boards will run on one thread: {:?}",
+                    e
+                );
+                None
+            }
+        };
+
+        Scheduler {
+            boards: vec![],
+            settings: settings::Gameplay::default(),
+            pool,
+        }
+    }
+
+    /// Adds a board and returns its index
+    pub fn add(&mut self, seed: &[u8; 32], rules: &Rules, action_delay: Duration) -> usize {
+        self.boards.push(Board {
+            engine: Engine::new(seed, true, rules),
+            bot: Bot::new(action_delay),
+            events: vec![],
+        });
+
+        self.boards.len() - 1
+    }
+
+    pub fn boards(&self) -> &[Board] {
+        &self.boards
+    }
+
+    pub fn board_mut(&mut self, id: usize) -> Option<&mut Board> {
+        self.boards.get_mut(id)
+    }
+
+    /// Number of boards still playing
+    pub fn alive(&self) -> usize {
+        self.boards.iter().filter(|b| !b.engine.game_over()).count()
+    }
+
+    pub fn tick(&mut self, dt: Duration) {
+        let settings = &self.settings;
+        let boards = &mut self.boards;
+
+        match &self.pool {
+            Some(pool) => pool.install(|| {
+                boards.par_iter_mut().for_each(|b| b.update(settings, dt));
+            }),
+            None => boards.iter_mut().for_each(|b| b.update(settings, dt)),
+        }
+    }
+
+    /// Events of the last tick from every board, in the order of the boards
+    pub fn events(&self) -> impl Iterator<Item = (usize, Event)> + '_ {
+        self.boards
+            .iter()
+            .enumerate()
+            .flat_map(|(id, b)| b.events.iter().map(move |&e| (id, e)))
+    }
+}
+
+#[test]
+fn scheduler_test() {
+    let rules = Rules::default();
+    let mut single = Scheduler::new(1);
+    let mut parallel = Scheduler::new(4);
+
+    for i in 0..8 {
+        let seed = [i; 32];
+        single.add(&seed, &rules, Duration::from_millis(50));
+        parallel.add(&seed, &rules, Duration::from_millis(50));
+    }
+
+    for _ in 0..600 {
+        single.tick(Duration::from_millis(16));
+        parallel.tick(Duration::from_millis(16));
+        assert!(single.events().eq(parallel.events()));
+    }
+
+    for (a, b) in single.boards().iter().zip(parallel.boards()) {
+        assert_eq!(a.engine.stack.grid(), b.engine.stack.grid());
+        assert_eq!(a.engine.pieces(), b.engine.pieces());
+    }
+    assert!(single.boards().iter().any(|b| b.engine.pieces() > 1));
+}
@@ -4,9 +4,12 @@ use ggez::{
     Context, GameResult,
 };
 
-#[derive(Default)]
+use crate::utils::Fnv64;
+
+#[derive(Default, Clone)]
 pub struct Score {
     score: i32,
+    lines: i32,
     last_clear: i32,
     combo: Option<i32>,
     btb: bool,
@@ -17,6 +20,18 @@ impl Score {
         self.score
     }
 
+    pub fn lines(&self) -> i32 {
+        self.lines
+    }
+
+    pub fn hash(&self, hash: &mut Fnv64) {
+        hash.write(self.score as u64);
+        hash.write(self.lines as u64);
+        hash.write(self.last_clear as u64);
+        hash.write(self.combo.map_or(u64::max_value(), |c| c as u64));
+        hash.write(self.btb as u64);
+    }
+
     pub fn soft_drop(&mut self, rows: i32) {
         self.score += rows;
     }
@@ -25,6 +40,10 @@ impl Score {
         self.score += rows * 2;
     }
 
+    pub fn undo_hard_drop(&mut self, rows: i32) {
+        self.score -= rows * 2;
+    }
+
     pub fn reset_combo(&mut self) {
         self.combo = None;
     }
@@ -37,9 +56,11 @@ impl Score {
         self.combo
     }
 
-    pub fn lock(&mut self, rows: i32, t_spin: bool) {
+    /// Returns the number of garbage lines the clear sends to the opponent.
+    /// The score of the clear is multiplied, usually by the level.
+    pub fn lock(&mut self, rows: i32, t_spin: bool, multiplier: i32) -> i32 {
         let mut score = 0;
-        let mut _garbage = 0;
+        let mut garbage = 0;
 
         // For back-to-back
         let last_hard = self.last_clear >= 800;
@@ -47,38 +68,38 @@ impl Score {
         match (rows, t_spin) {
             (1, false) => {
                 score = 100;
-                _garbage = 0;
+                garbage = 0;
             }
             (1, true) => {
                 score = 800;
-                _garbage = 2;
+                garbage = 2;
             }
             (2, false) => {
                 score = 300;
-                _garbage = 1;
+                garbage = 1;
             }
             (2, true) => {
                 score = 1200;
-                _garbage = 4;
+                garbage = 4;
             }
             (3, false) => {
                 score = 500;
-                _garbage = 3;
+                garbage = 3;
             }
             (3, true) => {
                 score = 1600;
-                _garbage = 6;
+                garbage = 6;
             }
             (4, false) => {
                 score = 800;
-                _garbage = 4;
+                garbage = 4;
             }
             _ => (),
         }
 
         self.btb = false;
         if last_hard {
-            _garbage += 1;
+            garbage += 1;
 
             if score >= 800 {
                 self.btb = true;
@@ -94,7 +115,37 @@ impl Score {
         }
 
         self.last_clear = score;
-        self.score += score;
+        self.score += score * multiplier;
+        self.lines += rows;
+
+        garbage
+    }
+
+    /// Bonus for the rows cleared by the cascade after a clear, given in every link of the chain,
+    /// the further links being worth more. Returns the number of garbage lines sent.
+    pub fn chain(&mut self, links: &[i32]) -> i32 {
+        let mut garbage = 0;
+
+        // The clear starting the chain is the first link
+        for (link, &rows) in (2..).zip(links) {
+            self.score += 100 * rows * link;
+            self.lines += rows;
+            garbage += rows + link - 2;
+        }
+
+        garbage
+    }
+
+    /// Bonus for the rows cleared at once when the zone ends, four of them score like a tetris.
+    /// Returns the number of garbage lines sent.
+    pub fn zone(&mut self, rows: i32) -> i32 {
+        if rows <= 0 {
+            return 0;
+        }
+
+        self.score += 50 * rows * rows;
+        self.lines += rows;
+        rows / 2
     }
 
     pub fn draw(
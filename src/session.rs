@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use imgui::{im_str, Condition, Ui, Window};
+
+/// Time away from the game that counts as a break, resetting the play time
+const BREAK_LENGTH: Duration = Duration::from_secs(5 * 60);
+
+/// Continuous play time since the game was started, kept across restarts
+#[derive(Default)]
+pub struct Session {
+    played: Duration,
+    away: Duration,
+    since_reminder: Duration,
+}
+
+impl Session {
+    pub fn played(&self) -> Duration {
+        self.played
+    }
+
+    /// Only running games count as play time.
+    /// Returns true when it's time to remind about taking a break.
+    pub fn update(&mut self, dt: Duration, playing: bool, reminder: Option<Duration>) -> bool {
+        if !playing {
+            self.away += dt;
+            if self.away >= BREAK_LENGTH {
+                self.played = Duration::new(0, 0);
+                self.since_reminder = Duration::new(0, 0);
+            }
+            return false;
+        }
+
+        self.away = Duration::new(0, 0);
+        self.played += dt;
+        self.since_reminder += dt;
+
+        match reminder {
+            Some(reminder) if self.since_reminder >= reminder => {
+                self.since_reminder = Duration::new(0, 0);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Play time in the corner of the screen
+    pub fn ui(&self, ui: &Ui) {
+        let [w, _] = ui.io().display_size;
+        Window::new(im_str!("Session"))
+            .title_bar(false)
+            .resizable(false)
+            .movable(false)
+            .always_auto_resize(true)
+            .bg_alpha(0.2)
+            .position([w - 90.0, 10.0], Condition::Always)
+            .build(ui, || {
+                ui.text_colored([0.8, 0.9, 1.0, 0.6], &im_str!("{}", clock(self.played)));
+            });
+    }
+}
+
+/// Hours, minutes and seconds, the hours only once there are any
+fn clock(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds / 3600 {
+        0 => format!("{:02}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+#[test]
+fn session_test() {
+    let mut session = Session::default();
+    let reminder = Some(Duration::from_secs(60));
+
+    assert!(!session.update(Duration::from_secs(50), true, reminder));
+    assert!(!session.update(Duration::from_secs(60), false, reminder));
+    assert!(session.update(Duration::from_secs(10), true, reminder));
+    assert_eq!(clock(session.played()), "01:00");
+
+    assert!(!session.update(Duration::from_secs(3600), true, None));
+    assert_eq!(clock(session.played()), "1:01:00");
+
+    session.update(BREAK_LENGTH, false, reminder);
+    assert_eq!(session.played(), Duration::new(0, 0));
+}
@@ -6,17 +6,34 @@ use imgui::{self, im_str, ComboBox, FontId, ImStr, ImString, Slider, Ui};
 use serde::{Deserialize, Serialize};
 use toml;
 
-use crate::utils;
-
-#[derive(Serialize, Deserialize)]
+use crate::{
+    assets::Assets,
+    engine::{LockMode, LOCK_MODES},
+    handicap::Handicaps,
+    hints::Hint,
+    hud::HudLayout,
+    input::{ActionBinding, ControlPreset, CONTROL_PRESETS},
+    macros::Macro,
+    metronome::{MetronomeMode, MAX_BPM, METRONOME_MODES, MIN_BPM},
+    monochrome,
+    postprocess::PostEffects,
+    sfx::{SoundMix, SOUNDS},
+    stack::{BorderStyle, ClearDelays, TopOut, BORDER_STYLES},
+};
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Settings {
     pub graphics: Graphics,
     pub gameplay: Gameplay,
     pub audio: Audio,
     pub input: Input,
+    pub hud: HudLayout,
+    pub feed: Feed,
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Graphics {
     pub window_size: (u32, u32),
     pub fullscreen: bool,
@@ -24,30 +41,111 @@ pub struct Graphics {
     pub animated_background: bool,
     pub hide_menu: bool,
     pub multi_sampling: NumSamples,
+    pub border: BorderStyle,
+    /// Play time of the session in the corner of the screen
+    pub session_timer: bool,
+    /// Clean picture for broadcasting, without any windows and camera movement
+    pub capture_mode: bool,
+    /// Solid background in capture mode, easy to key out
+    pub chroma_key: bool,
+    pub chroma_color: [u8; 3],
+    pub post_effects: PostEffects,
+    pub camera: bool,
+    pub profiler: bool,
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Gameplay {
+    /// Fits the blocks to the window, ignoring the block size
+    pub auto_block_size: bool,
     pub block_size: i32,
     pub ghost_piece: u32,
+    pub hold_preview: bool,
     pub entry_delay: u32,
     pub lock_delay: u32,
+    pub lock_mode: LockMode,
+    pub soft_drop_lock_delay: u32,
     pub clear_delay: u32,
+    /// Uses the clear delays below instead of the single clear delay
+    pub clear_delay_per_size: bool,
+    /// Time after a hard drop during which it can be undone in casual games, 0 disables it
+    pub undo_grace: u32,
+    /// Time for confirming a hard drop with a second press, 0 drops on the first one
+    pub confirm_drop: u32,
     pub skin: String,
+    /// Generated gray blocks with a pattern per piece instead of the skin
+    pub monochrome: bool,
     pub stack_grid: bool,
     pub stack_outline: bool,
+    pub vanish_rows: u32,
+    pub zone: bool,
+    /// Tips for new players, shown the first time they apply
+    pub hints: bool,
+    pub hints_seen: Vec<Hint>,
+    /// Minutes of play between reminders to take a break, 0 disables them
+    pub break_reminder: u32,
+    /// Writes every finished game into the replays directory
+    pub save_replays: bool,
+    /// Seconds an online match waits for a dropped connection to come back, 0 ends it at once
+    pub reconnect_window: u32,
+    /// Name the other player sees in online matches, and the match history keeps
+    pub player_name: String,
+    pub top_out: TopOut,
+    pub clear_delays: ClearDelays,
+    pub handicaps: Handicaps,
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Audio {
     pub music_volume: u32,
     pub sfx_volume: u32,
+    pub sfx_voices: u32,
+    pub music_fade_in: u32,
+    pub music_fade_out: u32,
+    pub metronome: MetronomeMode,
+    pub metronome_bpm: u32,
+    /// Sounds muted or made quieter, the rest plays at the SFX volume
+    pub sounds: SoundMix,
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Input {
     pub das: u32,
     pub arr: u32,
+    pub preserve_das: bool,
+    pub buffer_inputs: bool,
+    pub mouse: bool,
+    pub low_latency: bool,
+    /// Hard drops pressed before the piece spawned are ignored
+    pub hard_drop_fresh_press: bool,
+    /// Shortest time between hard drops, in milliseconds
+    pub hard_drop_interval: u32,
+    /// Preset the bindings started from
+    pub preset: ControlPreset,
+    /// Pieces keep sinking at soft drop speed without holding a key
+    pub auto_soft_drop: bool,
+    /// Soft drop speed with the gamepad stick pushed all the way down,
+    /// in multiples of gravity, 0 ignores the stick
+    pub analog_soft_drop: u32,
+    /// Seconds without input after which single player games pause, 0 disables it
+    pub idle_pause: u32,
+    pub bindings: Vec<ActionBinding>,
+    pub macros: Vec<Macro>,
+}
+
+/// Live stats sent to stream overlays as JSON datagrams
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Feed {
+    pub enabled: bool,
+    pub address: String,
+    /// Milliseconds between the updates
+    pub interval: u32,
+    /// Milliseconds the frames are held back, so that viewers can't pass the board on live
+    pub delay: u32,
 }
 
 #[derive(Default)]
@@ -57,6 +155,8 @@ pub struct SettingsState {
     pub skin_id: usize,
     pub skin_switched: bool,
     pub restart: bool,
+    /// HUD elements can be dragged around the board
+    pub edit_hud: bool,
 }
 
 static SAMPLINGS: [NumSamples; 6] = [
@@ -68,38 +168,122 @@ static SAMPLINGS: [NumSamples; 6] = [
     NumSamples::Sixteen,
 ];
 
-impl Settings {
-    pub fn new() -> Settings {
-        if let Some(settings) = Settings::load() {
-            settings
+impl Default for Graphics {
+    fn default() -> Graphics {
+        Graphics {
+            window_size: (800, 800),
+            fullscreen: false,
+            multi_sampling: NumSamples::Zero,
+            vsync: true,
+            animated_background: true,
+            hide_menu: false,
+            border: BorderStyle::default(),
+            session_timer: true,
+            capture_mode: false,
+            chroma_key: false,
+            chroma_color: [0, 177, 64],
+            post_effects: PostEffects::default(),
+            camera: false,
+            profiler: false,
+        }
+    }
+}
+
+impl Default for Gameplay {
+    fn default() -> Gameplay {
+        Gameplay {
+            auto_block_size: true,
+            block_size: 43,
+            ghost_piece: 10,
+            hold_preview: false,
+            entry_delay: 0,
+            lock_delay: 500,
+            lock_mode: LockMode::Delay,
+            soft_drop_lock_delay: 150,
+            clear_delay: 250,
+            clear_delay_per_size: false,
+            undo_grace: 0,
+            confirm_drop: 0,
+            skin: String::from("nblox.png"),
+            monochrome: false,
+            stack_grid: true,
+            stack_outline: true,
+            vanish_rows: 0,
+            zone: false,
+            hints: true,
+            hints_seen: vec![],
+            break_reminder: 0,
+            save_replays: true,
+            reconnect_window: 30,
+            player_name: String::from("Player"),
+            top_out: TopOut::default(),
+            clear_delays: ClearDelays::default(),
+            handicaps: Handicaps::default(),
+        }
+    }
+}
+
+impl Gameplay {
+    pub fn clear_delays(&self) -> ClearDelays {
+        if self.clear_delay_per_size {
+            self.clear_delays
         } else {
-            Settings {
-                graphics: Graphics {
-                    window_size: (800, 800),
-                    fullscreen: false,
-                    multi_sampling: NumSamples::Zero,
-                    vsync: true,
-                    animated_background: true,
-                    hide_menu: false,
-                },
-                gameplay: Gameplay {
-                    block_size: 43,
-                    ghost_piece: 10,
-                    entry_delay: 0,
-                    lock_delay: 500,
-                    clear_delay: 250,
-                    skin: String::from("nblox.png"),
-                    stack_grid: true,
-                    stack_outline: true,
-                },
-                audio: Audio {
-                    music_volume: 50,
-                    sfx_volume: 50,
-                },
-                input: Input { das: 133, arr: 33 },
-            }
+            ClearDelays::flat(self.clear_delay)
         }
     }
+}
+
+impl Default for Audio {
+    fn default() -> Audio {
+        Audio {
+            music_volume: 50,
+            sfx_volume: 50,
+            sfx_voices: 8,
+            music_fade_in: 500,
+            music_fade_out: 1000,
+            metronome: MetronomeMode::default(),
+            metronome_bpm: 120,
+            sounds: SoundMix::new(),
+        }
+    }
+}
+
+impl Default for Feed {
+    fn default() -> Feed {
+        Feed {
+            enabled: false,
+            address: String::from("127.0.0.1:34000"),
+            interval: 100,
+            delay: 0,
+        }
+    }
+}
+
+impl Default for Input {
+    fn default() -> Input {
+        Input {
+            das: 133,
+            arr: 33,
+            preserve_das: false,
+            buffer_inputs: false,
+            mouse: false,
+            low_latency: false,
+            hard_drop_fresh_press: false,
+            hard_drop_interval: 0,
+            preset: ControlPreset::default(),
+            auto_soft_drop: false,
+            analog_soft_drop: 20,
+            idle_pause: 30,
+            bindings: ControlPreset::default().bindings(),
+            macros: vec![],
+        }
+    }
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings::load().unwrap_or_default()
+    }
 
     fn path() -> PathBuf {
         let mut path = dirs::data_local_dir().unwrap_or_default();
@@ -132,11 +316,21 @@ impl Settings {
         None
     }
 
-    pub fn tileset(&self, ctx: &mut Context, state: &SettingsState) -> GameResult<Image> {
-        Image::new(
-            ctx,
-            utils::path(ctx, state.skins[state.skin_id].to_str().unwrap()),
-        )
+    pub fn tileset(
+        &self,
+        ctx: &mut Context,
+        state: &SettingsState,
+        assets: &mut Assets,
+    ) -> GameResult<Image> {
+        if self.gameplay.monochrome {
+            // Drawn at the block size, so that larger blocks get a denser pattern
+            let size = self.gameplay.block_size.max(0) as u16;
+            return assets.generated_image(ctx, &format!("monochrome:{}", size), |ctx| {
+                monochrome::tileset(ctx, size)
+            });
+        }
+
+        assets.image(ctx, state.skins[state.skin_id].to_str().unwrap())
     }
 
     pub fn draw(&mut self, state: &mut SettingsState, ui: &Ui, bold: FontId) {
@@ -203,6 +397,75 @@ impl Settings {
                 }
                 id.pop(&ui);
 
+                ui.text(im_str!("Border"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("border"));
+                let mut border_id = BORDER_STYLES
+                    .iter()
+                    .position(|&b| b == self.graphics.border)
+                    .unwrap_or(0);
+                let borders: Vec<ImString> = BORDER_STYLES
+                    .iter()
+                    .map(|b| ImString::new(b.name()))
+                    .collect();
+                let borders: Vec<&ImStr> = borders.iter().map(|b| b.as_ref()).collect();
+                if ComboBox::new(im_str!("")).build_simple_string(&ui, &mut border_id, &borders) {
+                    self.graphics.border = BORDER_STYLES[border_id];
+                }
+                id.pop(&ui);
+
+                ui.text(im_str!("HUD layout"));
+                ui.same_line(pos);
+                ui.checkbox(im_str!("Edit"), &mut state.edit_hud);
+                ui.same_line(0.0);
+                if ui.small_button(im_str!("Reset")) {
+                    self.hud = HudLayout::default();
+                }
+
+                ui.text(im_str!("Camera"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("camera"));
+                ui.checkbox(im_str!(""), &mut self.graphics.camera);
+                id.pop(&ui);
+
+                ui.text(im_str!("Profiler"));
+                ui.same_line(pos);
+                ui.checkbox(im_str!("<F3>"), &mut self.graphics.profiler);
+
+                ui.text(im_str!("Capture mode"));
+                ui.same_line(pos);
+                ui.checkbox(im_str!("<F10>"), &mut self.graphics.capture_mode);
+
+                ui.text(im_str!("Chroma key"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("chroma_key"));
+                ui.checkbox(im_str!("in capture mode"), &mut self.graphics.chroma_key);
+                id.pop(&ui);
+
+                ui.text(im_str!("Session timer"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("session_timer"));
+                ui.checkbox(im_str!(""), &mut self.graphics.session_timer);
+                id.pop(&ui);
+
+                ui.text(im_str!("Scanlines"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("scanlines"));
+                ui.checkbox(im_str!(""), &mut self.graphics.post_effects.scanlines);
+                id.pop(&ui);
+
+                ui.text(im_str!("Bloom"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("bloom"));
+                ui.checkbox(im_str!(""), &mut self.graphics.post_effects.bloom);
+                id.pop(&ui);
+
+                ui.text(im_str!("Aberration"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("aberration"));
+                ui.checkbox(im_str!(""), &mut self.graphics.post_effects.aberration);
+                id.pop(&ui);
+
                 if restart_popup {
                     ui.open_popup(im_str!("Restart needed"));
                 }
@@ -221,12 +484,26 @@ impl Settings {
                 Slider::new(im_str!(""), 0..=100).build(&ui, &mut self.gameplay.ghost_piece);
                 id.pop(&ui);
 
+                ui.text(im_str!("Hold preview"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("hold_preview"));
+                ui.checkbox(im_str!(""), &mut self.gameplay.hold_preview);
+                id.pop(&ui);
+
                 ui.text(im_str!("Block size"));
                 ui.same_line(pos);
-                let id = ui.push_id(im_str!("block_size"));
-                Slider::new(im_str!(""), 24..=43).build(&ui, &mut self.gameplay.block_size);
+                let id = ui.push_id(im_str!("auto_block_size"));
+                ui.checkbox(im_str!("Fit to window"), &mut self.gameplay.auto_block_size);
                 id.pop(&ui);
 
+                if !self.gameplay.auto_block_size {
+                    ui.text(im_str!("Manual size"));
+                    ui.same_line(pos);
+                    let id = ui.push_id(im_str!("block_size"));
+                    Slider::new(im_str!(""), 24..=43).build(&ui, &mut self.gameplay.block_size);
+                    id.pop(&ui);
+                }
+
                 ui.text(im_str!("Entry delay"));
                 ui.same_line(pos);
                 let id = ui.push_id(im_str!("entry_delay"));
@@ -239,10 +516,89 @@ impl Settings {
                 Slider::new(im_str!(""), 0..=1000).build(&ui, &mut self.gameplay.lock_delay);
                 id.pop(&ui);
 
-                ui.text(im_str!("Clear delay"));
+                ui.text(im_str!("Lock mode"));
                 ui.same_line(pos);
-                let id = ui.push_id(im_str!("clear_delay"));
-                Slider::new(im_str!(""), 0..=500).build(&ui, &mut self.gameplay.clear_delay);
+                let mut lock_mode_id = LOCK_MODES
+                    .iter()
+                    .position(|&m| m == self.gameplay.lock_mode)
+                    .unwrap();
+                let lock_modes: Vec<ImString> =
+                    LOCK_MODES.iter().map(|m| ImString::new(m.name())).collect();
+                let lock_modes: Vec<&ImStr> = lock_modes.iter().map(|m| m.as_ref()).collect();
+                let id = ui.push_id(im_str!("lock_mode"));
+                if ComboBox::new(im_str!("")).build_simple_string(
+                    &ui,
+                    &mut lock_mode_id,
+                    &lock_modes,
+                ) {
+                    self.gameplay.lock_mode = LOCK_MODES[lock_mode_id];
+                }
+                id.pop(&ui);
+
+                if self.gameplay.lock_mode == LockMode::SoftDropFast {
+                    ui.text(im_str!("Soft drop lock"));
+                    ui.same_line(pos);
+                    let id = ui.push_id(im_str!("soft_drop_lock_delay"));
+                    Slider::new(im_str!(""), 0..=1000)
+                        .build(&ui, &mut self.gameplay.soft_drop_lock_delay);
+                    id.pop(&ui);
+                }
+
+                if !self.gameplay.clear_delay_per_size {
+                    ui.text(im_str!("Clear delay"));
+                    ui.same_line(pos);
+                    let id = ui.push_id(im_str!("clear_delay"));
+                    Slider::new(im_str!(""), 0..=500).build(&ui, &mut self.gameplay.clear_delay);
+                    id.pop(&ui);
+                }
+
+                ui.text(im_str!("Delay per clear"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("clear_delay_per_size"));
+                ui.checkbox(im_str!(""), &mut self.gameplay.clear_delay_per_size);
+                id.pop(&ui);
+
+                if self.gameplay.clear_delay_per_size {
+                    ui.text(im_str!("Single"));
+                    ui.same_line(pos);
+                    let id = ui.push_id(im_str!("clear_delay_single"));
+                    Slider::new(im_str!(""), 0..=1000)
+                        .build(&ui, &mut self.gameplay.clear_delays.single);
+                    id.pop(&ui);
+
+                    ui.text(im_str!("Double"));
+                    ui.same_line(pos);
+                    let id = ui.push_id(im_str!("clear_delay_double"));
+                    Slider::new(im_str!(""), 0..=1000)
+                        .build(&ui, &mut self.gameplay.clear_delays.double);
+                    id.pop(&ui);
+
+                    ui.text(im_str!("Triple"));
+                    ui.same_line(pos);
+                    let id = ui.push_id(im_str!("clear_delay_triple"));
+                    Slider::new(im_str!(""), 0..=1000)
+                        .build(&ui, &mut self.gameplay.clear_delays.triple);
+                    id.pop(&ui);
+
+                    ui.text(im_str!("Tetris"));
+                    ui.same_line(pos);
+                    let id = ui.push_id(im_str!("clear_delay_quad"));
+                    Slider::new(im_str!(""), 0..=1000)
+                        .build(&ui, &mut self.gameplay.clear_delays.quad);
+                    id.pop(&ui);
+                }
+
+                ui.text(im_str!("Undo misdrops"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("undo_grace"));
+                Slider::new(im_str!("0 = off"), 0..=1000).build(&ui, &mut self.gameplay.undo_grace);
+                id.pop(&ui);
+
+                ui.text(im_str!("Confirm hard drop"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("confirm_drop"));
+                Slider::new(im_str!("0 = off"), 0..=3000)
+                    .build(&ui, &mut self.gameplay.confirm_drop);
                 id.pop(&ui);
 
                 ui.text(im_str!("Skin"));
@@ -262,6 +618,17 @@ impl Settings {
                 }
                 id.pop(&ui);
 
+                ui.text(im_str!("Monochrome"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("monochrome"));
+                if ui.checkbox(
+                    im_str!("Patterns instead of colors"),
+                    &mut self.gameplay.monochrome,
+                ) {
+                    state.skin_switched = true;
+                }
+                id.pop(&ui);
+
                 ui.text(im_str!("Stack grid"));
                 ui.same_line(pos);
                 let id = ui.push_id(im_str!("stack_grid"));
@@ -273,6 +640,92 @@ impl Settings {
                 let id = ui.push_id(im_str!("stack_outline"));
                 ui.checkbox(im_str!(""), &mut self.gameplay.stack_outline);
                 id.pop(&ui);
+
+                ui.text(im_str!("Vanish rows"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("vanish_rows"));
+                Slider::new(im_str!("<V> to peek"), 0..=4)
+                    .build(&ui, &mut self.gameplay.vanish_rows);
+                id.pop(&ui);
+
+                ui.text(im_str!("Zone meter"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("zone"));
+                ui.checkbox(im_str!(""), &mut self.gameplay.zone);
+                id.pop(&ui);
+
+                ui.text(im_str!("Tutorial hints"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("hints"));
+                ui.checkbox(im_str!(""), &mut self.gameplay.hints);
+                ui.same_line(0.0);
+                if ui.small_button(im_str!("Show again")) {
+                    self.gameplay.hints_seen.clear();
+                }
+                id.pop(&ui);
+
+                ui.text(im_str!("Break reminder"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("break_reminder"));
+                Slider::new(im_str!("minutes, 0 = off"), 0..=120)
+                    .build(&ui, &mut self.gameplay.break_reminder);
+                id.pop(&ui);
+
+                ui.text(im_str!("Save replays"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("save_replays"));
+                ui.checkbox(im_str!(""), &mut self.gameplay.save_replays);
+                id.pop(&ui);
+
+                ui.text(im_str!("Reconnect window"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("reconnect_window"));
+                Slider::new(im_str!("seconds, 0 = off"), 0..=120)
+                    .build(&ui, &mut self.gameplay.reconnect_window);
+                id.pop(&ui);
+
+                ui.text(im_str!("Block out"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("block_out"));
+                ui.checkbox(im_str!(""), &mut self.gameplay.top_out.block_out);
+                id.pop(&ui);
+
+                ui.text(im_str!("Lock out"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("lock_out"));
+                ui.checkbox(im_str!(""), &mut self.gameplay.top_out.lock_out);
+                id.pop(&ui);
+
+                ui.text(im_str!("Garbage out"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("garbage_out"));
+                ui.checkbox(im_str!(""), &mut self.gameplay.top_out.garbage_out);
+                id.pop(&ui);
+
+                ui.text(im_str!("Fog"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("fog"));
+                ui.checkbox(
+                    im_str!("Bottom half covered"),
+                    &mut self.gameplay.handicaps.fog,
+                );
+                id.pop(&ui);
+
+                ui.text(im_str!("Flashlight"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("flashlight"));
+                Slider::new(im_str!("blocks around the piece, 0 = off"), 0..=8)
+                    .build(&ui, &mut self.gameplay.handicaps.flashlight);
+                id.pop(&ui);
+
+                ui.text(im_str!("Flashing stack"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("flash"));
+                ui.checkbox(
+                    im_str!("Visible for a second after locks"),
+                    &mut self.gameplay.handicaps.flash,
+                );
+                id.pop(&ui);
             }
 
             ui.separator();
@@ -293,6 +746,72 @@ impl Settings {
                 let id = ui.push_id(im_str!("sfx"));
                 Slider::new(im_str!(""), 0..=100).build(&ui, &mut self.audio.sfx_volume);
                 id.pop(&ui);
+
+                ui.text(im_str!("SFX voices"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("sfx_voices"));
+                Slider::new(im_str!(""), 1..=32).build(&ui, &mut self.audio.sfx_voices);
+                id.pop(&ui);
+
+                ui.text(im_str!("Fade in"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("music_fade_in"));
+                Slider::new(im_str!(""), 0..=3000).build(&ui, &mut self.audio.music_fade_in);
+                id.pop(&ui);
+
+                ui.text(im_str!("Fade out"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("music_fade_out"));
+                Slider::new(im_str!(""), 0..=3000).build(&ui, &mut self.audio.music_fade_out);
+                id.pop(&ui);
+
+                ui.text(im_str!("Metronome"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("metronome"));
+                let mut mode_id = METRONOME_MODES
+                    .iter()
+                    .position(|&m| m == self.audio.metronome)
+                    .unwrap_or(0);
+                let modes: Vec<ImString> = METRONOME_MODES
+                    .iter()
+                    .map(|m| ImString::new(m.name()))
+                    .collect();
+                let modes: Vec<&ImStr> = modes.iter().map(|m| m.as_ref()).collect();
+                if ComboBox::new(im_str!("")).build_simple_string(&ui, &mut mode_id, &modes) {
+                    self.audio.metronome = METRONOME_MODES[mode_id];
+                }
+                id.pop(&ui);
+
+                if self.audio.metronome != MetronomeMode::Off {
+                    ui.text(im_str!("BPM"));
+                    ui.same_line(pos);
+                    let id = ui.push_id(im_str!("metronome_bpm"));
+                    Slider::new(im_str!(""), MIN_BPM..=MAX_BPM)
+                        .build(&ui, &mut self.audio.metronome_bpm);
+                    id.pop(&ui);
+                }
+
+                ui.text(im_str!("Sounds"));
+                ui.same_line(pos);
+                if ui.small_button(im_str!("Reset")) {
+                    self.audio.sounds.clear();
+                }
+
+                for (i, &(name, label)) in SOUNDS.iter().enumerate() {
+                    let mut level = self.audio.sounds.get(name).copied().unwrap_or_default();
+
+                    ui.text(&ImString::new(label));
+                    ui.same_line(pos);
+                    let id = ui.push_id(i as i32);
+                    let mut changed = ui.checkbox(im_str!(""), &mut level.enabled);
+                    ui.same_line(0.0);
+                    changed |= Slider::new(im_str!("%"), 0..=100).build(&ui, &mut level.volume);
+                    id.pop(&ui);
+
+                    if changed {
+                        self.audio.sounds.insert(String::from(name), level);
+                    }
+                }
             }
 
             ui.separator();
@@ -305,13 +824,118 @@ impl Settings {
                 ui.text(im_str!("DAS"));
                 ui.same_line(pos);
                 let id = ui.push_id(im_str!("das"));
-                Slider::new(im_str!(""), 100..=500).build(&ui, &mut self.input.das);
+                Slider::new(im_str!("ms"), 0..=500).build(&ui, &mut self.input.das);
                 id.pop(&ui);
 
                 ui.text(im_str!("ARR"));
                 ui.same_line(pos);
                 let id = ui.push_id(im_str!("arr"));
-                Slider::new(im_str!(""), 5..=200).build(&ui, &mut self.input.arr);
+                Slider::new(im_str!("ms, 0 = instant"), 0..=500).build(&ui, &mut self.input.arr);
+                id.pop(&ui);
+
+                ui.text(im_str!("Keep DAS"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("preserve_das"));
+                ui.checkbox(im_str!(""), &mut self.input.preserve_das);
+                id.pop(&ui);
+
+                ui.text(im_str!("Buffer inputs"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("buffer_inputs"));
+                ui.checkbox(im_str!(""), &mut self.input.buffer_inputs);
+                id.pop(&ui);
+
+                ui.text(im_str!("Fresh hard drop"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("hard_drop_fresh_press"));
+                ui.checkbox(im_str!(""), &mut self.input.hard_drop_fresh_press);
+                id.pop(&ui);
+
+                ui.text(im_str!("Hard drop interval"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("hard_drop_interval"));
+                Slider::new(im_str!("0 = off"), 0..=500)
+                    .build(&ui, &mut self.input.hard_drop_interval);
+                id.pop(&ui);
+
+                ui.text(im_str!("Auto soft drop"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("auto_soft_drop"));
+                ui.checkbox(im_str!(""), &mut self.input.auto_soft_drop);
+                id.pop(&ui);
+
+                ui.text(im_str!("Analog soft drop"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("analog_soft_drop"));
+                Slider::new(im_str!("x gravity, 0 = off"), 0..=40)
+                    .build(&ui, &mut self.input.analog_soft_drop);
+                id.pop(&ui);
+
+                ui.text(im_str!("Pause when idle"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("idle_pause"));
+                Slider::new(im_str!("seconds, 0 = off"), 0..=120)
+                    .build(&ui, &mut self.input.idle_pause);
+                id.pop(&ui);
+
+                ui.text(im_str!("Controls preset"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("preset"));
+                let mut preset_id = CONTROL_PRESETS
+                    .iter()
+                    .position(|&p| p == self.input.preset)
+                    .unwrap_or(0);
+                let presets: Vec<ImString> = CONTROL_PRESETS
+                    .iter()
+                    .map(|p| ImString::new(p.name()))
+                    .collect();
+                let presets: Vec<&ImStr> = presets.iter().map(|p| p.as_ref()).collect();
+                if ComboBox::new(im_str!("")).build_simple_string(&ui, &mut preset_id, &presets) {
+                    self.input.preset = CONTROL_PRESETS[preset_id];
+                    self.input.bindings = self.input.preset.bindings();
+                }
+                id.pop(&ui);
+
+                ui.text(im_str!("Mouse control"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("mouse"));
+                ui.checkbox(im_str!(""), &mut self.input.mouse);
+                id.pop(&ui);
+
+                ui.text(im_str!("Low latency"));
+                ui.same_line(pos);
+                if ui.checkbox(im_str!("disables V-Sync"), &mut self.input.low_latency) {
+                    ui.open_popup(im_str!("Restart needed"));
+                }
+            }
+
+            ui.separator();
+            let id = ui.push_font(bold);
+            ui.text_colored(header_color, im_str!("Overlay feed"));
+            id.pop(&ui);
+            ui.separator();
+
+            {
+                ui.text(im_str!("Enabled"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("feed"));
+                ui.checkbox(im_str!(""), &mut self.feed.enabled);
+                id.pop(&ui);
+
+                ui.text(im_str!("Address"));
+                ui.same_line(pos);
+                ui.text(im_str!("{} (UDP)", self.feed.address));
+
+                ui.text(im_str!("Interval"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("feed_interval"));
+                Slider::new(im_str!("ms"), 16..=1000).build(&ui, &mut self.feed.interval);
+                id.pop(&ui);
+
+                ui.text(im_str!("Delay"));
+                ui.same_line(pos);
+                let id = ui.push_id(im_str!("feed_delay"));
+                Slider::new(im_str!("ms, 0 = live"), 0..=10000).build(&ui, &mut self.feed.delay);
                 id.pop(&ui);
             }
 
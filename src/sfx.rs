@@ -1,35 +1,137 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use ggez::{
-    audio::{SoundSource, Source},
+    audio::{SoundData, SoundSource, Source},
     Context, GameResult,
 };
+use serde::{Deserialize, Serialize};
 
-use crate::utils;
+use crate::{metronome, utils};
+
+/// How many copies of the same sound can overlap
+const VOICES_PER_SOUND: usize = 4;
+
+/// Every sound along with the event it is played on
+pub const SOUNDS: [(&str, &str); 18] = [
+    ("ready", "Countdown ready"),
+    ("go", "Countdown go"),
+    ("gameover", "Game over"),
+    ("levelup", "Level up"),
+    ("move", "Move"),
+    ("rotate", "Rotate"),
+    ("rotate180", "Rotate 180"),
+    ("harddrop", "Hard drop"),
+    ("hold", "Hold"),
+    ("lock", "Lock"),
+    ("erase1", "Single"),
+    ("erase2", "Double"),
+    ("erase3", "Triple"),
+    ("erase4", "Tetris"),
+    ("tspin1", "T-Spin single"),
+    ("tspin2", "T-Spin double"),
+    ("tspin3", "T-Spin triple"),
+    ("tick", "Metronome"),
+];
+
+/// Per sound settings, sounds missing from the mix play at full SFX volume
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SoundLevel {
+    pub enabled: bool,
+    /// Percent of the SFX volume
+    pub volume: u32,
+}
+
+impl Default for SoundLevel {
+    fn default() -> SoundLevel {
+        SoundLevel {
+            enabled: true,
+            volume: 100,
+        }
+    }
+}
+
+pub type SoundMix = BTreeMap<String, SoundLevel>;
+
+fn level(mix: &SoundMix, name: &str) -> SoundLevel {
+    mix.get(name).copied().unwrap_or_default()
+}
+
+/// Volume of a single source, from 0 to 1
+fn gain(mix: &SoundMix, name: &str, volume: u32) -> f32 {
+    let level = level(mix, name);
+    if level.enabled {
+        volume as f32 / 100.0 * level.volume as f32 / 100.0
+    } else {
+        0.0
+    }
+}
+
+/// Sources sharing the decoded data of a single sound, reused in a round robin
+#[derive(Default)]
+struct Voices {
+    sources: Vec<Source>,
+    next: usize,
+}
+
+impl Voices {
+    fn playing(&self) -> usize {
+        self.sources.iter().filter(|s| s.playing()).count()
+    }
+
+    /// Free voice if there is one, otherwise the one that started playing the longest time ago
+    fn voice(&mut self) -> Option<&mut Source> {
+        if self.sources.is_empty() {
+            return None;
+        }
+
+        let id = self
+            .sources
+            .iter()
+            .position(|s| !s.playing())
+            .unwrap_or(self.next);
+        self.next = (id + 1) % self.sources.len();
+        self.sources.get_mut(id)
+    }
+}
 
 #[derive(Default)]
 pub struct Sfx {
-    sounds: HashMap<&'static str, Option<Source>>,
+    sounds: HashMap<&'static str, Voices>,
     volume: u32,
+    max_voices: u32,
+    mix: SoundMix,
 }
 
 impl Sfx {
-    pub fn load(ctx: &mut Context, volume: u32) -> GameResult<Sfx> {
-        let sounds = [
-            "ready", "go", "gameover", "levelup", "move", "rotate", "harddrop", "hold", "lock",
-            "erase1", "erase2", "erase3", "erase4", "tspin1", "tspin2", "tspin3",
-        ]
-        .iter()
-        .map(|&s| (s, Sfx::source(ctx, s, volume)))
-        .collect();
-
-        Ok(Sfx { sounds, volume })
+    /// Reads every sound once at startup, so nothing touches the filesystem mid-game
+    pub fn load(ctx: &mut Context, volume: u32, max_voices: u32) -> GameResult<Sfx> {
+        let sounds = SOUNDS
+            .iter()
+            .map(|&(s, _)| (s, Sfx::voices(ctx, s, volume)))
+            .collect();
+
+        Ok(Sfx {
+            sounds,
+            volume,
+            max_voices,
+            mix: SoundMix::new(),
+        })
     }
 
     pub fn play(&mut self, name: &'static str) {
-        if let Some(Some(sound)) = self.sounds.get_mut(name) {
-            sound
-                .play_detached()
+        if !level(&self.mix, name).enabled {
+            return;
+        }
+
+        let playing: usize = self.sounds.values().map(Voices::playing).sum();
+        if playing >= self.max_voices as usize {
+            return;
+        }
+
+        if let Some(source) = self.sounds.get_mut(name).and_then(Voices::voice) {
+            source
+                .play()
                 .unwrap_or_else(|e| log::error!("Unable to play {}: {:?}", name, e));
         } else {
             log::warn!("Sound doesn't exist: {}", name);
@@ -41,25 +143,89 @@ impl Sfx {
     }
 
     pub fn set_volume(&mut self, volume: u32) {
-        for (_, sound) in self.sounds.iter_mut() {
-            if let Some(sound) = sound {
-                sound.set_volume(volume as f32 / 100.0);
+        self.volume = volume;
+        self.apply_volume();
+    }
+
+    pub fn mix(&self) -> &SoundMix {
+        &self.mix
+    }
+
+    pub fn set_mix(&mut self, mix: &SoundMix) {
+        self.mix = mix.clone();
+        self.apply_volume();
+    }
+
+    fn apply_volume(&mut self) {
+        for (&name, voices) in self.sounds.iter_mut() {
+            let gain = gain(&self.mix, name, self.volume);
+            for source in voices.sources.iter_mut() {
+                source.set_volume(gain);
             }
         }
     }
 
-    fn source(ctx: &mut Context, name: &'static str, volume: u32) -> Option<Source> {
+    pub fn max_voices(&self) -> u32 {
+        self.max_voices
+    }
+
+    pub fn set_max_voices(&mut self, max_voices: u32) {
+        self.max_voices = max_voices;
+    }
+
+    fn voices(ctx: &mut Context, name: &'static str, volume: u32) -> Voices {
         let path = String::from("sfx/") + name + ".wav";
-        match Source::new(ctx, utils::path(ctx, &path)) {
-            Ok(mut s) => {
-                log::debug!("Loaded {}", path);
-                s.set_volume(volume as f32 / 100.0);
-                Some(s)
+        let data = if name == "tick" {
+            SoundData::from_bytes(&metronome::tick_wav())
+        } else {
+            match SoundData::new(ctx, utils::path(ctx, &path)) {
+                Ok(data) => data,
+                Err(e) => {
+                    log::error!("Unable to load {}: {:?}", path, e);
+                    return Voices::default();
+                }
             }
-            Err(e) => {
-                log::error!("Unable to load {}: {:?}", path, e);
-                None
+        };
+
+        let mut sources = Vec::with_capacity(VOICES_PER_SOUND);
+        for _ in 0..VOICES_PER_SOUND {
+            match Source::from_data(ctx, data.clone()) {
+                Ok(mut s) => {
+                    s.set_volume(volume as f32 / 100.0);
+                    sources.push(s);
+                }
+                Err(e) => {
+                    log::error!("Unable to create a voice for {}: {:?}", path, e);
+                    break;
+                }
             }
         }
+
+        log::debug!("Loaded {} with {} voices", path, sources.len());
+        Voices { sources, next: 0 }
     }
 }
+
+#[test]
+fn gain_test() {
+    let mut mix = SoundMix::new();
+    assert_eq!(gain(&mix, "move", 50), 0.5);
+
+    mix.insert(
+        String::from("move"),
+        SoundLevel {
+            enabled: true,
+            volume: 40,
+        },
+    );
+    mix.insert(
+        String::from("rotate"),
+        SoundLevel {
+            enabled: false,
+            volume: 100,
+        },
+    );
+    assert_eq!(gain(&mix, "move", 50), 0.2);
+    assert_eq!(gain(&mix, "rotate", 50), 0.0);
+    assert_eq!(gain(&mix, "lock", 50), 0.5);
+}
@@ -1,48 +1,8 @@
-use ggez::{nalgebra::Point2, Context, GameResult};
+use ggez::nalgebra::Point2;
+use serde::{Deserialize, Serialize};
 
 use crate::blocks::Blocks;
 
-pub type Kick = [(i32, i32); 4];
-pub type Kicks = [(Kick, Kick); 4];
-
-const KICKS_JLSTZ: Kicks = [
-    (
-        [(-1, 0), (-1, -1), (0, 2), (-1, 2)],
-        [(1, 0), (1, -1), (0, 2), (1, 2)],
-    ),
-    (
-        [(1, 0), (1, 1), (0, -2), (1, -2)],
-        [(1, 0), (1, 1), (0, -2), (1, -2)],
-    ),
-    (
-        [(1, 0), (1, -1), (0, 2), (1, 2)],
-        [(-1, 0), (-1, -1), (0, 2), (-1, 2)],
-    ),
-    (
-        [(-1, 0), (-1, 1), (0, -2), (-1, -2)],
-        [(-1, 0), (-1, 1), (0, -2), (-1, -2)],
-    ),
-];
-
-const KICKS_I: Kicks = [
-    (
-        [(-2, 0), (1, 0), (-2, 1), (1, -2)],
-        [(-1, 0), (2, 0), (-1, -2), (2, 1)],
-    ),
-    (
-        [(-1, 0), (2, 0), (-1, -2), (2, 1)],
-        [(2, 0), (-1, 0), (2, -1), (-1, 2)],
-    ),
-    (
-        [(2, 0), (-1, 0), (2, -1), (-1, 2)],
-        [(1, 0), (-2, 0), (1, 2), (-2, -1)],
-    ),
-    (
-        [(1, 0), (-2, 0), (1, 2), (-2, -1)],
-        [(-2, 0), (1, 0), (-2, 1), (1, -2)],
-    ),
-];
-
 #[derive(Clone)]
 pub struct ShapeGrid {
     pub offset_x: i32,
@@ -70,7 +30,7 @@ impl ShapeGrid {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub enum ShapeType {
     I = 1,
     J,
@@ -81,6 +41,29 @@ pub enum ShapeType {
     Z,
 }
 
+impl ShapeType {
+    pub fn from_char(c: char) -> Option<ShapeType> {
+        match c.to_ascii_uppercase() {
+            'I' => Some(ShapeType::I),
+            'J' => Some(ShapeType::J),
+            'L' => Some(ShapeType::L),
+            'O' => Some(ShapeType::O),
+            'S' => Some(ShapeType::S),
+            'T' => Some(ShapeType::T),
+            'Z' => Some(ShapeType::Z),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse_sequence(sequence: &str) -> Result<Vec<ShapeType>, char> {
+    sequence
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| ShapeType::from_char(c).ok_or(c))
+        .collect()
+}
+
 pub fn all_shape_types() -> Vec<ShapeType> {
     use ShapeType::*;
     vec![I, J, L, O, S, T, Z]
@@ -90,21 +73,17 @@ pub fn all_shape_types() -> Vec<ShapeType> {
 pub struct Shape {
     pub shape_type: ShapeType,
     pub grids: [ShapeGrid; 4],
-    pub kicks: Kicks,
 }
 
 impl Shape {
-    pub fn draw(
+    pub fn add(
         &self,
-        ctx: &mut Context,
         rotation: usize,
         position: Point2<f32>,
         blocks: &mut Blocks,
         block_size: i32,
         alpha: f32,
-    ) -> GameResult {
-        blocks.clear();
-
+    ) {
         let grid = &self.grids[rotation];
 
         for y in 0..4 {
@@ -122,10 +101,6 @@ impl Shape {
                 blocks.add(block, block_size, dest, alpha);
             }
         }
-
-        blocks.draw(ctx)?;
-
-        Ok(())
     }
 
     pub fn new(shape_type: ShapeType) -> Shape {
@@ -164,7 +139,6 @@ impl Shape {
                             [[0, x, 0, 0], [x, x, 0, 0], [x, 0, 0, 0], [0, 0, 0, 0]],
                         ),
                     ],
-                    kicks: KICKS_JLSTZ,
                 }
             }
             ShapeType::L => {
@@ -201,7 +175,6 @@ impl Shape {
                             [[x, x, 0, 0], [0, x, 0, 0], [0, x, 0, 0], [0, 0, 0, 0]],
                         ),
                     ],
-                    kicks: KICKS_JLSTZ,
                 }
             }
             ShapeType::O => {
@@ -238,7 +211,6 @@ impl Shape {
                             [[x, x, 0, 0], [x, x, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]],
                         ),
                     ],
-                    kicks: KICKS_JLSTZ,
                 }
             }
             ShapeType::S => {
@@ -275,7 +247,6 @@ impl Shape {
                             [[x, 0, 0, 0], [x, x, 0, 0], [0, x, 0, 0], [0, 0, 0, 0]],
                         ),
                     ],
-                    kicks: KICKS_JLSTZ,
                 }
             }
             ShapeType::I => {
@@ -312,7 +283,6 @@ impl Shape {
                             [[0, x, 0, 0], [0, x, 0, 0], [0, x, 0, 0], [0, x, 0, 0]],
                         ),
                     ],
-                    kicks: KICKS_I,
                 }
             }
             ShapeType::J => {
@@ -349,7 +319,6 @@ impl Shape {
                             [[0, x, 0, 0], [0, x, 0, 0], [x, x, 0, 0], [0, 0, 0, 0]],
                         ),
                     ],
-                    kicks: KICKS_JLSTZ,
                 }
             }
             ShapeType::T => {
@@ -386,7 +355,6 @@ impl Shape {
                             [[0, x, 0, 0], [x, x, 0, 0], [0, x, 0, 0], [0, 0, 0, 0]],
                         ),
                     ],
-                    kicks: KICKS_JLSTZ,
                 }
             }
         }
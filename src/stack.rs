@@ -1,37 +1,196 @@
-use std::time::Duration;
+use std::{mem, time::Duration};
 
 use ggez::{
-    graphics::{self, Color, DrawMode, DrawParam, Mesh, MeshBuilder, Rect},
+    conf::NumSamples,
+    graphics::{self, Canvas, Color, DrawMode, DrawParam, Mesh, MeshBuilder, Rect},
     nalgebra::{Point2, Vector2},
     timer, Context, GameResult,
 };
 use rand::Rng;
 use rand_distr::{Distribution, Normal, Uniform};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blocks::{self, Blocks, BOMB_BLOCK, CELLS_NUM, GARBAGE_BLOCK, STEEL_BLOCK},
+    clear::{ClearDetector, LineClear},
+    global::Global,
+    particles::Sparks,
+    piece::Piece,
+    profiler,
+};
 
-use crate::{blocks::Blocks, global::Global, piece::Piece, utils};
-
+#[derive(Default)]
 struct Clearing {
     rows: Vec<i32>,
+    cells: Vec<Vec<usize>>,
+    shifts: Vec<i32>,
+    current_duration: Duration,
+    max_duration: Duration,
+}
+
+struct Rising {
+    rows: i32,
     current_duration: Duration,
     max_duration: Duration,
 }
 
+/// Grid and locked blocks rendered once and reused until the stack changes
+struct Layer {
+    canvas: Canvas,
+    revision: u32,
+    block_size: i32,
+    vanish_rows: i32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GarbageRow {
+    pub hole: usize,
+}
+
 pub type Grid = Vec<Vec<usize>>;
 
+/// Frame drawn around the playfield
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum BorderStyle {
+    None,
+    Thin,
+    /// Thin frame fading out into a glow
+    Glow,
+}
+
+pub const BORDER_STYLES: [BorderStyle; 3] =
+    [BorderStyle::None, BorderStyle::Thin, BorderStyle::Glow];
+
+impl BorderStyle {
+    pub fn name(self) -> &'static str {
+        match self {
+            BorderStyle::None => "None",
+            BorderStyle::Thin => "Thin",
+            BorderStyle::Glow => "Glowing frame",
+        }
+    }
+}
+
+impl Default for BorderStyle {
+    fn default() -> BorderStyle {
+        BorderStyle::Thin
+    }
+}
+
+/// Settings the grid mesh was built with, it's built again when any of them changes
+#[derive(Copy, Clone, PartialEq)]
+struct GridStyle {
+    grid: bool,
+    outline: bool,
+    border: BorderStyle,
+}
+
+/// Conditions that end the game
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TopOut {
+    /// A new piece spawns overlapping the stack
+    pub block_out: bool,
+    /// A piece locks entirely inside the vanish zone
+    pub lock_out: bool,
+    /// Garbage pushes blocks out of the top of the stack
+    pub garbage_out: bool,
+}
+
+impl Default for TopOut {
+    fn default() -> TopOut {
+        TopOut {
+            block_out: true,
+            lock_out: true,
+            garbage_out: true,
+        }
+    }
+}
+
+/// Clear delay for every number of cleared rows, in milliseconds,
+/// so that small clears don't feel as sluggish as tetrises
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ClearDelays {
+    pub single: u32,
+    pub double: u32,
+    pub triple: u32,
+    /// Also used for the bigger clears at the end of the zone
+    pub quad: u32,
+}
+
+impl Default for ClearDelays {
+    fn default() -> ClearDelays {
+        ClearDelays {
+            single: 200,
+            double: 250,
+            triple: 350,
+            quad: 500,
+        }
+    }
+}
+
+impl ClearDelays {
+    /// Same delay for every clear
+    pub fn flat(delay: u32) -> ClearDelays {
+        ClearDelays {
+            single: delay,
+            double: delay,
+            triple: delay,
+            quad: delay,
+        }
+    }
+
+    pub fn get(&self, rows: i32) -> Duration {
+        let delay = match rows {
+            1 => self.single,
+            2 => self.double,
+            3 => self.triple,
+            _ => self.quad,
+        };
+
+        Duration::from_millis(delay.into())
+    }
+}
+
 pub struct Stack {
     pub width: i32,
     pub height: i32,
     pub vanish: i32,
+    pub top_out: TopOut,
+    /// Extra rows of the vanish zone drawn dimmed above the visible field
+    pub vanish_rows: i32,
 
     clearing: Option<Clearing>,
+    /// Buffers of the last finished clearing, reused by the next one
+    recycled: Option<Clearing>,
+    full_rows: Vec<i32>,
+    /// Garbage rows among the rows cleared by the last lock
+    cleared_garbage: i32,
+    detector: Box<dyn ClearDetector>,
+    /// Floating groups of blocks fall on their own after clears
+    cascade: bool,
+    /// Rows cleared in every link of the chain started by the last lock, after its own clear
+    chains: Vec<i32>,
+    /// Full rows piled up at the bottom while in the zone
+    zone: Option<i32>,
+    rising: Option<Rising>,
     destroyed_blocks: Vec<DestroyedBlock>,
+    sparks: Sparks,
     randomizer: Randomizer,
     game_over: bool,
 
     grid: Grid,
+    /// Column heights and holes, kept up to date as the grid changes
+    heights: Vec<i32>,
+    holes: Vec<i32>,
     grid_mesh: Option<(Mesh, i32)>,
+    border_mesh: Option<Mesh>,
+    grid_style: Option<GridStyle>,
     block_size: i32,
     update_grid: bool,
+    revision: u32,
+    layer: Option<Layer>,
 }
 
 struct DestroyedBlock {
@@ -68,25 +227,118 @@ pub enum Locked {
     Success(i32),
 }
 
+/// Shape of the top of the stack, the same measures the bot uses to judge placements
+pub struct Surface {
+    pub heights: Vec<i32>,
+    pub holes: Vec<i32>,
+    pub bumpiness: i32,
+}
+
+/// Height and number of holes of a single column
+fn scan_column(grid: &Grid, x: usize) -> (i32, i32) {
+    let mut top = None;
+    let mut holes = 0;
+
+    for (y, row) in grid.iter().enumerate() {
+        if row[x] != 0 {
+            if top.is_none() {
+                top = Some(y);
+            }
+        } else if top.is_some() {
+            holes += 1;
+        }
+    }
+
+    (top.map_or(0, |y| (grid.len() - y) as i32), holes)
+}
+
+impl Surface {
+    pub fn new(grid: &Grid) -> Surface {
+        let width = grid.first().map_or(0, |row| row.len());
+        let (heights, holes) = (0..width).map(|x| scan_column(grid, x)).unzip();
+        Surface::from_columns(heights, holes)
+    }
+
+    fn from_columns(heights: Vec<i32>, holes: Vec<i32>) -> Surface {
+        let bumpiness = heights.windows(2).map(|w| (w[0] - w[1]).abs()).sum();
+
+        Surface {
+            heights,
+            holes,
+            bumpiness,
+        }
+    }
+
+    pub fn aggregate_height(&self) -> i32 {
+        self.heights.iter().sum()
+    }
+
+    pub fn total_holes(&self) -> i32 {
+        self.holes.iter().sum()
+    }
+}
+
 impl Stack {
     pub fn new(width: i32, height: i32, vanish: i32) -> Stack {
         Stack {
             width,
             height,
             vanish,
+            top_out: TopOut::default(),
+            vanish_rows: 0,
             clearing: None,
+            recycled: None,
+            full_rows: vec![],
+            cleared_garbage: 0,
+            detector: Box::new(LineClear),
+            cascade: false,
+            chains: vec![],
+            zone: None,
+            rising: None,
             destroyed_blocks: vec![],
+            sparks: Sparks::default(),
             randomizer: Randomizer::new(),
             game_over: false,
             grid: vec![vec![0; width as usize]; (height + vanish) as usize],
+            heights: vec![0; width as usize],
+            holes: vec![0; width as usize],
             grid_mesh: None,
+            border_mesh: None,
+            grid_style: None,
             block_size: 0,
             update_grid: true,
+            revision: 0,
+            layer: None,
         }
     }
 
     pub fn place_random(&mut self, x: usize, y: usize) {
         self.grid[y][x] = rand::thread_rng().gen_range(1, 8);
+        self.rescan_column(x);
+        self.changed();
+    }
+
+    /// Puts a cell of any kind into the stack, e.g. a bomb in a gimmick mode
+    pub fn place(&mut self, x: usize, y: usize, cell: usize) {
+        self.grid[y][x] = cell;
+        self.rescan_column(x);
+        self.changed();
+    }
+
+    fn changed(&mut self) {
+        self.update_grid = true;
+        self.revision = self.revision.wrapping_add(1);
+    }
+
+    /// Changes every time the blocks of the stack change
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// True while rows are clearing, rising or blocks are flying off,
+    /// so the blocks have to be laid out again every frame
+    pub fn animating(&self) -> bool {
+        self.clearing.is_some() || self.rising.is_some() || !self.destroyed_blocks.is_empty()
     }
 
     pub fn build_grid(&mut self, ctx: &mut Context, grid: bool, outline: bool) -> GameResult {
@@ -238,29 +490,128 @@ impl Stack {
             }
         }
 
-        grid_mesh.rectangle(
-            DrawMode::stroke(3.0),
-            Rect::new(
-                0.0,
-                0.0,
-                (self.width * self.block_size) as f32,
-                (self.height * self.block_size) as f32,
-            ),
-            Color::new(0.8, 0.9, 1.0, 0.8),
-        );
-
         self.grid_mesh = Some((grid_mesh.build(ctx)?, self.block_size));
 
         Ok(())
     }
 
+    /// The border is kept apart from the grid, because the glow reaches outside of the cached layer
+    fn build_border(&mut self, ctx: &mut Context, style: BorderStyle) -> GameResult {
+        const BORDER_COLOR: Color = Color::new(0.8, 0.9, 1.0, 0.8);
+        const GLOW_LAYERS: i32 = 6;
+
+        let width = (self.width * self.block_size) as f32;
+        let height = (self.height * self.block_size) as f32;
+        let mut border = MeshBuilder::new();
+
+        match style {
+            BorderStyle::None => {
+                self.border_mesh = None;
+                return Ok(());
+            }
+            BorderStyle::Thin => {
+                border.rectangle(
+                    DrawMode::stroke(3.0),
+                    Rect::new(0.0, 0.0, width, height),
+                    BORDER_COLOR,
+                );
+            }
+            BorderStyle::Glow => {
+                for i in (0..GLOW_LAYERS).rev() {
+                    let spread = 2.0 + i as f32 * 2.5;
+                    let alpha = 0.5 * (1.0 - i as f32 / GLOW_LAYERS as f32);
+                    border.rectangle(
+                        DrawMode::stroke(3.0),
+                        Rect::new(
+                            -spread,
+                            -spread,
+                            width + spread * 2.0,
+                            height + spread * 2.0,
+                        ),
+                        Color::new(0.4, 0.7, 1.0, alpha),
+                    );
+                }
+
+                border.rectangle(
+                    DrawMode::stroke(2.0),
+                    Rect::new(0.0, 0.0, width, height),
+                    Color::new(0.9, 0.95, 1.0, 1.0),
+                );
+            }
+        }
+
+        self.border_mesh = Some(border.build(ctx)?);
+        Ok(())
+    }
+
     pub fn grid(&self) -> &Grid {
         &self.grid
     }
 
+    pub fn surface(&self) -> Surface {
+        Surface::from_columns(self.heights.clone(), self.holes.clone())
+    }
+
+    /// Height of every column, counted from the floor
+    pub fn heights(&self) -> &[i32] {
+        &self.heights
+    }
+
+    /// Empty cells under the top block of every column
+    pub fn holes(&self) -> &[i32] {
+        &self.holes
+    }
+
+    /// Number of rows between the floor and the highest block
+    pub fn filled_height(&self) -> i32 {
+        self.heights.iter().cloned().max().unwrap_or(0)
+    }
+
+    fn rescan_column(&mut self, x: usize) {
+        let (height, holes) = scan_column(&self.grid, x);
+        self.heights[x] = height;
+        self.holes[x] = holes;
+    }
+
+    fn rescan(&mut self) {
+        for x in 0..self.width as usize {
+            self.rescan_column(x);
+        }
+    }
+
+    pub fn set_grid(&mut self, grid: Grid) -> Result<(), String> {
+        if grid.len() != (self.height + self.vanish) as usize {
+            return Err(format!(
+                "Expected {} rows, got {}",
+                self.height + self.vanish,
+                grid.len()
+            ));
+        }
+
+        for row in &grid {
+            if row.len() != self.width as usize {
+                return Err(format!(
+                    "Expected {} columns, got {}",
+                    self.width,
+                    row.len()
+                ));
+            }
+
+            if let Some(block) = row.iter().find(|&&b| b >= CELLS_NUM) {
+                return Err(format!("Invalid block: {}", block));
+            }
+        }
+
+        self.grid = grid;
+        self.rescan();
+        self.changed();
+        Ok(())
+    }
+
     pub fn clear(&mut self) {
-        self.update_grid = true;
-        self.grid = vec![vec![0; self.width as usize]; (self.height + self.vanish) as usize]
+        self.changed();
+        self.grid = vec![vec![0; self.width as usize]; (self.height + self.vanish) as usize];
+        self.rescan();
     }
 
     pub fn collision(&self, piece: &Piece) -> bool {
@@ -288,15 +639,15 @@ impl Stack {
         false
     }
 
-    pub fn lock(&mut self, piece: &Piece, clear_delay: Duration) -> Locked {
-        self.update_grid = true;
+    pub fn lock(&mut self, piece: &Piece, clear_delays: ClearDelays) -> Locked {
+        self.changed();
         let mut collision = self.collision(&piece);
 
         let grid = piece.grid();
         let x = piece.x + grid.offset_x;
         let y = piece.y + grid.offset_y;
 
-        if y + grid.height <= self.vanish {
+        if self.top_out.lock_out && y + grid.height <= self.vanish {
             collision = true;
         }
 
@@ -309,26 +660,151 @@ impl Stack {
             }
         }
 
+        // Only the columns of the piece change until rows are cleared
+        for mx in 0..grid.width {
+            self.rescan_column((x + mx) as usize);
+        }
+
         if !collision {
-            Locked::Success(self.clear_full_rows(clear_delay))
+            Locked::Success(self.clear_full_rows(clear_delays))
         } else {
             Locked::Collision
         }
     }
 
+    /// Pushes the stack up and inserts garbage rows at the bottom.
+    /// Returns true if any blocks were pushed out of the top of the stack
+    /// and the garbage out rule is enabled.
+    pub fn insert_garbage(&mut self, rows: &[GarbageRow]) -> bool {
+        self.insert_rows(rows, GARBAGE_BLOCK)
+    }
+
+    /// Pushes the stack up and inserts rows of steel without holes, which never clear
+    pub fn insert_steel(&mut self, rows: usize) -> bool {
+        let hole = self.width as usize;
+        self.insert_rows(&vec![GarbageRow { hole }; rows], STEEL_BLOCK)
+    }
+
+    fn insert_rows(&mut self, rows: &[GarbageRow], cell: usize) -> bool {
+        let n = rows.len();
+        if n == 0 {
+            return false;
+        }
+
+        let n = n.min(self.grid.len());
+        let overflow = self.grid[..n].iter().any(|row| row.iter().any(|&b| b != 0));
+
+        // Rows pushed out of the top are reused for the garbage
+        self.grid.rotate_left(n);
+
+        let first = self.grid.len() - n;
+        for (cells, row) in self.grid[first..].iter_mut().zip(&rows[..n]) {
+            for c in cells.iter_mut() {
+                *c = cell;
+            }
+            if row.hole < cells.len() {
+                cells[row.hole] = 0;
+            }
+        }
+
+        for x in 0..self.width as usize {
+            if overflow || self.heights[x] == 0 {
+                self.rescan_column(x);
+            } else {
+                self.heights[x] += n as i32;
+                self.holes[x] += rows[..n].iter().filter(|r| r.hole == x).count() as i32;
+            }
+        }
+
+        if let Some(clearing) = self.clearing.as_mut() {
+            for y in clearing.rows.iter_mut() {
+                *y -= n as i32;
+            }
+
+            let len = clearing.shifts.len();
+            clearing.shifts.drain(..n);
+            clearing.shifts.resize(len, 0);
+        }
+
+        let previous = self.rising.as_ref().map_or(0, |r| r.rows);
+        self.rising = Some(Rising {
+            rows: previous + n as i32,
+            current_duration: Duration::new(0, 0),
+            max_duration: Duration::from_millis(150),
+        });
+
+        self.changed();
+        overflow && self.top_out.garbage_out
+    }
+
     pub fn blocked(&self) -> bool {
         self.clearing.is_some()
     }
 
-    pub fn update(&mut self, ctx: &mut Context, g: &mut Global) -> GameResult {
+    /// Garbage rows among the rows cleared by the last locked piece
+    pub fn cleared_garbage(&self) -> i32 {
+        self.cleared_garbage
+    }
+
+    pub fn set_detector(&mut self, detector: Box<dyn ClearDetector>) {
+        self.detector = detector;
+    }
+
+    pub fn set_cascade(&mut self, cascade: bool) {
+        self.cascade = cascade;
+    }
+
+    /// Rows cleared in every further link of the chain started by the last locked piece
+    pub fn chains(&self) -> &[i32] {
+        &self.chains
+    }
+
+    /// Rows still containing garbage blocks
+    pub fn garbage_rows(&self) -> i32 {
+        self.grid
+            .iter()
+            .filter(|row| row.iter().any(|&b| blocks::is_garbage(b)))
+            .count() as i32
+    }
+
+    pub fn finish_clearing(&mut self) {
+        if let Some(clearing) = self.clearing.take() {
+            self.recycled = Some(clearing);
+            self.changed();
+        }
+    }
+
+    /// Ends every animation at once, for stacks that are never drawn
+    pub fn skip_animations(&mut self) {
+        self.finish_clearing();
+        self.rising = None;
+        self.destroyed_blocks.clear();
+        self.sparks.clear();
+    }
+
+    fn collapse_rows(&mut self, rows: &[i32]) {
+        for &y in rows {
+            for y in (1..=y).rev() {
+                for x in 0..self.width {
+                    self.grid[y as usize][x as usize] = self.grid[y as usize - 1][x as usize];
+                }
+            }
+
+            for x in 0..self.width {
+                self.grid[0][x as usize] = 0;
+            }
+        }
+    }
+
+    pub fn update(&mut self, ctx: &mut Context, g: &mut Global, blocks: &mut Blocks) -> GameResult {
         if let Some(clearing) = self.clearing.as_mut() {
-            clearing.current_duration += timer::delta(ctx);
+            clearing.current_duration += g.clock.delta();
 
             if clearing.current_duration >= clearing.max_duration {
                 let mut rng = rand::thread_rng();
 
-                for &y in &clearing.rows {
-                    for x in 0..self.width {
+                for (&y, cells) in clearing.rows.iter().zip(&clearing.cells) {
+                    for (x, &block_id) in cells.iter().enumerate() {
                         let vx = self.randomizer.uniform_vx.sample(&mut rng);
                         let vy = self.randomizer.normal_vy.sample(&mut rng);
                         let vr = self.randomizer.uniform_vr.sample(&mut rng);
@@ -336,8 +812,6 @@ impl Stack {
                             self.randomizer.uniform_lifetime.sample(&mut rng),
                         );
 
-                        let block_id = self.grid[y as usize][x as usize];
-
                         if block_id != 0 {
                             self.destroyed_blocks.push(DestroyedBlock {
                                 block_id,
@@ -353,28 +827,25 @@ impl Stack {
                     }
                 }
 
-                for &y in &clearing.rows {
-                    for y in (1..=y).rev() {
-                        for x in 0..self.width {
-                            self.grid[y as usize][x as usize] =
-                                self.grid[y as usize - 1][x as usize];
-                        }
-                    }
-                }
+                self.finish_clearing();
+            }
+        }
 
-                self.clearing = None;
-                self.update_grid = true;
+        if let Some(rising) = self.rising.as_mut() {
+            rising.current_duration += g.clock.delta();
+            if rising.current_duration >= rising.max_duration {
+                self.rising = None;
             }
         }
 
-        let dt = utils::dt_f32(ctx);
+        let dt = g.clock.delta_f32();
         let g_force = Vector2::new(0.0, 75.0) * dt;
 
         for block in &mut self.destroyed_blocks {
             block.speed += g_force;
             block.position += block.speed * dt;
             block.rotation += block.rotation_speed * dt;
-            block.visible += timer::delta(ctx);
+            block.visible += g.clock.delta();
             block.alpha = (1.0
                 - timer::duration_to_f64(block.visible) / timer::duration_to_f64(block.lifetime))
                 as f32;
@@ -383,67 +854,210 @@ impl Stack {
         self.destroyed_blocks
             .retain(|block| block.visible < block.lifetime);
 
+        self.sparks.update(g.clock.delta());
+
+        let style = GridStyle {
+            grid: g.settings.gameplay.stack_grid,
+            outline: g.settings.gameplay.stack_outline,
+            border: g.settings.graphics.border,
+        };
+
+        // Also makes the cached layer outdated, as it contains the grid
+        if self.grid_style != Some(style) {
+            self.grid_style = Some(style);
+            self.changed();
+        }
+
         if self.update_grid {
-            self.build_grid(
-                ctx,
-                g.settings.gameplay.stack_grid,
-                g.settings.gameplay.stack_outline,
-            )?;
+            self.build_grid(ctx, style.grid, style.outline && self.clearing.is_none())?;
+            self.build_border(ctx, style.border)?;
             self.update_grid = false;
         }
 
+        if !self.animating() && !self.layer_valid() && self.grid_mesh.is_some() {
+            self.render_layer(ctx, blocks)?;
+        }
+
         Ok(())
     }
 
+    fn layer_rows(&self) -> i32 {
+        self.vanish_rows.max(0).min(self.vanish - 1)
+    }
+
+    fn layer_valid(&self) -> bool {
+        match &self.layer {
+            Some(layer) => {
+                layer.revision == self.revision
+                    && layer.block_size == self.block_size
+                    && layer.vanish_rows == self.layer_rows()
+            }
+            None => false,
+        }
+    }
+
+    /// Draws the grid and the locked blocks into the cached layer.
+    /// Called during update, when nothing else is being drawn.
+    fn render_layer(&mut self, ctx: &mut Context, blocks: &mut Blocks) -> GameResult {
+        let rows = self.layer_rows();
+        let width = (self.width * self.block_size) as u16;
+        let height = ((self.height + rows) * self.block_size) as u16;
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        let canvas = match self.layer.take() {
+            Some(layer)
+                if layer.canvas.image().width() == width
+                    && layer.canvas.image().height() == height =>
+            {
+                layer.canvas
+            }
+            _ => Canvas::new(ctx, width, height, NumSamples::One)?,
+        };
+
+        let screen = graphics::screen_coordinates(ctx);
+        graphics::set_canvas(ctx, Some(&canvas));
+        graphics::set_screen_coordinates(ctx, Rect::new(0.0, 0.0, width.into(), height.into()))?;
+        graphics::clear(ctx, Color::new(0.0, 0.0, 0.0, 0.0));
+
+        let origin = Point2::new(0.0, (rows * self.block_size) as f32);
+        if let Some((mesh, _)) = &self.grid_mesh {
+            profiler::count_draw();
+            graphics::draw(ctx, mesh, DrawParam::new().dest(origin))?;
+        }
+
+        blocks.clear();
+        self.add_blocks(origin, blocks, self.block_size);
+        blocks.draw(ctx)?;
+        blocks.clear();
+
+        graphics::set_canvas(ctx, None);
+        graphics::set_screen_coordinates(ctx, screen)?;
+
+        self.layer = Some(Layer {
+            canvas,
+            revision: self.revision,
+            block_size: self.block_size,
+            vanish_rows: rows,
+        });
+
+        Ok(())
+    }
+
+    /// Draws the background, the grid lines and the border.
+    /// Returns true if the locked blocks came from the cached layer,
+    /// otherwise they have to be added to the batch with `add_blocks`.
     pub fn draw(
         &mut self,
         ctx: &mut Context,
         position: Point2<f32>,
-        blocks: &mut Blocks,
         block_size: i32,
-    ) -> GameResult {
+    ) -> GameResult<bool> {
         if self.block_size != block_size {
             self.block_size = block_size;
             self.update_grid = true;
         }
 
-        blocks.clear();
+        let mut cached = false;
+        if !self.animating() && self.layer_valid() {
+            if let Some(layer) = &self.layer {
+                let offset = Vector2::new(0.0, (layer.vanish_rows * block_size) as f32);
+                profiler::count_draw();
+                graphics::draw(ctx, &layer.canvas, DrawParam::new().dest(position - offset))?;
+                cached = true;
+            }
+        }
+
+        if !cached {
+            if let Some((mesh, _)) = &self.grid_mesh {
+                profiler::count_draw();
+                graphics::draw(ctx, mesh, DrawParam::new().dest(position))?;
+            }
+        }
+
+        if let Some(border) = &self.border_mesh {
+            profiler::count_draw();
+            graphics::draw(ctx, border, DrawParam::new().dest(position))?;
+        }
 
+        Ok(cached)
+    }
+
+    /// Adds the blocks of the stack to the batch, including the ones being cleared
+    pub fn add_blocks(&self, position: Point2<f32>, blocks: &mut Blocks, block_size: i32) {
         let alpha = 0.5;
 
-        for y in 0..=self.height {
-            let mut alpha = alpha;
+        let rising_offset = if let Some(rising) = &self.rising {
+            let ratio = rising.current_duration.as_secs_f32() / rising.max_duration.as_secs_f32();
+            (1.0 - ratio.min(1.0)) * (rising.rows * block_size) as f32
+        } else {
+            0.0
+        };
+
+        // Cleared rows fade out during the first half of the clear delay,
+        // then the rows above them slide down during the second half
+        let (fade, slide) = if let Some(clearing) = &self.clearing {
+            let ratio = if clearing.max_duration > Duration::new(0, 0) {
+                clearing.current_duration.as_secs_f32() / clearing.max_duration.as_secs_f32()
+            } else {
+                1.0
+            };
+
+            (
+                (1.0 - ratio * 2.0).max(0.0),
+                ((ratio - 0.5) * 2.0).max(0.0).min(1.0),
+            )
+        } else {
+            (0.0, 1.0)
+        };
 
-            if let Some(clearing) = &self.clearing {
-                let y = self.vanish + y - 1;
-                if clearing.rows.contains(&y) {
-                    let ratio = clearing.current_duration.as_secs_f32()
-                        / clearing.max_duration.as_secs_f32();
+        let first = -self.vanish_rows.max(0).min(self.vanish - 1);
+        for y in first..=self.height {
+            let row = (self.vanish + y - 1) as usize;
+            let alpha = if y < 0 { alpha * 0.4 } else { alpha };
 
-                    alpha *= 1.0 - ratio;
-                }
+            let mut offset = rising_offset;
+            if let Some(clearing) = &self.clearing {
+                offset -= clearing.shifts[row] as f32 * (1.0 - slide) * block_size as f32;
             }
 
             for x in 0..self.width {
-                let block = self.grid[(self.vanish + y - 1) as usize][x as usize];
+                let block = self.grid[row][x as usize];
                 if block == 0 {
                     continue;
                 }
 
                 let destination = Point2::new(
                     position[0] + (x * block_size) as f32,
-                    position[1] + ((y - 1) * block_size) as f32,
+                    position[1] + ((y - 1) * block_size) as f32 + offset,
                 );
 
                 blocks.add(block, block_size, destination, alpha);
             }
         }
 
-        graphics::draw(
-            ctx,
-            &self.grid_mesh.as_ref().unwrap().0,
-            DrawParam::new().dest(position),
-        )?;
+        if let Some(clearing) = &self.clearing {
+            for (&y, cells) in clearing.rows.iter().zip(&clearing.cells) {
+                let y = y - self.vanish;
+                if y < first - 1 {
+                    continue;
+                }
+
+                for (x, &block) in cells.iter().enumerate() {
+                    if block == 0 {
+                        continue;
+                    }
+
+                    let destination = Point2::new(
+                        position[0] + (x as i32 * block_size) as f32,
+                        position[1] + (y * block_size) as f32 + rising_offset,
+                    );
+
+                    blocks.add(block, block_size, destination, alpha * fade);
+                }
+            }
+        }
 
         for block in &self.destroyed_blocks {
             blocks.add_destroyed(
@@ -456,50 +1070,346 @@ impl Stack {
                     .color(Color::new(1.0, 1.0, 1.0, 0.5 * block.alpha)),
             );
         }
+    }
 
-        blocks.draw(ctx)?;
-
-        Ok(())
+    /// Sparks are drawn on top of the blocks
+    pub fn draw_sparks(
+        &self,
+        ctx: &mut Context,
+        position: Point2<f32>,
+        blocks: &Blocks,
+        block_size: i32,
+    ) -> GameResult {
+        self.sparks.draw(ctx, position, blocks, block_size)
     }
 
-    fn clear_full_rows(&mut self, clear_delay: Duration) -> i32 {
-        let rows = self.get_full_rows();
+    fn clear_full_rows(&mut self, clear_delays: ClearDelays) -> i32 {
+        let mut rows = mem::take(&mut self.full_rows);
+        self.get_full_rows(&mut rows);
         let length = rows.len();
+        self.chains.clear();
+        self.cleared_garbage = rows
+            .iter()
+            .filter(|&&y| self.grid[y as usize].iter().any(|&b| blocks::is_garbage(b)))
+            .count() as i32;
+
+        if length > 0 && self.zone.is_some() {
+            self.pile_zone_rows(&rows);
+        } else if length > 0 {
+            // Bigger clears give bigger bursts
+            let count = 2 + 2 * length;
+            for &y in &rows {
+                for x in 0..self.width {
+                    let block_id = self.grid[y as usize][x as usize];
+                    self.sparks.burst(x, y - self.vanish, block_id, count);
+                }
+            }
+
+            let blasted = self.blast_bombs(&rows);
+            self.clear_rows(&rows, clear_delays.get(length as i32));
+
+            // Full rows have no holes, so a column only shrinks by the cleared rows,
+            // unless its top block was cleared and whatever was below is exposed
+            let len = self.grid.len() as i32;
+            for x in 0..self.width as usize {
+                if blasted || rows.contains(&(len - self.heights[x])) {
+                    self.rescan_column(x);
+                } else {
+                    self.heights[x] -= length as i32;
+                }
+            }
+
+            if self.cascade {
+                self.cascade_chains();
+            }
+        }
 
-        if length > 0 {
-            self.clear_rows(&rows, clear_delay);
+        self.full_rows = rows;
+        if length == 0 && self.zone.is_none() {
+            return self.clear_groups();
         }
 
         length as i32
     }
 
-    fn get_full_rows(&self) -> Vec<i32> {
+    /// Clears the groups found by the detector and lets the blocks above fall, sticking together.
+    /// Groups made by the fallen blocks clear as further links of the chain.
+    /// Returns the number of groups cleared right after the lock.
+    fn clear_groups(&mut self) -> i32 {
+        let mut cells = vec![];
+        let mut first = 0;
+
+        loop {
+            let groups = self.detector.groups(&self.grid, &mut cells);
+            if groups == 0 {
+                break;
+            }
+
+            for &(x, y) in &cells {
+                let block_id = self.grid[y][x];
+                self.sparks
+                    .burst(x as i32, y as i32 - self.vanish, block_id, 6);
+                self.grid[y][x] = 0;
+            }
+
+            if first == 0 {
+                first = groups;
+            } else {
+                self.chains.push(groups);
+            }
+
+            self.settle();
+        }
+
+        if first > 0 {
+            self.rescan();
+            self.changed();
+        }
+
+        first
+    }
+
+    /// Lets the floating groups fall and clears the rows they fill, over and over until
+    /// nothing moves. The links of the chain clear at once, only the first clear is animated.
+    fn cascade_chains(&mut self) {
         let mut rows = vec![];
 
-        for y in 0..self.height + self.vanish {
-            let mut full = true;
+        while self.settle() {
+            self.get_full_rows(&mut rows);
+            if rows.is_empty() {
+                break;
+            }
+
+            for &y in &rows {
+                for x in 0..self.width {
+                    let block_id = self.grid[y as usize][x as usize];
+                    self.sparks.burst(x, y - self.vanish, block_id, 6);
+                }
+            }
+
+            self.blast_bombs(&rows);
+            self.collapse_rows(&rows);
+            self.chains.push(rows.len() as i32);
+        }
+
+        self.rescan();
+        self.changed();
+    }
+
+    /// Drops every group of connected blocks as far as it goes, the lowest groups first.
+    /// Steel stays in place. Returns true if anything moved.
+    fn settle(&mut self) -> bool {
+        const NONE: usize = usize::max_value();
+
+        let width = self.width as usize;
+        let height = self.grid.len();
+
+        // Groups found with a flood fill, the grid keeps the group of every cell
+        let mut group = vec![vec![NONE; width]; height];
+        let mut groups: Vec<Vec<(usize, usize)>> = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                let block = self.grid[y][x];
+                if block == 0 || block == STEEL_BLOCK || group[y][x] != NONE {
+                    continue;
+                }
+
+                let id = groups.len();
+                let mut cells = vec![(x, y)];
+                group[y][x] = id;
+
+                let mut i = 0;
+                while i < cells.len() {
+                    let (cx, cy) = cells[i];
+                    i += 1;
+
+                    let neighbours = [
+                        (cx.wrapping_sub(1), cy),
+                        (cx + 1, cy),
+                        (cx, cy.wrapping_sub(1)),
+                        (cx, cy + 1),
+                    ];
+                    for &(nx, ny) in &neighbours {
+                        if nx < width
+                            && ny < height
+                            && self.grid[ny][nx] != 0
+                            && self.grid[ny][nx] != STEEL_BLOCK
+                            && group[ny][nx] == NONE
+                        {
+                            group[ny][nx] = id;
+                            cells.push((nx, ny));
+                        }
+                    }
+                }
+
+                groups.push(cells);
+            }
+        }
+
+        let mut order: Vec<usize> = (0..groups.len()).collect();
+        order.sort_by_key(|&id| {
+            let bottom = groups[id].iter().map(|&(_, y)| y).max().unwrap_or(0);
+            height - bottom
+        });
+
+        let mut moved = false;
+        for id in order {
+            let cells = &mut groups[id];
+            let fits = |drop: usize| {
+                cells.iter().all(|&(x, y)| {
+                    y + drop < height && (self.grid[y + drop][x] == 0 || group[y + drop][x] == id)
+                })
+            };
+
+            let mut drop = 0;
+            while fits(drop + 1) {
+                drop += 1;
+            }
+            if drop == 0 {
+                continue;
+            }
+
+            let blocks: Vec<usize> = cells.iter().map(|&(x, y)| self.grid[y][x]).collect();
+            for &(x, y) in cells.iter() {
+                self.grid[y][x] = 0;
+                group[y][x] = NONE;
+            }
+            for (cell, &block) in cells.iter_mut().zip(&blocks) {
+                cell.1 += drop;
+                self.grid[cell.1][cell.0] = block;
+                group[cell.1][cell.0] = id;
+            }
 
+            moved = true;
+        }
+
+        moved
+    }
+
+    /// Empties the cells around the bombs in the cleared rows, except for steel.
+    /// Returns true if anything outside of the cleared rows was destroyed.
+    fn blast_bombs(&mut self, rows: &[i32]) -> bool {
+        let mut blasted = false;
+
+        for &y in rows {
             for x in 0..self.width {
-                if self.grid[y as usize][x as usize] == 0 {
-                    full = false;
-                    break;
+                if self.grid[y as usize][x as usize] != BOMB_BLOCK {
+                    continue;
+                }
+
+                for by in (y - 1).max(0)..=(y + 1).min(self.grid.len() as i32 - 1) {
+                    if rows.contains(&by) {
+                        continue;
+                    }
+
+                    for bx in (x - 1).max(0)..=(x + 1).min(self.width - 1) {
+                        let block_id = self.grid[by as usize][bx as usize];
+                        if block_id != 0 && block_id != STEEL_BLOCK {
+                            self.sparks.burst(bx, by - self.vanish, block_id, 6);
+                            self.grid[by as usize][bx as usize] = 0;
+                            blasted = true;
+                        }
+                    }
                 }
             }
+        }
+
+        blasted
+    }
 
-            if full {
-                rows.push(y);
+    /// Moves the full rows down onto the rows already piled up in the zone,
+    /// instead of clearing them
+    fn pile_zone_rows(&mut self, rows: &[i32]) {
+        let piled = self.zone_rows();
+        let top = self.grid.len() - piled as usize;
+
+        // The other rows keep their order and fall into the gaps
+        let mut kept = Vec::with_capacity(self.grid.len());
+        let mut full = Vec::with_capacity(rows.len());
+        for (y, row) in self.grid.drain(..top).enumerate() {
+            if rows.contains(&(y as i32)) {
+                full.push(row);
+            } else {
+                kept.push(row);
             }
         }
 
-        rows
+        kept.append(&mut full);
+        kept.append(&mut self.grid);
+        self.grid = kept;
+
+        self.zone = Some(piled + rows.len() as i32);
+        self.rescan();
+        self.changed();
+    }
+
+    /// Full rows stop being cleared and pile up at the bottom instead
+    pub fn start_zone(&mut self) {
+        self.zone = Some(0);
+    }
+
+    /// Clears every row piled up during the zone at once, returns their number
+    pub fn end_zone(&mut self, clear_delays: ClearDelays) -> i32 {
+        let piled = match self.zone.take() {
+            Some(piled) if piled > 0 => piled,
+            _ => return 0,
+        };
+
+        let len = self.grid.len() as i32;
+        let rows: Vec<i32> = (len - piled..len).collect();
+        for &y in &rows {
+            for x in 0..self.width {
+                let block_id = self.grid[y as usize][x as usize];
+                self.sparks.burst(x, y - self.vanish, block_id, 4);
+            }
+        }
+
+        self.clear_rows(&rows, clear_delays.get(piled));
+        self.rescan();
+        piled
+    }
+
+    /// Rows piled up at the bottom during the zone
+    pub fn zone_rows(&self) -> i32 {
+        self.zone.unwrap_or(0)
+    }
+
+    fn get_full_rows(&self, rows: &mut Vec<i32>) {
+        // Rows piled up in the zone are full, but they only clear when it ends
+        let limit = self.height + self.vanish - self.zone_rows();
+        self.detector.full_rows(&self.grid, limit as usize, rows);
     }
 
     fn clear_rows(&mut self, rows: &[i32], clear_delay: Duration) {
-        self.clearing = Some(Clearing {
-            rows: Vec::from(rows),
-            current_duration: Duration::new(0, 0),
-            max_duration: clear_delay,
-        });
+        let mut clearing = self.recycled.take().unwrap_or_default();
+
+        clearing.rows.clear();
+        clearing.rows.extend_from_slice(rows);
+
+        clearing.cells.resize_with(rows.len(), Vec::new);
+        for (cells, &y) in clearing.cells.iter_mut().zip(rows) {
+            cells.clone_from(&self.grid[y as usize]);
+        }
+
+        // How many rows each row will fall after collapsing
+        clearing.shifts.clear();
+        clearing.shifts.resize(self.grid.len(), 0);
+        let mut fallen = 0;
+        for y in (0..self.grid.len()).rev() {
+            if rows.contains(&(y as i32)) {
+                fallen += 1;
+            } else {
+                clearing.shifts[y + fallen] = fallen as i32;
+            }
+        }
+
+        clearing.current_duration = Duration::new(0, 0);
+        clearing.max_duration = clear_delay;
+
+        self.collapse_rows(rows);
+        self.changed();
+
+        self.clearing = Some(clearing);
     }
 
     pub fn game_over(&mut self) {
@@ -514,6 +1424,7 @@ impl Stack {
         }
 
         self.clear_rows(&rows, Duration::new(0, 0));
+        self.rescan();
         self.game_over = true;
     }
 
@@ -575,3 +1486,141 @@ impl Stack {
         }
     }
 }
+
+#[test]
+fn surface_tracking_test() {
+    let mut stack = Stack::new(10, 20, 20);
+    let mut garbage = vec![GarbageRow { hole: 3 }; 4];
+    garbage.push(GarbageRow { hole: 0 });
+    stack.insert_garbage(&garbage);
+
+    for x in 0..9 {
+        stack.place_random(x, 34);
+    }
+
+    // Vertical I in the last column clears the rows placed above the garbage
+    let mut piece = Piece::new(crate::shape::ShapeType::I, &stack);
+    piece.rotate(true, &stack);
+    while piece.shift(1, 0, &stack) {}
+    piece.fall(&stack);
+    assert!(match stack.lock(&piece, ClearDelays::flat(0)) {
+        Locked::Success(rows) => rows == 1,
+        Locked::Collision => false,
+    });
+
+    let surface = Surface::new(stack.grid());
+    assert_eq!(surface.heights, stack.heights());
+    assert_eq!(surface.holes, stack.holes());
+    assert_eq!(8, stack.filled_height());
+    assert_eq!(0, stack.cleared_garbage());
+    assert_eq!(5, stack.garbage_rows());
+}
+
+#[test]
+fn zone_rows_test() {
+    let mut stack = Stack::new(10, 20, 20);
+    stack.start_zone();
+
+    for x in 0..9 {
+        stack.place_random(x, 37);
+        stack.place_random(x, 38);
+    }
+    for x in 1..9 {
+        stack.place_random(x, 39);
+    }
+
+    // Vertical I in the last column fills the two rows above the bottom one
+    let mut piece = Piece::new(crate::shape::ShapeType::I, &stack);
+    piece.rotate(true, &stack);
+    while piece.shift(1, 0, &stack) {}
+    piece.fall(&stack);
+    assert!(match stack.lock(&piece, ClearDelays::flat(0)) {
+        Locked::Success(rows) => rows == 2,
+        Locked::Collision => false,
+    });
+
+    // The full rows sink to the bottom and lift the row that was below them
+    assert_eq!(2, stack.zone_rows());
+    assert!(stack.grid()[38].iter().all(|&b| b != 0));
+    assert!(stack.grid()[39].iter().all(|&b| b != 0));
+    assert_eq!(0, stack.grid()[37][0]);
+    assert_eq!(stack.heights(), &[2, 3, 3, 3, 3, 3, 3, 3, 3, 4][..]);
+
+    assert_eq!(2, stack.end_zone(ClearDelays::flat(0)));
+    assert_eq!(0, stack.zone_rows());
+    assert_eq!(stack.heights(), &[0, 1, 1, 1, 1, 1, 1, 1, 1, 2][..]);
+}
+
+#[test]
+fn special_cells_test() {
+    let mut stack = Stack::new(10, 20, 20);
+    stack.insert_steel(1);
+
+    for x in (0..9).filter(|&x| x != 4) {
+        stack.place_random(x, 38);
+    }
+    stack.place(4, 38, BOMB_BLOCK);
+    for &x in &[0, 3, 4, 5] {
+        stack.place_random(x, 37);
+    }
+
+    // Vertical I in the last column clears the row with the bomb, but never the steel
+    let mut piece = Piece::new(crate::shape::ShapeType::I, &stack);
+    piece.rotate(true, &stack);
+    while piece.shift(1, 0, &stack) {}
+    piece.fall(&stack);
+    assert!(match stack.lock(&piece, ClearDelays::flat(0)) {
+        Locked::Success(rows) => rows == 1,
+        Locked::Collision => false,
+    });
+
+    assert_eq!(1, stack.cleared_garbage());
+    assert!(stack.grid()[39].iter().all(|&b| b == STEEL_BLOCK));
+
+    // The bomb blasted the blocks above it
+    assert_ne!(0, stack.grid()[38][0]);
+    assert!(stack.grid()[38][3..=5].iter().all(|&b| b == 0));
+
+    let surface = Surface::new(stack.grid());
+    assert_eq!(surface.heights, stack.heights());
+    assert_eq!(surface.holes, stack.holes());
+}
+
+#[test]
+fn cascade_test() {
+    let mut stack = Stack::new(10, 20, 20);
+    stack.set_cascade(true);
+
+    for x in 0..9 {
+        stack.place_random(x, 39);
+        if x != 3 {
+            stack.place_random(x, 38);
+        }
+    }
+    stack.place_random(3, 33);
+
+    // The floating block falls into the gap once the bottom row clears, clearing another row
+    let mut piece = Piece::new(crate::shape::ShapeType::I, &stack);
+    piece.rotate(true, &stack);
+    while piece.shift(1, 0, &stack) {}
+    piece.fall(&stack);
+    assert!(match stack.lock(&piece, ClearDelays::flat(0)) {
+        Locked::Success(rows) => rows == 1,
+        Locked::Collision => false,
+    });
+    assert_eq!(stack.chains(), &[1]);
+
+    // Only the rest of the I is left
+    assert_eq!(stack.heights(), &[0, 0, 0, 0, 0, 0, 0, 0, 0, 2][..]);
+    let surface = Surface::new(stack.grid());
+    assert_eq!(surface.holes, stack.holes());
+}
+
+#[test]
+fn clear_delays_test() {
+    let delays = ClearDelays::default();
+    assert_eq!(delays.get(1), Duration::from_millis(200));
+    assert_eq!(delays.get(4), Duration::from_millis(500));
+    assert_eq!(delays.get(8), Duration::from_millis(500));
+    assert_eq!(ClearDelays::flat(100).get(2), Duration::from_millis(100));
+}
@@ -0,0 +1,295 @@
+use std::{collections::BTreeMap, fs, path::PathBuf, time::Duration};
+
+use chrono::Local;
+use dirs;
+use serde::{Deserialize, Serialize};
+use serde_json;
+
+use crate::{finesse::FinesseRecord, history::History, mode::GameMode};
+
+/// How many games are kept in the recent games list
+const MAX_GAMES: usize = 100;
+
+#[derive(Serialize, Deserialize, Copy, Clone, Default, Debug, PartialEq)]
+#[serde(default)]
+pub struct Clears {
+    pub single: u32,
+    pub double: u32,
+    pub triple: u32,
+    pub tetris: u32,
+    pub t_spin: u32,
+}
+
+impl Clears {
+    pub fn add(&mut self, rows: i32, t_spin: bool) {
+        match (rows, t_spin) {
+            (0, _) => (),
+            (_, true) => self.t_spin += 1,
+            (1, _) => self.single += 1,
+            (2, _) => self.double += 1,
+            (3, _) => self.triple += 1,
+            _ => self.tetris += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &Clears) {
+        self.single += other.single;
+        self.double += other.double;
+        self.triple += other.triple;
+        self.tetris += other.tetris;
+        self.t_spin += other.t_spin;
+    }
+
+    pub fn total(&self) -> u32 {
+        self.single + self.double + self.triple + self.tetris + self.t_spin
+    }
+
+    /// Names and counts in the order they are shown in the breakdown
+    pub fn breakdown(&self) -> [(&'static str, u32); 5] {
+        [
+            ("Single", self.single),
+            ("Double", self.double),
+            ("Triple", self.triple),
+            ("Tetris", self.tetris),
+            ("T-Spin", self.t_spin),
+        ]
+    }
+}
+
+/// How efficiently garbage was cleared, counted only while there was garbage on the board
+#[derive(Serialize, Deserialize, Copy, Clone, Default, Debug, PartialEq)]
+#[serde(default)]
+pub struct Dig {
+    /// Pieces locked while there was garbage on the board
+    pub pieces: u32,
+    /// Garbage rows cleared
+    pub lines: u32,
+    /// Pieces that cleared no garbage and left some behind
+    pub wasted: u32,
+}
+
+impl Dig {
+    /// Adds a locked piece, given the garbage rows it cleared and the ones still left
+    pub fn add(&mut self, cleared: i32, left: i32) {
+        if cleared == 0 && left == 0 {
+            return;
+        }
+
+        self.pieces += 1;
+        self.lines += cleared as u32;
+        if cleared == 0 {
+            self.wasted += 1;
+        }
+    }
+
+    /// Garbage rows cleared per piece
+    pub fn efficiency(&self) -> f32 {
+        self.lines as f32 / self.pieces.max(1) as f32
+    }
+
+    /// Grade of the downstacking, fast diggers clear a garbage row every two to three pieces
+    pub fn grade(&self) -> &'static str {
+        match self.efficiency() {
+            e if e >= 0.45 => "S",
+            e if e >= 0.35 => "A",
+            e if e >= 0.25 => "B",
+            e if e >= 0.15 => "C",
+            _ => "D",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GameRecord {
+    pub mode: GameMode,
+    pub score: i32,
+    pub lines: i32,
+    pub duration: Duration,
+    pub date: String,
+    pub clears: Clears,
+}
+
+impl GameRecord {
+    pub fn new(
+        mode: GameMode,
+        score: i32,
+        lines: i32,
+        duration: Duration,
+        clears: Clears,
+    ) -> GameRecord {
+        GameRecord {
+            mode,
+            score,
+            lines,
+            duration,
+            date: Local::now().format("%Y-%m-%d %H:%M").to_string(),
+            clears,
+        }
+    }
+}
+
+/// Totals over every finished game, persisted next to the settings
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Stats {
+    pub played: u32,
+    pub lines: i64,
+    pub time: Duration,
+    pub clears: Clears,
+    pub bests: Vec<GameRecord>,
+    pub recent: Vec<GameRecord>,
+    /// Finesse drill tries of every placement
+    pub finesse: BTreeMap<String, FinesseRecord>,
+    /// Versus matches against other players
+    pub history: History,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats::load().unwrap_or_default()
+    }
+
+    fn path() -> PathBuf {
+        let mut path = dirs::data_local_dir().unwrap_or_default();
+        path.push("klocki");
+        path.push("stats.json");
+        path
+    }
+
+    pub fn save(&self) {
+        let path = Stats::path();
+        let json = serde_json::to_string_pretty(self).unwrap();
+
+        if let Err(e) = fs::write(&path, json) {
+            log::error!("Unable to save stats: {:?}", e);
+        } else {
+            log::info!("Saved stats to: {:?}", &path);
+        }
+    }
+
+    fn load() -> Option<Stats> {
+        let path = Stats::path();
+        let contents = fs::read_to_string(&path).ok()?;
+
+        match serde_json::from_str(&contents) {
+            Ok(stats) => Some(stats),
+            Err(e) => {
+                log::error!("Error while reading stats file: {:?}", e);
+                None
+            }
+        }
+    }
+
+    pub fn record(&mut self, game: GameRecord) {
+        self.played += 1;
+        self.lines += i64::from(game.lines);
+        self.time += game.duration;
+        self.clears.merge(&game.clears);
+
+        // Modes with a win condition only count won games, races go by the time
+        let win_condition = game.mode.win_condition();
+        let won = win_condition.map_or(true, |w| w.met(game.lines, game.duration));
+        let better = |best: &GameRecord| match win_condition {
+            Some(w) if w.race() => game.duration < best.duration,
+            _ => game.score > best.score,
+        };
+
+        match self.bests.iter_mut().find(|b| b.mode == game.mode) {
+            Some(best) if won && better(best) => *best = game.clone(),
+            Some(_) => (),
+            None if won => self.bests.push(game.clone()),
+            None => (),
+        }
+
+        self.recent.insert(0, game);
+        self.recent.truncate(MAX_GAMES);
+    }
+
+    pub fn best(&self, mode: GameMode) -> Option<&GameRecord> {
+        self.bests.iter().find(|b| b.mode == mode)
+    }
+}
+
+#[test]
+fn stats_record_test() {
+    let mut stats = Stats::default();
+    let mut clears = Clears::default();
+    clears.add(4, false);
+    clears.add(2, true);
+    clears.add(0, true);
+
+    let game = |score| {
+        GameRecord::new(
+            GameMode::Endless,
+            score,
+            10,
+            Duration::from_secs(60),
+            clears,
+        )
+    };
+    stats.record(game(500));
+    stats.record(game(300));
+
+    assert_eq!(stats.played, 2);
+    assert_eq!(stats.clears.tetris, 2);
+    assert_eq!(stats.clears.t_spin, 2);
+    assert_eq!(stats.clears.total(), 4);
+    assert_eq!(stats.best(GameMode::Endless).unwrap().score, 500);
+    assert_eq!(stats.recent[0].score, 300);
+
+    let sprint = |lines, secs| {
+        GameRecord::new(
+            GameMode::Sprint,
+            0,
+            lines,
+            Duration::from_secs(secs),
+            clears,
+        )
+    };
+    stats.record(sprint(20, 30));
+    assert!(stats.best(GameMode::Sprint).is_none());
+    stats.record(sprint(40, 90));
+    stats.record(sprint(40, 120));
+    assert_eq!(
+        stats.best(GameMode::Sprint).unwrap().duration,
+        Duration::from_secs(90)
+    );
+
+    let ultra = |score, secs| {
+        GameRecord::new(
+            GameMode::Ultra,
+            score,
+            10,
+            Duration::from_secs(secs),
+            clears,
+        )
+    };
+    stats.record(ultra(5000, 60));
+    assert!(stats.best(GameMode::Ultra).is_none());
+    stats.record(ultra(3000, 120));
+    stats.record(ultra(4000, 120));
+    assert_eq!(stats.best(GameMode::Ultra).unwrap().score, 4000);
+}
+
+#[test]
+fn dig_test() {
+    let mut dig = Dig::default();
+    dig.add(0, 0);
+    assert_eq!(dig.pieces, 0);
+
+    dig.add(0, 4);
+    dig.add(0, 4);
+    dig.add(1, 3);
+    dig.add(2, 0);
+
+    assert_eq!(dig.pieces, 4);
+    assert_eq!(dig.lines, 3);
+    assert_eq!(dig.wasted, 2);
+    assert_eq!(dig.grade(), "S");
+
+    for _ in 0..6 {
+        dig.add(0, 2);
+    }
+    assert_eq!(dig.wasted, 8);
+    assert_eq!(dig.grade(), "B");
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use ggez::{
     filesystem,
     graphics::{self, Rect},
@@ -28,3 +30,109 @@ pub fn path(ctx: &Context, path: &str) -> String {
         String::from(path)
     }
 }
+
+/// FNV-1a over little endian values, unlike the standard hasher it gives
+/// the same results on every platform and compiler version
+pub struct Fnv64(u64);
+
+impl Default for Fnv64 {
+    fn default() -> Fnv64 {
+        Fnv64(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv64 {
+    pub fn write(&mut self, value: u64) {
+        for byte in value.to_le_bytes().iter() {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Turns a seed code into a seed, codes of 64 hex digits map directly to the seed bytes
+/// while anything else is hashed so that players can share memorable seeds
+pub fn seed_from_code(code: &str) -> [u8; 32] {
+    let mut seed = [0u8; 32];
+
+    let code = code.trim();
+    if code.len() == 64 && code.chars().all(|c| c.is_ascii_hexdigit()) {
+        for (i, byte) in seed.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&code[i * 2..i * 2 + 2], 16).unwrap();
+        }
+
+        return seed;
+    }
+
+    // FNV-1a, salted differently for every 8 bytes of the seed
+    for (i, chunk) in seed.chunks_mut(8).enumerate() {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325 ^ i as u64;
+        for byte in code.bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+
+        chunk.copy_from_slice(&hash.to_le_bytes());
+    }
+
+    seed
+}
+
+pub fn seed_code(seed: &[u8; 32]) -> String {
+    seed.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Minutes, seconds and hundredths, for timed modes
+pub fn race_time(duration: Duration) -> String {
+    let hundredths = duration.as_millis() / 10;
+    format!(
+        "{}:{:02}.{:02}",
+        hundredths / 6000,
+        hundredths / 100 % 60,
+        hundredths % 100
+    )
+}
+
+/// Largest block size fitting a board along with the hold box on its left,
+/// the queue and score on its right and some margin for the popups
+pub fn fit_block_size(screen: Rect, width: i32, height: i32) -> i32 {
+    const HUD_COLUMNS: f32 = 11.0;
+    const MARGIN_ROWS: f32 = 4.0;
+    const MIN_BLOCK_SIZE: i32 = 8;
+    const MAX_BLOCK_SIZE: i32 = 64;
+
+    let by_width = screen.w / (width as f32 + HUD_COLUMNS);
+    let by_height = screen.h / (height as f32 + MARGIN_ROWS);
+
+    (by_width.min(by_height) as i32)
+        .max(MIN_BLOCK_SIZE)
+        .min(MAX_BLOCK_SIZE)
+}
+
+#[test]
+fn race_time_test() {
+    assert_eq!(race_time(Duration::from_millis(83_456)), "1:23.45");
+    assert_eq!(race_time(Duration::from_millis(9_999)), "0:09.99");
+}
+
+#[test]
+fn fit_block_size_test() {
+    // Narrow window, the width decides
+    assert_eq!(
+        fit_block_size(Rect::new(0.0, 0.0, 700.0, 1080.0), 10, 20),
+        33
+    );
+    // Wide window, the height decides
+    assert_eq!(
+        fit_block_size(Rect::new(0.0, 0.0, 1920.0, 1080.0), 10, 20),
+        45
+    );
+    assert_eq!(
+        fit_block_size(Rect::new(0.0, 0.0, 100.0, 1080.0), 10, 20),
+        8
+    );
+}
@@ -0,0 +1,146 @@
+use std::{path::Path, time::Duration};
+
+use crate::{engine::Engine, replay::ReplayData, settings};
+
+pub struct Simulation {
+    pub engine: Engine,
+    /// First checkpoint at which the simulated state differs from the recorded one
+    pub desync: Option<u32>,
+    /// Time the simulated game took, counting only the actions played until it was over
+    pub clock: Duration,
+}
+
+/// Plays back the actions of the replay instantly, using the recorded rules
+/// and the default gameplay settings, until the game is over.
+pub fn simulate(replay_data: &ReplayData) -> Simulation {
+    let settings = settings::Gameplay::default();
+    let mut engine = Engine::new(&replay_data.seed, false, &replay_data.rules);
+
+    let mut checkpoint = 0;
+    let mut desync = None;
+    let mut clock = Duration::new(0, 0);
+
+    for (i, timed) in replay_data.actions.iter().enumerate() {
+        if engine.game_over() {
+            break;
+        }
+
+        while let Some(c) = replay_data.checkpoints.get(checkpoint) {
+            if c.action as usize > i {
+                break;
+            }
+
+            if desync.is_none() && c.hash != engine.state_hash() {
+                desync = Some(c.action);
+            }
+            checkpoint += 1;
+        }
+
+        clock += timed.duration;
+        engine.skip(&settings, timed.action);
+    }
+
+    Simulation {
+        engine,
+        desync,
+        clock,
+    }
+}
+
+/// Re-simulates a replay without a window or audio and compares the outcome
+/// with the results stored in the replay. Returns the process exit code.
+pub fn run(path: &Path) -> i32 {
+    let replay_data = match ReplayData::load(path) {
+        Some(replay_data) => replay_data,
+        None => {
+            eprintln!("Unable to load replay {:?}", path);
+            return 2;
+        }
+    };
+
+    let Simulation {
+        engine,
+        desync,
+        clock: duration,
+    } = simulate(&replay_data);
+
+    let score = engine.score.score();
+    let lines = engine.score.lines();
+
+    println!("Score: {}", score);
+    println!("Lines: {}", lines);
+    println!(
+        "Time:  {}:{:06.3}",
+        duration.as_secs() / 60,
+        duration.as_secs_f32() % 60.0
+    );
+
+    let results = match replay_data.results {
+        Some(results) => results,
+        None => {
+            eprintln!("Replay does not contain any stored results");
+            return 2;
+        }
+    };
+
+    let mut matching = true;
+    if let Some(action) = desync {
+        eprintln!("State mismatch at the checkpoint before action {}", action);
+        matching = false;
+    }
+
+    if results.score != score {
+        eprintln!(
+            "Score mismatch: stored {}, simulated {}",
+            results.score, score
+        );
+        matching = false;
+    }
+
+    if results.lines != lines {
+        eprintln!(
+            "Lines mismatch: stored {}, simulated {}",
+            results.lines, lines
+        );
+        matching = false;
+    }
+
+    if results.duration != duration {
+        eprintln!(
+            "Time mismatch: stored {:?}, simulated {:?}",
+            results.duration, duration
+        );
+        matching = false;
+    }
+
+    if !engine.game_over() {
+        eprintln!("Replay ends before the game is over");
+        matching = false;
+    }
+
+    if matching {
+        println!("Replay verified");
+        0
+    } else {
+        1
+    }
+}
+
+#[test]
+fn simulate_clock_test() {
+    use crate::{action::Action, rules::Rules};
+
+    // Actions recorded after the game is over don't count towards its time
+    let mut replay_data = ReplayData::new(&[1; 32], &Rules::default());
+    for _ in 0..5 {
+        replay_data.add(Action::HardDrop, Duration::from_secs(1));
+        replay_data.add(Action::LockPiece, Duration::new(0, 0));
+    }
+    replay_data.add(Action::GameOver, Duration::from_secs(2));
+    replay_data.add(Action::MoveLeft, Duration::from_secs(10));
+
+    let simulation = simulate(&replay_data);
+    assert!(simulation.engine.game_over());
+    assert_eq!(simulation.clock, Duration::from_secs(7));
+    assert_eq!(replay_data.duration(), Duration::from_secs(17));
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use crate::utils::Fnv64;
+
+/// Lines filling the whole meter
+pub const ZONE_LINES: i32 = 16;
+
+/// Smallest part of the meter that can be spent, a quarter like in Tetris Effect
+pub const ZONE_MIN_LINES: i32 = 4;
+
+/// Time in the zone for every line in the meter, a full meter lasts 20 seconds
+const ZONE_TIME_PER_LINE: Duration = Duration::from_millis(1250);
+
+/// Meter filling with line clears, spent to stop gravity for a while.
+/// Rows cleared in the meantime pile up at the bottom and are all cleared at once in the end.
+#[derive(Default, Clone)]
+pub struct Zone {
+    meter: i32,
+    remaining: Option<Duration>,
+}
+
+impl Zone {
+    pub fn active(&self) -> bool {
+        self.remaining.is_some()
+    }
+
+    pub fn ready(&self) -> bool {
+        !self.active() && self.meter >= ZONE_MIN_LINES
+    }
+
+    /// From 0 to 1, drains while the zone is active
+    pub fn meter(&self) -> f32 {
+        match self.remaining {
+            Some(remaining) => {
+                remaining.as_secs_f32() / (ZONE_TIME_PER_LINE * ZONE_LINES as u32).as_secs_f32()
+            }
+            None => self.meter as f32 / ZONE_LINES as f32,
+        }
+    }
+
+    pub fn fill(&mut self, lines: i32) {
+        if !self.active() {
+            self.meter = (self.meter + lines).min(ZONE_LINES);
+        }
+    }
+
+    /// Spends the whole meter
+    pub fn start(&mut self) {
+        self.remaining = Some(ZONE_TIME_PER_LINE * self.meter as u32);
+        self.meter = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.remaining = None;
+    }
+
+    /// Returns true once, when the time runs out
+    pub fn update(&mut self, dt: Duration) -> bool {
+        match self.remaining.as_mut() {
+            Some(remaining) if *remaining > Duration::new(0, 0) => {
+                *remaining = remaining.checked_sub(dt).unwrap_or_default();
+                *remaining == Duration::new(0, 0)
+            }
+            _ => false,
+        }
+    }
+
+    /// The remaining time is left out, the end of the zone comes as an action
+    pub fn hash(&self, hash: &mut Fnv64) {
+        hash.write(self.meter as u64);
+        hash.write(self.active() as u64);
+    }
+}
+
+#[test]
+fn zone_test() {
+    let mut zone = Zone::default();
+    zone.fill(3);
+    assert!(!zone.ready());
+
+    zone.fill(20);
+    assert!(zone.ready());
+    assert_eq!(zone.meter(), 1.0);
+
+    zone.start();
+    assert!(zone.active() && !zone.ready());
+
+    // Clears during the zone don't fill the meter
+    zone.fill(4);
+    assert!(!zone.update(Duration::from_secs(19)));
+    assert!(zone.update(Duration::from_secs(2)));
+    assert!(!zone.update(Duration::from_secs(1)));
+
+    zone.end();
+    assert!(!zone.active());
+    assert_eq!(zone.meter(), 0.0);
+}